@@ -1,3 +1,7 @@
+-- First versioned schema. Databases from before versioned migrations are brought up to it
+-- (see db/schema.rs), which is why it only creates what doesn't exist yet.
+-- Released migrations are checksummed: change the schema in a new file, never in this one.
+
 -- Buckets/accounts configuration
 CREATE TABLE IF NOT EXISTS buckets (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -24,6 +28,8 @@ CREATE TABLE IF NOT EXISTS uploads (
     error_message TEXT,
     started_at DATETIME,
     completed_at DATETIME,
+    etag TEXT,
+    verification TEXT,
     FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE SET NULL
 );
 
@@ -50,6 +56,15 @@ CREATE TABLE IF NOT EXISTS sync_folders (
     enabled BOOLEAN DEFAULT 1,
     last_sync DATETIME,
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    poll_interval_secs INTEGER DEFAULT 300,
+    delete_removed BOOLEAN DEFAULT 0,
+    deletion_policy TEXT CHECK(deletion_policy IN ('never', 'immediate', 'trash')) DEFAULT 'trash',
+    deletion_max_files INTEGER DEFAULT 50,
+    deletion_max_percent INTEGER DEFAULT 10,
+    schedule TEXT CHECK(schedule IN ('realtime', 'interval', 'cron')) DEFAULT 'realtime',
+    schedule_cron TEXT,
+    next_run_at TEXT,
+    settle_secs INTEGER DEFAULT 5,
     FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE CASCADE
 );
 
@@ -63,6 +78,9 @@ CREATE TABLE IF NOT EXISTS file_metadata (
     last_modified DATETIME,
     content_type TEXT,
     cached_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    local_size INTEGER,
+    local_mtime INTEGER,
+    sha256 TEXT,
     FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE CASCADE,
     UNIQUE(bucket_id, remote_path)
 );
@@ -73,8 +91,93 @@ CREATE TABLE IF NOT EXISTS settings (
     value TEXT NOT NULL
 );
 
+-- Per-file state as of the last successful sync
+CREATE TABLE IF NOT EXISTS sync_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    folder_id INTEGER NOT NULL,
+    relative_path TEXT NOT NULL,
+    local_size INTEGER NOT NULL,
+    local_mtime INTEGER NOT NULL,
+    local_inode INTEGER,
+    local_hash TEXT,
+    remote_etag TEXT NOT NULL,
+    remote_last_modified TEXT,
+    synced_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (folder_id) REFERENCES sync_folders(id) ON DELETE CASCADE,
+    UNIQUE(folder_id, relative_path)
+);
+
+-- Concurrent edits kept as both copies
+CREATE TABLE IF NOT EXISTS sync_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    folder_id INTEGER NOT NULL,
+    relative_path TEXT NOT NULL,
+    conflict_path TEXT NOT NULL,
+    detected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME,
+    FOREIGN KEY (folder_id) REFERENCES sync_folders(id) ON DELETE CASCADE
+);
+
+-- Background and manual maintenance runs
+CREATE TABLE IF NOT EXISTS maintenance_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task TEXT NOT NULL,
+    trigger TEXT CHECK(trigger IN ('scheduled', 'manual')) NOT NULL,
+    bucket_name TEXT,
+    status TEXT CHECK(status IN ('success', 'error')) NOT NULL,
+    cleaned_count INTEGER DEFAULT 0,
+    protected_count INTEGER DEFAULT 0,
+    message TEXT,
+    started_at DATETIME NOT NULL,
+    finished_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Deletions held back by the mass-deletion threshold until the user confirms them
+CREATE TABLE IF NOT EXISTS held_deletions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    folder_id INTEGER NOT NULL,
+    relative_path TEXT NOT NULL,
+    side TEXT NOT NULL CHECK(side IN ('local', 'remote')),
+    approved BOOLEAN DEFAULT 0,
+    detected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (folder_id) REFERENCES sync_folders(id) ON DELETE CASCADE,
+    UNIQUE(folder_id, relative_path)
+);
+
+-- Sync folder files being uploaded or that failed to sync, for status badges; files without
+-- a row are synced or pending depending on whether they still match the journal
+CREATE TABLE IF NOT EXISTS sync_file_states (
+    folder_id INTEGER NOT NULL,
+    relative_path TEXT NOT NULL,
+    state TEXT NOT NULL CHECK(state IN ('uploading', 'error')),
+    error_message TEXT,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (folder_id, relative_path),
+    FOREIGN KEY (folder_id) REFERENCES sync_folders(id) ON DELETE CASCADE
+);
+
+-- Files that failed to sync. Transient failures are retried with backoff; permanent ones, and
+-- transient ones that keep failing, are quarantined until the user retries or ignores them
+CREATE TABLE IF NOT EXISTS sync_errors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    folder_id INTEGER NOT NULL,
+    relative_path TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK(kind IN ('transient', 'permanent')),
+    message TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    next_retry_at DATETIME,
+    ignored BOOLEAN DEFAULT 0,
+    first_failed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_failed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (folder_id) REFERENCES sync_folders(id) ON DELETE CASCADE,
+    UNIQUE(folder_id, relative_path)
+);
+
 -- Indexes for performance
 CREATE INDEX IF NOT EXISTS idx_uploads_status ON uploads(status);
+CREATE INDEX IF NOT EXISTS idx_uploads_file_path ON uploads(file_path);
 CREATE INDEX IF NOT EXISTS idx_upload_chunks_upload_id ON upload_chunks(upload_id);
 CREATE INDEX IF NOT EXISTS idx_file_metadata_bucket ON file_metadata(bucket_id, remote_path);
 CREATE INDEX IF NOT EXISTS idx_sync_folders_enabled ON sync_folders(enabled);
+CREATE INDEX IF NOT EXISTS idx_maintenance_log_finished ON maintenance_log(finished_at);
+CREATE INDEX IF NOT EXISTS idx_sync_conflicts_folder ON sync_conflicts(folder_id, resolved_at);
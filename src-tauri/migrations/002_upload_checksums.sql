@@ -0,0 +1,2 @@
+-- Whole-file SHA-256 of each upload, so exported history can prove what was backed up
+ALTER TABLE uploads ADD COLUMN sha256 TEXT;
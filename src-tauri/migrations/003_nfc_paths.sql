@@ -0,0 +1,4 @@
+-- Relative paths and keys are stored in NFC from now on. SQLite can't normalize Unicode, so
+-- the rows journaled before are re-keyed in Rust, just before this migration is recorded
+-- (see `normalize_paths` in src/db/schema.rs).
+SELECT 1;
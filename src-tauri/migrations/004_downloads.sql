@@ -0,0 +1,18 @@
+-- Finished downloads, so exported transfer history covers both directions. Columns match
+-- `uploads`, so the same history filters apply to both.
+CREATE TABLE IF NOT EXISTS downloads (
+    id TEXT PRIMARY KEY,
+    bucket_id INTEGER,
+    file_path TEXT NOT NULL,
+    remote_path TEXT NOT NULL,
+    total_size INTEGER NOT NULL,
+    status TEXT NOT NULL CHECK(status IN ('completed', 'failed', 'cancelled')),
+    error_message TEXT,
+    started_at DATETIME,
+    completed_at DATETIME,
+    etag TEXT,
+    sha256 TEXT,
+    FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_downloads_completed_at ON downloads(completed_at);
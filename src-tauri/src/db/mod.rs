@@ -1,11 +1,132 @@
-use sqlx::{sqlite::SqlitePool, Pool, Sqlite};
+mod schema;
+
+pub(crate) use schema::run_migrations;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqliteSynchronous};
+use sqlx::{Pool, QueryBuilder, Sqlite};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 use crate::crypto::Crypto;
+use crate::utils::{
+    DatabaseDiagnostics, DeletionPolicy, DeletionSide, HeldDeletion, ScheduleKind, SyncConflict, SyncError, SyncErrorKind,
+    SyncMode, TableRowCount,
+};
+
+/// What we last knew about a local file and the object it was uploaded to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileFingerprint {
+    pub remote_size: i64,
+    pub etag: Option<String>,
+    pub sha256: Option<String>,
+    pub local_size: i64,
+    /// Milliseconds since the Unix epoch
+    pub local_mtime: i64,
+}
+
+/// A finished download, for the transfer history
+#[derive(Debug, Clone)]
+pub struct DownloadRecord {
+    pub local_path: String,
+    pub remote_path: String,
+    /// Bytes written; 0 if the download failed
+    pub size: i64,
+    pub etag: Option<String>,
+    /// Hex SHA-256 of the downloaded file, where it was hashed
+    pub sha256: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// Why the download failed; `None` if it completed
+    pub error: Option<String>,
+}
+
+const SYNC_ERROR_COLUMNS: &str =
+    "id, folder_id, relative_path, kind, message, attempts, next_retry_at, ignored, first_failed_at, last_failed_at";
+
+type SyncErrorRow = (i64, i64, String, String, String, i64, Option<String>, bool, String, String);
+
+fn sync_error_from_row(row: SyncErrorRow) -> Option<SyncError> {
+    let (id, folder_id, relative_path, kind, message, attempts, next_retry_at, ignored, first_failed_at, last_failed_at) = row;
+    Some(SyncError {
+        id,
+        folder_id,
+        relative_path,
+        kind: SyncErrorKind::parse(&kind)?,
+        message,
+        attempts,
+        next_retry_at,
+        ignored,
+        first_failed_at,
+        last_failed_at,
+    })
+}
+
+/// State of one file as of its last successful sync
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncEntry {
+    pub local_size: i64,
+    /// Milliseconds since the Unix epoch
+    pub local_mtime: i64,
+    /// Inode (file index) of the local file, where the platform has one
+    pub local_inode: Option<i64>,
+    /// Hex SHA-256 of the local content
+    pub local_hash: Option<String>,
+    pub remote_etag: String,
+    /// RFC 3339 modification time of the remote object
+    pub remote_last_modified: Option<String>,
+}
+
+type SyncEntryRow = (String, i64, i64, Option<i64>, Option<String>, String, Option<String>);
+
+const SYNC_ENTRY_COLUMNS: &str =
+    "relative_path, local_size, local_mtime, local_inode, local_hash, remote_etag, remote_last_modified";
+
+/// Paths per statement in batch queries, well under SQLite's bound parameter limit
+const SYNC_ENTRY_BATCH: usize = 500;
+
+impl SyncEntry {
+    fn from_row(row: SyncEntryRow) -> (String, Self) {
+        let (path, local_size, local_mtime, local_inode, local_hash, remote_etag, remote_last_modified) = row;
+        (path, Self { local_size, local_mtime, local_inode, local_hash, remote_etag, remote_last_modified })
+    }
+}
+
+/// Default for `sync_folders.poll_interval_secs`
+pub const DEFAULT_POLL_INTERVAL_SECS: i64 = 300;
+/// Defaults for the mass-deletion threshold: more files than this in one pass are held...
+pub const DEFAULT_DELETION_MAX_FILES: i64 = 50;
+/// ...as is more than this percentage of the folder
+pub const DEFAULT_DELETION_MAX_PERCENT: i64 = 10;
+/// Default for `sync_folders.settle_secs`
+pub const DEFAULT_SETTLE_SECS: i64 = 5;
+
+type SyncFolderRow = (
+    i64,
+    String,
+    String,
+    Option<String>,
+    bool,
+    Option<String>,
+    Option<i64>,
+    Option<bool>,
+    Option<String>,
+    Option<i64>,
+    Option<i64>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<i64>,
+);
+
+/// How long a connection waits for another's write lock before giving up with SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct Database {
     pool: Pool<Sqlite>,
     crypto: Crypto,
+    path: PathBuf,
 }
 
 impl Database {
@@ -24,22 +145,20 @@ impl Database {
         }
 
         let db_url = format!("sqlite://{}?mode=rwc", path.display());
-        let pool = SqlitePool::connect(&db_url).await?;
+        // WAL lets the UI read while sync writes; NORMAL is durable enough under WAL
+        let options = SqliteConnectOptions::from_str(&db_url)?
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .foreign_keys(true)
+            .busy_timeout(BUSY_TIMEOUT);
+        let pool = SqlitePool::connect_with(options).await?;
 
-        // Run migrations
-        let migrations = include_str!("../../migrations/001_init.sql");
-        sqlx::query(migrations).execute(&pool).await?;
-
-        // Run migration to add created_at column if it doesn't exist
-        // SQLite doesn't support IF NOT EXISTS for ALTER TABLE, so we check first
-        let _ = sqlx::query("ALTER TABLE sync_folders ADD COLUMN created_at DATETIME DEFAULT CURRENT_TIMESTAMP")
-            .execute(&pool)
-            .await; // Ignore error if column already exists
+        run_migrations(&pool).await?;
 
         // Initialize crypto for credential encryption
         let crypto = Crypto::new()?;
 
-        Ok(Self { pool, crypto })
+        Ok(Self { pool, crypto, path })
     }
 
     pub fn pool(&self) -> &Pool<Sqlite> {
@@ -105,6 +224,355 @@ impl Database {
         Ok(result.map(|(name,)| name))
     }
 
+    /// Id of the bucket the app is currently connected to
+    pub async fn get_current_bucket_id(&self) -> Result<Option<i64>> {
+        let result = sqlx::query_as::<_, (i64,)>(
+            "SELECT id FROM buckets ORDER BY created_at DESC LIMIT 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.map(|(id,)| id))
+    }
+
+    /// Cached fingerprint for a remote object, if one was recorded with local file details
+    pub async fn get_file_fingerprint(&self, bucket_id: i64, remote_path: &str) -> Result<Option<FileFingerprint>> {
+        let result = sqlx::query_as::<_, (i64, Option<String>, Option<String>, i64, i64)>(
+            "SELECT size, etag, sha256, local_size, local_mtime FROM file_metadata
+             WHERE bucket_id = ? AND remote_path = ? AND local_size IS NOT NULL AND local_mtime IS NOT NULL"
+        )
+        .bind(bucket_id)
+        .bind(remote_path)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.map(|(remote_size, etag, sha256, local_size, local_mtime)| FileFingerprint {
+            remote_size,
+            etag,
+            sha256,
+            local_size,
+            local_mtime,
+        }))
+    }
+
+    /// Cached fingerprints for every key under `prefix`, keyed by remote path
+    pub async fn get_file_fingerprints(
+        &self,
+        bucket_id: i64,
+        prefix: &str,
+    ) -> Result<HashMap<String, FileFingerprint>> {
+        let rows = sqlx::query_as::<_, (String, i64, Option<String>, Option<String>, i64, i64)>(
+            "SELECT remote_path, size, etag, sha256, local_size, local_mtime FROM file_metadata
+             WHERE bucket_id = ? AND substr(remote_path, 1, length(?)) = ?
+               AND local_size IS NOT NULL AND local_mtime IS NOT NULL"
+        )
+        .bind(bucket_id)
+        .bind(prefix)
+        .bind(prefix)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(remote_path, remote_size, etag, sha256, local_size, local_mtime)| {
+                (remote_path, FileFingerprint { remote_size, etag, sha256, local_size, local_mtime })
+            })
+            .collect())
+    }
+
+    /// Cached fingerprints of the given keys that have one, keyed by remote path
+    pub async fn get_file_fingerprints_for(
+        &self,
+        bucket_id: i64,
+        remote_paths: &[String],
+    ) -> Result<HashMap<String, FileFingerprint>> {
+        let mut fingerprints = HashMap::new();
+        for chunk in remote_paths.chunks(SYNC_ENTRY_BATCH) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "SELECT remote_path, size, etag, sha256, local_size, local_mtime FROM file_metadata
+                 WHERE local_size IS NOT NULL AND local_mtime IS NOT NULL AND bucket_id = ",
+            );
+            query.push_bind(bucket_id).push(" AND remote_path IN (");
+            let mut keys = query.separated(", ");
+            for key in chunk {
+                keys.push_bind(key);
+            }
+            query.push(")");
+
+            let rows = query
+                .build_query_as::<(String, i64, Option<String>, Option<String>, i64, i64)>()
+                .fetch_all(&self.pool)
+                .await?;
+            fingerprints.extend(rows.into_iter().map(|(remote_path, remote_size, etag, sha256, local_size, local_mtime)| {
+                (remote_path, FileFingerprint { remote_size, etag, sha256, local_size, local_mtime })
+            }));
+        }
+        Ok(fingerprints)
+    }
+
+    /// Record the fingerprint of a file that now matches the remote object
+    pub async fn save_file_fingerprint(
+        &self,
+        bucket_id: i64,
+        remote_path: &str,
+        fingerprint: &FileFingerprint,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO file_metadata (bucket_id, remote_path, size, etag, sha256, local_size, local_mtime, cached_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+             ON CONFLICT(bucket_id, remote_path) DO UPDATE SET
+                size = excluded.size,
+                etag = excluded.etag,
+                sha256 = excluded.sha256,
+                local_size = excluded.local_size,
+                local_mtime = excluded.local_mtime,
+                cached_at = CURRENT_TIMESTAMP"
+        )
+        .bind(bucket_id)
+        .bind(remote_path)
+        .bind(fingerprint.remote_size)
+        .bind(&fingerprint.etag)
+        .bind(&fingerprint.sha256)
+        .bind(fingerprint.local_size)
+        .bind(fingerprint.local_mtime)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Read a raw value from the settings table
+    pub async fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let result = sqlx::query_as::<_, (String,)>(
+            "SELECT value FROM settings WHERE key = ?"
+        )
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.map(|(value,)| value))
+    }
+
+    /// Write several settings in one transaction; either all of them are written or none
+    pub async fn set_settings(&self, settings: &[(&str, String)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (key, value) in settings {
+            sqlx::query(
+                "INSERT INTO settings (key, value) VALUES (?, ?)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value"
+            )
+            .bind(key)
+            .bind(value)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Write a raw value to the settings table
+    pub async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO settings (key, value) VALUES (?, ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value"
+        )
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Decrypted credentials for every configured bucket, as (id, name, account_id, access_key_id, secret_access_key)
+    pub async fn load_all_credentials(&self) -> Result<Vec<(i64, String, String, String, String)>> {
+        let rows = sqlx::query_as::<_, (i64, String, String, String, String)>(
+            "SELECT id, name, account_id, access_key_id, secret_access_key FROM buckets ORDER BY created_at DESC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(id, name, account_id, encrypted_access_key, encrypted_secret_key)| {
+                let access_key_id = self.crypto.decrypt(&encrypted_access_key)?;
+                let secret_access_key = self.crypto.decrypt(&encrypted_secret_key)?;
+                Ok((id, name, account_id, access_key_id, secret_access_key))
+            })
+            .collect()
+    }
+
+    /// Record one maintenance run
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add_maintenance_log(
+        &self,
+        task: &str,
+        trigger: &str,
+        bucket_name: Option<&str>,
+        status: &str,
+        cleaned_count: i64,
+        protected_count: i64,
+        message: Option<&str>,
+        started_at: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO maintenance_log
+                (task, trigger, bucket_name, status, cleaned_count, protected_count, message, started_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(task)
+        .bind(trigger)
+        .bind(bucket_name)
+        .bind(status)
+        .bind(cleaned_count)
+        .bind(protected_count)
+        .bind(message)
+        .bind(started_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Most recent maintenance runs, newest first
+    pub async fn get_maintenance_log(&self, limit: i64) -> Result<Vec<crate::utils::MaintenanceLogEntry>> {
+        let rows = sqlx::query_as::<_, (i64, String, String, Option<String>, String, i64, i64, Option<String>, String, Option<String>)>(
+            "SELECT id, task, trigger, bucket_name, status, cleaned_count, protected_count, message, started_at, finished_at
+             FROM maintenance_log
+             ORDER BY id DESC
+             LIMIT ?"
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, task, trigger, bucket_name, status, cleaned_count, protected_count, message, started_at, finished_at)| {
+                crate::utils::MaintenanceLogEntry {
+                    id,
+                    task,
+                    trigger,
+                    bucket_name,
+                    status,
+                    cleaned_count,
+                    protected_count,
+                    message,
+                    started_at,
+                    finished_at,
+                }
+            })
+            .collect())
+    }
+
+    /// Delete finished uploads older than `retention_days`, with their chunks, and downloads as
+    /// old. Failed uploads that can still be resumed are kept.
+    pub async fn prune_upload_history(&self, retention_days: i64) -> Result<u64> {
+        let uploads = sqlx::query(
+            "DELETE FROM uploads
+             WHERE (status IN ('completed', 'cancelled') OR (status = 'failed' AND upload_id IS NULL))
+               AND COALESCE(completed_at, started_at) < datetime('now', '-' || ? || ' days')"
+        )
+        .bind(retention_days)
+        .execute(&self.pool)
+        .await?;
+        let downloads = sqlx::query(
+            "DELETE FROM downloads WHERE COALESCE(completed_at, started_at) < datetime('now', '-' || ? || ' days')"
+        )
+        .bind(retention_days)
+        .execute(&self.pool)
+        .await?;
+        Ok(uploads.rows_affected() + downloads.rows_affected())
+    }
+
+    /// Add a finished download to the current bucket's transfer history
+    pub async fn record_download(&self, download: &DownloadRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO downloads
+                (id, bucket_id, file_path, remote_path, total_size, status, error_message, started_at, completed_at, etag, sha256)
+             VALUES (?, (SELECT id FROM buckets ORDER BY created_at DESC LIMIT 1), ?, ?, ?, ?, ?, ?, datetime('now'), ?, ?)"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&download.local_path)
+        .bind(&download.remote_path)
+        .bind(download.size)
+        .bind(if download.error.is_some() { "failed" } else { "completed" })
+        .bind(&download.error)
+        .bind(download.started_at.format("%Y-%m-%d %H:%M:%S").to_string())
+        .bind(&download.etag)
+        .bind(&download.sha256)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete chunk rows whose upload is gone, left from before foreign keys were enforced
+    pub async fn delete_orphaned_chunks(&self) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM upload_chunks WHERE upload_id NOT IN (SELECT id FROM uploads)")
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Rebuild the database file to return free pages to the filesystem; returns the bytes freed
+    pub async fn vacuum(&self) -> Result<i64> {
+        let before = self.free_bytes().await?;
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        // Fold the rewritten pages back into the main file so the WAL doesn't keep them
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&self.pool).await?;
+        Ok((before - self.free_bytes().await?).max(0))
+    }
+
+    /// Problems `PRAGMA integrity_check` finds; empty when the database is sound
+    pub async fn integrity_check(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query_as::<_, (String,)>("PRAGMA integrity_check")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|(line,)| line).filter(|line| line != "ok").collect())
+    }
+
+    async fn free_bytes(&self) -> Result<i64> {
+        let (free_pages, page_size): (i64, i64) =
+            sqlx::query_as("SELECT freelist_count, page_size FROM pragma_freelist_count, pragma_page_size")
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(free_pages * page_size)
+    }
+
+    /// Size of the database and the number of rows in each table
+    pub async fn diagnostics(&self) -> Result<DatabaseDiagnostics> {
+        let file_size = |suffix: &str| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            std::fs::metadata(path).map(|m| m.len() as i64).unwrap_or(0)
+        };
+        let (journal_mode,): (String,) = sqlx::query_as("PRAGMA journal_mode").fetch_one(&self.pool).await?;
+
+        let tables = sqlx::query_as::<_, (String,)>(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations'
+             ORDER BY name"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut row_counts = Vec::with_capacity(tables.len());
+        for (name,) in tables {
+            let (rows,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM \"{}\"", name.replace('"', "\"\"")))
+                .fetch_one(&self.pool)
+                .await?;
+            row_counts.push(TableRowCount { table: name, rows });
+        }
+
+        Ok(DatabaseDiagnostics {
+            path: self.path.display().to_string(),
+            size_bytes: file_size(""),
+            wal_bytes: file_size("-wal"),
+            free_bytes: self.free_bytes().await?,
+            journal_mode,
+            schema_version: schema::schema_version(&self.pool).await?.unwrap_or(0),
+            tables: row_counts,
+        })
+    }
+
     /// Get all sync folders
     pub async fn get_sync_folders(&self) -> Result<Vec<crate::SyncFolder>> {
         // First, get the current bucket id
@@ -119,8 +587,10 @@ impl Database {
             None => return Ok(Vec::new()),
         };
 
-        let result = sqlx::query_as::<_, (i64, String, String, bool, Option<String>)>(
-            "SELECT id, local_path, remote_path, enabled, last_sync 
+        let result = sqlx::query_as::<_, SyncFolderRow>(
+            "SELECT id, local_path, remote_path, sync_mode, enabled, last_sync, poll_interval_secs, delete_removed,
+                    deletion_policy, deletion_max_files, deletion_max_percent, schedule, schedule_cron, next_run_at,
+                    settle_secs
              FROM sync_folders 
              WHERE bucket_id = ? 
              ORDER BY id DESC"
@@ -129,19 +599,41 @@ impl Database {
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(result.into_iter().map(|(id, local_path, remote_path, enabled, last_sync)| {
+        Ok(result.into_iter().map(|row| {
+            let (id, local_path, remote_path, sync_mode, enabled, last_sync, poll_interval_secs, delete_removed,
+                 deletion_policy, deletion_max_files, deletion_max_percent, schedule, schedule_cron, next_run_at,
+                 settle_secs) = row;
             crate::SyncFolder {
                 id,
                 local_path,
                 remote_path,
+                sync_mode: sync_mode
+                    .as_deref()
+                    .and_then(SyncMode::parse)
+                    .unwrap_or(SyncMode::UploadOnly),
                 enabled,
                 last_sync,
+                poll_interval_secs: poll_interval_secs.unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+                delete_removed: delete_removed.unwrap_or(false),
+                deletion_policy: deletion_policy
+                    .as_deref()
+                    .and_then(DeletionPolicy::parse)
+                    .unwrap_or(DeletionPolicy::Trash),
+                deletion_max_files: deletion_max_files.unwrap_or(DEFAULT_DELETION_MAX_FILES),
+                deletion_max_percent: deletion_max_percent.unwrap_or(DEFAULT_DELETION_MAX_PERCENT),
+                schedule: schedule
+                    .as_deref()
+                    .and_then(ScheduleKind::parse)
+                    .unwrap_or(ScheduleKind::Realtime),
+                schedule_cron,
+                next_run_at,
+                settle_secs: settle_secs.unwrap_or(DEFAULT_SETTLE_SECS),
             }
         }).collect())
     }
 
     /// Add a new sync folder
-    pub async fn add_sync_folder(&self, local_path: &str, remote_path: &str) -> Result<i64> {
+    pub async fn add_sync_folder(&self, local_path: &str, remote_path: &str, sync_mode: SyncMode) -> Result<i64> {
         // Get the current bucket id
         let bucket_id: (i64,) = sqlx::query_as(
             "SELECT id FROM buckets ORDER BY created_at DESC LIMIT 1"
@@ -151,11 +643,12 @@ impl Database {
 
         let result = sqlx::query(
             "INSERT INTO sync_folders (bucket_id, local_path, remote_path, sync_mode, enabled)
-             VALUES (?, ?, ?, 'upload_only', 1)"
+             VALUES (?, ?, ?, ?, 1)"
         )
         .bind(bucket_id.0)
         .bind(local_path)
         .bind(remote_path)
+        .bind(sync_mode.as_str())
         .execute(&self.pool)
         .await?;
 
@@ -164,13 +657,102 @@ impl Database {
 
     /// Remove a sync folder
     pub async fn remove_sync_folder(&self, folder_id: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        // Foreign keys aren't enforced, so clear the folder's sync state explicitly
+        for table in ["sync_entries", "sync_conflicts", "held_deletions", "sync_file_states", "sync_errors"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE folder_id = ?", table))
+                .bind(folder_id)
+                .execute(&mut *tx)
+                .await?;
+        }
         sqlx::query("DELETE FROM sync_folders WHERE id = ?")
+            .bind(folder_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record that a sync folder just finished a sync pass
+    pub async fn mark_sync_folder_synced(&self, folder_id: i64) -> Result<()> {
+        sqlx::query("UPDATE sync_folders SET last_sync = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(folder_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Remote poll interval and, for download-only mirrors, whether files removed remotely are deleted locally
+    pub async fn set_sync_folder_options(&self, folder_id: i64, poll_interval_secs: i64, delete_removed: bool) -> Result<()> {
+        sqlx::query("UPDATE sync_folders SET poll_interval_secs = ?, delete_removed = ? WHERE id = ?")
+            .bind(poll_interval_secs)
+            .bind(delete_removed)
+            .bind(folder_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// How a folder propagates deletions, and how many in one pass are held for confirmation
+    pub async fn set_sync_folder_deletion_policy(
+        &self,
+        folder_id: i64,
+        policy: DeletionPolicy,
+        max_files: i64,
+        max_percent: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE sync_folders SET deletion_policy = ?, deletion_max_files = ?, deletion_max_percent = ? WHERE id = ?"
+        )
+        .bind(policy.as_str())
+        .bind(max_files)
+        .bind(max_percent)
+        .bind(folder_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// When a folder's full passes run; `cron` is only kept for cron schedules
+    pub async fn set_sync_folder_schedule(&self, folder_id: i64, schedule: ScheduleKind, cron: Option<&str>) -> Result<()> {
+        let cron = cron.filter(|_| schedule == ScheduleKind::Cron);
+        sqlx::query("UPDATE sync_folders SET schedule = ?, schedule_cron = ? WHERE id = ?")
+            .bind(schedule.as_str())
+            .bind(cron)
+            .bind(folder_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// How long a changed file has to keep its size and modification time before it is synced
+    pub async fn set_sync_folder_settle_secs(&self, folder_id: i64, settle_secs: i64) -> Result<()> {
+        sqlx::query("UPDATE sync_folders SET settle_secs = ? WHERE id = ?")
+            .bind(settle_secs)
+            .bind(folder_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// When the scheduler next starts a full pass of a folder, as RFC 3339
+    pub async fn set_sync_folder_next_run(&self, folder_id: i64, next_run_at: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE sync_folders SET next_run_at = ? WHERE id = ?")
+            .bind(next_run_at)
             .bind(folder_id)
             .execute(&self.pool)
             .await?;
         Ok(())
     }
 
+    /// Paused folders have no next run, including ones left over from an earlier session
+    pub async fn clear_paused_next_runs(&self) -> Result<()> {
+        sqlx::query("UPDATE sync_folders SET next_run_at = NULL WHERE enabled = 0 AND next_run_at IS NOT NULL")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
     /// Toggle sync folder enabled status
     pub async fn toggle_sync_folder(&self, folder_id: i64, enabled: bool) -> Result<()> {
         sqlx::query("UPDATE sync_folders SET enabled = ? WHERE id = ?")
@@ -180,6 +762,512 @@ impl Database {
             .await?;
         Ok(())
     }
+
+    /// Journal of every file in a sync folder, keyed by relative path
+    pub async fn get_sync_entries(&self, folder_id: i64) -> Result<HashMap<String, SyncEntry>> {
+        let rows = sqlx::query_as::<_, SyncEntryRow>(&format!(
+            "SELECT {} FROM sync_entries WHERE folder_id = ?",
+            SYNC_ENTRY_COLUMNS
+        ))
+        .bind(folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(SyncEntry::from_row).collect())
+    }
+
+    /// Journal entries for specific paths; paths without an entry are left out
+    pub async fn get_sync_entries_for(&self, folder_id: i64, relative_paths: &[String]) -> Result<HashMap<String, SyncEntry>> {
+        let mut entries = HashMap::new();
+        for chunk in relative_paths.chunks(SYNC_ENTRY_BATCH) {
+            let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {} FROM sync_entries WHERE folder_id = ", SYNC_ENTRY_COLUMNS));
+            query.push_bind(folder_id).push(" AND relative_path IN (");
+            let mut paths = query.separated(", ");
+            for path in chunk {
+                paths.push_bind(path);
+            }
+            query.push(")");
+
+            let rows = query.build_query_as::<SyncEntryRow>().fetch_all(&self.pool).await?;
+            entries.extend(rows.into_iter().map(SyncEntry::from_row));
+        }
+        Ok(entries)
+    }
+
+    pub async fn get_sync_entry(&self, folder_id: i64, relative_path: &str) -> Result<Option<SyncEntry>> {
+        let paths = [relative_path.to_string()];
+        Ok(self.get_sync_entries_for(folder_id, &paths).await?.remove(relative_path))
+    }
+
+    /// Record that files are in sync on both sides, in one transaction
+    pub async fn save_sync_entries(&self, folder_id: i64, entries: &[(String, SyncEntry)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (relative_path, entry) in entries {
+            sqlx::query(
+                "INSERT INTO sync_entries (folder_id, relative_path, local_size, local_mtime, local_inode, local_hash,
+                                           remote_etag, remote_last_modified, synced_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+                 ON CONFLICT(folder_id, relative_path) DO UPDATE SET
+                    local_size = excluded.local_size,
+                    local_mtime = excluded.local_mtime,
+                    local_inode = excluded.local_inode,
+                    local_hash = excluded.local_hash,
+                    remote_etag = excluded.remote_etag,
+                    remote_last_modified = excluded.remote_last_modified,
+                    synced_at = CURRENT_TIMESTAMP"
+            )
+            .bind(folder_id)
+            .bind(relative_path)
+            .bind(entry.local_size)
+            .bind(entry.local_mtime)
+            .bind(entry.local_inode)
+            .bind(&entry.local_hash)
+            .bind(&entry.remote_etag)
+            .bind(&entry.remote_last_modified)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn save_sync_entry(&self, folder_id: i64, relative_path: &str, entry: &SyncEntry) -> Result<()> {
+        self.save_sync_entries(folder_id, &[(relative_path.to_string(), entry.clone())]).await
+    }
+
+    /// Forget files that no longer exist on either side, in one transaction
+    pub async fn delete_sync_entries(&self, folder_id: i64, relative_paths: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for chunk in relative_paths.chunks(SYNC_ENTRY_BATCH) {
+            let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM sync_entries WHERE folder_id = ");
+            query.push_bind(folder_id).push(" AND relative_path IN (");
+            let mut paths = query.separated(", ");
+            for path in chunk {
+                paths.push_bind(path);
+            }
+            query.push(")");
+            query.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn delete_sync_entry(&self, folder_id: i64, relative_path: &str) -> Result<()> {
+        self.delete_sync_entries(folder_id, &[relative_path.to_string()]).await
+    }
+
+    /// Deletions waiting for confirmation, for one folder or all of them
+    pub async fn get_held_deletions(&self, folder_id: Option<i64>) -> Result<Vec<HeldDeletion>> {
+        let rows = sqlx::query_as::<_, (i64, i64, String, String, String)>(
+            "SELECT id, folder_id, relative_path, side, detected_at
+             FROM held_deletions
+             WHERE approved = 0 AND (? IS NULL OR folder_id = ?)
+             ORDER BY folder_id, relative_path"
+        )
+        .bind(folder_id)
+        .bind(folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, folder_id, relative_path, side, detected_at)| {
+                Some(HeldDeletion {
+                    id,
+                    folder_id,
+                    relative_path,
+                    side: DeletionSide::parse(&side)?,
+                    detected_at,
+                })
+            })
+            .collect())
+    }
+
+    /// Paths the user has confirmed for deletion and the next pass may delete
+    pub async fn get_approved_deletions(&self, folder_id: i64) -> Result<HashSet<String>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "SELECT relative_path FROM held_deletions WHERE folder_id = ? AND approved = 1"
+        )
+        .bind(folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|(path,)| path).collect())
+    }
+
+    /// Replace a folder's held deletions with `held`, keeping when each was first held.
+    /// Approvals are consumed, since the pass that reads them either carries them out or no longer wants them.
+    pub async fn replace_held_deletions(&self, folder_id: i64, held: &[(String, DeletionSide)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let existing: HashSet<String> = sqlx::query_as::<_, (String,)>(
+            "SELECT relative_path FROM held_deletions WHERE folder_id = ? AND approved = 0"
+        )
+        .bind(folder_id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(path,)| path)
+        .collect();
+        let wanted: HashSet<&String> = held.iter().map(|(path, _)| path).collect();
+
+        sqlx::query("DELETE FROM held_deletions WHERE folder_id = ? AND approved = 1")
+            .bind(folder_id)
+            .execute(&mut *tx)
+            .await?;
+        for stale in existing.iter().filter(|path| !wanted.contains(path)) {
+            sqlx::query("DELETE FROM held_deletions WHERE folder_id = ? AND relative_path = ?")
+                .bind(folder_id)
+                .bind(stale)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for (relative_path, side) in held.iter().filter(|(path, _)| !existing.contains(path)) {
+            sqlx::query("INSERT INTO held_deletions (folder_id, relative_path, side) VALUES (?, ?, ?)")
+                .bind(folder_id)
+                .bind(relative_path)
+                .bind(side.as_str())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Let the next pass carry out a folder's held deletions
+    pub async fn approve_held_deletions(&self, folder_id: i64) -> Result<usize> {
+        let result = sqlx::query("UPDATE held_deletions SET approved = 1 WHERE folder_id = ? AND approved = 0")
+            .bind(folder_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() as usize)
+    }
+
+    /// Reject a folder's held deletions. Their journal entries go too, so the next pass
+    /// treats the surviving copies as new and restores the deleted side from them.
+    pub async fn discard_held_deletions(&self, folder_id: i64) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM sync_entries WHERE folder_id = ? AND relative_path IN
+                (SELECT relative_path FROM held_deletions WHERE folder_id = ? AND approved = 0)"
+        )
+        .bind(folder_id)
+        .bind(folder_id)
+        .execute(&mut *tx)
+        .await?;
+        let result = sqlx::query("DELETE FROM held_deletions WHERE folder_id = ? AND approved = 0")
+            .bind(folder_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() as usize)
+    }
+
+    /// Record a concurrent edit that was kept as two copies
+    pub async fn add_sync_conflict(&self, folder_id: i64, relative_path: &str, conflict_path: &str) -> Result<i64> {
+        let result = sqlx::query(
+            "INSERT INTO sync_conflicts (folder_id, relative_path, conflict_path) VALUES (?, ?, ?)"
+        )
+        .bind(folder_id)
+        .bind(relative_path)
+        .bind(conflict_path)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Unresolved conflicts, for one folder or all of them
+    pub async fn get_sync_conflicts(&self, folder_id: Option<i64>) -> Result<Vec<SyncConflict>> {
+        let rows = sqlx::query_as::<_, (i64, i64, String, String, String, Option<String>)>(
+            "SELECT id, folder_id, relative_path, conflict_path, detected_at, resolved_at
+             FROM sync_conflicts
+             WHERE resolved_at IS NULL AND (? IS NULL OR folder_id = ?)
+             ORDER BY detected_at DESC"
+        )
+        .bind(folder_id)
+        .bind(folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, folder_id, relative_path, conflict_path, detected_at, resolved_at)| SyncConflict {
+                id,
+                folder_id,
+                relative_path,
+                conflict_path,
+                detected_at,
+                resolved_at,
+            })
+            .collect())
+    }
+
+    pub async fn resolve_sync_conflict(&self, conflict_id: i64) -> Result<()> {
+        sqlx::query("UPDATE sync_conflicts SET resolved_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(conflict_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// How many times in a row a file has failed to sync
+    pub async fn get_sync_error_attempts(&self, folder_id: i64, relative_path: &str) -> Result<i64> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            "SELECT attempts FROM sync_errors WHERE folder_id = ? AND relative_path = ?"
+        )
+        .bind(folder_id)
+        .bind(relative_path)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map_or(0, |(attempts,)| attempts))
+    }
+
+    /// Record a failed sync. Times are UTC, as `YYYY-MM-DD HH:MM:SS` like CURRENT_TIMESTAMP.
+    pub async fn save_sync_error(
+        &self,
+        folder_id: i64,
+        relative_path: &str,
+        kind: SyncErrorKind,
+        message: &str,
+        attempts: i64,
+        next_retry_at: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO sync_errors (folder_id, relative_path, kind, message, attempts, next_retry_at)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(folder_id, relative_path) DO UPDATE SET
+                kind = excluded.kind,
+                message = excluded.message,
+                attempts = excluded.attempts,
+                next_retry_at = excluded.next_retry_at,
+                last_failed_at = CURRENT_TIMESTAMP"
+        )
+        .bind(folder_id)
+        .bind(relative_path)
+        .bind(kind.as_str())
+        .bind(message)
+        .bind(attempts)
+        .bind(next_retry_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Failed files, for one folder or all of them
+    pub async fn get_sync_errors(&self, folder_id: Option<i64>) -> Result<Vec<SyncError>> {
+        let rows = sqlx::query_as::<_, SyncErrorRow>(&format!(
+            "SELECT {} FROM sync_errors WHERE ? IS NULL OR folder_id = ? ORDER BY folder_id, relative_path",
+            SYNC_ERROR_COLUMNS
+        ))
+        .bind(folder_id)
+        .bind(folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().filter_map(sync_error_from_row).collect())
+    }
+
+    /// Files of a folder that are left out of sync until the user retries them, keyed by relative path
+    pub async fn get_quarantined_sync_errors(&self, folder_id: i64) -> Result<HashMap<String, SyncError>> {
+        let rows = sqlx::query_as::<_, SyncErrorRow>(&format!(
+            "SELECT {} FROM sync_errors WHERE folder_id = ? AND (kind = 'permanent' OR ignored = 1)",
+            SYNC_ERROR_COLUMNS
+        ))
+        .bind(folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(sync_error_from_row)
+            .map(|error| (error.relative_path.clone(), error))
+            .collect())
+    }
+
+    /// Transient errors of a folder whose retry is due at `now`, with their attempts so far
+    pub async fn get_due_sync_errors(&self, folder_id: i64, now: &str) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            "SELECT relative_path, attempts FROM sync_errors
+             WHERE folder_id = ? AND kind = 'transient' AND ignored = 0 AND next_retry_at <= ?
+             ORDER BY relative_path"
+        )
+        .bind(folder_id)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    /// When the next transient error of a folder is due for a retry
+    pub async fn get_next_sync_error_retry(&self, folder_id: i64) -> Result<Option<String>> {
+        let row = sqlx::query_as::<_, (Option<String>,)>(
+            "SELECT MIN(next_retry_at) FROM sync_errors WHERE folder_id = ? AND kind = 'transient' AND ignored = 0"
+        )
+        .bind(folder_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.0)
+    }
+
+    pub async fn set_sync_error_retry(&self, folder_id: i64, relative_path: &str, next_retry_at: &str) -> Result<()> {
+        sqlx::query("UPDATE sync_errors SET next_retry_at = ? WHERE folder_id = ? AND relative_path = ?")
+            .bind(next_retry_at)
+            .bind(folder_id)
+            .bind(relative_path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Forget a file's failures once it synced
+    pub async fn delete_sync_error(&self, folder_id: i64, relative_path: &str) -> Result<()> {
+        sqlx::query("DELETE FROM sync_errors WHERE folder_id = ? AND relative_path = ?")
+            .bind(folder_id)
+            .bind(relative_path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Forget transient errors that a pass retried without them failing again: those last
+    /// failing before `retried_at`, among `relative_paths` or across the folder
+    pub async fn resolve_sync_errors(&self, folder_id: i64, relative_paths: Option<&[String]>, retried_at: &str) -> Result<()> {
+        let query = || {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "DELETE FROM sync_errors WHERE kind = 'transient' AND ignored = 0 AND last_failed_at < ",
+            );
+            query.push_bind(retried_at.to_string()).push(" AND folder_id = ").push_bind(folder_id);
+            query
+        };
+        let relative_paths = match relative_paths {
+            Some(paths) => paths,
+            None => {
+                query().build().execute(&self.pool).await?;
+                return Ok(());
+            }
+        };
+
+        for chunk in relative_paths.chunks(SYNC_ENTRY_BATCH) {
+            let mut query = query();
+            query.push(" AND relative_path IN (");
+            let mut paths = query.separated(", ");
+            for path in chunk {
+                paths.push_bind(path);
+            }
+            query.push(")");
+            query.build().execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Put a failed file back in the retry queue, due now, and return its folder
+    pub async fn retry_sync_error(&self, error_id: i64) -> Result<Option<i64>> {
+        sqlx::query(
+            "UPDATE sync_errors SET kind = 'transient', attempts = 0, ignored = 0, next_retry_at = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(error_id)
+        .execute(&self.pool)
+        .await?;
+        let row = sqlx::query_as::<_, (i64,)>("SELECT folder_id FROM sync_errors WHERE id = ?")
+            .bind(error_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|(folder_id,)| folder_id))
+    }
+
+    /// Leave a failed file out of sync until the user retries it
+    pub async fn ignore_sync_error(&self, error_id: i64) -> Result<()> {
+        sqlx::query("UPDATE sync_errors SET ignored = 1 WHERE id = ?")
+            .bind(error_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record that a sync folder file is uploading (`uploading`) or failed to sync (`error`)
+    pub async fn set_sync_file_state(
+        &self,
+        folder_id: i64,
+        relative_path: &str,
+        state: &str,
+        error_message: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO sync_file_states (folder_id, relative_path, state, error_message, updated_at)
+             VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+             ON CONFLICT(folder_id, relative_path) DO UPDATE SET
+                state = excluded.state,
+                error_message = excluded.error_message,
+                updated_at = excluded.updated_at"
+        )
+        .bind(folder_id)
+        .bind(relative_path)
+        .bind(state)
+        .bind(error_message)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Forget the recorded state of a file once it synced
+    pub async fn clear_sync_file_state(&self, folder_id: i64, relative_path: &str) -> Result<()> {
+        sqlx::query("DELETE FROM sync_file_states WHERE folder_id = ? AND relative_path = ?")
+            .bind(folder_id)
+            .bind(relative_path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Forget every recorded state of a folder, before a full pass records them afresh
+    pub async fn clear_sync_file_states(&self, folder_id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM sync_file_states WHERE folder_id = ?")
+            .bind(folder_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Forget uploads recorded as in progress, which can't be after a restart
+    pub async fn clear_interrupted_sync_file_states(&self) -> Result<()> {
+        sqlx::query("DELETE FROM sync_file_states WHERE state = 'uploading'")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Recorded state and error message of the given files that have one
+    pub async fn get_sync_file_states(
+        &self,
+        folder_id: i64,
+        relative_paths: &[String],
+    ) -> Result<HashMap<String, (String, Option<String>)>> {
+        let mut states = HashMap::new();
+        for chunk in relative_paths.chunks(SYNC_ENTRY_BATCH) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "SELECT relative_path, state, error_message FROM sync_file_states WHERE folder_id = ",
+            );
+            query.push_bind(folder_id).push(" AND relative_path IN (");
+            let mut paths = query.separated(", ");
+            for path in chunk {
+                paths.push_bind(path);
+            }
+            query.push(")");
+
+            let rows = query
+                .build_query_as::<(String, String, Option<String>)>()
+                .fetch_all(&self.pool)
+                .await?;
+            states.extend(rows.into_iter().map(|(path, state, message)| (path, (state, message))));
+        }
+        Ok(states)
+    }
+
+    /// How many files across all folders are in each recorded state
+    pub async fn count_sync_file_states(&self) -> Result<HashMap<String, i64>> {
+        let rows = sqlx::query_as::<_, (String, i64)>("SELECT state, COUNT(*) FROM sync_file_states GROUP BY state")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().collect())
+    }
 }
 
 #[cfg(test)]
@@ -286,7 +1374,8 @@ mod tests {
         // Add sync folder
         let folder_id = db.add_sync_folder(
             "/home/user/documents",
-            "documents/"
+            "documents/",
+            SyncMode::UploadOnly
         ).await.unwrap();
         
         assert!(folder_id > 0);
@@ -296,12 +1385,28 @@ mod tests {
         assert_eq!(folders.len(), 1);
         assert_eq!(folders[0].local_path, "/home/user/documents");
         assert_eq!(folders[0].remote_path, "documents/");
+        assert_eq!(folders[0].sync_mode, SyncMode::UploadOnly);
         assert!(folders[0].enabled);
+        assert_eq!(folders[0].schedule, ScheduleKind::Realtime);
+        assert_eq!(folders[0].settle_secs, DEFAULT_SETTLE_SECS);
+
+        // Schedule a nightly scan
+        db.set_sync_folder_schedule(folder_id, ScheduleKind::Cron, Some("0 2 * * *")).await.unwrap();
+        db.set_sync_folder_next_run(folder_id, Some("2026-10-17T02:00:00+00:00")).await.unwrap();
+        let folders = db.get_sync_folders().await.unwrap();
+        assert_eq!(folders[0].schedule, ScheduleKind::Cron);
+        assert_eq!(folders[0].schedule_cron.as_deref(), Some("0 2 * * *"));
+        assert_eq!(folders[0].next_run_at.as_deref(), Some("2026-10-17T02:00:00+00:00"));
+
+        db.set_sync_folder_settle_secs(folder_id, 30).await.unwrap();
+        assert_eq!(db.get_sync_folders().await.unwrap()[0].settle_secs, 30);
         
         // Toggle disabled
         db.toggle_sync_folder(folder_id, false).await.unwrap();
+        db.clear_paused_next_runs().await.unwrap();
         let folders = db.get_sync_folders().await.unwrap();
         assert!(!folders[0].enabled);
+        assert_eq!(folders[0].next_run_at, None);
         
         // Remove folder
         db.remove_sync_folder(folder_id).await.unwrap();
@@ -323,14 +1428,125 @@ mod tests {
         ).await.unwrap();
         
         // Add multiple folders
-        db.add_sync_folder("/path/to/docs", "docs/").await.unwrap();
-        db.add_sync_folder("/path/to/photos", "photos/").await.unwrap();
-        db.add_sync_folder("/path/to/videos", "videos/").await.unwrap();
+        db.add_sync_folder("/path/to/docs", "docs/", SyncMode::UploadOnly).await.unwrap();
+        db.add_sync_folder("/path/to/photos", "photos/", SyncMode::UploadOnly).await.unwrap();
+        db.add_sync_folder("/path/to/videos", "videos/", SyncMode::UploadOnly).await.unwrap();
         
         let folders = db.get_sync_folders().await.unwrap();
         assert_eq!(folders.len(), 3);
     }
 
+    #[tokio::test]
+    async fn test_sync_entries_and_conflicts() {
+        let (db, _temp_dir) = setup_test_db().await;
+        db.save_credentials("bucket", "account", "key", "secret", "https://endpoint.com").await.unwrap();
+        let folder_id = db.add_sync_folder("/path/to/docs", "docs/", SyncMode::Bidirectional).await.unwrap();
+        assert_eq!(db.get_sync_folders().await.unwrap()[0].sync_mode, SyncMode::Bidirectional);
+
+        let entry = SyncEntry {
+            local_size: 10,
+            local_mtime: 1000,
+            local_inode: Some(42),
+            local_hash: Some("abc".to_string()),
+            remote_etag: "\"def\"".to_string(),
+            remote_last_modified: Some("2026-10-16T08:00:00Z".to_string()),
+        };
+        db.save_sync_entry(folder_id, "a/b.txt", &entry).await.unwrap();
+        db.save_sync_entry(folder_id, "a/b.txt", &SyncEntry { local_mtime: 2000, ..entry.clone() }).await.unwrap();
+
+        let entries = db.get_sync_entries(folder_id).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries["a/b.txt"].local_mtime, 2000);
+
+        let conflict_id = db.add_sync_conflict(folder_id, "a/b.txt", "a/b (conflict).txt").await.unwrap();
+        assert_eq!(db.get_sync_conflicts(Some(folder_id)).await.unwrap().len(), 1);
+        assert_eq!(db.get_sync_conflicts(None).await.unwrap().len(), 1);
+        db.resolve_sync_conflict(conflict_id).await.unwrap();
+        assert!(db.get_sync_conflicts(Some(folder_id)).await.unwrap().is_empty());
+
+        db.set_sync_file_state(folder_id, "a/b.txt", "uploading", None).await.unwrap();
+        db.set_sync_file_state(folder_id, "a/b.txt", "error", Some("Access denied")).await.unwrap();
+        db.set_sync_file_state(folder_id, "c.txt", "uploading", None).await.unwrap();
+        let paths = ["a/b.txt".to_string(), "missing.txt".to_string()];
+        let states = db.get_sync_file_states(folder_id, &paths).await.unwrap();
+        assert_eq!(states.len(), 1);
+        assert_eq!(states["a/b.txt"], ("error".to_string(), Some("Access denied".to_string())));
+        let counts = db.count_sync_file_states().await.unwrap();
+        assert_eq!((counts["error"], counts["uploading"]), (1, 1));
+        db.clear_sync_file_state(folder_id, "c.txt").await.unwrap();
+        assert!(!db.count_sync_file_states().await.unwrap().contains_key("uploading"));
+
+        // Removing the folder drops its journal
+        db.delete_sync_entry(folder_id, "missing.txt").await.unwrap();
+        db.remove_sync_folder(folder_id).await.unwrap();
+        assert!(db.get_sync_entry(folder_id, "a/b.txt").await.unwrap().is_none());
+        assert!(db.count_sync_file_states().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sync_entries_in_batches() {
+        let (db, _temp_dir) = setup_test_db().await;
+        db.save_credentials("bucket", "account", "key", "secret", "https://endpoint.com").await.unwrap();
+        let folder_id = db.add_sync_folder("/path/to/docs", "docs/", SyncMode::Bidirectional).await.unwrap();
+
+        // More paths than fit in one statement
+        let entries: Vec<(String, SyncEntry)> = (0..1200)
+            .map(|i| (format!("file{}.txt", i), SyncEntry { local_size: i, remote_etag: format!("\"{}\"", i), ..Default::default() }))
+            .collect();
+        db.save_sync_entries(folder_id, &entries).await.unwrap();
+        assert_eq!(db.get_sync_entries(folder_id).await.unwrap().len(), 1200);
+
+        let wanted: Vec<String> = vec!["file7.txt".to_string(), "file1100.txt".to_string(), "nope.txt".to_string()];
+        let found = db.get_sync_entries_for(folder_id, &wanted).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["file1100.txt"].local_size, 1100);
+
+        let gone: Vec<String> = entries.iter().take(1000).map(|(path, _)| path.clone()).collect();
+        db.delete_sync_entries(folder_id, &gone).await.unwrap();
+        assert_eq!(db.get_sync_entries(folder_id).await.unwrap().len(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_held_deletions() {
+        let (db, _temp_dir) = setup_test_db().await;
+        db.save_credentials("bucket", "account", "key", "secret", "https://endpoint.com").await.unwrap();
+        let folder_id = db.add_sync_folder("/path/to/docs", "docs/", SyncMode::Bidirectional).await.unwrap();
+
+        db.set_sync_folder_deletion_policy(folder_id, DeletionPolicy::Immediate, 20, 5).await.unwrap();
+        let folder = &db.get_sync_folders().await.unwrap()[0];
+        assert_eq!(folder.deletion_policy, DeletionPolicy::Immediate);
+        assert_eq!((folder.deletion_max_files, folder.deletion_max_percent), (20, 5));
+
+        let entries: Vec<(String, SyncEntry)> = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(|path| (path.to_string(), SyncEntry::default()))
+            .collect();
+        db.save_sync_entries(folder_id, &entries).await.unwrap();
+
+        let held = vec![("a.txt".to_string(), DeletionSide::Remote), ("b.txt".to_string(), DeletionSide::Local)];
+        db.replace_held_deletions(folder_id, &held).await.unwrap();
+        let listed = db.get_held_deletions(Some(folder_id)).await.unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed.iter().find(|h| h.relative_path == "b.txt").unwrap().side, DeletionSide::Local);
+
+        // A later pass that no longer wants a.txt deleted drops it
+        db.replace_held_deletions(folder_id, &held[1..]).await.unwrap();
+        assert_eq!(db.get_held_deletions(None).await.unwrap().len(), 1);
+
+        // Approved deletions are handed to the next pass and consumed by it
+        assert_eq!(db.approve_held_deletions(folder_id).await.unwrap(), 1);
+        assert!(db.get_held_deletions(Some(folder_id)).await.unwrap().is_empty());
+        assert!(db.get_approved_deletions(folder_id).await.unwrap().contains("b.txt"));
+        db.replace_held_deletions(folder_id, &[]).await.unwrap();
+        assert!(db.get_approved_deletions(folder_id).await.unwrap().is_empty());
+
+        // Discarding forgets the journal entries so the deleted side gets restored
+        db.replace_held_deletions(folder_id, &held).await.unwrap();
+        assert_eq!(db.discard_held_deletions(folder_id).await.unwrap(), 2);
+        let remaining = db.get_sync_entries(folder_id).await.unwrap();
+        assert_eq!(remaining.keys().collect::<Vec<_>>(), vec!["c.txt"]);
+    }
+
     #[tokio::test]
     async fn test_sync_folders_without_bucket() {
         let (db, _temp_dir) = setup_test_db().await;
@@ -355,6 +1571,203 @@ mod tests {
         assert!(folders.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_sync_error_queue() {
+        let (db, _temp_dir) = setup_test_db().await;
+        db.save_credentials("bucket", "account", "key", "secret", "https://endpoint.com").await.unwrap();
+        let folder_id = db.add_sync_folder("/path/to/docs", "docs/", SyncMode::UploadOnly).await.unwrap();
+
+        db.save_sync_error(folder_id, "a.txt", SyncErrorKind::Transient, "timed out", 1, Some("2026-10-18 12:00:30"))
+            .await
+            .unwrap();
+        db.save_sync_error(folder_id, "a.txt", SyncErrorKind::Transient, "timed out", 2, Some("2026-10-18 12:01:30"))
+            .await
+            .unwrap();
+        db.save_sync_error(folder_id, "b.txt", SyncErrorKind::Permanent, "Permission denied", 1, None)
+            .await
+            .unwrap();
+        assert_eq!(db.get_sync_error_attempts(folder_id, "a.txt").await.unwrap(), 2);
+        assert_eq!(db.get_sync_error_attempts(folder_id, "none.txt").await.unwrap(), 0);
+
+        // Only transient errors are retried, once due
+        assert_eq!(db.get_next_sync_error_retry(folder_id).await.unwrap().as_deref(), Some("2026-10-18 12:01:30"));
+        assert!(db.get_due_sync_errors(folder_id, "2026-10-18 12:01:00").await.unwrap().is_empty());
+        assert_eq!(
+            db.get_due_sync_errors(folder_id, "2026-10-18 12:02:00").await.unwrap(),
+            vec![("a.txt".to_string(), 2)]
+        );
+
+        let quarantined = db.get_quarantined_sync_errors(folder_id).await.unwrap();
+        assert_eq!(quarantined.len(), 1);
+        let error_id = quarantined["b.txt"].id;
+        db.ignore_sync_error(error_id).await.unwrap();
+        assert!(db.get_sync_errors(Some(folder_id)).await.unwrap().iter().any(|e| e.ignored));
+
+        // Retrying takes it out of quarantine
+        assert_eq!(db.retry_sync_error(error_id).await.unwrap(), Some(folder_id));
+        assert!(db.get_quarantined_sync_errors(folder_id).await.unwrap().is_empty());
+        assert_eq!(db.retry_sync_error(999).await.unwrap(), None);
+
+        // A pass that retried them without new failures resolves transient errors
+        db.resolve_sync_errors(folder_id, Some(&["a.txt".to_string()]), "9999-01-01 00:00:00").await.unwrap();
+        assert_eq!(db.get_sync_errors(None).await.unwrap().len(), 1);
+        db.resolve_sync_errors(folder_id, None, "2000-01-01 00:00:00").await.unwrap();
+        assert_eq!(db.get_sync_errors(None).await.unwrap().len(), 1);
+        db.delete_sync_error(folder_id, "b.txt").await.unwrap();
+        assert!(db.get_sync_errors(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_file_fingerprints_by_prefix() {
+        let (db, _temp_dir) = setup_test_db().await;
+        db.save_credentials("bucket", "account", "key", "secret", "https://endpoint.com").await.unwrap();
+        let bucket_id = db.get_current_bucket_id().await.unwrap().unwrap();
+
+        let fingerprint = FileFingerprint {
+            remote_size: 1,
+            etag: Some("abc".to_string()),
+            sha256: None,
+            local_size: 1,
+            local_mtime: 1000,
+        };
+        db.save_file_fingerprint(bucket_id, "docs/a.txt", &fingerprint).await.unwrap();
+        db.save_file_fingerprint(bucket_id, "docs/sub/b.txt", &fingerprint).await.unwrap();
+        db.save_file_fingerprint(bucket_id, "docs2/c.txt", &fingerprint).await.unwrap();
+
+        let found = db.get_file_fingerprints(bucket_id, "docs/").await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found.get("docs/sub/b.txt"), Some(&fingerprint));
+
+        let keys = ["docs/a.txt".to_string(), "docs2/c.txt".to_string(), "docs/none.txt".to_string()];
+        let found = db.get_file_fingerprints_for(bucket_id, &keys).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.contains_key("docs2/c.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_set_setting_overwrites() {
+        let (db, _temp_dir) = setup_test_db().await;
+
+        db.set_setting("maintenance_interval_hours", "6").await.unwrap();
+        db.set_setting("maintenance_interval_hours", "12").await.unwrap();
+
+        let value = db.get_setting("maintenance_interval_hours").await.unwrap();
+        assert_eq!(value, Some("12".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_load_all_credentials() {
+        let (db, _temp_dir) = setup_test_db().await;
+
+        db.save_credentials("bucket-a", "acct", "key-a", "secret-a", "https://a.com").await.unwrap();
+        db.save_credentials("bucket-b", "acct", "key-b", "secret-b", "https://b.com").await.unwrap();
+
+        let mut buckets = db.load_all_credentials().await.unwrap();
+        buckets.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].1, "bucket-a");
+        assert_eq!(buckets[0].3, "key-a");
+        assert_eq!(buckets[1].4, "secret-b");
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_log_roundtrip() {
+        let (db, _temp_dir) = setup_test_db().await;
+
+        db.add_maintenance_log("stale_uploads", "scheduled", Some("bucket"), "success", 2, 1, None, "2024-01-01T00:00:00Z")
+            .await
+            .unwrap();
+        db.add_maintenance_log("stale_uploads", "manual", Some("bucket"), "error", 0, 0, Some("boom"), "2024-01-02T00:00:00Z")
+            .await
+            .unwrap();
+
+        let log = db.get_maintenance_log(10).await.unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].trigger, "manual");
+        assert_eq!(log[0].message.as_deref(), Some("boom"));
+        assert_eq!(log[1].cleaned_count, 2);
+        assert_eq!(log[1].protected_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_are_recorded_and_pruned() {
+        let (db, _temp_dir) = setup_test_db().await;
+        db.save_credentials("bucket", "account", "key", "secret", "https://endpoint.com").await.unwrap();
+
+        let download = DownloadRecord {
+            local_path: "/restore/a.txt".to_string(),
+            remote_path: "docs/a.txt".to_string(),
+            size: 12,
+            etag: Some("\"e1\"".to_string()),
+            sha256: None,
+            started_at: Utc::now() - chrono::Duration::seconds(3),
+            error: None,
+        };
+        db.record_download(&download).await.unwrap();
+        db.record_download(&DownloadRecord { size: 0, error: Some("Access denied".to_string()), ..download })
+            .await
+            .unwrap();
+
+        let rows: Vec<(Option<i64>, String, Option<String>)> =
+            sqlx::query_as("SELECT bucket_id, status, error_message FROM downloads ORDER BY rowid")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        let bucket_id = db.get_current_bucket_id().await.unwrap();
+        assert_eq!(rows[0], (bucket_id, "completed".to_string(), None));
+        assert_eq!(rows[1], (bucket_id, "failed".to_string(), Some("Access denied".to_string())));
+
+        sqlx::query("UPDATE downloads SET completed_at = datetime('now', '-40 days') WHERE status = 'failed'")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(db.prune_upload_history(30).await.unwrap(), 1);
+        let (left,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM downloads").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(left, 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_setting() {
+        let (db, _temp_dir) = setup_test_db().await;
+
+        assert!(db.get_setting("conflict_resolution").await.unwrap().is_none());
+
+        sqlx::query("INSERT INTO settings (key, value) VALUES ('conflict_resolution', 'rename')")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let value = db.get_setting("conflict_resolution").await.unwrap();
+        assert_eq!(value, Some("rename".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_file_fingerprint_roundtrip() {
+        let (db, _temp_dir) = setup_test_db().await;
+        db.save_credentials("test-bucket", "account", "key", "secret", "https://endpoint.com")
+            .await
+            .unwrap();
+        let bucket_id = db.get_current_bucket_id().await.unwrap().unwrap();
+
+        assert!(db.get_file_fingerprint(bucket_id, "a/b.txt").await.unwrap().is_none());
+
+        let mut fingerprint = FileFingerprint {
+            remote_size: 42,
+            etag: Some("\"abc\"".to_string()),
+            sha256: None,
+            local_size: 42,
+            local_mtime: 1_700_000_000_000,
+        };
+        db.save_file_fingerprint(bucket_id, "a/b.txt", &fingerprint).await.unwrap();
+        assert_eq!(db.get_file_fingerprint(bucket_id, "a/b.txt").await.unwrap(), Some(fingerprint.clone()));
+
+        // Saving again replaces the row
+        fingerprint.sha256 = Some("def".to_string());
+        fingerprint.local_mtime += 1;
+        db.save_file_fingerprint(bucket_id, "a/b.txt", &fingerprint).await.unwrap();
+        assert_eq!(db.get_file_fingerprint(bucket_id, "a/b.txt").await.unwrap(), Some(fingerprint));
+    }
+
     #[tokio::test]
     async fn test_credentials_are_encrypted() {
         let (db, _temp_dir) = setup_test_db().await;
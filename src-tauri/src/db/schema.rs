@@ -0,0 +1,379 @@
+use anyhow::Result;
+use sqlx::migrate::Migrator;
+use sqlx::{Pool, Sqlite};
+use unicode_normalization::is_nfc;
+
+use crate::utils::paths::normalize_key;
+
+/// Schema migrations from `migrations/`, applied in order and recorded with their checksums in
+/// `_sqlx_migrations`. A released migration must never change; schema changes go in a new file.
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Columns that databases from before versioned migrations got through `ALTER TABLE`. The first
+/// migration creates them with their tables, so only older databases may be missing them; each
+/// definition matches the migration's, constraints included.
+const UNVERSIONED_COLUMNS: &[(&str, &str)] = &[
+    ("sync_folders", "created_at DATETIME DEFAULT CURRENT_TIMESTAMP"),
+    ("sync_folders", "poll_interval_secs INTEGER DEFAULT 300"),
+    ("sync_folders", "delete_removed BOOLEAN DEFAULT 0"),
+    ("sync_folders", "deletion_policy TEXT CHECK(deletion_policy IN ('never', 'immediate', 'trash')) DEFAULT 'trash'"),
+    ("sync_folders", "deletion_max_files INTEGER DEFAULT 50"),
+    ("sync_folders", "deletion_max_percent INTEGER DEFAULT 10"),
+    ("sync_folders", "schedule TEXT CHECK(schedule IN ('realtime', 'interval', 'cron')) DEFAULT 'realtime'"),
+    ("sync_folders", "schedule_cron TEXT"),
+    ("sync_folders", "next_run_at TEXT"),
+    ("sync_folders", "settle_secs INTEGER DEFAULT 5"),
+    ("sync_entries", "local_inode INTEGER"),
+    ("sync_entries", "remote_last_modified TEXT"),
+    ("file_metadata", "local_size INTEGER"),
+    ("file_metadata", "local_mtime INTEGER"),
+    ("file_metadata", "sha256 TEXT"),
+    ("uploads", "etag TEXT"),
+    ("uploads", "verification TEXT"),
+];
+
+/// The migration that moves stored paths to NFC
+const NFC_PATHS_VERSION: i64 = 3;
+
+/// Columns holding relative paths or object keys, which match local paths only in NFC
+const PATH_COLUMNS: &[(&str, &str)] = &[
+    ("sync_entries", "relative_path"),
+    ("sync_errors", "relative_path"),
+    ("sync_file_states", "relative_path"),
+    ("held_deletions", "relative_path"),
+    ("sync_conflicts", "relative_path"),
+    ("sync_conflicts", "conflict_path"),
+    ("file_metadata", "remote_path"),
+];
+
+/// Returned when the database was written by a newer version of the app, whose schema this
+/// version doesn't know
+#[derive(Debug, thiserror::Error)]
+#[error("The database is at schema version {found}, newer than this version of the app supports ({supported}). Please update the app.")]
+pub struct SchemaTooNew {
+    pub found: i64,
+    pub supported: i64,
+}
+
+/// Bring a database up to the current schema
+pub(crate) async fn run_migrations(pool: &Pool<Sqlite>) -> Result<()> {
+    let supported = latest_version();
+    let version = schema_version(pool).await?;
+    match version {
+        Some(found) if found > supported => return Err(SchemaTooNew { found, supported }.into()),
+        Some(_) => {}
+        None => upgrade_unversioned(pool).await?,
+    }
+    // Before the migration is recorded, so an interrupted run is repeated on the next start
+    if version.is_none_or(|version| version < NFC_PATHS_VERSION) {
+        normalize_paths(pool).await?;
+    }
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+/// Version of the newest migration this build knows
+fn latest_version() -> i64 {
+    MIGRATOR.iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+/// Newest migration applied to the database, or None if it has never been migrated: either
+/// new, or written before migrations were versioned
+pub(super) async fn schema_version(pool: &Pool<Sqlite>) -> Result<Option<i64>> {
+    let tracked: Option<(String,)> =
+        sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'")
+            .fetch_optional(pool)
+            .await?;
+    if tracked.is_none() {
+        return Ok(None);
+    }
+
+    let (version,): (Option<i64>,) = sqlx::query_as("SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1")
+        .fetch_one(pool)
+        .await?;
+    Ok(Some(version.unwrap_or(0)))
+}
+
+/// Add the columns an unversioned database is missing, so the first migration's indexes and
+/// the queries after it find them. Tables it is missing are left for the migration to create.
+async fn upgrade_unversioned(pool: &Pool<Sqlite>) -> Result<()> {
+    for (table, definition) in UNVERSIONED_COLUMNS {
+        let column = definition.split_whitespace().next().unwrap_or(definition);
+        let (table_exists, column_exists): (bool, bool) = sqlx::query_as(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1),
+                    EXISTS(SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        )
+        .bind(table)
+        .bind(column)
+        .fetch_one(pool)
+        .await?;
+
+        if table_exists && !column_exists {
+            log::info!("Adding {}.{} to a database from before versioned migrations", table, column);
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {}", table, definition))
+                .execute(pool)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Re-key rows stored under paths that aren't NFC. Where a row already exists under the NFC
+/// path, it was written since paths were normalized and the older row is dropped.
+async fn normalize_paths(pool: &Pool<Sqlite>) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for (table, column) in PATH_COLUMNS {
+        let (exists,): (bool,) =
+            sqlx::query_as("SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)")
+                .bind(table)
+                .fetch_one(&mut *tx)
+                .await?;
+        if !exists {
+            continue;
+        }
+
+        let rows: Vec<(i64, String)> = sqlx::query_as(&format!("SELECT rowid, {} FROM {}", column, table))
+            .fetch_all(&mut *tx)
+            .await?;
+        for (rowid, path) in rows.into_iter().filter(|(_, path)| !is_nfc(path)) {
+            let renamed = sqlx::query(&format!("UPDATE OR IGNORE {} SET {} = ? WHERE rowid = ?", table, column))
+                .bind(normalize_key(&path))
+                .bind(rowid)
+                .execute(&mut *tx)
+                .await?;
+            if renamed.rows_affected() == 0 {
+                sqlx::query(&format!("DELETE FROM {} WHERE rowid = ?", table))
+                    .bind(rowid)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePool;
+    use std::collections::BTreeSet;
+    use tempfile::TempDir;
+
+    /// Schemas of databases written before migrations were versioned, one for every change
+    const HISTORICAL_SCHEMAS: &[(&str, &str)] = &[
+        ("baseline", include_str!("schema_history/00_baseline.sql")),
+        ("file_fingerprints", include_str!("schema_history/01_file_fingerprints.sql")),
+        ("upload_verification", include_str!("schema_history/02_upload_verification.sql")),
+        ("maintenance_log", include_str!("schema_history/03_maintenance_log.sql")),
+        ("sync_journal", include_str!("schema_history/04_sync_journal.sql")),
+        ("mirror_mode", include_str!("schema_history/05_mirror_mode.sql")),
+        ("journal_inodes", include_str!("schema_history/06_journal_inodes.sql")),
+        ("held_deletions", include_str!("schema_history/07_held_deletions.sql")),
+        ("sync_schedules", include_str!("schema_history/08_sync_schedules.sql")),
+        ("settle_time", include_str!("schema_history/09_settle_time.sql")),
+        ("file_states", include_str!("schema_history/10_file_states.sql")),
+        ("sync_errors", include_str!("schema_history/11_sync_errors.sql")),
+    ];
+
+    async fn open_pool(temp_dir: &TempDir, name: &str) -> SqlitePool {
+        let db_url = format!("sqlite://{}?mode=rwc", temp_dir.path().join(name).display());
+        SqlitePool::connect(&db_url).await.unwrap()
+    }
+
+    /// Every column's definition, every table's CHECK constraints and foreign keys, and every index,
+    /// outside migration bookkeeping. Column order and the text of the CREATE statement differ
+    /// between a fresh and an upgraded database, so the definitions are compared piece by piece.
+    async fn schema_of(pool: &SqlitePool) -> BTreeSet<String> {
+        let tables: Vec<(String, String)> = sqlx::query_as(
+            "SELECT name, sql FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations'",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        let columns: Vec<(String, String, String, bool, Option<String>, i64)> = sqlx::query_as(
+            "SELECT m.name, p.name, p.type, p.\"notnull\", p.dflt_value, p.pk
+             FROM sqlite_master m, pragma_table_info(m.name) p
+             WHERE m.type = 'table' AND m.name NOT LIKE 'sqlite_%' AND m.name != '_sqlx_migrations'",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        let foreign_keys: Vec<(String, String, String, String, String)> = sqlx::query_as(
+            "SELECT m.name, f.\"from\", f.\"table\", f.\"to\", f.on_delete
+             FROM sqlite_master m, pragma_foreign_key_list(m.name) f
+             WHERE m.type = 'table' AND m.name NOT LIKE 'sqlite_%' AND m.name != '_sqlx_migrations'",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        let indexes: Vec<(String, Option<String>)> =
+            sqlx::query_as("SELECT name, sql FROM sqlite_master WHERE type = 'index' AND name NOT LIKE 'sqlite_%'")
+                .fetch_all(pool)
+                .await
+                .unwrap();
+
+        let checks = tables
+            .iter()
+            .flat_map(|(table, sql)| checks_in(sql).into_iter().map(move |check| format!("{} {}", table, check)));
+        columns
+            .into_iter()
+            .map(|(table, column, kind, not_null, default, pk)| {
+                format!("{}.{} {} not_null={} default={:?} pk={}", table, column, kind, not_null, default, pk)
+            })
+            .chain(checks)
+            .chain(foreign_keys.into_iter().map(|(table, from, to_table, to, on_delete)| {
+                format!("{}.{} references {}({}) on delete {}", table, from, to_table, to, on_delete)
+            }))
+            .chain(indexes.into_iter().map(|(name, sql)| format!("index {} {}", name, sql.unwrap_or_default())))
+            .collect()
+    }
+
+    /// The CHECK constraints in a CREATE TABLE statement, each up to its closing parenthesis
+    fn checks_in(sql: &str) -> Vec<String> {
+        sql.match_indices("CHECK(")
+            .map(|(start, _)| {
+                let mut depth = 0;
+                let end = sql[start..]
+                    .char_indices()
+                    .find(|&(_, c)| {
+                        match c {
+                            '(' => depth += 1,
+                            ')' => depth -= 1,
+                            _ => {}
+                        }
+                        c == ')' && depth == 0
+                    })
+                    .map_or(sql.len(), |(i, _)| start + i + 1);
+                sql[start..end].to_string()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_upgrades_every_historical_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        let fresh = open_pool(&temp_dir, "fresh.db").await;
+        run_migrations(&fresh).await.unwrap();
+        let current = schema_of(&fresh).await;
+
+        for (name, schema) in HISTORICAL_SCHEMAS {
+            let pool = open_pool(&temp_dir, &format!("{}.db", name)).await;
+            sqlx::raw_sql(schema).execute(&pool).await.unwrap();
+            sqlx::query(
+                "INSERT INTO buckets (name, account_id, access_key_id, secret_access_key, endpoint)
+                 VALUES ('bucket', 'account', 'key', 'secret', 'https://endpoint.com')",
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO sync_folders (bucket_id, local_path, remote_path) VALUES (1, '/docs', 'docs/')")
+                .execute(&pool)
+                .await
+                .unwrap();
+            assert_eq!(schema_version(&pool).await.unwrap(), None, "{}", name);
+
+            run_migrations(&pool).await.unwrap();
+            assert_eq!(schema_of(&pool).await, current, "upgrading from {}", name);
+            assert_eq!(schema_version(&pool).await.unwrap(), Some(latest_version()), "{}", name);
+
+            // Existing rows survive and pick up the defaults of added columns
+            let (remote_path, poll, settle): (String, i64, i64) =
+                sqlx::query_as("SELECT remote_path, poll_interval_secs, settle_secs FROM sync_folders")
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            assert_eq!((remote_path.as_str(), poll, settle), ("docs/", 300, 5), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrations_run_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let pool = open_pool(&temp_dir, "app.db").await;
+        assert_eq!(schema_version(&pool).await.unwrap(), None);
+
+        run_migrations(&pool).await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        assert_eq!(schema_version(&pool).await.unwrap(), Some(latest_version()));
+        let (applied,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM _sqlx_migrations")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, MIGRATOR.iter().count() as i64);
+    }
+
+    #[tokio::test]
+    async fn test_refuses_newer_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        let pool = open_pool(&temp_dir, "app.db").await;
+        run_migrations(&pool).await.unwrap();
+
+        // As left behind by a later release
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+             VALUES (?, 'from the future', 1, x'00', 0)",
+        )
+        .bind(latest_version() + 1)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let err = run_migrations(&pool).await.unwrap_err();
+        let too_new = err.downcast_ref::<SchemaTooNew>().unwrap();
+        assert_eq!(too_new.found, latest_version() + 1);
+        assert_eq!(too_new.supported, latest_version());
+    }
+
+    #[tokio::test]
+    async fn test_paths_are_moved_to_nfc() {
+        let temp_dir = TempDir::new().unwrap();
+        let pool = open_pool(&temp_dir, "app.db").await;
+        run_migrations(&pool).await.unwrap();
+        // As a database from before paths were normalized
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version >= ?")
+            .bind(NFC_PATHS_VERSION)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        sqlx::query(
+            "INSERT INTO buckets (name, account_id, access_key_id, secret_access_key, endpoint)
+             VALUES ('bucket', 'account', 'key', 'secret', 'https://endpoint.com')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO sync_folders (bucket_id, local_path, remote_path) VALUES (1, '/docs', 'docs/')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (path, etag) in [("Cafe\u{301}.txt", "old"), ("Re\u{301}sume\u{301}.pdf", "only"), ("Caf\u{e9}.txt", "new")] {
+            sqlx::query(
+                "INSERT INTO sync_entries (folder_id, relative_path, local_size, local_mtime, remote_etag)
+                 VALUES (1, ?, 1, 1, ?)",
+            )
+            .bind(path)
+            .bind(etag)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        run_migrations(&pool).await.unwrap();
+        let entries: Vec<(String, String)> =
+            sqlx::query_as("SELECT relative_path, remote_etag FROM sync_entries ORDER BY relative_path")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ("Caf\u{e9}.txt".to_string(), "new".to_string()),
+                ("R\u{e9}sum\u{e9}.pdf".to_string(), "only".to_string()),
+            ]
+        );
+        assert_eq!(schema_version(&pool).await.unwrap(), Some(latest_version()));
+    }
+}
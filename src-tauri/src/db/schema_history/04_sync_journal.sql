@@ -0,0 +1,116 @@
+CREATE TABLE buckets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT UNIQUE NOT NULL,
+    account_id TEXT NOT NULL,
+    region TEXT,
+    endpoint TEXT NOT NULL,
+    access_key_id TEXT NOT NULL,
+    secret_access_key TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE uploads (
+    id TEXT PRIMARY KEY,
+    bucket_id INTEGER,
+    file_path TEXT NOT NULL,
+    remote_path TEXT NOT NULL,
+    total_size INTEGER NOT NULL,
+    uploaded_size INTEGER DEFAULT 0,
+    chunk_size INTEGER NOT NULL,
+    upload_id TEXT,
+    status TEXT CHECK(status IN ('pending', 'uploading', 'paused', 'completed', 'failed', 'cancelled')) DEFAULT 'pending',
+    error_message TEXT,
+    started_at DATETIME,
+    completed_at DATETIME, etag TEXT, verification TEXT,
+    FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE SET NULL
+);
+
+CREATE TABLE upload_chunks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    upload_id TEXT NOT NULL,
+    part_number INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    etag TEXT,
+    status TEXT CHECK(status IN ('pending', 'uploading', 'completed', 'failed')) DEFAULT 'pending',
+    uploaded_at DATETIME,
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE,
+    UNIQUE(upload_id, part_number)
+);
+
+CREATE TABLE sync_folders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    bucket_id INTEGER NOT NULL,
+    local_path TEXT NOT NULL,
+    remote_path TEXT NOT NULL,
+    sync_mode TEXT CHECK(sync_mode IN ('upload', 'download', 'bidirectional', 'upload_only')) DEFAULT 'upload_only',
+    enabled BOOLEAN DEFAULT 1,
+    last_sync DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE CASCADE
+);
+
+CREATE TABLE file_metadata (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    bucket_id INTEGER NOT NULL,
+    remote_path TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    etag TEXT,
+    last_modified DATETIME,
+    content_type TEXT,
+    cached_at DATETIME DEFAULT CURRENT_TIMESTAMP, local_size INTEGER, local_mtime INTEGER, sha256 TEXT,
+    FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE CASCADE,
+    UNIQUE(bucket_id, remote_path)
+);
+
+CREATE TABLE settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE sync_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    folder_id INTEGER NOT NULL,
+    relative_path TEXT NOT NULL,
+    local_size INTEGER NOT NULL,
+    local_mtime INTEGER NOT NULL,
+    local_hash TEXT,
+    remote_etag TEXT NOT NULL,
+    synced_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (folder_id) REFERENCES sync_folders(id) ON DELETE CASCADE,
+    UNIQUE(folder_id, relative_path)
+);
+
+CREATE TABLE sync_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    folder_id INTEGER NOT NULL,
+    relative_path TEXT NOT NULL,
+    conflict_path TEXT NOT NULL,
+    detected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    resolved_at DATETIME,
+    FOREIGN KEY (folder_id) REFERENCES sync_folders(id) ON DELETE CASCADE
+);
+
+CREATE TABLE maintenance_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task TEXT NOT NULL,
+    trigger TEXT CHECK(trigger IN ('scheduled', 'manual')) NOT NULL,
+    bucket_name TEXT,
+    status TEXT CHECK(status IN ('success', 'error')) NOT NULL,
+    cleaned_count INTEGER DEFAULT 0,
+    protected_count INTEGER DEFAULT 0,
+    message TEXT,
+    started_at DATETIME NOT NULL,
+    finished_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_uploads_status ON uploads(status);
+
+CREATE INDEX idx_upload_chunks_upload_id ON upload_chunks(upload_id);
+
+CREATE INDEX idx_file_metadata_bucket ON file_metadata(bucket_id, remote_path);
+
+CREATE INDEX idx_sync_folders_enabled ON sync_folders(enabled);
+
+CREATE INDEX idx_maintenance_log_finished ON maintenance_log(finished_at);
+
+CREATE INDEX idx_sync_conflicts_folder ON sync_conflicts(folder_id, resolved_at);
@@ -0,0 +1,547 @@
+//! Bucket inventory: every object under a prefix written to CSV, JSON Lines or a SQLite
+//! snapshot. Buckets can hold tens of millions of keys, so the listing is checkpointed after
+//! each page and an interrupted export picks up from its last continuation token.
+
+use anyhow::{bail, Context, Result};
+use aws_sdk_s3::Client;
+use aws_smithy_types::date_time::Format;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use crate::r2::operations::{head_content_type, list_objects_page};
+use crate::utils::csv;
+
+/// Emitted with an [`InventoryProgress`] after each page
+pub const PROGRESS_EVENT: &str = "inventory-progress";
+
+/// Content types cost one HEAD request per object; this many run at once
+const CONTENT_TYPE_CONCURRENCY: usize = 16;
+
+const CSV_HEADER: &str = "key,size,etag,last_modified,content_type,storage_class";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InventoryFormat {
+    Csv,
+    Jsonl,
+    Sqlite,
+}
+
+/// One object in the inventory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InventoryEntry {
+    pub key: String,
+    pub size: i64,
+    pub etag: String,
+    /// RFC 3339
+    pub last_modified: Option<String>,
+    /// Only filled in when the export asks for content types
+    pub content_type: Option<String>,
+    pub storage_class: Option<String>,
+}
+
+impl InventoryEntry {
+    fn from_object(object: &aws_sdk_s3::types::Object) -> Self {
+        Self {
+            key: object.key().unwrap_or("").to_string(),
+            size: object.size().unwrap_or(0),
+            etag: object.e_tag().unwrap_or("").to_string(),
+            last_modified: object.last_modified().and_then(|dt| dt.fmt(Format::DateTime).ok()),
+            content_type: None,
+            storage_class: object.storage_class().map(|class| class.as_str().to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryProgress {
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+    pub objects: u64,
+    pub bytes: i64,
+    /// Whether this export continued an interrupted one
+    pub resumed: bool,
+    pub done: bool,
+}
+
+/// What an export is of; a checkpoint only resumes an export of the same thing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventorySource {
+    pub bucket: String,
+    pub prefix: Option<String>,
+    pub format: InventoryFormat,
+    pub content_types: bool,
+}
+
+/// How far an export got. Saved after every page, together with the output it covers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Checkpoint {
+    source: InventorySource,
+    /// Where to list from next; `None` before the first page
+    continuation_token: Option<String>,
+    objects: u64,
+    bytes: i64,
+    /// Length of a text output at this checkpoint; anything after it is from an unfinished page
+    file_len: u64,
+}
+
+/// Output being written, checkpointed after each page
+enum InventoryWriter {
+    /// CSV or JSON Lines, with the checkpoint in a file next to it
+    Text { file: tokio::fs::File, checkpoint_path: PathBuf },
+    /// The objects and the checkpoint in one database, so each page commits with its checkpoint
+    Sqlite { pool: SqlitePool },
+}
+
+/// Checkpoint file for a text export to `path`
+fn checkpoint_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".checkpoint.json");
+    PathBuf::from(name)
+}
+
+impl InventoryWriter {
+    /// Open the output, continuing an interrupted export of the same source if there is one,
+    /// and return where it left off
+    async fn open(path: &Path, source: &InventorySource) -> Result<(Self, Checkpoint, bool)> {
+        match source.format {
+            InventoryFormat::Sqlite => Self::open_sqlite(path, source).await,
+            InventoryFormat::Csv | InventoryFormat::Jsonl => Self::open_text(path, source).await,
+        }
+    }
+
+    async fn open_text(path: &Path, source: &InventorySource) -> Result<(Self, Checkpoint, bool)> {
+        let checkpoint_path = checkpoint_path(path);
+        let saved = match tokio::fs::read(&checkpoint_path).await {
+            Ok(bytes) => serde_json::from_slice::<Checkpoint>(&bytes).ok().filter(|c| c.source == *source),
+            Err(_) => None,
+        };
+
+        if let Some(checkpoint) = saved {
+            if let Ok(mut file) = tokio::fs::OpenOptions::new().write(true).open(path).await {
+                if file.metadata().await?.len() >= checkpoint.file_len {
+                    // Drop whatever part of a page was written after the checkpoint
+                    file.set_len(checkpoint.file_len).await?;
+                    file.seek(std::io::SeekFrom::End(0)).await?;
+                    log::info!("Resuming inventory of {} at {} objects", source.bucket, checkpoint.objects);
+                    return Ok((Self::Text { file, checkpoint_path }, checkpoint, true));
+                }
+            }
+        }
+
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        if source.format == InventoryFormat::Csv {
+            file.write_all(CSV_HEADER.as_bytes()).await?;
+            file.write_all(b"\r\n").await?;
+        }
+        let checkpoint = Checkpoint {
+            source: source.clone(),
+            continuation_token: None,
+            objects: 0,
+            bytes: 0,
+            file_len: file.metadata().await?.len(),
+        };
+        let mut writer = Self::Text { file, checkpoint_path };
+        writer.save_text_checkpoint(&checkpoint).await?;
+        Ok((writer, checkpoint, false))
+    }
+
+    async fn open_sqlite(path: &Path, source: &InventorySource) -> Result<(Self, Checkpoint, bool)> {
+        if path.exists() {
+            // An empty file is what a save dialog may leave behind; anything else has to be a snapshot
+            if tokio::fs::metadata(path).await?.len() > 0 {
+                let pool = connect_snapshot(path).await?;
+                if !is_snapshot(&pool).await {
+                    pool.close().await;
+                    bail!("{} is not an inventory snapshot and won't be overwritten; choose another file", path.display());
+                }
+                if let Some(checkpoint) = load_sqlite_checkpoint(&pool).await.filter(|c| c.source == *source) {
+                    log::info!("Resuming inventory of {} at {} objects", source.bucket, checkpoint.objects);
+                    return Ok((Self::Sqlite { pool }, checkpoint, true));
+                }
+                pool.close().await;
+            }
+            remove_snapshot(path).await?;
+        }
+
+        let pool = connect_snapshot(path).await?;
+        sqlx::raw_sql(
+            "CREATE TABLE objects (
+                key TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                etag TEXT NOT NULL,
+                last_modified TEXT,
+                content_type TEXT,
+                storage_class TEXT
+            );
+            CREATE TABLE export_state (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                checkpoint TEXT NOT NULL,
+                complete BOOLEAN NOT NULL DEFAULT 0
+            );",
+        )
+        .execute(&pool)
+        .await?;
+
+        let checkpoint = Checkpoint {
+            source: source.clone(),
+            continuation_token: None,
+            objects: 0,
+            bytes: 0,
+            file_len: 0,
+        };
+        sqlx::query("INSERT INTO export_state (id, checkpoint) VALUES (1, ?)")
+            .bind(serde_json::to_string(&checkpoint)?)
+            .execute(&pool)
+            .await?;
+        Ok((Self::Sqlite { pool }, checkpoint, false))
+    }
+
+    /// Write a page and move the checkpoint past it
+    async fn write_page(&mut self, entries: &[InventoryEntry], checkpoint: &mut Checkpoint, next_token: Option<String>) -> Result<()> {
+        checkpoint.continuation_token = next_token;
+        checkpoint.objects += entries.len() as u64;
+        checkpoint.bytes += entries.iter().map(|e| e.size).sum::<i64>();
+
+        match self {
+            Self::Text { file, .. } => {
+                let mut out = String::new();
+                for entry in entries {
+                    match checkpoint.source.format {
+                        InventoryFormat::Csv => {
+                            let size = entry.size.to_string();
+                            csv::push_record(
+                                &mut out,
+                                [
+                                    entry.key.as_str(),
+                                    size.as_str(),
+                                    entry.etag.as_str(),
+                                    entry.last_modified.as_deref().unwrap_or(""),
+                                    entry.content_type.as_deref().unwrap_or(""),
+                                    entry.storage_class.as_deref().unwrap_or(""),
+                                ],
+                            );
+                        }
+                        _ => {
+                            out.push_str(&serde_json::to_string(entry)?);
+                            out.push('\n');
+                        }
+                    }
+                }
+                file.write_all(out.as_bytes()).await?;
+                file.sync_data().await?;
+                checkpoint.file_len += out.len() as u64;
+                self.save_text_checkpoint(checkpoint).await
+            }
+            Self::Sqlite { pool } => {
+                let mut tx = pool.begin().await?;
+                for entry in entries {
+                    sqlx::query(
+                        "INSERT OR REPLACE INTO objects (key, size, etag, last_modified, content_type, storage_class)
+                         VALUES (?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(&entry.key)
+                    .bind(entry.size)
+                    .bind(&entry.etag)
+                    .bind(&entry.last_modified)
+                    .bind(&entry.content_type)
+                    .bind(&entry.storage_class)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                sqlx::query("UPDATE export_state SET checkpoint = ? WHERE id = 1")
+                    .bind(serde_json::to_string(checkpoint)?)
+                    .execute(&mut *tx)
+                    .await?;
+                tx.commit().await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Written to a temporary file and renamed over the old one, so it is never half written
+    async fn save_text_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+        if let Self::Text { checkpoint_path, .. } = self {
+            let mut temp = checkpoint_path.clone().into_os_string();
+            temp.push(".tmp");
+            tokio::fs::write(&temp, serde_json::to_vec(checkpoint)?).await?;
+            tokio::fs::rename(&temp, &checkpoint_path).await?;
+        }
+        Ok(())
+    }
+
+    /// Mark the export complete, so it is started afresh rather than resumed next time
+    async fn finish(self) -> Result<()> {
+        match self {
+            Self::Text { file, checkpoint_path } => {
+                file.sync_all().await?;
+                tokio::fs::remove_file(&checkpoint_path).await?;
+            }
+            Self::Sqlite { pool } => {
+                sqlx::query("UPDATE export_state SET complete = 1 WHERE id = 1")
+                    .execute(&pool)
+                    .await?;
+                pool.close().await;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn connect_snapshot(path: &Path) -> Result<SqlitePool> {
+    let db_url = format!("sqlite://{}?mode=rwc", path.display());
+    SqlitePool::connect(&db_url)
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Whether the database is a snapshot written by an export, and so safe to replace
+async fn is_snapshot(pool: &SqlitePool) -> bool {
+    sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'export_state'")
+        .fetch_one(pool)
+        .await
+        .is_ok_and(|(tables,)| tables == 1)
+}
+
+/// Delete a snapshot along with its journal files, so none of them is applied to the new one
+async fn remove_snapshot(path: &Path) -> Result<()> {
+    tokio::fs::remove_file(path).await?;
+    for suffix in ["-wal", "-shm", "-journal"] {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        match tokio::fs::remove_file(&sidecar).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Checkpoint of an unfinished snapshot; `None` for a finished one or any other database
+async fn load_sqlite_checkpoint(pool: &SqlitePool) -> Option<Checkpoint> {
+    let (checkpoint,): (String,) = sqlx::query_as("SELECT checkpoint FROM export_state WHERE id = 1 AND complete = 0")
+        .fetch_optional(pool)
+        .await
+        .ok()??;
+    serde_json::from_str(&checkpoint).ok()
+}
+
+/// Totals for a finished export
+#[derive(Debug, Clone, Serialize)]
+pub struct InventorySummary {
+    pub objects: u64,
+    pub bytes: i64,
+    pub resumed: bool,
+}
+
+/// Write every object under `prefix` to `path`, resuming an interrupted export of the same
+/// bucket, prefix and format. Without `content_types` only what listings return is recorded.
+pub async fn export(
+    client: &Client,
+    source: InventorySource,
+    path: &Path,
+    on_progress: impl Fn(&InventoryProgress),
+) -> Result<InventorySummary> {
+    let (mut writer, mut checkpoint, resumed) = InventoryWriter::open(path, &source).await?;
+    let progress = |checkpoint: &Checkpoint, done: bool| InventoryProgress {
+        file_path: path.display().to_string(),
+        objects: checkpoint.objects,
+        bytes: checkpoint.bytes,
+        resumed,
+        done,
+    };
+    on_progress(&progress(&checkpoint, false));
+
+    loop {
+        let (objects, next_token) = list_objects_page(
+            client,
+            &source.bucket,
+            source.prefix.as_deref(),
+            checkpoint.continuation_token.as_deref(),
+        )
+        .await?;
+
+        let mut entries: Vec<InventoryEntry> = objects.iter().map(InventoryEntry::from_object).collect();
+        if source.content_types {
+            let bucket = source.bucket.as_str();
+            entries = stream::iter(entries)
+                .map(|mut entry| async move {
+                    entry.content_type = head_content_type(client, bucket, &entry.key).await?;
+                    anyhow::Ok(entry)
+                })
+                .buffered(CONTENT_TYPE_CONCURRENCY)
+                .try_collect()
+                .await?;
+        }
+
+        let done = next_token.is_none();
+        writer.write_page(&entries, &mut checkpoint, next_token).await?;
+        if done {
+            break;
+        }
+        on_progress(&progress(&checkpoint, false));
+    }
+
+    writer.finish().await?;
+    on_progress(&progress(&checkpoint, true));
+    Ok(InventorySummary { objects: checkpoint.objects, bytes: checkpoint.bytes, resumed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn source(format: InventoryFormat) -> InventorySource {
+        InventorySource { bucket: "photos".to_string(), prefix: Some("2024/".to_string()), format, content_types: false }
+    }
+
+    fn entry(key: &str, size: i64) -> InventoryEntry {
+        InventoryEntry {
+            key: key.to_string(),
+            size,
+            etag: "\"abc\"".to_string(),
+            last_modified: Some("2024-03-01T10:00:00Z".to_string()),
+            content_type: None,
+            storage_class: Some("STANDARD".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_csv_export_resumes_after_interruption() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("inventory.csv");
+        let source = source(InventoryFormat::Csv);
+
+        let (mut writer, mut checkpoint, resumed) = InventoryWriter::open(&path, &source).await.unwrap();
+        assert!(!resumed);
+        writer.write_page(&[entry("2024/a.jpg", 10), entry("2024/b, c.jpg", 20)], &mut checkpoint, Some("token-1".into())).await.unwrap();
+        // Interrupted partway through writing the next page
+        if let InventoryWriter::Text { file, .. } = &mut writer {
+            file.write_all(b"2024/half").await.unwrap();
+        }
+        drop(writer);
+        assert!(checkpoint_path(&path).exists());
+
+        let (mut writer, mut checkpoint, resumed) = InventoryWriter::open(&path, &source).await.unwrap();
+        assert!(resumed);
+        assert_eq!(checkpoint.continuation_token.as_deref(), Some("token-1"));
+        assert_eq!((checkpoint.objects, checkpoint.bytes), (2, 30));
+        writer.write_page(&[entry("2024/d.jpg", 5)], &mut checkpoint, None).await.unwrap();
+        writer.finish().await.unwrap();
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            csv,
+            "key,size,etag,last_modified,content_type,storage_class\r\n\
+             2024/a.jpg,10,\"\"\"abc\"\"\",2024-03-01T10:00:00Z,,STANDARD\r\n\
+             \"2024/b, c.jpg\",20,\"\"\"abc\"\"\",2024-03-01T10:00:00Z,,STANDARD\r\n\
+             2024/d.jpg,5,\"\"\"abc\"\"\",2024-03-01T10:00:00Z,,STANDARD\r\n"
+        );
+        assert!(!checkpoint_path(&path).exists());
+
+        // A finished export is started afresh
+        let (_, checkpoint, resumed) = InventoryWriter::open(&path, &source).await.unwrap();
+        assert!(!resumed);
+        assert_eq!(checkpoint.objects, 0);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_for_another_source_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("inventory.jsonl");
+
+        let (mut writer, mut checkpoint, _) = InventoryWriter::open(&path, &source(InventoryFormat::Jsonl)).await.unwrap();
+        writer.write_page(&[entry("2024/a.jpg", 10)], &mut checkpoint, Some("token-1".into())).await.unwrap();
+        drop(writer);
+
+        let other = InventorySource { prefix: Some("2023/".to_string()), ..source(InventoryFormat::Jsonl) };
+        let (_, checkpoint, resumed) = InventoryWriter::open(&path, &other).await.unwrap();
+        assert!(!resumed);
+        assert_eq!(checkpoint.continuation_token, None);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("inventory.jsonl");
+
+        let (mut writer, mut checkpoint, _) = InventoryWriter::open(&path, &source(InventoryFormat::Jsonl)).await.unwrap();
+        let with_type = InventoryEntry { content_type: Some("image/jpeg".to_string()), ..entry("2024/a.jpg", 10) };
+        writer.write_page(&[with_type], &mut checkpoint, None).await.unwrap();
+        writer.finish().await.unwrap();
+
+        let jsonl = std::fs::read_to_string(&path).unwrap();
+        let record: serde_json::Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+        assert_eq!(record["key"], "2024/a.jpg");
+        assert_eq!(record["content_type"], "image/jpeg");
+        assert_eq!(record["storage_class"], "STANDARD");
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_snapshot_resumes() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("inventory.db");
+        let source = source(InventoryFormat::Sqlite);
+
+        let (mut writer, mut checkpoint, _) = InventoryWriter::open(&path, &source).await.unwrap();
+        writer.write_page(&[entry("2024/a.jpg", 10), entry("2024/b.jpg", 20)], &mut checkpoint, Some("token-1".into())).await.unwrap();
+        if let InventoryWriter::Sqlite { pool } = writer {
+            pool.close().await;
+        }
+
+        let (mut writer, mut checkpoint, resumed) = InventoryWriter::open(&path, &source).await.unwrap();
+        assert!(resumed);
+        assert_eq!(checkpoint.continuation_token.as_deref(), Some("token-1"));
+        // Keys listed again replace their rows rather than duplicating them
+        writer.write_page(&[entry("2024/b.jpg", 20), entry("2024/c.jpg", 30)], &mut checkpoint, None).await.unwrap();
+        writer.finish().await.unwrap();
+
+        let pool = connect_snapshot(&path).await.unwrap();
+        let (count, total): (i64, i64) = sqlx::query_as("SELECT COUNT(*), SUM(size) FROM objects").fetch_one(&pool).await.unwrap();
+        assert_eq!((count, total), (3, 60));
+        assert!(load_sqlite_checkpoint(&pool).await.is_none());
+        pool.close().await;
+
+        // A finished snapshot is replaced by a new export, without its old journal files
+        let wal = temp_dir.path().join("inventory.db-wal");
+        std::fs::write(&wal, b"stale").unwrap();
+        let (_, checkpoint, resumed) = InventoryWriter::open(&path, &source).await.unwrap();
+        assert!(!resumed);
+        assert_eq!(checkpoint.objects, 0);
+        assert!(!wal.exists());
+    }
+
+    #[tokio::test]
+    async fn test_other_files_are_not_overwritten_by_a_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = source(InventoryFormat::Sqlite);
+
+        let database = temp_dir.path().join("app.db");
+        let pool = connect_snapshot(&database).await.unwrap();
+        sqlx::query("CREATE TABLE settings (key TEXT)").execute(&pool).await.unwrap();
+        pool.close().await;
+        let err = InventoryWriter::open(&database, &source).await.err().unwrap();
+        assert!(err.to_string().contains("not an inventory snapshot"));
+        let pool = connect_snapshot(&database).await.unwrap();
+        assert!(sqlx::query("SELECT * FROM settings").fetch_all(&pool).await.is_ok());
+        pool.close().await;
+
+        let text = temp_dir.path().join("notes.db");
+        std::fs::write(&text, "not a database").unwrap();
+        assert!(InventoryWriter::open(&text, &source).await.is_err());
+        assert_eq!(std::fs::read_to_string(&text).unwrap(), "not a database");
+
+        // What a save dialog leaves behind is fine to use
+        let empty = temp_dir.path().join("empty.db");
+        std::fs::write(&empty, "").unwrap();
+        let (_, _, resumed) = InventoryWriter::open(&empty, &source).await.unwrap();
+        assert!(!resumed);
+    }
+}
@@ -7,13 +7,23 @@ mod utils;
 mod upload;
 mod crypto;
 mod migration;
+mod maintenance;
+mod sync;
+mod settings;
+mod inventory;
 
 use r2::R2Client;
 use r2::multipart::MultipartUpload;
 use db::Database;
-use upload::UploadManager;
+use upload::{ConflictAction, ConflictPolicy, ConflictResolver, UploadManager};
+use upload::conflict::{ConflictPrompt, PROMPT_TIMEOUT_SECS};
+use upload::fingerprint::{ChangedDuringUpload, UploadError, Uploaded};
 use migration::{BackupData, CredentialsBackup, SyncFolderBackup, SettingBackup, UploadHistoryBackup};
-use utils::{R2Object, R2Credentials, UploadProgress, UploadStatus};
+use utils::{
+    R2Object, R2Credentials, UploadHistoryEntry, UploadPlanItem, UploadPreview, UploadPreviewEntry,
+    UploadProgress, UploadStatus, UploadVerification,
+};
+use utils::paths;
 use std::sync::Arc;
 use std::collections::HashMap;
 use tokio::sync::Mutex;
@@ -27,6 +37,12 @@ pub struct AppState {
     pub upload_manager: Arc<UploadManager>,
     /// Active multipart uploads that can be paused/cancelled
     pub active_uploads: Arc<Mutex<HashMap<String, Arc<MultipartUpload>>>>,
+    /// Pending "ask" prompts for uploads that hit an existing remote key
+    pub conflict_resolver: Arc<ConflictResolver>,
+    /// Watchers for the enabled sync folders
+    pub sync_engine: Arc<sync::SyncEngine>,
+    /// Settings in effect, replaced when they are saved
+    pub settings: Arc<settings::LiveSettings>,
 }
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -78,6 +94,13 @@ async fn connect_r2(
     // Store the client
     *app_state.r2_client.lock().await = Some(client);
 
+    // Sync folders belong to the connected bucket
+    let sync_engine = app_state.sync_engine.clone();
+    drop(app_state);
+    if let Err(e) = sync_engine.reload().await {
+        log::warn!("Failed to restart sync: {}", e);
+    }
+
     Ok("Connected successfully! Connection verified by listing objects.".to_string())
 }
 
@@ -142,6 +165,13 @@ async fn load_and_connect(
     // Store the client
     *app_state.r2_client.lock().await = Some(client);
 
+    // Sync folders belong to the connected bucket
+    let sync_engine = app_state.sync_engine.clone();
+    drop(app_state);
+    if let Err(e) = sync_engine.reload().await {
+        log::warn!("Failed to restart sync: {}", e);
+    }
+
     Ok(format!("Auto-connected to bucket: {}", bucket))
 }
 
@@ -170,16 +200,30 @@ async fn list_objects(
 
 #[tauri::command]
 async fn upload_file(
+    app: tauri::AppHandle,
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
     local_path: String,
     remote_key: String,
+    conflict_policy: Option<String>,
 ) -> Result<String, String> {
-    let app_state = state.lock().await;
-    let client_guard = app_state.r2_client.lock().await;
-    
-    let client = client_guard
-        .as_ref()
-        .ok_or("Not connected to R2")?;
+    let remote_key = paths::normalize_key(&remote_key);
+    paths::check_key(&remote_key).map_err(|e| e.to_string())?;
+
+    let (client, bucket, db, settings) = {
+        let app_state = state.lock().await;
+        let client_guard = app_state.r2_client.lock().await;
+        
+        let client = client_guard
+            .as_ref()
+            .ok_or("Not connected to R2")?;
+
+        (client.client().clone(), client.bucket().to_string(), app_state.db.clone(), app_state.settings.current())
+    };
+
+    let mut policy = match conflict_policy.as_deref() {
+        Some(p) => ConflictPolicy::from_setting(p),
+        None => default_conflict_policy(&db).await,
+    };
 
     // Check file size to determine if multipart upload is needed
     let metadata = tokio::fs::metadata(&local_path)
@@ -187,32 +231,209 @@ async fn upload_file(
         .map_err(|e| e.to_string())?;
 
     let file_size = metadata.len();
-    
-    // Use multipart upload for files > 100MB
-    if file_size > 100 * 1024 * 1024 {
-        r2::multipart::upload_large_file(
-            client.client(),
-            client.bucket(),
-            &remote_key,
-            &local_path,
-            Some(10 * 1024 * 1024), // 10MB chunks
-        )
+
+    loop {
+        let (key, if_absent) = match resolve_upload_target(
+            &app, &client, &bucket, &local_path, &remote_key, policy, None,
+        ).await? {
+            UploadTarget::Key { key, if_absent } => (key, if_absent),
+            UploadTarget::Skip => return Ok(String::new()),
+        };
+
+        // Use multipart upload for files > 100MB
+        let result = if file_size > 100 * 1024 * 1024 {
+            // Multipart ETags aren't content hashes, so record the whole-file hash as metadata
+            let hashes = upload::fingerprint::hash_file(&local_path)
+                .await
+                .map_err(|e| e.to_string())?;
+            r2::multipart::upload_large_file(
+                &client,
+                &bucket,
+                &key,
+                &local_path,
+                Some(settings.chunk_size),
+                settings.parallel_uploads,
+                if_absent,
+                Some(&hashes.sha256),
+            )
+            .await
+            .map(|_| "Uploaded with multipart".to_string())
+        } else {
+            r2::operations::put_object_with_progress(
+                &client,
+                &bucket,
+                &key,
+                &local_path,
+                &r2::operations::PutOptions { if_absent, ..Default::default() },
+                |_, _, _, _| {},
+            )
+            .await
+        };
+
+        match result {
+            // Another upload took the free name first; look for the next one
+            Err(e) if key != remote_key && e.is::<r2::operations::ObjectExists>() => {
+                log::info!("{} was taken during the upload, renaming again", key);
+                policy = ConflictPolicy::Rename;
+            }
+            result => return result.map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Where an upload should be written once the conflict policy has been applied
+enum UploadTarget {
+    /// Upload to `key`; `if_absent` makes the write fail rather than replace an object
+    Key { key: String, if_absent: bool },
+    Skip,
+}
+
+/// Highest "name (n).ext" suffix tried before giving up on a rename
+const MAX_RENAME_SUFFIX: u32 = 1000;
+
+/// Conflict policy from the settings table, falling back to the app default
+async fn default_conflict_policy(db: &Database) -> ConflictPolicy {
+    let value = db.get_setting("conflict_resolution")
         .await
-        .map_err(|e| e.to_string())?;
-        Ok("Uploaded with multipart".to_string())
-    } else {
-        let etag = r2::operations::put_object(
-            client.client(),
-            client.bucket(),
-            &remote_key,
-            &local_path,
-        )
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| utils::AppSettings::default().conflict_resolution);
+    ConflictPolicy::from_setting(&value)
+}
+
+/// Check whether `remote_key` already exists and apply `policy` to decide where the upload goes
+async fn resolve_upload_target(
+    app: &tauri::AppHandle,
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    local_path: &str,
+    remote_key: &str,
+    policy: ConflictPolicy,
+    batch_id: Option<&str>,
+) -> Result<UploadTarget, String> {
+    if policy == ConflictPolicy::Overwrite {
+        return Ok(UploadTarget::Key { key: remote_key.to_string(), if_absent: false });
+    }
+
+    let existing = r2::operations::head_object(client, bucket, remote_key)
         .await
         .map_err(|e| e.to_string())?;
-        Ok(etag)
+
+    let existing = match existing {
+        Some(info) => info,
+        None => return Ok(UploadTarget::Key { key: remote_key.to_string(), if_absent: true }),
+    };
+
+    let action = match policy {
+        ConflictPolicy::Overwrite => ConflictAction::Overwrite,
+        ConflictPolicy::Skip => ConflictAction::Skip,
+        ConflictPolicy::Rename => ConflictAction::Rename,
+        ConflictPolicy::Ask => ask_upload_conflict(app, local_path, &existing, batch_id).await,
+    };
+
+    match action {
+        ConflictAction::Overwrite => Ok(UploadTarget::Key { key: remote_key.to_string(), if_absent: false }),
+        ConflictAction::Skip => {
+            log::info!("Skipping upload of {}: {} already exists", local_path, remote_key);
+            Ok(UploadTarget::Skip)
+        }
+        ConflictAction::Rename => {
+            for n in 1..=MAX_RENAME_SUFFIX {
+                let candidate = upload::conflict::suffixed_key(remote_key, n);
+                let taken = r2::operations::head_object(client, bucket, &candidate)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if taken.is_none() {
+                    return Ok(UploadTarget::Key { key: candidate, if_absent: true });
+                }
+            }
+            Err(format!("No free name found for {} after {} attempts", remote_key, MAX_RENAME_SUFFIX))
+        }
+    }
+}
+
+/// Emit an `upload-conflict` event and wait for the frontend's answer.
+/// Falls back to skipping if nobody answers in time.
+async fn ask_upload_conflict(
+    app: &tauri::AppHandle,
+    local_path: &str,
+    existing: &r2::operations::ObjectInfo,
+    batch_id: Option<&str>,
+) -> ConflictAction {
+    let resolver = {
+        let state = app.state::<Arc<Mutex<AppState>>>();
+        let app_state = state.lock().await;
+        app_state.conflict_resolver.clone()
+    };
+
+    if let Some(batch_id) = batch_id {
+        if let Some(action) = resolver.remembered(batch_id).await {
+            return action;
+        }
+    }
+
+    let local_size = tokio::fs::metadata(local_path)
+        .await
+        .map(|m| m.len() as i64)
+        .unwrap_or(0);
+
+    let prompt = ConflictPrompt {
+        id: uuid::Uuid::new_v4().to_string(),
+        batch_id: batch_id.map(|b| b.to_string()),
+        local_path: local_path.to_string(),
+        remote_key: existing.key.clone(),
+        local_size,
+        remote_size: existing.size,
+        remote_last_modified: existing.last_modified.clone(),
+    };
+
+    let rx = resolver.register(&prompt.id, batch_id).await;
+    if let Err(e) = app.emit("upload-conflict", &prompt) {
+        log::error!("Failed to emit upload conflict event: {}", e);
+        resolver.forget(&prompt.id).await;
+        return ConflictAction::Skip;
+    }
+
+    match tokio::time::timeout(std::time::Duration::from_secs(PROMPT_TIMEOUT_SECS), rx).await {
+        Ok(Ok(action)) => action,
+        _ => {
+            log::warn!("No answer for upload conflict on {}, skipping", existing.key);
+            resolver.forget(&prompt.id).await;
+            ConflictAction::Skip
+        }
     }
 }
 
+/// Answer an `upload-conflict` prompt
+#[tauri::command]
+async fn resolve_upload_conflict(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    prompt_id: String,
+    action: String,
+    remember_for_batch: bool,
+) -> Result<(), String> {
+    let action = ConflictAction::parse(&action)
+        .ok_or_else(|| format!("Unknown conflict action: {}", action))?;
+
+    let resolver = state.lock().await.conflict_resolver.clone();
+    if !resolver.answer(&prompt_id, action, remember_for_batch).await {
+        return Err("This conflict is no longer waiting for an answer".to_string());
+    }
+
+    Ok(())
+}
+
+/// Forget conflict answers remembered for a finished upload batch
+#[tauri::command]
+async fn end_upload_batch(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    batch_id: String,
+) -> Result<(), String> {
+    let resolver = state.lock().await.conflict_resolver.clone();
+    resolver.end_batch(&batch_id).await;
+    Ok(())
+}
+
 #[tauri::command]
 async fn download_file(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
@@ -226,17 +447,43 @@ async fn download_file(
         .as_ref()
         .ok_or("Not connected to R2")?;
 
-    r2::operations::get_object_streaming(
+    let started_at = chrono::Utc::now();
+    let result = r2::operations::get_object_streaming(
         client.client(),
         client.bucket(),
         &remote_key,
         &local_path,
         None,
     )
-    .await
-    .map_err(|e| e.to_string())?;
+    .await;
+    record_download(&app_state.db, &remote_key, &local_path, started_at, &result).await;
+    result.map_err(|e| e.to_string())
+}
 
-    Ok(())
+/// Add a download to the transfer history. Failing to record it doesn't fail the download.
+async fn record_download(
+    db: &Database,
+    remote_key: &str,
+    local_path: &str,
+    started_at: chrono::DateTime<chrono::Utc>,
+    result: &anyhow::Result<()>,
+) {
+    let size = match result {
+        Ok(()) => tokio::fs::metadata(local_path).await.map(|m| m.len() as i64).unwrap_or(0),
+        Err(_) => 0,
+    };
+    let download = db::DownloadRecord {
+        local_path: local_path.to_string(),
+        remote_path: remote_key.to_string(),
+        size,
+        etag: None,
+        sha256: None,
+        started_at,
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+    };
+    if let Err(e) = db.record_download(&download).await {
+        log::warn!("Failed to record download of {}: {}", remote_key, e);
+    }
 }
 
 /// Download progress info sent to frontend
@@ -264,7 +511,7 @@ async fn download_file_with_progress(
 ) -> Result<String, String> {
     let download_id = uuid::Uuid::new_v4().to_string();
     
-    let (client_clone, bucket_clone) = {
+    let (client_clone, bucket_clone, db) = {
         let app_state = state.lock().await;
         let client_guard = app_state.r2_client.lock().await;
         
@@ -272,7 +519,7 @@ async fn download_file_with_progress(
             .as_ref()
             .ok_or("Not connected to R2")?;
         
-        (client.client().clone(), client.bucket().to_string())
+        (client.client().clone(), client.bucket().to_string(), app_state.db.clone())
     };
 
     let file_name = remote_key.split('/').last().unwrap_or(&remote_key).to_string();
@@ -318,15 +565,17 @@ async fn download_file_with_progress(
         app_clone.emit("download-progress", &progress_event).ok();
     });
 
-    r2::operations::get_object_streaming(
+    let started_at = chrono::Utc::now();
+    let result = r2::operations::get_object_streaming(
         &client_clone,
         &bucket_clone,
         &remote_key,
         &local_path,
         Some(progress_callback),
     )
-    .await
-    .map_err(|e| e.to_string())?;
+    .await;
+    record_download(&db, &remote_key, &local_path, started_at, &result).await;
+    result.map_err(|e| e.to_string())?;
 
     // Emit completion
     let complete_progress = DownloadProgress {
@@ -373,11 +622,7 @@ async fn download_folder_as_zip(
     };
 
     // List all files in the folder
-    let prefix = if folder_path.ends_with('/') {
-        folder_path.clone()
-    } else {
-        format!("{}/", folder_path)
-    };
+    let prefix = paths::dir_prefix(&folder_path);
     
     let objects = r2::operations::list_objects(&client_clone, &bucket_clone, Some(&prefix))
         .await
@@ -434,12 +679,28 @@ async fn download_folder_as_zip(
             .send()
             .await
             .map_err(|e| format!("Failed to download {}: {}", file.key, e))?;
+
+        let expected = r2::checksum::ExpectedDigest::for_object(
+            response.e_tag(),
+            response.metadata()
+                .and_then(|m| m.get(r2::operations::SHA256_METADATA_KEY))
+                .map(|s| s.as_str()),
+        );
         
         let data = response.body
             .collect()
             .await
             .map_err(|e| format!("Failed to read file data: {}", e))?
             .into_bytes();
+
+        // Never put a corrupted file into the archive
+        let mut verifier = r2::checksum::StreamingVerifier::new(expected);
+        verifier.update(&data);
+        if let Err(mismatch) = verifier.finish(&file.key) {
+            drop(zip);
+            std::fs::remove_file(&local_path).ok();
+            return Err(mismatch.to_string());
+        }
         
         // Add to zip
         zip.start_file(relative_path, options)
@@ -562,6 +823,9 @@ async fn rename_file(
     old_key: String,
     new_key: String,
 ) -> Result<(), String> {
+    let new_key = paths::normalize_key(&new_key);
+    paths::check_key(&new_key).map_err(|e| e.to_string())?;
+
     let app_state = state.lock().await;
     let client_guard = app_state.r2_client.lock().await;
     
@@ -617,27 +881,51 @@ async fn cleanup_stalled_uploads(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
     max_age_hours: Option<i64>,
 ) -> Result<CleanupResult, String> {
-    let app_state = state.lock().await;
-    let client_guard = app_state.r2_client.lock().await;
-    
-    let client = client_guard
-        .as_ref()
-        .ok_or("Not connected to R2")?;
+    let (client, bucket, db, upload_manager) = {
+        let app_state = state.lock().await;
+        let client_guard = app_state.r2_client.lock().await;
 
-    // Default to 24 hours (1 day) if not specified
-    let threshold_hours = max_age_hours.unwrap_or(24);
+        let client = client_guard
+            .as_ref()
+            .ok_or("Not connected to R2")?;
 
-    let (cleaned_count, cleaned_uploads) = r2::operations::cleanup_stalled_uploads(
-        client.client(),
-        client.bucket(),
+        (
+            client.client().clone(),
+            client.bucket().to_string(),
+            app_state.db.clone(),
+            app_state.upload_manager.clone(),
+        )
+    };
+
+    // Default to the configured threshold if not specified
+    let threshold_hours = match max_age_hours {
+        Some(hours) => hours,
+        None => maintenance::MaintenanceSettings::load(&db)
+            .await
+            .map_err(|e| e.to_string())?
+            .stale_upload_max_age_hours,
+    };
+
+    let protected = upload_manager
+        .resumable_multipart_ids()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let result = maintenance::cleanup_bucket(
+        &db,
+        &client,
+        &bucket,
         threshold_hours,
+        &protected,
+        maintenance::Trigger::Manual,
     )
     .await
     .map_err(|e| format!("Failed to cleanup stalled uploads: {}", e))?;
 
     Ok(CleanupResult {
-        cleaned_count,
-        cleaned_uploads,
+        cleaned_count: result.cleaned.len() as i32,
+        cleaned_uploads: result.cleaned,
+        protected_count: result.protected as i32,
         threshold_hours,
     })
 }
@@ -646,9 +934,283 @@ async fn cleanup_stalled_uploads(
 struct CleanupResult {
     cleaned_count: i32,
     cleaned_uploads: Vec<r2::operations::StalledUpload>,
+    protected_count: i32,
     threshold_hours: i64,
 }
 
+#[tauri::command]
+async fn get_maintenance_settings(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<maintenance::MaintenanceSettings, String> {
+    let db = state.lock().await.db.clone();
+    maintenance::MaintenanceSettings::load(&db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_maintenance_settings(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    settings: maintenance::MaintenanceSettings,
+) -> Result<(), String> {
+    let db = state.lock().await.db.clone();
+    settings.save(&db).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_settings(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<utils::AppSettings, String> {
+    Ok(state.lock().await.settings.current())
+}
+
+/// Validate and save `settings`, then apply them to new transfers straight away
+#[tauri::command]
+async fn update_settings(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    settings: utils::AppSettings,
+) -> Result<utils::AppSettings, String> {
+    let (db, live) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.settings.clone())
+    };
+    settings.save(&db).await.map_err(|e| e.to_string())?;
+    live.replace(settings.clone());
+    app.emit(settings::CHANGED_EVENT, &settings).ok();
+    Ok(settings)
+}
+
+/// Prune old history, compact the database and check its integrity now
+#[tauri::command]
+async fn run_database_maintenance(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<maintenance::DatabaseMaintenance, String> {
+    let db = state.lock().await.db.clone();
+    let settings = maintenance::MaintenanceSettings::load(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+    maintenance::run_database_maintenance(&db, settings.history_retention_days, maintenance::Trigger::Manual)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_database_diagnostics(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<utils::DatabaseDiagnostics, String> {
+    let db = state.lock().await.db.clone();
+    db.diagnostics().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_maintenance_log(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    limit: Option<i64>,
+) -> Result<Vec<utils::MaintenanceLogEntry>, String> {
+    let db = state.lock().await.db.clone();
+    db.get_maintenance_log(limit.unwrap_or(50))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Parts already stored for a stalled upload, and the local file it was started from if known
+#[derive(serde::Serialize)]
+struct StalledUploadDetails {
+    upload_id: String,
+    key: String,
+    parts: Vec<r2::operations::UploadedPart>,
+    stored_bytes: i64,
+    local_path: Option<String>,
+}
+
+#[tauri::command]
+async fn get_stalled_upload_parts(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    key: String,
+    upload_id: String,
+) -> Result<StalledUploadDetails, String> {
+    let (client, bucket, upload_manager) = {
+        let app_state = state.lock().await;
+        let client_guard = app_state.r2_client.lock().await;
+
+        let client = client_guard
+            .as_ref()
+            .ok_or("Not connected to R2")?;
+
+        (client.client().clone(), client.bucket().to_string(), app_state.upload_manager.clone())
+    };
+
+    let parts = r2::operations::list_parts(&client, &bucket, &key, &upload_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let local_path = upload_manager
+        .find_by_multipart_id(&upload_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|(_, path)| path);
+
+    Ok(StalledUploadDetails {
+        stored_bytes: parts.iter().map(|p| p.size).sum(),
+        upload_id,
+        key,
+        parts,
+        local_path,
+    })
+}
+
+/// Adopt a stalled multipart upload: check its stored parts against `local_path`,
+/// upload whatever is missing and complete it, instead of aborting and starting over
+#[tauri::command]
+async fn resume_stalled_upload(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    key: String,
+    upload_id: String,
+    local_path: String,
+) -> Result<String, String> {
+    let (client, bucket, db, upload_manager, settings) = {
+        let app_state = state.lock().await;
+        let client_guard = app_state.r2_client.lock().await;
+
+        let client = client_guard
+            .as_ref()
+            .ok_or("Not connected to R2")?;
+
+        (
+            client.client().clone(),
+            client.bucket().to_string(),
+            app_state.db.clone(),
+            app_state.upload_manager.clone(),
+            app_state.settings.current(),
+        )
+    };
+
+    let stored = r2::operations::list_parts(&client, &bucket, &key, &upload_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let upload = r2::multipart::MultipartUpload::from_existing(
+        client.clone(),
+        bucket.clone(),
+        key.clone(),
+        upload_id.clone(),
+        &stored,
+    )
+    .with_concurrency(settings.parallel_uploads);
+
+    let file_size = tokio::fs::metadata(&local_path)
+        .await
+        .map_err(|e| e.to_string())?
+        .len() as i64;
+
+    // Reuse the original upload record when we have one
+    let record_id = match upload_manager.find_by_multipart_id(&upload_id).await.map_err(|e| e.to_string())? {
+        Some((id, _)) => id,
+        None => {
+            let bucket_id = db.get_current_bucket_id()
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("No bucket configured")?;
+            let id = upload_manager
+                .create_upload(bucket_id, &local_path, &key, file_size, settings.chunk_size as i64)
+                .await
+                .map_err(|e| e.to_string())?;
+            upload_manager
+                .set_multipart_upload_id(&id, &upload_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            id
+        }
+    };
+    upload_manager
+        .update_upload_status(&record_id, "uploading", None, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let file_name = match paths::file_name(&local_path) {
+        "" => "unknown".to_string(),
+        name => name.to_string(),
+    };
+
+    let progress_for = {
+        let record_id = record_id.clone();
+        let file_name = file_name.clone();
+        let local_path = local_path.clone();
+        let key = key.clone();
+        move |uploaded: i64, speed: f64, eta: i64, status: UploadStatus, error_message: Option<String>| UploadProgress {
+            id: record_id.clone(),
+            file_name: file_name.clone(),
+            file_path: local_path.clone(),
+            remote_path: key.clone(),
+            total_size: file_size,
+            uploaded_size: uploaded,
+            progress: if file_size > 0 { (uploaded as f64 / file_size as f64) * 100.0 } else { 0.0 },
+            speed,
+            eta,
+            status,
+            error_message,
+        }
+    };
+
+    let stored_bytes: i64 = stored.iter().map(|p| p.size).sum();
+    app.emit("upload-progress", &progress_for(stored_bytes, 0.0, 0, UploadStatus::Uploading, None)).ok();
+
+    let fail = |err_msg: String| {
+        let um = upload_manager.clone();
+        let id = record_id.clone();
+        let app = app.clone();
+        let event = progress_for(stored_bytes, 0.0, 0, UploadStatus::Failed, Some(err_msg.clone()));
+        async move {
+            um.update_upload_status(&id, "failed", None, Some(&err_msg)).await.ok();
+            app.emit("upload-progress", &event).ok();
+            err_msg
+        }
+    };
+
+    let parts = {
+        let app = app.clone();
+        let progress_for = progress_for.clone();
+        // Stored parts are kept on failure so the upload can be adopted again
+        match upload
+            .resume_file_concurrent(&local_path, &stored, move |info| {
+                let event = progress_for(info.uploaded_bytes, info.speed_bytes_per_sec, info.eta_seconds, UploadStatus::Uploading, None);
+                app.emit("upload-progress", &event).ok();
+            })
+            .await
+        {
+            Ok(parts) => parts,
+            Err(e) => return Err(fail(e.to_string()).await),
+        }
+    };
+
+    let part_count = parts.len();
+    let etag = match upload.complete(parts).await {
+        Ok(etag) => etag,
+        Err(e) => return Err(fail(format!("Failed to complete multipart upload: {}", e)).await),
+    };
+
+    let verification = upload.verify(part_count, &etag).await.unwrap_or_else(|e| {
+        log::warn!("Could not verify resumed upload of {}: {}", key, e);
+        UploadVerification::Unverified
+    });
+    upload_manager
+        .set_upload_verification(&record_id, Some(&etag), verification)
+        .await
+        .map_err(|e| e.to_string())?;
+    if verification == UploadVerification::Mismatch {
+        return Err(fail("Uploaded object does not match the local file".to_string()).await);
+    }
+
+    upload_manager
+        .update_upload_status(&record_id, "completed", Some(file_size), None)
+        .await
+        .map_err(|e| e.to_string())?;
+    app.emit("upload-progress", &progress_for(file_size, 0.0, 0, UploadStatus::Completed, None)).ok();
+
+    Ok(record_id)
+}
+
 #[tauri::command]
 async fn abort_stalled_upload(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
@@ -692,9 +1254,31 @@ async fn upload_file_with_progress(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
     local_path: String,
     remote_key: String,
+    conflict_policy: Option<String>,
+    batch_id: Option<String>,
+    skip_unchanged: Option<bool>,
 ) -> Result<String, String> {
-    // Clone what we need before the async block
-    let (client_clone, bucket_clone, upload_id, file_size, file_name, upload_manager) = {
+    upload_tracked_file(app, state, local_path, remote_key, conflict_policy, batch_id, skip_unchanged)
+        .await
+        .map(|uploaded| uploaded.id)
+        .map_err(|e| e.to_string())
+}
+
+/// Upload one file, returning the upload's id and what it sent. Sync tells a file that changed
+/// mid-upload apart from other failures by the error's variant.
+pub(crate) async fn upload_tracked_file(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    local_path: String,
+    remote_key: String,
+    conflict_policy: Option<String>,
+    batch_id: Option<String>,
+    skip_unchanged: Option<bool>,
+) -> Result<Uploaded, UploadError> {
+    let remote_key = paths::normalize_key(&remote_key);
+    paths::check_key(&remote_key).map_err(|e| e.to_string())?;
+
+    let (client_clone, bucket_clone, db, settings) = {
         let app_state = state.lock().await;
         let client_guard = app_state.r2_client.lock().await;
         
@@ -702,6 +1286,111 @@ async fn upload_file_with_progress(
             .as_ref()
             .ok_or("Not connected to R2")?;
 
+        (client.client().clone(), client.bucket().to_string(), app_state.db.clone(), app_state.settings.current())
+    };
+
+    let bucket_id = db.get_current_bucket_id()
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("No bucket configured")?;
+
+    // In "only upload changed files" mode, leave identical remote copies alone
+    let mut hashes = None;
+    if skip_unchanged.unwrap_or(false) {
+        let check = upload::fingerprint::check_unchanged(
+            &db,
+            &client_clone,
+            &bucket_clone,
+            Some(bucket_id),
+            &local_path,
+            &remote_key,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+        if check.is_unchanged() {
+            log::info!("Skipping unchanged {} ({})", local_path, check.reason());
+            return Ok(Uploaded::skipped(emit_skipped_upload(&app, &local_path, &remote_key, "unchanged")));
+        }
+        hashes = check.hashes;
+    }
+
+    let upload_manager = state.lock().await.upload_manager.clone();
+    let setup = UploadSetup { client: client_clone, bucket: bucket_clone, bucket_id, db, settings, upload_manager };
+
+    // Decide where the upload goes before anything is recorded or sent
+    let mut policy = match conflict_policy.as_deref() {
+        Some(p) => ConflictPolicy::from_setting(p),
+        None => default_conflict_policy(&setup.db).await,
+    };
+    loop {
+        let target = resolve_upload_target(
+            &app,
+            &setup.client,
+            &setup.bucket,
+            &local_path,
+            &remote_key,
+            policy,
+            batch_id.as_deref(),
+        )
+        .await?;
+        let (key, if_absent) = match target {
+            UploadTarget::Key { key, if_absent } => (key, if_absent),
+            UploadTarget::Skip => {
+                let reason = format!("{} already exists", remote_key);
+                return Ok(Uploaded::skipped(emit_skipped_upload(&app, &local_path, &remote_key, &reason)));
+            }
+        };
+
+        let renamed = key != remote_key;
+        match upload_to_key(&app, &setup, &local_path, key, if_absent, hashes.clone()).await {
+            // Another upload took the free name first; look for the next one
+            Err(UploadError::Exists(taken)) if renamed => {
+                log::info!("{} was taken during the upload, renaming again", taken.0);
+                policy = ConflictPolicy::Rename;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The connection and settings an upload runs with
+struct UploadSetup {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    bucket_id: i64,
+    db: Arc<Database>,
+    settings: utils::AppSettings,
+    upload_manager: Arc<UploadManager>,
+}
+
+/// Upload one file to the key its conflict policy settled on
+async fn upload_to_key(
+    app: &tauri::AppHandle,
+    setup: &UploadSetup,
+    local_path: &str,
+    remote_key: String,
+    if_absent: bool,
+    hashes: Option<upload::fingerprint::FileHashes>,
+) -> Result<Uploaded, UploadError> {
+    let UploadSetup { client, bucket, db, settings, .. } = setup;
+    let (app, local_path) = (app.clone(), local_path.to_string());
+
+    // Stat before hashing so an edit made while hashing shows up as a change next time
+    let local_stat = upload::fingerprint::LocalStat::read(&local_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    let hashes = match hashes {
+        Some(hashes) => hashes,
+        None => upload::fingerprint::hash_file(&local_path)
+            .await
+            .map_err(|e| e.to_string())?,
+    };
+
+    // Clone what we need before the async block
+    let (upload_id, file_size, file_name, upload_manager) = {
+        let upload_manager = setup.upload_manager.clone();
+
         // Get file size
         let metadata = tokio::fs::metadata(&local_path)
             .await
@@ -709,33 +1398,27 @@ async fn upload_file_with_progress(
         let file_size = metadata.len() as i64;
 
         // Create upload record
-        let upload_id = app_state.upload_manager
-            .create_upload(1, &local_path, &remote_key, file_size, 10 * 1024 * 1024)
+        let upload_id = upload_manager
+            .create_upload(setup.bucket_id, &local_path, &remote_key, file_size, settings.chunk_size as i64)
             .await
             .map_err(|e| e.to_string())?;
 
         // Update status to uploading
-        app_state.upload_manager
+        upload_manager
             .update_upload_status(&upload_id, "uploading", None, None)
             .await
             .map_err(|e| e.to_string())?;
+        upload_manager
+            .set_upload_sha256(&upload_id, &hashes.sha256)
+            .await
+            .map_err(|e| e.to_string())?;
 
-        // Get file name - normalize path separators for Windows compatibility
-        let normalized_path = local_path.replace('\\', "/");
-        let file_name = std::path::Path::new(&normalized_path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string();
+        let file_name = match paths::file_name(&local_path) {
+            "" => "unknown".to_string(),
+            name => name.to_string(),
+        };
 
-        (
-            client.client().clone(),
-            client.bucket().to_string(),
-            upload_id,
-            file_size,
-            file_name,
-            app_state.upload_manager.clone(),
-        )
+        (upload_id, file_size, file_name, upload_manager)
     };
 
     // Emit initial progress event
@@ -760,23 +1443,32 @@ async fn upload_file_with_progress(
     let local_path_clone = local_path.clone();
     let remote_key_clone = remote_key.clone();
 
-    // Use multipart upload for files > 100MB (with 8 concurrent uploads)
-    if file_size > 100 * 1024 * 1024 {
+    // Use multipart upload for files > 100MB
+    let (etag, verification) = if file_size > 100 * 1024 * 1024 {
         log::info!(
-            "Starting multipart upload for {} ({} MB) with 5MB chunks",
+            "Starting multipart upload for {} ({} MB) with {} MB chunks",
             file_name,
-            file_size / (1024 * 1024)
+            file_size / (1024 * 1024),
+            settings.chunk_size / (1024 * 1024)
         );
         
         let app_clone = app.clone();
-        let upload = r2::multipart::MultipartUpload::new(
-            client_clone.clone(),
-            bucket_clone.clone(),
+        let upload = r2::multipart::MultipartUpload::new_with_sha256(
+            client.clone(),
+            bucket.clone(),
             remote_key.clone(),
-            Some(5 * 1024 * 1024), // 5MB chunks for more frequent progress updates
+            Some(settings.chunk_size),
+            Some(&hashes.sha256),
         )
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())?
+        .with_if_absent(if_absent)
+        .with_concurrency(settings.parallel_uploads);
+
+        // Lets a stalled upload be matched back to this file later
+        if let Err(e) = upload_manager.set_multipart_upload_id(&upload_id, upload.upload_id()).await {
+            log::warn!("Failed to record multipart upload id for {}: {}", remote_key, e);
+        }
 
         // Use concurrent upload with speed/ETA tracking
         let parts = upload
@@ -819,10 +1511,10 @@ async fn upload_file_with_progress(
             })?;
 
         // Complete the multipart upload - CRITICAL step
-        upload
-            .complete(parts)
-            .await
-            .map_err(|e| {
+        let part_count = parts.len();
+        let etag = match upload.complete(parts).await {
+            Ok(etag) => etag,
+            Err(e) => {
                 let um = upload_manager.clone();
                 let id = upload_id.clone();
                 let err_msg = format!("Failed to complete multipart upload: {}", e);
@@ -830,12 +1522,19 @@ async fn upload_file_with_progress(
                     um.update_upload_status(&id, "failed", None, Some(&err_msg)).await.ok();
                 });
                 // Abort the upload on failure
-                let upload_ref = upload;
                 tauri::async_runtime::spawn(async move {
-                    upload_ref.abort().await.ok();
+                    upload.abort().await.ok();
                 });
-                e.to_string()
-            })?;
+                return Err(UploadError::from_transfer(e));
+            }
+        };
+
+        // Confirm the assembled object is made of exactly the parts we sent
+        let verification = upload.verify(part_count, &etag).await.unwrap_or_else(|e| {
+            log::warn!("Could not verify multipart upload of {}: {}", remote_key, e);
+            UploadVerification::Unverified
+        });
+        (etag, verification)
     } else {
         // For smaller files, emit a progress event before starting
         let progress_event = UploadProgress {
@@ -854,11 +1553,12 @@ async fn upload_file_with_progress(
         app.emit("upload-progress", &progress_event).ok();
 
         // For smaller files, use simple put_object with progress tracking
-        r2::operations::put_object_with_progress(
-            &client_clone,
-            &bucket_clone,
+        let etag = r2::operations::put_object_with_progress(
+            client,
+            bucket,
             &remote_key,
             &local_path,
+            &r2::operations::PutOptions { if_absent, sha256: Some(hashes.sha256.clone()) },
             {
                 let app = app.clone();
                 let upload_id = upload_id.clone();
@@ -892,33 +1592,235 @@ async fn upload_file_with_progress(
             tauri::async_runtime::spawn(async move {
                 um.update_upload_status(&id, "failed", None, Some(&err_msg)).await.ok();
             });
-            e.to_string()
+            UploadError::from_transfer(e)
         })?;
+
+        // A single-part ETag is the MD5 of the content
+        let verification = if r2::checksum::etags_match(&etag, &hashes.md5) {
+            UploadVerification::Verified
+        } else {
+            log::error!("ETag {} for {} does not match local MD5 {}", etag, remote_key, hashes.md5);
+            UploadVerification::Mismatch
+        };
+        (etag, verification)
+    };
+
+    // Compared with the stat taken before hashing, so any write during hashing or sending counts
+    let changed = upload::fingerprint::LocalStat::read(&local_path)
+        .await
+        .is_ok_and(|after| after.size != local_stat.size || after.mtime != local_stat.mtime);
+    let verification = if changed { UploadVerification::Mismatch } else { verification };
+
+    upload_manager
+        .set_upload_verification(&upload_id, Some(&etag), verification)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if verification == UploadVerification::Mismatch {
+        let error = if changed {
+            UploadError::Changed(ChangedDuringUpload(local_path.clone()))
+        } else {
+            UploadError::Failed("Uploaded object does not match the local file".to_string())
+        };
+        let err_msg = error.to_string();
+        upload_manager
+            .update_upload_status(&upload_id, "failed", None, Some(&err_msg))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let progress = UploadProgress {
+            id: upload_id.clone(),
+            file_name,
+            file_path: local_path.clone(),
+            remote_path: remote_key.clone(),
+            total_size: file_size,
+            uploaded_size: file_size,
+            progress: 100.0,
+            speed: 0.0,
+            eta: 0,
+            status: UploadStatus::Failed,
+            error_message: Some(err_msg.clone()),
+        };
+        app.emit("upload-progress", &progress).ok();
+        return Err(error);
     }
 
-    // Mark as completed
+    // Mark as completed
+    upload_manager
+        .update_upload_status(&upload_id, "completed", Some(file_size), None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Remember what was uploaded so unchanged files can be skipped next time.
+    // Multipart ETags aren't content hashes; the stored SHA-256 identifies those.
+    let fingerprint = db::FileFingerprint {
+        remote_size: file_size,
+        etag: Some(etag),
+        sha256: Some(hashes.sha256.clone()),
+        local_size: local_stat.size,
+        local_mtime: local_stat.mtime,
+    };
+    if let Err(e) = db.save_file_fingerprint(setup.bucket_id, &remote_key, &fingerprint).await {
+        log::warn!("Failed to cache fingerprint for {}: {}", remote_key, e);
+    }
+
+    // Emit completion event
+    let progress = UploadProgress {
+        id: upload_id.clone(),
+        file_name,
+        file_path: local_path.clone(),
+        remote_path: remote_key.clone(),
+        total_size: file_size,
+        uploaded_size: file_size,
+        progress: 100.0,
+        speed: 0.0,
+        eta: 0,
+        status: UploadStatus::Completed,
+        error_message: None,
+    };
+    app.emit("upload-progress", &progress).ok();
+
+    Ok(Uploaded { id: upload_id, sent: Some((local_stat, hashes.sha256)) })
+}
+
+/// Finished uploads, newest first, including whether each was verified
+#[tauri::command]
+async fn get_upload_history(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    limit: Option<i64>,
+) -> Result<Vec<UploadHistoryEntry>, String> {
+    let upload_manager = state.lock().await.upload_manager.clone();
+    upload_manager
+        .get_upload_history(limit.unwrap_or(100))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// One page of finished transfers matching `filter`, newest first
+#[tauri::command]
+async fn query_transfer_history(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    filter: Option<utils::HistoryFilter>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+) -> Result<utils::HistoryPage, String> {
+    let upload_manager = state.lock().await.upload_manager.clone();
+    upload_manager
+        .query_history(&filter.unwrap_or_default(), offset.unwrap_or(0), limit.unwrap_or(100))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Write the transfers matching `filter` to `file_path` as CSV or JSON Lines, returning the
+/// number of rows written
+#[tauri::command]
+async fn export_transfer_history(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    filter: Option<utils::HistoryFilter>,
+    format: upload::export::ExportFormat,
+    file_path: String,
+) -> Result<u64, String> {
+    let upload_manager = state.lock().await.upload_manager.clone();
     upload_manager
-        .update_upload_status(&upload_id, "completed", Some(file_size), None)
+        .export_history(&filter.unwrap_or_default(), format, std::path::Path::new(&file_path))
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())
+}
 
-    // Emit completion event
-    let progress = UploadProgress {
-        id: upload_id.clone(),
-        file_name,
-        file_path: local_path.clone(),
-        remote_path: remote_key.clone(),
-        total_size: file_size,
-        uploaded_size: file_size,
-        progress: 100.0,
-        speed: 0.0,
-        eta: 0,
-        status: UploadStatus::Completed,
-        error_message: None,
+/// Write every object under `prefix` in the connected bucket to `file_path`, continuing an
+/// interrupted export of the same prefix and format. Progress goes out as `inventory-progress`.
+#[tauri::command]
+async fn export_bucket_inventory(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    prefix: Option<String>,
+    format: inventory::InventoryFormat,
+    file_path: String,
+    include_content_type: Option<bool>,
+) -> Result<inventory::InventorySummary, String> {
+    let (client, bucket) = {
+        let app_state = state.lock().await;
+        let client_guard = app_state.r2_client.lock().await;
+        let client = client_guard.as_ref().ok_or("Not connected to R2")?;
+        (client.client().clone(), client.bucket().to_string())
     };
-    app.emit("upload-progress", &progress).ok();
 
-    Ok(upload_id)
+    let source = inventory::InventorySource {
+        bucket,
+        prefix: prefix.filter(|p| !p.is_empty()),
+        format,
+        content_types: include_content_type.unwrap_or(false),
+    };
+    inventory::export(&client, source, std::path::Path::new(&file_path), |progress| {
+        app.emit(inventory::PROGRESS_EVENT, progress).ok();
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Success rate, throughput and bytes per day over the transfers matching `filter`
+#[tauri::command]
+async fn get_transfer_stats(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    filter: Option<utils::HistoryFilter>,
+) -> Result<utils::HistoryStats, String> {
+    let upload_manager = state.lock().await.upload_manager.clone();
+    upload_manager
+        .history_stats(&filter.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Dry run of an "only upload changed files" upload: report what would be sent and what skipped
+#[tauri::command]
+async fn preview_upload(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    files: Vec<UploadPlanItem>,
+) -> Result<UploadPreview, String> {
+    let (client, bucket, db) = {
+        let app_state = state.lock().await;
+        let client_guard = app_state.r2_client.lock().await;
+
+        let client = client_guard
+            .as_ref()
+            .ok_or("Not connected to R2")?;
+
+        (client.client().clone(), client.bucket().to_string(), app_state.db.clone())
+    };
+    let bucket_id = db.get_current_bucket_id().await.map_err(|e| e.to_string())?;
+
+    let mut preview = UploadPreview::default();
+    for file in files {
+        let check = upload::fingerprint::check_unchanged(
+            &db,
+            &client,
+            &bucket,
+            bucket_id,
+            &file.local_path,
+            &file.remote_key,
+        )
+        .await
+        .map_err(|e| format!("{}: {}", file.local_path, e))?;
+
+        let upload = !check.is_unchanged();
+        if upload {
+            preview.upload_count += 1;
+            preview.upload_bytes += check.local.size;
+        } else {
+            preview.skip_count += 1;
+            preview.skip_bytes += check.local.size;
+        }
+
+        preview.entries.push(UploadPreviewEntry {
+            local_path: file.local_path,
+            remote_key: file.remote_key,
+            size: check.local.size,
+            upload,
+            reason: check.reason().to_string(),
+        });
+    }
+
+    Ok(preview)
 }
 
 #[tauri::command]
@@ -1016,7 +1918,34 @@ async fn retry_upload(
     };
     
     // Start a new upload with the same paths
-    upload_file_with_progress(app, state, local_path, remote_key).await
+    upload_file_with_progress(app, state, local_path, remote_key, None, None, None).await
+}
+
+/// Tell the frontend an upload was skipped, e.g. because the remote key already exists.
+/// Returns the id used for the (unpersisted) progress event.
+fn emit_skipped_upload(app: &tauri::AppHandle, local_path: &str, remote_key: &str, reason: &str) -> String {
+    let id = uuid::Uuid::new_v4().to_string();
+    let file_name = match paths::file_name(local_path) {
+        "" => "unknown".to_string(),
+        name => name.to_string(),
+    };
+
+    let progress = UploadProgress {
+        id: id.clone(),
+        file_name,
+        file_path: local_path.to_string(),
+        remote_path: remote_key.to_string(),
+        total_size: 0,
+        uploaded_size: 0,
+        progress: 0.0,
+        speed: 0.0,
+        eta: 0,
+        status: UploadStatus::Cancelled,
+        error_message: Some(format!("Skipped: {}", reason)),
+    };
+    app.emit("upload-progress", &progress).ok();
+
+    id
 }
 
 #[tauri::command]
@@ -1030,12 +1959,8 @@ async fn create_folder(
         .as_ref()
         .ok_or("Not connected to R2")?;
 
-    // Ensure path ends with /
-    let folder_key = if folder_path.ends_with('/') {
-        folder_path.clone()
-    } else {
-        format!("{}/", folder_path)
-    };
+    let folder_key = paths::dir_prefix(&paths::normalize_key(&folder_path));
+    paths::check_key(&folder_key).map_err(|e| e.to_string())?;
 
     // Create an empty object with trailing slash to mark as folder
     r2::operations::put_object_from_bytes(
@@ -1222,13 +2147,31 @@ async fn import_config(
 }
 
 /// Sync folder data structure
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct SyncFolder {
     pub id: i64,
     pub local_path: String,
     pub remote_path: String,
+    pub sync_mode: utils::SyncMode,
     pub enabled: bool,
     pub last_sync: Option<String>,
+    /// How often remote changes are picked up by two-way and download-only folders,
+    /// and how often interval-scheduled folders are scanned
+    pub poll_interval_secs: i64,
+    /// Download-only: delete local files whose object was removed from the bucket
+    pub delete_removed: bool,
+    pub deletion_policy: utils::DeletionPolicy,
+    /// A pass deleting more files than this has its deletions held for confirmation
+    pub deletion_max_files: i64,
+    /// A pass deleting more than this percentage of the folder has its deletions held
+    pub deletion_max_percent: i64,
+    pub schedule: utils::ScheduleKind,
+    /// Five-field cron expression for cron schedules
+    pub schedule_cron: Option<String>,
+    /// When the scheduler next starts a full pass; `None` while paused or when only changes trigger one
+    pub next_run_at: Option<String>,
+    /// How long a changed file has to stay the same size and age before it is synced
+    pub settle_secs: i64,
 }
 
 /// Get all sync folders
@@ -1248,11 +2191,60 @@ async fn add_sync_folder(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
     local_path: String,
     remote_path: String,
+    sync_mode: Option<utils::SyncMode>,
+    poll_interval_secs: Option<i64>,
+    delete_removed: Option<bool>,
 ) -> Result<i64, String> {
-    let app_state = state.lock().await;
-    app_state.db.add_sync_folder(&local_path, &remote_path)
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    let sync_mode = sync_mode.unwrap_or(utils::SyncMode::UploadOnly);
+    let poll_interval_secs = poll_interval_secs.unwrap_or(db::DEFAULT_POLL_INTERVAL_SECS);
+    validate_poll_interval(poll_interval_secs)?;
+
+    let folder_id = db.add_sync_folder(&local_path, &remote_path, sync_mode)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+    db.set_sync_folder_options(folder_id, poll_interval_secs, delete_removed.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine.reload().await.map_err(|e| e.to_string())?;
+    Ok(folder_id)
+}
+
+/// Shortest allowed remote poll interval, to keep listing requests reasonable
+const MIN_POLL_INTERVAL_SECS: i64 = 30;
+
+fn validate_poll_interval(secs: i64) -> Result<(), String> {
+    if secs < MIN_POLL_INTERVAL_SECS {
+        return Err(format!("Poll interval must be at least {} seconds", MIN_POLL_INTERVAL_SECS));
+    }
+    Ok(())
+}
+
+/// Longest allowed settle time
+const MAX_SETTLE_SECS: i64 = 600;
+
+/// Change how often a folder polls for remote changes and whether a mirror deletes removed files
+#[tauri::command]
+async fn update_sync_folder_options(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+    poll_interval_secs: i64,
+    delete_removed: bool,
+) -> Result<(), String> {
+    validate_poll_interval(poll_interval_secs)?;
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    db.set_sync_folder_options(folder_id, poll_interval_secs, delete_removed)
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine.reload().await.map_err(|e| e.to_string())
 }
 
 /// Remove a sync folder
@@ -1261,10 +2253,15 @@ async fn remove_sync_folder(
     state: tauri::State<'_, Arc<Mutex<AppState>>>,
     folder_id: i64,
 ) -> Result<(), String> {
-    let app_state = state.lock().await;
-    app_state.db.remove_sync_folder(folder_id)
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    db.remove_sync_folder(folder_id)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+    sync_engine.reload().await.map_err(|e| e.to_string())
 }
 
 /// Toggle sync folder enabled status
@@ -1274,12 +2271,265 @@ async fn toggle_sync_folder(
     folder_id: i64,
     enabled: bool,
 ) -> Result<(), String> {
-    let app_state = state.lock().await;
-    app_state.db.toggle_sync_folder(folder_id, enabled)
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    db.toggle_sync_folder(folder_id, enabled)
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine.reload().await.map_err(|e| e.to_string())
+}
+
+/// Upload anything new or changed in a sync folder right away
+#[tauri::command]
+async fn sync_folder_now(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+) -> Result<(), String> {
+    let sync_engine = state.lock().await.sync_engine.clone();
+    sync_engine.sync_now(folder_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Everything a full sync pass of a folder would do, without doing it
+#[tauri::command]
+async fn preview_sync_plan(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+) -> Result<utils::SyncPlan, String> {
+    let sync_engine = state.lock().await.sync_engine.clone();
+    sync_engine.preview(folder_id)
         .await
         .map_err(|e| e.to_string())
 }
 
+/// Carry out a previewed sync plan, skipping any path that changed since it was made
+#[tauri::command]
+async fn execute_sync_plan(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    plan: utils::SyncPlan,
+) -> Result<utils::PlanExecution, String> {
+    let sync_engine = state.lock().await.sync_engine.clone();
+    sync_engine.execute(&plan)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Unresolved two-way sync conflicts, for one folder or all of them
+#[tauri::command]
+async fn get_sync_conflicts(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: Option<i64>,
+) -> Result<Vec<utils::SyncConflict>, String> {
+    let db = state.lock().await.db.clone();
+    db.get_sync_conflicts(folder_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Mark a conflict as handled once the user has kept the copy they want
+#[tauri::command]
+async fn resolve_sync_conflict(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    conflict_id: i64,
+) -> Result<(), String> {
+    let db = state.lock().await.db.clone();
+    db.resolve_sync_conflict(conflict_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Set how a folder propagates deletions and when a pass's deletions are held for confirmation
+#[tauri::command]
+async fn set_sync_deletion_policy(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+    policy: utils::DeletionPolicy,
+    max_files: i64,
+    max_percent: i64,
+) -> Result<(), String> {
+    if max_files < 0 || !(0..=100).contains(&max_percent) {
+        return Err("Deletion threshold must be a file count and a percentage between 0 and 100".to_string());
+    }
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    db.set_sync_folder_deletion_policy(folder_id, policy, max_files, max_percent)
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine.reload().await.map_err(|e| e.to_string())
+}
+
+/// Set when a folder's full passes run: watched in real time, at its interval, or on a cron schedule
+#[tauri::command]
+async fn set_sync_folder_schedule(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+    schedule: utils::ScheduleKind,
+    cron: Option<String>,
+) -> Result<(), String> {
+    let cron = cron.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    if schedule == utils::ScheduleKind::Cron {
+        let expression = cron.as_deref().ok_or("A cron schedule needs an expression")?;
+        sync::schedule::CronSchedule::parse(expression).map_err(|e| e.to_string())?;
+    }
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    db.set_sync_folder_schedule(folder_id, schedule, cron.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine.reload().await.map_err(|e| e.to_string())
+}
+
+/// Set how long a changed file has to stop changing before it is synced; 0 syncs it right away
+#[tauri::command]
+async fn set_sync_folder_settle_time(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+    settle_secs: i64,
+) -> Result<(), String> {
+    if !(0..=MAX_SETTLE_SECS).contains(&settle_secs) {
+        return Err(format!("Settle time must be between 0 and {} seconds", MAX_SETTLE_SECS));
+    }
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    db.set_sync_folder_settle_secs(folder_id, settle_secs)
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine.reload().await.map_err(|e| e.to_string())
+}
+
+/// Deletions held back by the mass-deletion threshold, for one folder or all of them
+#[tauri::command]
+async fn get_held_deletions(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: Option<i64>,
+) -> Result<Vec<utils::HeldDeletion>, String> {
+    let db = state.lock().await.db.clone();
+    db.get_held_deletions(folder_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Files that failed to sync, for one folder or all of them: those waiting for a retry and those in quarantine
+#[tauri::command]
+async fn get_sync_errors(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: Option<i64>,
+) -> Result<Vec<utils::SyncError>, String> {
+    let db = state.lock().await.db.clone();
+    db.get_sync_errors(folder_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Retry a failed file right away, taking it out of quarantine
+#[tauri::command]
+async fn retry_sync_error(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    error_id: i64,
+) -> Result<(), String> {
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    let folder_id = db.retry_sync_error(error_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Sync error {} not found", error_id))?;
+    sync_engine.retry_errors(folder_id).await;
+    Ok(())
+}
+
+/// Leave a failed file out of sync until it is retried
+#[tauri::command]
+async fn ignore_sync_error(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    error_id: i64,
+) -> Result<(), String> {
+    let db = state.lock().await.db.clone();
+    db.ignore_sync_error(error_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Carry out a folder's held deletions on its next pass, which starts right away
+#[tauri::command]
+async fn confirm_held_deletions(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+) -> Result<usize, String> {
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    let approved = db.approve_held_deletions(folder_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine.sync_now(folder_id).await.map_err(|e| e.to_string())?;
+    Ok(approved)
+}
+
+/// Reject a folder's held deletions; the next pass restores the deleted copies
+#[tauri::command]
+async fn discard_held_deletions(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+) -> Result<usize, String> {
+    let (db, sync_engine) = {
+        let app_state = state.lock().await;
+        (app_state.db.clone(), app_state.sync_engine.clone())
+    };
+
+    let discarded = db.discard_held_deletions(folder_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    sync_engine.sync_now(folder_id).await.map_err(|e| e.to_string())?;
+    Ok(discarded)
+}
+
+/// Diff from a sync folder's most recent reconciliation scan
+#[tauri::command]
+async fn get_sync_report(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    folder_id: i64,
+) -> Result<Option<utils::ReconcileReport>, String> {
+    let sync_engine = state.lock().await.sync_engine.clone();
+    Ok(sync_engine.last_report(folder_id))
+}
+
+/// Latest status of every watched sync folder
+#[tauri::command]
+async fn get_sync_status(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+) -> Result<Vec<utils::SyncStatus>, String> {
+    let sync_engine = state.lock().await.sync_engine.clone();
+    Ok(sync_engine.statuses())
+}
+
+/// Sync status of local files and folders, for badges
+#[tauri::command]
+async fn get_file_sync_status(
+    state: tauri::State<'_, Arc<Mutex<AppState>>>,
+    paths: Vec<String>,
+) -> Result<Vec<utils::FileSyncStatus>, String> {
+    let sync_engine = state.lock().await.sync_engine.clone();
+    sync_engine.file_statuses(&paths).await.map_err(|e| e.to_string())
+}
+
 /// Get temp directory path
 #[tauri::command]
 async fn get_temp_dir() -> Result<String, String> {
@@ -1341,8 +2591,16 @@ async fn export_migration_backup(
         .map(|f| SyncFolderBackup {
             local_path: f.local_path,
             remote_path: f.remote_path,
-            sync_mode: "upload_only".to_string(),
+            sync_mode: f.sync_mode.as_str().to_string(),
             enabled: f.enabled,
+            poll_interval_secs: Some(f.poll_interval_secs),
+            delete_removed: f.delete_removed,
+            deletion_policy: Some(f.deletion_policy.as_str().to_string()),
+            deletion_max_files: Some(f.deletion_max_files),
+            deletion_max_percent: Some(f.deletion_max_percent),
+            schedule: Some(f.schedule.as_str().to_string()),
+            schedule_cron: f.schedule_cron,
+            settle_secs: Some(f.settle_secs),
         })
         .collect();
     
@@ -1442,8 +2700,44 @@ async fn import_migration_backup(
     
     // Import sync folders
     for folder in backup.sync_folders {
-        match app_state.db.add_sync_folder(&folder.local_path, &folder.remote_path).await {
-            Ok(_) => result.sync_folders_imported += 1,
+        let sync_mode = utils::SyncMode::parse(&folder.sync_mode).unwrap_or(utils::SyncMode::UploadOnly);
+        match app_state.db.add_sync_folder(&folder.local_path, &folder.remote_path, sync_mode).await {
+            Ok(folder_id) => {
+                if !folder.enabled {
+                    app_state.db.toggle_sync_folder(folder_id, false).await.ok();
+                }
+                let poll_interval_secs = folder.poll_interval_secs.unwrap_or(db::DEFAULT_POLL_INTERVAL_SECS);
+                app_state.db
+                    .set_sync_folder_options(folder_id, poll_interval_secs, folder.delete_removed)
+                    .await
+                    .ok();
+                let deletion_policy = folder.deletion_policy
+                    .as_deref()
+                    .and_then(utils::DeletionPolicy::parse)
+                    .unwrap_or(utils::DeletionPolicy::Trash);
+                app_state.db
+                    .set_sync_folder_deletion_policy(
+                        folder_id,
+                        deletion_policy,
+                        folder.deletion_max_files.unwrap_or(db::DEFAULT_DELETION_MAX_FILES),
+                        folder.deletion_max_percent.unwrap_or(db::DEFAULT_DELETION_MAX_PERCENT),
+                    )
+                    .await
+                    .ok();
+                let schedule = folder.schedule
+                    .as_deref()
+                    .and_then(utils::ScheduleKind::parse)
+                    .unwrap_or(utils::ScheduleKind::Realtime);
+                app_state.db
+                    .set_sync_folder_schedule(folder_id, schedule, folder.schedule_cron.as_deref())
+                    .await
+                    .ok();
+                app_state.db
+                    .set_sync_folder_settle_secs(folder_id, folder.settle_secs.unwrap_or(db::DEFAULT_SETTLE_SECS))
+                    .await
+                    .ok();
+                result.sync_folders_imported += 1
+            }
             Err(e) => eprintln!("Failed to import sync folder {}: {}", folder.local_path, e),
         }
     }
@@ -1466,6 +2760,12 @@ async fn import_migration_backup(
     // Note: We don't import upload history by default as paths may differ on new machine
     // But we track what was available
     result.upload_history_imported = backup.upload_history.len() as i32;
+
+    let sync_engine = app_state.sync_engine.clone();
+    drop(app_state);
+    if let Err(e) = sync_engine.reload().await {
+        log::warn!("Failed to restart sync: {}", e);
+    }
     
     Ok(result)
 }
@@ -1531,24 +2831,45 @@ pub fn run() {
                 Database::new(None).await.expect("Failed to initialize database")
             });
 
-            let upload_manager = UploadManager::new(db.pool().clone());
+            let db = Arc::new(db);
+            let upload_manager = Arc::new(UploadManager::new(db.pool().clone()));
+            let app_settings = tauri::async_runtime::block_on(utils::AppSettings::load(&db))
+                .unwrap_or_else(|e| {
+                    log::warn!("Failed to load settings, using defaults: {}", e);
+                    utils::AppSettings::default()
+                });
+
+            // Periodic stale upload cleanup across all buckets
+            maintenance::spawn(db.clone(), upload_manager.clone());
+
+            let sync_engine = Arc::new(sync::SyncEngine::new(app.handle().clone(), db.clone()));
 
             let app_state = Arc::new(Mutex::new(AppState {
-                db: Arc::new(db),
+                db,
                 r2_client: Arc::new(Mutex::new(None)),
-                upload_manager: Arc::new(upload_manager),
+                upload_manager,
                 active_uploads: Arc::new(Mutex::new(HashMap::new())),
+                conflict_resolver: Arc::new(ConflictResolver::new()),
+                sync_engine: sync_engine.clone(),
+                settings: Arc::new(settings::LiveSettings::new(app_settings)),
             }));
 
             app.manage(app_state);
 
+            // Start watching enabled sync folders
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = sync_engine.reload().await {
+                    log::error!("Failed to start sync: {}", e);
+                }
+            });
+
             // Create system tray menu
             let show_item = MenuItem::with_id(app, "show", "Show Window", true, None::<&str>)?;
             let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
             let menu = Menu::with_items(app, &[&show_item, &quit_item])?;
 
             // Build tray icon
-            let _tray = TrayIconBuilder::new()
+            let _tray = TrayIconBuilder::with_id(sync::status::TRAY_ID)
                 .menu(&menu)
                 .tooltip("Cloudflare Backup")
                 .icon(app.default_window_icon().unwrap().clone())
@@ -1615,6 +2936,7 @@ pub fn run() {
             import_migration_backup,
             preview_migration_backup,
             get_sync_folders,
+            update_sync_folder_options,
             add_sync_folder,
             remove_sync_folder,
             toggle_sync_folder,
@@ -1624,6 +2946,40 @@ pub fn run() {
             list_stalled_uploads,
             cleanup_stalled_uploads,
             abort_stalled_upload,
+            resolve_upload_conflict,
+            end_upload_batch,
+            preview_upload,
+            get_upload_history,
+            query_transfer_history,
+            export_transfer_history,
+            export_bucket_inventory,
+            get_transfer_stats,
+            get_stalled_upload_parts,
+            resume_stalled_upload,
+            get_maintenance_settings,
+            set_maintenance_settings,
+            get_settings,
+            update_settings,
+            get_maintenance_log,
+            run_database_maintenance,
+            get_database_diagnostics,
+            sync_folder_now,
+            preview_sync_plan,
+            execute_sync_plan,
+            get_sync_status,
+            get_file_sync_status,
+            get_sync_report,
+            get_sync_conflicts,
+            resolve_sync_conflict,
+            set_sync_deletion_policy,
+            set_sync_folder_schedule,
+            set_sync_folder_settle_time,
+            get_held_deletions,
+            confirm_held_deletions,
+            discard_held_deletions,
+            get_sync_errors,
+            retry_sync_error,
+            ignore_sync_error,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,420 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::db::Database;
+use crate::r2::operations::{cleanup_stalled_uploads, StaleCleanup};
+use crate::r2::R2Client;
+use crate::upload::UploadManager;
+
+pub const STALE_UPLOADS_TASK: &str = "stale_uploads";
+pub const DATABASE_TASK: &str = "database";
+
+const ENABLED_KEY: &str = "maintenance_enabled";
+const MAX_AGE_KEY: &str = "stale_upload_max_age_hours";
+const INTERVAL_KEY: &str = "maintenance_interval_hours";
+const RETENTION_KEY: &str = "history_retention_days";
+
+/// Database upkeep rewrites the whole file, so it runs at most daily whatever the interval
+const DATABASE_INTERVAL_HOURS: i64 = 24;
+
+/// Wait after launch before the first run, so cleanup doesn't compete with startup
+const STARTUP_DELAY: Duration = Duration::from_secs(5 * 60);
+/// How often the loop wakes to check whether a run is due; settings changes apply on the next tick
+const TICK: Duration = Duration::from_secs(5 * 60);
+
+/// Who started a maintenance run, as stored in the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Scheduled,
+    Manual,
+}
+
+impl Trigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Trigger::Scheduled => "scheduled",
+            Trigger::Manual => "manual",
+        }
+    }
+}
+
+/// User-configurable maintenance options, stored in the settings table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceSettings {
+    pub enabled: bool,
+    #[serde(rename = "staleUploadMaxAgeHours")]
+    pub stale_upload_max_age_hours: i64,
+    #[serde(rename = "intervalHours")]
+    pub interval_hours: i64,
+    /// Days finished uploads stay in the history; 0 keeps them forever
+    #[serde(rename = "historyRetentionDays")]
+    pub history_retention_days: i64,
+}
+
+impl Default for MaintenanceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            stale_upload_max_age_hours: 24,
+            interval_hours: 6,
+            history_retention_days: 90,
+        }
+    }
+}
+
+impl MaintenanceSettings {
+    pub async fn load(db: &Database) -> Result<Self> {
+        let defaults = Self::default();
+        let hours = |value: Option<String>, default: i64| {
+            value
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|h| *h >= 1)
+                .unwrap_or(default)
+        };
+
+        Ok(Self {
+            enabled: db
+                .get_setting(ENABLED_KEY)
+                .await?
+                .map(|v| v != "false")
+                .unwrap_or(defaults.enabled),
+            stale_upload_max_age_hours: hours(db.get_setting(MAX_AGE_KEY).await?, defaults.stale_upload_max_age_hours),
+            interval_hours: hours(db.get_setting(INTERVAL_KEY).await?, defaults.interval_hours),
+            history_retention_days: db
+                .get_setting(RETENTION_KEY)
+                .await?
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|days| *days >= 0)
+                .unwrap_or(defaults.history_retention_days),
+        })
+    }
+
+    pub async fn save(&self, db: &Database) -> Result<()> {
+        if self.stale_upload_max_age_hours < 1 || self.interval_hours < 1 {
+            bail!("Maintenance intervals must be at least one hour");
+        }
+        if self.history_retention_days < 0 {
+            bail!("History retention can't be negative");
+        }
+
+        db.set_setting(ENABLED_KEY, &self.enabled.to_string()).await?;
+        db.set_setting(MAX_AGE_KEY, &self.stale_upload_max_age_hours.to_string()).await?;
+        db.set_setting(INTERVAL_KEY, &self.interval_hours.to_string()).await?;
+        db.set_setting(RETENTION_KEY, &self.history_retention_days.to_string()).await?;
+        Ok(())
+    }
+}
+
+fn now() -> String {
+    // Same format as SQLite's CURRENT_TIMESTAMP, so log columns sort and parse alike
+    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Clean up one bucket and record the outcome in the maintenance log
+pub async fn cleanup_bucket(
+    db: &Database,
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    max_age_hours: i64,
+    protected: &HashSet<String>,
+    trigger: Trigger,
+) -> Result<StaleCleanup> {
+    let started_at = now();
+    let result = cleanup_stalled_uploads(client, bucket, max_age_hours, protected).await;
+
+    let logged = match &result {
+        Ok(cleanup) => {
+            let message = (cleanup.failed > 0)
+                .then(|| format!("{} uploads could not be aborted", cleanup.failed));
+            db.add_maintenance_log(
+                STALE_UPLOADS_TASK,
+                trigger.as_str(),
+                Some(bucket),
+                "success",
+                cleanup.cleaned.len() as i64,
+                cleanup.protected as i64,
+                message.as_deref(),
+                &started_at,
+            )
+            .await
+        }
+        Err(e) => {
+            db.add_maintenance_log(
+                STALE_UPLOADS_TASK,
+                trigger.as_str(),
+                Some(bucket),
+                "error",
+                0,
+                0,
+                Some(&e.to_string()),
+                &started_at,
+            )
+            .await
+        }
+    };
+    if let Err(e) = logged {
+        log::warn!("Failed to write maintenance log: {}", e);
+    }
+
+    result
+}
+
+/// Run stale upload cleanup against every configured bucket.
+/// Upload ids the local uploads table can still resume are never aborted.
+pub async fn run_stale_upload_cleanup(
+    db: &Database,
+    upload_manager: &UploadManager,
+    max_age_hours: i64,
+    trigger: Trigger,
+) -> Result<usize> {
+    let protected = upload_manager.resumable_multipart_ids().await?;
+    let mut cleaned = 0;
+
+    for (_, name, account_id, access_key_id, secret_access_key) in db.load_all_credentials().await? {
+        let client = match R2Client::new(&account_id, &access_key_id, &secret_access_key, &name).await {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("Skipping maintenance for bucket {}: {}", name, e);
+                continue;
+            }
+        };
+
+        // Failures are already logged per bucket; carry on with the rest
+        if let Ok(result) = cleanup_bucket(db, client.client(), client.bucket(), max_age_hours, &protected, trigger).await {
+            cleaned += result.cleaned.len();
+        }
+    }
+
+    Ok(cleaned)
+}
+
+/// Outcome of one database maintenance run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseMaintenance {
+    #[serde(rename = "prunedUploads")]
+    pub pruned_uploads: u64,
+    #[serde(rename = "orphanedChunks")]
+    pub orphaned_chunks: u64,
+    #[serde(rename = "reclaimedBytes")]
+    pub reclaimed_bytes: i64,
+    /// What `PRAGMA integrity_check` reported; empty when the database is sound
+    #[serde(rename = "integrityErrors")]
+    pub integrity_errors: Vec<String>,
+}
+
+/// Prune old upload history and orphaned chunks, compact the file and check its integrity,
+/// recording the outcome in the maintenance log
+pub async fn run_database_maintenance(db: &Database, retention_days: i64, trigger: Trigger) -> Result<DatabaseMaintenance> {
+    let started_at = now();
+    let result = database_maintenance(db, retention_days).await;
+
+    let logged = match &result {
+        Ok(run) => {
+            let corrupt = !run.integrity_errors.is_empty();
+            let message = if corrupt {
+                format!("Integrity check failed: {}", run.integrity_errors.join("; "))
+            } else {
+                format!("Removed {} orphaned chunks, reclaimed {} bytes", run.orphaned_chunks, run.reclaimed_bytes)
+            };
+            db.add_maintenance_log(
+                DATABASE_TASK,
+                trigger.as_str(),
+                None,
+                if corrupt { "error" } else { "success" },
+                run.pruned_uploads as i64,
+                0,
+                Some(&message),
+                &started_at,
+            )
+            .await
+        }
+        Err(e) => {
+            db.add_maintenance_log(DATABASE_TASK, trigger.as_str(), None, "error", 0, 0, Some(&e.to_string()), &started_at)
+                .await
+        }
+    };
+    if let Err(e) = logged {
+        log::warn!("Failed to write maintenance log: {}", e);
+    }
+
+    result
+}
+
+async fn database_maintenance(db: &Database, retention_days: i64) -> Result<DatabaseMaintenance> {
+    let pruned_uploads = if retention_days > 0 { db.prune_upload_history(retention_days).await? } else { 0 };
+    let orphaned_chunks = db.delete_orphaned_chunks().await?;
+    let reclaimed_bytes = db.vacuum().await?;
+    let integrity_errors = db.integrity_check().await?;
+    if !integrity_errors.is_empty() {
+        log::error!("Database integrity check failed: {:?}", integrity_errors);
+    }
+
+    Ok(DatabaseMaintenance { pruned_uploads, orphaned_chunks, reclaimed_bytes, integrity_errors })
+}
+
+/// Whether a scheduled run is due, given when the last one finished
+fn is_due(last_run: Option<Instant>, interval_hours: i64, now: Instant) -> bool {
+    match last_run {
+        Some(last) => now.duration_since(last) >= Duration::from_secs(interval_hours.max(1) as u64 * 3600),
+        None => true,
+    }
+}
+
+/// Start the background maintenance loop
+pub fn spawn(db: Arc<Database>, upload_manager: Arc<UploadManager>) {
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(STARTUP_DELAY).await;
+        let mut last_run: Option<Instant> = None;
+        let mut last_database_run: Option<Instant> = None;
+
+        loop {
+            let settings = match MaintenanceSettings::load(&db).await {
+                Ok(settings) => settings,
+                Err(e) => {
+                    log::warn!("Failed to load maintenance settings: {}", e);
+                    MaintenanceSettings::default()
+                }
+            };
+
+            if settings.enabled && is_due(last_run, settings.interval_hours, Instant::now()) {
+                match run_stale_upload_cleanup(&db, &upload_manager, settings.stale_upload_max_age_hours, Trigger::Scheduled).await {
+                    Ok(cleaned) => log::info!("Scheduled maintenance removed {} stalled uploads", cleaned),
+                    Err(e) => log::error!("Scheduled maintenance failed: {}", e),
+                }
+                last_run = Some(Instant::now());
+            }
+
+            if settings.enabled && is_due(last_database_run, DATABASE_INTERVAL_HOURS, Instant::now()) {
+                match run_database_maintenance(&db, settings.history_retention_days, Trigger::Scheduled).await {
+                    Ok(run) => log::info!(
+                        "Database maintenance pruned {} uploads and {} orphaned chunks",
+                        run.pruned_uploads,
+                        run.orphaned_chunks
+                    ),
+                    Err(e) => log::error!("Database maintenance failed: {}", e),
+                }
+                last_database_run = Some(Instant::now());
+            }
+
+            tokio::time::sleep(TICK).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_due() {
+        let start = Instant::now();
+        assert!(is_due(None, 6, start));
+        assert!(!is_due(Some(start), 6, start + Duration::from_secs(3600)));
+        assert!(is_due(Some(start), 6, start + Duration::from_secs(6 * 3600)));
+    }
+
+    #[tokio::test]
+    async fn test_settings_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(Some(temp_dir.path().join("test.db"))).await.unwrap();
+
+        assert_eq!(MaintenanceSettings::load(&db).await.unwrap(), MaintenanceSettings::default());
+
+        let settings = MaintenanceSettings {
+            enabled: false,
+            stale_upload_max_age_hours: 48,
+            interval_hours: 12,
+            history_retention_days: 0,
+        };
+        settings.save(&db).await.unwrap();
+        assert_eq!(MaintenanceSettings::load(&db).await.unwrap(), settings);
+
+        let invalid = MaintenanceSettings { interval_hours: 0, ..settings.clone() };
+        assert!(invalid.save(&db).await.is_err());
+        let invalid = MaintenanceSettings { history_retention_days: -1, ..settings };
+        assert!(invalid.save(&db).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_database_maintenance() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(Some(temp_dir.path().join("test.db"))).await.unwrap();
+        let pool = db.pool();
+
+        for (id, status, upload_id, age_days) in [
+            ("old-done", "completed", None, 100),
+            ("old-cancelled", "cancelled", None, 100),
+            ("old-resumable", "failed", Some("mp-1"), 100),
+            ("old-paused", "paused", Some("mp-2"), 100),
+            ("recent-done", "completed", None, 1),
+        ] {
+            sqlx::query(
+                "INSERT INTO uploads (id, file_path, remote_path, total_size, chunk_size, upload_id, status, started_at, completed_at)
+                 VALUES (?, '/a', 'a', 10, 5, ?, ?, datetime('now', ?), datetime('now', ?))",
+            )
+            .bind(id)
+            .bind(upload_id)
+            .bind(status)
+            .bind(format!("-{} days", age_days))
+            .bind(format!("-{} days", age_days))
+            .execute(pool)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO upload_chunks (upload_id, part_number, size) VALUES (?, 1, 5)")
+                .bind(id)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+        // A chunk written while foreign keys were off
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await.unwrap();
+        sqlx::query("INSERT INTO upload_chunks (upload_id, part_number, size) VALUES ('gone', 1, 5)")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await.unwrap();
+        drop(conn);
+
+        let run = run_database_maintenance(&db, 90, Trigger::Manual).await.unwrap();
+        assert_eq!(run.pruned_uploads, 2);
+        assert_eq!(run.orphaned_chunks, 1);
+        assert!(run.integrity_errors.is_empty());
+
+        let remaining: Vec<(String,)> = sqlx::query_as("SELECT id FROM uploads ORDER BY id").fetch_all(pool).await.unwrap();
+        let remaining: Vec<&str> = remaining.iter().map(|(id,)| id.as_str()).collect();
+        assert_eq!(remaining, ["old-paused", "old-resumable", "recent-done"]);
+        let (chunks,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM upload_chunks").fetch_one(pool).await.unwrap();
+        assert_eq!(chunks, 3);
+
+        let log = db.get_maintenance_log(1).await.unwrap();
+        assert_eq!(log[0].task, DATABASE_TASK);
+        assert_eq!(log[0].cleaned_count, 2);
+
+        // Keeping history forever prunes nothing
+        let run = run_database_maintenance(&db, 0, Trigger::Manual).await.unwrap();
+        assert_eq!(run.pruned_uploads, 0);
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(Some(temp_dir.path().join("test.db"))).await.unwrap();
+        db.set_setting("theme", "dark").await.unwrap();
+
+        let diagnostics = db.diagnostics().await.unwrap();
+        assert_eq!(diagnostics.journal_mode, "wal");
+        assert!(diagnostics.size_bytes > 0);
+        assert!(diagnostics.schema_version >= 1);
+        let settings = diagnostics.tables.iter().find(|t| t.table == "settings").unwrap();
+        assert_eq!(settings.rows, 1);
+        assert!(!diagnostics.tables.iter().any(|t| t.table == "_sqlx_migrations"));
+
+        let (foreign_keys,): (i64,) = sqlx::query_as("PRAGMA foreign_keys").fetch_one(db.pool()).await.unwrap();
+        assert_eq!(foreign_keys, 1);
+    }
+}
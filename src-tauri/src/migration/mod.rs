@@ -38,6 +38,22 @@ pub struct SyncFolderBackup {
     pub remote_path: String,
     pub sync_mode: String,
     pub enabled: bool,
+    #[serde(default)]
+    pub poll_interval_secs: Option<i64>,
+    #[serde(default)]
+    pub delete_removed: bool,
+    #[serde(default)]
+    pub deletion_policy: Option<String>,
+    #[serde(default)]
+    pub deletion_max_files: Option<i64>,
+    #[serde(default)]
+    pub deletion_max_percent: Option<i64>,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub schedule_cron: Option<String>,
+    #[serde(default)]
+    pub settle_secs: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -168,6 +184,14 @@ mod tests {
                     remote_path: "docs/".to_string(),
                     sync_mode: "upload_only".to_string(),
                     enabled: true,
+                    poll_interval_secs: Some(300),
+                    delete_removed: false,
+                    deletion_policy: Some("trash".to_string()),
+                    deletion_max_files: Some(50),
+                    deletion_max_percent: Some(10),
+                    schedule: Some("cron".to_string()),
+                    schedule_cron: Some("0 2 * * *".to_string()),
+                    settle_secs: Some(5),
                 },
             ],
             settings: vec![
@@ -0,0 +1,169 @@
+//! Upload bodies handed to the connection a block at a time, so a file is never held in memory,
+//! the upload limit holds over short windows, and callers can follow how much has been sent.
+
+use aws_smithy_types::body::SdkBody;
+use futures::{Stream, StreamExt};
+use http_body::{Body, Frame, SizeHint};
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::AsyncReadExt;
+use tokio::time::{Instant, Sleep};
+use tokio_util::bytes::Bytes;
+use tokio_util::io::ReaderStream;
+
+use super::throttle::{self, Throttle};
+
+/// Bytes sent at a time
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Called with how many bytes of the body have been sent so far
+pub type SentCallback = Arc<dyn Fn(u64) + Send + Sync>;
+
+type Blocks = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>>;
+
+/// The first `len` bytes of the file at `path`. Retries read the file again from the start.
+pub fn file_body(path: &Path, len: u64, on_sent: Option<SentCallback>) -> SdkBody {
+    let path = path.to_path_buf();
+    SdkBody::retryable(move || {
+        SdkBody::from_body_1_x(PacedBody::new(file_blocks(&path, len), len, on_sent.clone(), &throttle::UPLOADS))
+    })
+}
+
+/// Data already in memory, such as a multipart part
+pub fn bytes_body(data: Bytes) -> SdkBody {
+    SdkBody::retryable(move || {
+        SdkBody::from_body_1_x(PacedBody::new(bytes_blocks(data.clone()), data.len() as u64, None, &throttle::UPLOADS))
+    })
+}
+
+/// A file's blocks; an error opening it is reported on the first read
+fn file_blocks(path: &Path, len: u64) -> Blocks {
+    match std::fs::File::open(path) {
+        Ok(file) => Box::pin(ReaderStream::with_capacity(tokio::fs::File::from_std(file).take(len), BLOCK_SIZE)),
+        Err(error) => Box::pin(futures::stream::iter([Err(error)])),
+    }
+}
+
+fn bytes_blocks(data: Bytes) -> Blocks {
+    let blocks: Vec<_> = (0..data.len())
+        .step_by(BLOCK_SIZE)
+        .map(|start| Ok(data.slice(start..data.len().min(start + BLOCK_SIZE))))
+        .collect();
+    Box::pin(futures::stream::iter(blocks))
+}
+
+/// Sends each block once the throttle has room for it
+struct PacedBody {
+    blocks: Blocks,
+    len: u64,
+    sent: u64,
+    on_sent: Option<SentCallback>,
+    throttle: &'static Throttle,
+    /// A block waiting for its turn, and when that comes
+    waiting: Option<(Bytes, Pin<Box<Sleep>>)>,
+}
+
+impl PacedBody {
+    fn new(blocks: Blocks, len: u64, on_sent: Option<SentCallback>, throttle: &'static Throttle) -> Self {
+        Self { blocks, len, sent: 0, on_sent, throttle, waiting: None }
+    }
+}
+
+impl Body for PacedBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        let this = &mut *self;
+        let block = match this.waiting.take() {
+            Some((block, mut turn)) => {
+                if turn.as_mut().poll(cx).is_pending() {
+                    this.waiting = Some((block, turn));
+                    return Poll::Pending;
+                }
+                block
+            }
+            None => {
+                let block = match ready!(this.blocks.poll_next_unpin(cx)) {
+                    Some(Ok(block)) => block,
+                    other => return Poll::Ready(other.map(|result| result.map(Frame::data))),
+                };
+                let now = Instant::now();
+                match this.throttle.reserve(block.len() as u64, now) {
+                    Some(start) if start > now => {
+                        this.waiting = Some((block, Box::pin(tokio::time::sleep_until(start))));
+                        // Polled once so the timer wakes this task
+                        return self.poll_frame(cx);
+                    }
+                    _ => block,
+                }
+            }
+        };
+
+        this.sent += block.len() as u64;
+        if let Some(on_sent) = &this.on_sent {
+            on_sent(this.sent);
+        }
+        Poll::Ready(Some(Ok(Frame::data(block))))
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[tokio::test]
+    async fn test_file_body_streams_in_blocks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.bin");
+        let data: Vec<u8> = (0..BLOCK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let sent = Arc::new(AtomicU64::new(0));
+        let on_sent: SentCallback = {
+            let sent = sent.clone();
+            Arc::new(move |n| sent.store(n, Ordering::SeqCst))
+        };
+        // Bytes written after the upload started aren't sent
+        let body = file_body(&path, data.len() as u64 - 10, Some(on_sent));
+        assert_eq!(body.content_length(), Some(data.len() as u64 - 10));
+
+        let bytes = aws_sdk_s3::primitives::ByteStream::new(body).collect().await.unwrap().into_bytes();
+        assert_eq!(&bytes[..], &data[..data.len() - 10]);
+        assert_eq!(sent.load(Ordering::SeqCst), data.len() as u64 - 10);
+    }
+
+    #[tokio::test]
+    async fn test_blocks_are_paced_by_the_throttle() {
+        static THROTTLE: Throttle = Throttle::new();
+        // Three blocks at 20 blocks a second: the second waits 50 ms, the third 100 ms
+        THROTTLE.set_limit(BLOCK_SIZE as u64 * 20);
+        let data = Bytes::from(vec![7u8; BLOCK_SIZE * 3]);
+        let body = PacedBody::new(bytes_blocks(data.clone()), data.len() as u64, None, &THROTTLE);
+
+        let started = Instant::now();
+        let bytes = aws_sdk_s3::primitives::ByteStream::new(SdkBody::from_body_1_x(body))
+            .collect()
+            .await
+            .unwrap()
+            .into_bytes();
+        assert_eq!(bytes, data);
+        assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_fails_on_read() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let body = file_body(&temp_dir.path().join("missing"), 10, None);
+        assert!(aws_sdk_s3::primitives::ByteStream::new(body).collect().await.is_err());
+    }
+}
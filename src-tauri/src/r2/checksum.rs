@@ -0,0 +1,222 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+/// Returned when downloaded content does not hash to what the object says it should
+#[derive(Debug, thiserror::Error)]
+#[error("Checksum mismatch for {key}: expected {algorithm} {expected}, got {actual}")]
+pub struct ChecksumMismatch {
+    pub key: String,
+    pub algorithm: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Base64 SHA-256, the encoding used by the `x-amz-checksum-sha256` header
+pub fn sha256_base64(data: &[u8]) -> String {
+    BASE64.encode(Sha256::digest(data))
+}
+
+/// Hex SHA-256, the encoding stored in the `sha256` object metadata
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// SHA-256 of the first `len` bytes of a file, read in blocks so it is never held in memory;
+/// returns (hex, base64)
+pub async fn sha256_file(path: &Path, len: u64) -> std::io::Result<(String, String)> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)?.take(len);
+        let mut sha256 = Sha256::new();
+        let mut buffer = vec![0u8; 1024 * 1024];
+        loop {
+            let n = file.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            sha256.update(&buffer[..n]);
+        }
+        let digest = sha256.finalize();
+        Ok((format!("{:x}", digest), BASE64.encode(digest)))
+    })
+    .await?
+}
+
+/// Raw MD5 of one part, the building block of a multipart ETag
+pub fn md5_digest(data: &[u8]) -> [u8; 16] {
+    Md5::digest(data).into()
+}
+
+/// The ETag S3-compatible stores give a multipart object:
+/// hex MD5 of the concatenated part MD5s, followed by `-<part count>`
+pub fn multipart_etag(part_md5s: &[[u8; 16]]) -> String {
+    let mut md5 = Md5::new();
+    for digest in part_md5s {
+        md5.update(digest);
+    }
+    format!("{:x}-{}", md5.finalize(), part_md5s.len())
+}
+
+/// Compare ETags ignoring the surrounding quotes and hex case
+pub fn etags_match(a: &str, b: &str) -> bool {
+    a.trim_matches('"').eq_ignore_ascii_case(b.trim_matches('"'))
+}
+
+/// The digest a downloaded object is expected to have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpectedDigest {
+    /// Hex SHA-256 from the object's `sha256` metadata
+    Sha256(String),
+    /// Hex MD5 from a single-part ETag
+    Md5(String),
+    /// Multipart object uploaded without a `sha256` header; nothing to check against
+    Unknown,
+}
+
+impl ExpectedDigest {
+    /// Pick the strongest digest available for an object
+    pub fn for_object(etag: Option<&str>, sha256_metadata: Option<&str>) -> Self {
+        if let Some(sha256) = sha256_metadata.filter(|s| !s.is_empty()) {
+            return ExpectedDigest::Sha256(sha256.to_ascii_lowercase());
+        }
+
+        // Multipart ETags ("<hash>-<parts>") are not an MD5 of the content
+        match etag.map(|e| e.trim_matches('"')) {
+            Some(etag) if !etag.is_empty() && !etag.contains('-') => {
+                ExpectedDigest::Md5(etag.to_ascii_lowercase())
+            }
+            _ => ExpectedDigest::Unknown,
+        }
+    }
+}
+
+/// Hashes content as it streams past and checks it against an `ExpectedDigest` at the end
+pub struct StreamingVerifier {
+    expected: ExpectedDigest,
+    sha256: Sha256,
+    md5: Md5,
+}
+
+impl StreamingVerifier {
+    pub fn new(expected: ExpectedDigest) -> Self {
+        Self {
+            expected,
+            sha256: Sha256::new(),
+            md5: Md5::new(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self.expected {
+            ExpectedDigest::Sha256(_) => self.sha256.update(data),
+            ExpectedDigest::Md5(_) => self.md5.update(data),
+            ExpectedDigest::Unknown => {}
+        }
+    }
+
+    /// Returns `Ok(true)` if the content was verified, `Ok(false)` if there was nothing to verify against
+    pub fn finish(self, key: &str) -> Result<bool, ChecksumMismatch> {
+        let (algorithm, expected, actual) = match self.expected {
+            ExpectedDigest::Sha256(expected) => ("SHA-256", expected, format!("{:x}", self.sha256.finalize())),
+            ExpectedDigest::Md5(expected) => ("MD5", expected, format!("{:x}", self.md5.finalize())),
+            ExpectedDigest::Unknown => return Ok(false),
+        };
+
+        if expected == actual {
+            Ok(true)
+        } else {
+            Err(ChecksumMismatch {
+                key: key.to_string(),
+                algorithm,
+                expected,
+                actual,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    const HELLO_MD5: &str = "5eb63bbbe01eeed093cb22bb8f5acdc3";
+
+    #[test]
+    fn test_sha256_encodings() {
+        assert_eq!(sha256_hex(b"hello world"), HELLO_SHA256);
+        assert_eq!(sha256_base64(b"hello world"), "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=");
+    }
+
+    #[tokio::test]
+    async fn test_sha256_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("hello.txt");
+        std::fs::write(&path, b"hello world").unwrap();
+
+        let (hex, base64) = sha256_file(&path, 11).await.unwrap();
+        assert_eq!(hex, HELLO_SHA256);
+        assert_eq!(base64, sha256_base64(b"hello world"));
+        assert_eq!(sha256_file(&path, 5).await.unwrap().0, sha256_hex(b"hello"));
+    }
+
+    #[test]
+    fn test_multipart_etag() {
+        // "hello world" uploaded as the parts "hello " and "world"
+        let parts = [md5_digest(b"hello "), md5_digest(b"world")];
+        assert_eq!(multipart_etag(&parts), "e09e4fd6265b36115fe3db32df945d84-2");
+    }
+
+    #[test]
+    fn test_etags_match() {
+        assert!(etags_match("\"ABC-2\"", "abc-2"));
+        assert!(!etags_match("\"abc-2\"", "abc-3"));
+    }
+
+    #[test]
+    fn test_expected_digest_prefers_sha256() {
+        let expected = ExpectedDigest::for_object(Some("\"abc\""), Some("DEF"));
+        assert_eq!(expected, ExpectedDigest::Sha256("def".to_string()));
+    }
+
+    #[test]
+    fn test_expected_digest_from_etag() {
+        assert_eq!(
+            ExpectedDigest::for_object(Some("\"ABC\""), None),
+            ExpectedDigest::Md5("abc".to_string())
+        );
+        assert_eq!(ExpectedDigest::for_object(Some("\"abc-4\""), None), ExpectedDigest::Unknown);
+        assert_eq!(ExpectedDigest::for_object(None, None), ExpectedDigest::Unknown);
+    }
+
+    #[test]
+    fn test_verifier_accepts_matching_content() {
+        let mut verifier = StreamingVerifier::new(ExpectedDigest::Sha256(HELLO_SHA256.to_string()));
+        verifier.update(b"hello ");
+        verifier.update(b"world");
+        assert!(verifier.finish("k").unwrap());
+
+        let mut verifier = StreamingVerifier::new(ExpectedDigest::Md5(HELLO_MD5.to_string()));
+        verifier.update(b"hello world");
+        assert!(verifier.finish("k").unwrap());
+    }
+
+    #[test]
+    fn test_verifier_rejects_corrupt_content() {
+        let mut verifier = StreamingVerifier::new(ExpectedDigest::Md5(HELLO_MD5.to_string()));
+        verifier.update(b"hello w0rld");
+        let err = verifier.finish("docs/a.txt").unwrap_err();
+        assert_eq!(err.key, "docs/a.txt");
+        assert_eq!(err.algorithm, "MD5");
+    }
+
+    #[test]
+    fn test_verifier_unknown_is_unverified() {
+        let mut verifier = StreamingVerifier::new(ExpectedDigest::Unknown);
+        verifier.update(b"anything");
+        assert!(!verifier.finish("k").unwrap());
+    }
+}
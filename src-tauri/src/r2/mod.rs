@@ -1,5 +1,8 @@
 pub mod client;
 pub mod operations;
 pub mod multipart;
+pub mod checksum;
+pub mod body;
+pub mod throttle;
 
 pub use client::R2Client;
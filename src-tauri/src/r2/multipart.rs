@@ -1,7 +1,8 @@
 use aws_sdk_s3::Client;
 use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart};
 use anyhow::{Result, Context};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicI64, AtomicBool, Ordering};
@@ -9,15 +10,30 @@ use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::fs::File;
 use tokio::sync::{Mutex, Semaphore};
+use tokio_util::bytes::Bytes;
 use futures::future::join_all;
+use super::body::bytes_body;
+use super::checksum::{etags_match, md5_digest, multipart_etag, sha256_base64};
+use super::operations::{
+    head_object, is_not_implemented, is_precondition_failed, ObjectExists, UploadedPart, SHA256_METADATA_KEY,
+};
+use crate::utils::UploadVerification;
 
 const DEFAULT_CHUNK_SIZE: usize = 5 * 1024 * 1024; // 5MB for more frequent progress updates
 const MIN_CHUNK_SIZE: usize = 5 * 1024 * 1024; // 5MB minimum for S3
-const MAX_CONCURRENT_UPLOADS: usize = 8; // 8 concurrent chunk uploads
+const DEFAULT_CONCURRENT_UPLOADS: usize = 8; // 8 concurrent chunk uploads
 const MAX_RETRIES: u32 = 5; // Maximum retries per part
 const INITIAL_BACKOFF_MS: u64 = 1000; // 1 second initial backoff
 const MAX_BACKOFF_MS: u64 = 30000; // 30 seconds max backoff
 
+/// Digests of one uploaded part, kept for completing and verifying the upload
+#[derive(Debug, Clone)]
+struct PartDigest {
+    md5: [u8; 16],
+    /// Base64 SHA-256 sent as the part checksum, if checksums are enabled
+    sha256: Option<String>,
+}
+
 /// Progress information for uploads
 #[derive(Clone)]
 pub struct UploadProgressInfo {
@@ -33,8 +49,16 @@ pub struct MultipartUpload {
     key: String,
     upload_id: String,
     chunk_size: usize,
+    /// Parts uploaded at the same time
+    concurrency: usize,
     cancelled: Arc<AtomicBool>,
     paused: Arc<AtomicBool>,
+    /// Complete with `If-None-Match: *` so an object created meanwhile is never replaced
+    if_absent: bool,
+    /// Whether the upload was created with SHA-256 part checksums
+    checksums: bool,
+    /// Digests of the parts uploaded by this session, by part number
+    part_digests: Arc<std::sync::Mutex<HashMap<i32, PartDigest>>>,
 }
 
 impl MultipartUpload {
@@ -44,13 +68,41 @@ impl MultipartUpload {
         key: String,
         chunk_size: Option<usize>,
     ) -> Result<Self> {
-        let response = client
-            .create_multipart_upload()
-            .bucket(&bucket)
-            .key(&key)
-            .send()
-            .await
-            .context("Failed to create multipart upload")?;
+        Self::new_with_sha256(client, bucket, key, chunk_size, None).await
+    }
+
+    /// Like `new`, but records the content hash as the object's `sha256` metadata
+    pub async fn new_with_sha256(
+        client: Client,
+        bucket: String,
+        key: String,
+        chunk_size: Option<usize>,
+        sha256: Option<&str>,
+    ) -> Result<Self> {
+        // Ask for SHA-256 part checksums, falling back to none if the backend can't do them
+        let mut checksums = true;
+        let response = loop {
+            let mut request = client
+                .create_multipart_upload()
+                .bucket(&bucket)
+                .key(&key);
+
+            if let Some(sha256) = sha256 {
+                request = request.metadata(SHA256_METADATA_KEY, sha256);
+            }
+            if checksums {
+                request = request.checksum_algorithm(ChecksumAlgorithm::Sha256);
+            }
+
+            match request.send().await {
+                Ok(response) => break response,
+                Err(e) if checksums && is_not_implemented(&e) => {
+                    log::warn!("Backend does not support part checksums, uploading {} without them", key);
+                    checksums = false;
+                }
+                Err(e) => return Err(anyhow::Error::new(e).context("Failed to create multipart upload")),
+            }
+        };
 
         let upload_id = response
             .upload_id()
@@ -65,11 +117,62 @@ impl MultipartUpload {
             key,
             upload_id,
             chunk_size: chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).max(MIN_CHUNK_SIZE),
+            concurrency: DEFAULT_CONCURRENT_UPLOADS,
             cancelled: Arc::new(AtomicBool::new(false)),
             paused: Arc::new(AtomicBool::new(false)),
+            if_absent: false,
+            checksums,
+            part_digests: Arc::new(std::sync::Mutex::new(HashMap::new())),
         })
     }
 
+    /// Pick up an existing multipart upload, e.g. one left behind by a crashed session.
+    /// `parts` are the parts already stored for it, as returned by `list_parts`.
+    pub fn from_existing(
+        client: Client,
+        bucket: String,
+        key: String,
+        upload_id: String,
+        parts: &[UploadedPart],
+    ) -> Self {
+        // Every part but the last has the chunk size the upload was started with
+        let chunk_size = parts
+            .iter()
+            .find(|p| p.part_number == 1)
+            .map(|p| p.size as usize)
+            .unwrap_or(DEFAULT_CHUNK_SIZE)
+            .max(MIN_CHUNK_SIZE);
+
+        log::info!("Resuming multipart upload {} for key: {} ({} parts stored)", upload_id, key, parts.len());
+
+        Self {
+            client,
+            bucket,
+            key,
+            upload_id,
+            chunk_size,
+            concurrency: DEFAULT_CONCURRENT_UPLOADS,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            if_absent: false,
+            // Parts must keep using checksums if the upload was created with them
+            checksums: parts.iter().any(|p| p.checksum_sha256.is_some()),
+            part_digests: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Upload up to `concurrency` parts at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Only complete the upload if no object exists at the key at completion time
+    pub fn with_if_absent(mut self, if_absent: bool) -> Self {
+        self.if_absent = if_absent;
+        self
+    }
+
     pub fn upload_id(&self) -> &str {
         &self.upload_id
     }
@@ -100,7 +203,8 @@ impl MultipartUpload {
         key: &str,
         upload_id: &str,
         part_number: i32,
-        data: Vec<u8>,
+        data: Bytes,
+        checksum: Option<&str>,
     ) -> Result<String> {
         let mut last_error = None;
         let mut backoff_ms = INITIAL_BACKOFF_MS;
@@ -115,18 +219,22 @@ impl MultipartUpload {
                 backoff_ms = (backoff_ms * 2).min(MAX_BACKOFF_MS);
             }
             
-            let body = ByteStream::from(data.clone());
+            let body = ByteStream::new(bytes_body(data.clone()));
 
-            match client
+            let mut request = client
                 .upload_part()
                 .bucket(bucket)
                 .key(key)
                 .upload_id(upload_id)
                 .part_number(part_number)
-                .body(body)
-                .send()
-                .await
-            {
+                .body(body);
+
+            // The backend rejects the part if it doesn't hash to this
+            if let Some(checksum) = checksum {
+                request = request.checksum_sha256(checksum);
+            }
+
+            match request.send().await {
                 Ok(response) => {
                     let etag = response.e_tag()
                         .context(format!("No ETag returned for part {}", part_number))?
@@ -150,21 +258,26 @@ impl MultipartUpload {
         ))
     }
 
-    pub async fn complete(&self, mut parts: Vec<(i32, String)>) -> Result<()> {
+    /// Complete the upload, returning the ETag of the assembled object
+    pub async fn complete(&self, mut parts: Vec<(i32, String)>) -> Result<String> {
         // Sort parts by part number - CRITICAL for S3/R2
         parts.sort_by_key(|(num, _)| *num);
 
         log::info!("Completing multipart upload {} with {} parts", self.upload_id, parts.len());
 
-        let completed_parts: Vec<CompletedPart> = parts
-            .into_iter()
-            .map(|(part_number, etag)| {
-                CompletedPart::builder()
-                    .part_number(part_number)
-                    .e_tag(etag)
-                    .build()
-            })
-            .collect();
+        let completed_parts: Vec<CompletedPart> = {
+            let digests = self.part_digests.lock().unwrap();
+            parts
+                .into_iter()
+                .map(|(part_number, etag)| {
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .e_tag(etag)
+                        .set_checksum_sha256(digests.get(&part_number).and_then(|d| d.sha256.clone()))
+                        .build()
+                })
+                .collect()
+        };
 
         let completed_upload = CompletedMultipartUpload::builder()
             .set_parts(Some(completed_parts))
@@ -173,6 +286,7 @@ impl MultipartUpload {
         // Retry logic for completing multipart upload
         let mut last_error = None;
         let mut backoff_ms = INITIAL_BACKOFF_MS;
+        let mut if_absent = self.if_absent;
         
         for attempt in 0..MAX_RETRIES {
             if attempt > 0 {
@@ -184,18 +298,30 @@ impl MultipartUpload {
                 backoff_ms = (backoff_ms * 2).min(MAX_BACKOFF_MS);
             }
             
-            match self.client
+            let mut request = self.client
                 .complete_multipart_upload()
                 .bucket(&self.bucket)
                 .key(&self.key)
                 .upload_id(&self.upload_id)
-                .multipart_upload(completed_upload.clone())
-                .send()
-                .await
-            {
-                Ok(_) => {
+                .multipart_upload(completed_upload.clone());
+
+            if if_absent {
+                request = request.if_none_match("*");
+            }
+
+            match request.send().await {
+                Ok(response) => {
                     log::info!("Successfully completed multipart upload for key: {} (attempt {})", self.key, attempt + 1);
-                    return Ok(());
+                    return Ok(response.e_tag().unwrap_or("").to_string());
+                }
+                Err(e) if if_absent && is_precondition_failed(&e) => {
+                    // Not retryable - the key was taken while the parts were uploading
+                    return Err(ObjectExists(self.key.clone()).into());
+                }
+                Err(e) if if_absent && is_not_implemented(&e) => {
+                    log::warn!("Backend does not support conditional completes, completing {} unconditionally", self.key);
+                    if_absent = false;
+                    last_error = Some(e);
                 }
                 Err(e) => {
                     log::error!("Complete multipart upload failed (attempt {}): {}", attempt + 1, e);
@@ -211,6 +337,41 @@ impl MultipartUpload {
         ))
     }
 
+    /// ETag the completed object should have, computed from the parts this session uploaded.
+    /// `None` if any of parts 1..=N was uploaded elsewhere (e.g. by an earlier session).
+    pub fn expected_etag(&self, part_count: usize) -> Option<String> {
+        let digests = self.part_digests.lock().unwrap();
+        let md5s = (1..=part_count as i32)
+            .map(|n| digests.get(&n).map(|d| d.md5))
+            .collect::<Option<Vec<_>>>()?;
+        Some(multipart_etag(&md5s))
+    }
+
+    /// Check the completed object against the parts we sent, using the ETag from
+    /// CompleteMultipartUpload and a follow-up HeadObject
+    pub async fn verify(&self, part_count: usize, completed_etag: &str) -> Result<UploadVerification> {
+        let expected = match self.expected_etag(part_count) {
+            Some(expected) => expected,
+            None => return Ok(UploadVerification::Unverified),
+        };
+
+        let head_etag = head_object(&self.client, &self.bucket, &self.key)
+            .await?
+            .map(|info| info.etag)
+            .unwrap_or_default();
+
+        if etags_match(completed_etag, &expected) && etags_match(&head_etag, &expected) {
+            log::info!("Verified multipart upload for {} (ETag {})", self.key, expected);
+            Ok(UploadVerification::Verified)
+        } else {
+            log::error!(
+                "Multipart upload for {} does not match local file: expected ETag {}, complete returned {}, head returned {}",
+                self.key, expected, completed_etag, head_etag
+            );
+            Ok(UploadVerification::Mismatch)
+        }
+    }
+
     pub async fn abort(&self) -> Result<()> {
         log::warn!("Aborting multipart upload {} for key: {}", self.upload_id, self.key);
         
@@ -226,12 +387,83 @@ impl MultipartUpload {
         Ok(())
     }
 
-    /// Upload a file with concurrent chunk uploads (up to 8 parallel) and progress tracking
+    /// Upload a file with concurrent chunk uploads and progress tracking
     pub async fn upload_file_concurrent<F>(
         &self,
         file_path: &str,
         progress_callback: F,
     ) -> Result<Vec<(i32, String)>>
+    where
+        F: FnMut(UploadProgressInfo) + Send + 'static,
+    {
+        self.upload_parts_concurrent(file_path, Vec::new(), progress_callback).await
+    }
+
+    /// Finish a resumed upload: check the stored parts against the local file, then
+    /// upload only the parts that are missing. Fails without touching anything if the
+    /// stored parts don't come from this file.
+    pub async fn resume_file_concurrent<F>(
+        &self,
+        file_path: &str,
+        stored: &[UploadedPart],
+        progress_callback: F,
+    ) -> Result<Vec<(i32, String)>>
+    where
+        F: FnMut(UploadProgressInfo) + Send + 'static,
+    {
+        let path = Path::new(file_path);
+        let file_size = tokio::fs::metadata(path).await?.len();
+        let chunk_size = self.chunk_size as u64;
+        let num_parts = file_size.div_ceil(chunk_size) as i32;
+
+        let mut file = File::open(path).await?;
+        let mut existing = Vec::with_capacity(stored.len());
+        for part in stored {
+            if part.part_number < 1 || part.part_number > num_parts {
+                anyhow::bail!(
+                    "Stored part {} is beyond the end of {} ({} parts)",
+                    part.part_number, file_path, num_parts
+                );
+            }
+
+            let offset = (part.part_number as u64 - 1) * chunk_size;
+            let expected_size = chunk_size.min(file_size - offset);
+            if part.size as u64 != expected_size {
+                anyhow::bail!(
+                    "Stored part {} is {} bytes but the local file has {} bytes there",
+                    part.part_number, part.size, expected_size
+                );
+            }
+
+            let mut buffer = vec![0u8; expected_size as usize];
+            file.seek(std::io::SeekFrom::Start(offset)).await?;
+            file.read_exact(&mut buffer).await?;
+
+            // A single part's ETag is the MD5 of its content
+            let md5 = md5_digest(&buffer);
+            let md5_hex: String = md5.iter().map(|b| format!("{:02x}", b)).collect();
+            if !etags_match(&part.etag, &md5_hex) {
+                anyhow::bail!("Stored part {} does not match {}", part.part_number, file_path);
+            }
+
+            let digest = PartDigest {
+                md5,
+                sha256: part.checksum_sha256.clone().or_else(|| self.checksums.then(|| sha256_base64(&buffer))),
+            };
+            self.part_digests.lock().unwrap().insert(part.part_number, digest);
+            existing.push((part.part_number, part.etag.clone()));
+        }
+
+        self.upload_parts_concurrent(file_path, existing, progress_callback).await
+    }
+
+    /// Upload every part of `file_path` not already in `existing`
+    async fn upload_parts_concurrent<F>(
+        &self,
+        file_path: &str,
+        existing: Vec<(i32, String)>,
+        progress_callback: F,
+    ) -> Result<Vec<(i32, String)>>
     where
         F: FnMut(UploadProgressInfo) + Send + 'static,
     {
@@ -243,17 +475,26 @@ impl MultipartUpload {
         let num_parts = ((file_size as usize + chunk_size - 1) / chunk_size) as i32;
         
         log::info!(
-            "Starting concurrent upload of {} ({} bytes) in {} parts with {} concurrent uploads",
-            file_path, file_size, num_parts, MAX_CONCURRENT_UPLOADS
+            "Starting concurrent upload of {} ({} bytes) in {} parts with {} concurrent uploads ({} already stored)",
+            file_path, file_size, num_parts, self.concurrency, existing.len()
         );
 
+        // Bytes of parts that are already stored count as uploaded
+        let already_uploaded: i64 = existing
+            .iter()
+            .map(|(n, _)| (file_size - (*n as i64 - 1) * chunk_size as i64).min(chunk_size as i64))
+            .sum();
+        let skip: std::collections::HashSet<i32> = existing.iter().map(|(n, _)| *n).collect();
+
         // Shared state for tracking progress
-        let total_uploaded = Arc::new(AtomicI64::new(0));
+        let total_uploaded = Arc::new(AtomicI64::new(already_uploaded));
         let chunks_in_flight = Arc::new(AtomicI64::new(0));
         let max_reported_progress = Arc::new(AtomicI64::new(0)); // Track max to prevent jumping back
         let start_time = Instant::now();
-        let parts: Arc<Mutex<Vec<(i32, String)>>> = Arc::new(Mutex::new(Vec::with_capacity(num_parts as usize)));
-        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_UPLOADS));
+        let mut initial_parts = Vec::with_capacity(num_parts as usize);
+        initial_parts.extend(existing);
+        let parts: Arc<Mutex<Vec<(i32, String)>>> = Arc::new(Mutex::new(initial_parts));
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
         let progress_callback = Arc::new(Mutex::new(progress_callback));
         let upload_done = Arc::new(AtomicBool::new(false));
 
@@ -329,6 +570,13 @@ impl MultipartUpload {
             }
 
             let bytes_to_read = std::cmp::min(chunk_size as u64, file_size as u64 - offset) as usize;
+            if skip.contains(&part_number) {
+                part_number += 1;
+                offset += bytes_to_read as u64;
+                continue;
+            }
+            // Take a slot before reading, so no more parts are held in memory than are being sent
+            let permit = semaphore.clone().acquire_owned().await?;
             let mut buffer = vec![0u8; bytes_to_read];
             
             file.seek(std::io::SeekFrom::Start(offset)).await?;
@@ -338,7 +586,6 @@ impl MultipartUpload {
             let bucket = self.bucket.clone();
             let key = self.key.clone();
             let upload_id = self.upload_id.clone();
-            let sem = semaphore.clone();
             let parts_clone = parts.clone();
             let total_uploaded_clone = total_uploaded.clone();
             let progress_callback_clone = progress_callback.clone();
@@ -347,10 +594,12 @@ impl MultipartUpload {
             let current_part = part_number;
             let chunk_len = buffer.len() as i64;
             let in_flight_clone = chunks_in_flight.clone();
+            let checksums_enabled = self.checksums;
+            let part_digests = self.part_digests.clone();
 
             let task = tokio::spawn(async move {
-                // Acquire semaphore permit to limit concurrency
-                let _permit = sem.acquire().await.unwrap();
+                // Held until the part is sent, to limit concurrency
+                let _permit = permit;
 
                 // Check for cancellation or wait while paused
                 loop {
@@ -368,19 +617,25 @@ impl MultipartUpload {
                 
                 log::debug!("Uploading part {} ({} bytes)", current_part, chunk_len);
 
+                let digest = PartDigest {
+                    md5: md5_digest(&buffer),
+                    sha256: checksums_enabled.then(|| sha256_base64(&buffer)),
+                };
                 let result = Self::upload_part_internal(
                     &client,
                     &bucket,
                     &key,
                     &upload_id,
                     current_part,
-                    buffer,
+                    buffer.into(),
+                    digest.sha256.as_deref(),
                 ).await;
                 
                 // Mark this chunk as no longer in-flight
                 in_flight_clone.fetch_sub(1, Ordering::SeqCst);
                 
                 let etag = result?;
+                part_digests.lock().unwrap().insert(current_part, digest);
 
                 // Update progress
                 let uploaded = total_uploaded_clone.fetch_add(chunk_len, Ordering::SeqCst) + chunk_len;
@@ -488,14 +743,20 @@ impl MultipartUpload {
             }
 
             let chunk = buffer[..bytes_read].to_vec();
+            let digest = PartDigest {
+                md5: md5_digest(&chunk),
+                sha256: self.checksums.then(|| sha256_base64(&chunk)),
+            };
             let etag = Self::upload_part_internal(
                 &self.client,
                 &self.bucket,
                 &self.key,
                 &self.upload_id,
                 part_number,
-                chunk,
+                chunk.into(),
+                digest.sha256.as_deref(),
             ).await?;
+            self.part_digests.lock().unwrap().insert(part_number, digest);
             parts.push((part_number, etag));
 
             total_uploaded += bytes_read as i64;
@@ -508,23 +769,35 @@ impl MultipartUpload {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_large_file(
     client: &Client,
     bucket: &str,
     key: &str,
     file_path: &str,
     chunk_size: Option<usize>,
+    concurrency: usize,
+    if_absent: bool,
+    sha256: Option<&str>,
 ) -> Result<()> {
-    let upload = MultipartUpload::new(
+    let upload = MultipartUpload::new_with_sha256(
         client.clone(),
         bucket.to_string(),
         key.to_string(),
         chunk_size,
+        sha256,
     )
-    .await?;
+    .await?
+    .with_concurrency(concurrency)
+    .with_if_absent(if_absent);
 
     let parts = upload.upload_file_concurrent(file_path, |_progress| {}).await?;
-    upload.complete(parts).await?;
+    let part_count = parts.len();
+    let etag = upload.complete(parts).await?;
+
+    if upload.verify(part_count, &etag).await? == UploadVerification::Mismatch {
+        anyhow::bail!("Uploaded object {} does not match the local file", key);
+    }
 
     Ok(())
 }
@@ -1,15 +1,87 @@
 use aws_sdk_s3::Client;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
 use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
 use anyhow::{Result, Context};
 use crate::utils::R2Object;
+use super::body::{file_body, SentCallback};
+use super::checksum::{sha256_file, ExpectedDigest, StreamingVerifier};
 use chrono::DateTime;
 use tokio::io::AsyncWriteExt;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicI64, Ordering};
 
 /// Progress callback for download operations
 pub type DownloadProgressCallback = Box<dyn Fn(i64, i64, f64, i64) + Send + Sync>;
 
+/// Returned when a conditional write is rejected because the key already exists
+#[derive(Debug, thiserror::Error)]
+#[error("Object already exists: {0}")]
+pub struct ObjectExists(pub String);
+
+/// User metadata key holding the hex SHA-256 of the uploaded content.
+/// Multipart ETags are not content hashes, so this is what identifies their content.
+pub const SHA256_METADATA_KEY: &str = "sha256";
+
+/// Options applied to single-request uploads
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    /// Send `If-None-Match: *` so the write fails instead of replacing an existing object
+    pub if_absent: bool,
+    /// Stored as the `sha256` user metadata header; computed from the body when not set
+    pub sha256: Option<String>,
+}
+
+/// Metadata for a single remote object, as returned by HeadObject
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: i64,
+    pub etag: String,
+    pub last_modified: Option<String>,
+    /// Content hash from the `sha256` metadata header, if the uploader set one
+    pub sha256: Option<String>,
+}
+
+/// True when the backend rejected a conditional request (HTTP 412)
+pub(crate) fn is_precondition_failed<E: ProvideErrorMetadata, R>(err: &SdkError<E, R>) -> bool {
+    match err {
+        SdkError::ServiceError(e) => e.err().code() == Some("PreconditionFailed"),
+        _ => false,
+    }
+}
+
+/// True when the backend does not implement a request header we sent (HTTP 501)
+pub(crate) fn is_not_implemented<E: ProvideErrorMetadata, R>(err: &SdkError<E, R>) -> bool {
+    match err {
+        SdkError::ServiceError(e) => e.err().code() == Some("NotImplemented"),
+        _ => false,
+    }
+}
+
+/// Fetch object metadata, returning `None` when the key does not exist
+pub async fn head_object(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+) -> Result<Option<ObjectInfo>> {
+    match client.head_object().bucket(bucket).key(key).send().await {
+        Ok(response) => Ok(Some(ObjectInfo {
+            key: key.to_string(),
+            size: response.content_length().unwrap_or(0),
+            etag: response.e_tag().unwrap_or("").to_string(),
+            last_modified: response.last_modified().map(|dt| dt.to_string()),
+            sha256: response
+                .metadata()
+                .and_then(|m| m.get(SHA256_METADATA_KEY))
+                .cloned(),
+        })),
+        Err(SdkError::ServiceError(e)) if e.err().is_not_found() => Ok(None),
+        Err(e) => Err(anyhow::Error::new(e).context(format!("Failed to head object {}", key))),
+    }
+}
+
 pub async fn list_objects(
     client: &Client,
     bucket: &str,
@@ -22,28 +94,82 @@ pub async fn list_objects(
     }
 
     let response = request.send().await?;
-    
+
+    Ok(response.contents().iter().map(r2_object_from).collect())
+}
+
+/// One page of up to 1000 objects under `prefix`, and the token for the next page if there is one
+pub async fn list_objects_page(
+    client: &Client,
+    bucket: &str,
+    prefix: Option<&str>,
+    continuation_token: Option<&str>,
+) -> Result<(Vec<aws_sdk_s3::types::Object>, Option<String>)> {
+    let mut request = client.list_objects_v2().bucket(bucket);
+    if let Some(p) = prefix {
+        request = request.prefix(p);
+    }
+    if let Some(token) = continuation_token {
+        request = request.continuation_token(token);
+    }
+
+    let response = request
+        .send()
+        .await
+        .context("Failed to list objects")?;
+
+    let next = match response.next_continuation_token() {
+        Some(token) if response.is_truncated().unwrap_or(false) => Some(token.to_string()),
+        _ => None,
+    };
+    Ok((response.contents().to_vec(), next))
+}
+
+/// List every object under `prefix`, following continuation tokens past the 1000-key page limit
+pub async fn list_all_objects(
+    client: &Client,
+    bucket: &str,
+    prefix: Option<&str>,
+) -> Result<Vec<R2Object>> {
     let mut objects = Vec::new();
+    let mut continuation_token: Option<String> = None;
 
-    if let Some(contents) = response.contents {
-        for obj in contents {
-            objects.push(R2Object {
-                key: obj.key().unwrap_or("").to_string(),
-                size: obj.size().unwrap_or(0),
-                last_modified: obj
-                    .last_modified()
-                    .and_then(|dt| DateTime::parse_from_rfc3339(&dt.to_string()).ok())
-                    .map(|dt| dt.with_timezone(&chrono::Utc))
-                    .unwrap_or_else(chrono::Utc::now),
-                etag: obj.e_tag().unwrap_or("").to_string(),
-                is_directory: false,
-            });
+    loop {
+        let (page, next) = list_objects_page(client, bucket, prefix, continuation_token.as_deref()).await?;
+        objects.extend(page.iter().map(r2_object_from));
+
+        match next {
+            Some(token) => continuation_token = Some(token),
+            None => break,
         }
     }
 
     Ok(objects)
 }
 
+/// The `Content-Type` of an object, which listings don't include; `None` if it has none or is gone
+pub async fn head_content_type(client: &Client, bucket: &str, key: &str) -> Result<Option<String>> {
+    match client.head_object().bucket(bucket).key(key).send().await {
+        Ok(response) => Ok(response.content_type().map(str::to_string)),
+        Err(SdkError::ServiceError(e)) if e.err().is_not_found() => Ok(None),
+        Err(e) => Err(anyhow::Error::new(e).context(format!("Failed to head object {}", key))),
+    }
+}
+
+fn r2_object_from(obj: &aws_sdk_s3::types::Object) -> R2Object {
+    R2Object {
+        key: obj.key().unwrap_or("").to_string(),
+        size: obj.size().unwrap_or(0),
+        last_modified: obj
+            .last_modified()
+            .and_then(|dt| DateTime::parse_from_rfc3339(&dt.to_string()).ok())
+            .map(|dt| dt.with_timezone(&chrono::Utc))
+            .unwrap_or_else(chrono::Utc::now),
+        etag: obj.e_tag().unwrap_or("").to_string(),
+        is_directory: false,
+    }
+}
+
 /// Download object with streaming (memory efficient for large files)
 pub async fn get_object_streaming(
     client: &Client,
@@ -61,6 +187,10 @@ pub async fn get_object_streaming(
         .context("Failed to get object from R2")?;
 
     let content_length = response.content_length().unwrap_or(0);
+    let expected = ExpectedDigest::for_object(
+        response.e_tag(),
+        response.metadata().and_then(|m| m.get(SHA256_METADATA_KEY)).map(|s| s.as_str()),
+    );
     let mut byte_stream = response.body;
     
     // Create file for writing
@@ -70,10 +200,26 @@ pub async fn get_object_streaming(
 
     let downloaded = Arc::new(AtomicI64::new(0));
     let start_time = std::time::Instant::now();
+    let mut verifier = StreamingVerifier::new(expected);
 
     // Stream chunks to file
-    while let Some(chunk) = byte_stream.try_next().await? {
-        file.write_all(&chunk).await?;
+    loop {
+        let chunk = match byte_stream.try_next().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                drop(file);
+                remove_partial_download(local_path).await;
+                return Err(anyhow::Error::new(e).context("Failed to read object body"));
+            }
+        };
+
+        if let Err(e) = file.write_all(&chunk).await {
+            drop(file);
+            remove_partial_download(local_path).await;
+            return Err(e.into());
+        }
+        verifier.update(&chunk);
         
         let total_downloaded = downloaded.fetch_add(chunk.len() as i64, Ordering::SeqCst) + chunk.len() as i64;
         
@@ -89,11 +235,29 @@ pub async fn get_object_streaming(
     }
 
     file.flush().await?;
+    drop(file);
+
+    // A corrupted download must never be left looking like a good file
+    match verifier.finish(key) {
+        Ok(true) => log::debug!("Verified checksum of {}", key),
+        Ok(false) => log::debug!("No checksum available to verify {}", key),
+        Err(mismatch) => {
+            log::error!("{}", mismatch);
+            remove_partial_download(local_path).await;
+            return Err(mismatch.into());
+        }
+    }
     
     log::info!("Downloaded {} bytes to {}", content_length, local_path);
     Ok(())
 }
 
+async fn remove_partial_download(local_path: &str) {
+    if let Err(e) = tokio::fs::remove_file(local_path).await {
+        log::warn!("Failed to remove partial download {}: {}", local_path, e);
+    }
+}
+
 /// Legacy get_object (kept for compatibility, but uses streaming internally now)
 pub async fn get_object(
     client: &Client,
@@ -110,96 +274,96 @@ pub async fn put_object(
     key: &str,
     file_path: &str,
 ) -> Result<String> {
-    let body = ByteStream::from_path(std::path::Path::new(file_path)).await?;
-
-    let response = client
-        .put_object()
-        .bucket(bucket)
-        .key(key)
-        .body(body)
-        .send()
-        .await?;
-
-    Ok(response.e_tag().unwrap_or("").to_string())
+    send_put(client, bucket, key, std::path::Path::new(file_path), None, &PutOptions::default()).await
 }
 
 /// Upload progress callback type
 pub type UploadProgressCallback = Box<dyn Fn(i64, i64, f64, i64) + Send + Sync>;
 
-/// Upload a file with progress tracking
+/// Upload a file with progress tracking. The file is streamed from disk and progress follows
+/// the bytes handed to the connection.
 pub async fn put_object_with_progress<F>(
     client: &Client,
     bucket: &str,
     key: &str,
     file_path: &str,
+    options: &PutOptions,
     progress_callback: F,
 ) -> Result<String>
 where
     F: Fn(i64, i64, f64, i64) + Send + 'static,
 {
-    use tokio::io::AsyncReadExt;
-    
     let path = std::path::Path::new(file_path);
     let file_size = tokio::fs::metadata(path).await?.len() as i64;
-    
-    // For very small files (< 1MB), just upload directly without chunked progress
-    if file_size < 1024 * 1024 {
-        let body = ByteStream::from_path(path).await?;
-        let response = client
+
+    let progress_callback = Arc::new(std::sync::Mutex::new(progress_callback));
+    let on_sent: SentCallback = {
+        let progress_callback = progress_callback.clone();
+        let start_time = std::time::Instant::now();
+        Arc::new(move |sent| {
+            let sent = sent as i64;
+            let elapsed = start_time.elapsed().as_secs_f64();
+            let speed = if elapsed > 0.0 { sent as f64 / elapsed } else { 0.0 };
+            let eta = if speed > 0.0 { ((file_size - sent) as f64 / speed) as i64 } else { 0 };
+            (progress_callback.lock().unwrap())(sent, file_size, speed, eta);
+        })
+    };
+    let etag = send_put(client, bucket, key, path, Some(on_sent), options).await?;
+
+    // Emit final progress
+    (progress_callback.lock().unwrap())(file_size, file_size, 0.0, 0);
+    Ok(etag)
+}
+
+/// Send a single PutObject request honouring `options`.
+/// The body's SHA-256 goes in `x-amz-checksum-sha256` so the backend rejects a corrupted transfer.
+/// If the backend rejects an optional header as unsupported, the write is retried without them.
+async fn send_put(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    path: &std::path::Path,
+    on_sent: Option<SentCallback>,
+    options: &PutOptions,
+) -> Result<String> {
+    // The length is taken before hashing, so bytes appended since are neither hashed nor sent
+    let len = tokio::fs::metadata(path).await?.len();
+    let (hex, checksum) = sha256_file(path, len)
+        .await
+        .with_context(|| format!("Failed to hash {}", path.display()))?;
+    let sha256 = options.sha256.clone().unwrap_or(hex);
+
+    let mut optional_headers = true;
+    loop {
+        let mut request = client
             .put_object()
             .bucket(bucket)
             .key(key)
-            .body(body)
-            .send()
-            .await?;
-        
-        // Emit final progress
-        progress_callback(file_size, file_size, 0.0, 0);
-        return Ok(response.e_tag().unwrap_or("").to_string());
-    }
+            .body(ByteStream::new(file_body(path, len, on_sent.clone())))
+            .metadata(SHA256_METADATA_KEY, &sha256);
 
-    // For larger files, read in chunks and track progress
-    let mut file = tokio::fs::File::open(path).await?;
-    let mut data = Vec::with_capacity(file_size as usize);
-    
-    let chunk_size = 256 * 1024; // 256KB chunks for progress reporting
-    let mut buffer = vec![0u8; chunk_size];
-    let mut total_read: i64 = 0;
-    let start_time = std::time::Instant::now();
-    
-    loop {
-        let bytes_read = file.read(&mut buffer).await?;
-        if bytes_read == 0 {
-            break;
+        if optional_headers {
+            request = request.checksum_sha256(&checksum);
+            if options.if_absent {
+                request = request.if_none_match("*");
+            }
+        }
+
+        match request.send().await {
+            Ok(response) => return Ok(response.e_tag().unwrap_or("").to_string()),
+            Err(e) if optional_headers && options.if_absent && is_precondition_failed(&e) => {
+                return Err(ObjectExists(key.to_string()).into());
+            }
+            Err(e) if optional_headers && is_not_implemented(&e) => {
+                log::warn!(
+                    "Backend does not support checksum or conditional headers, uploading {} without them",
+                    key
+                );
+                optional_headers = false;
+            }
+            Err(e) => return Err(e.into()),
         }
-        
-        data.extend_from_slice(&buffer[..bytes_read]);
-        total_read += bytes_read as i64;
-        
-        // Calculate speed and ETA
-        let elapsed = start_time.elapsed().as_secs_f64();
-        let speed = if elapsed > 0.0 { total_read as f64 / elapsed } else { 0.0 };
-        let remaining = file_size - total_read;
-        let eta = if speed > 0.0 { (remaining as f64 / speed) as i64 } else { 0 };
-        
-        // Report progress during read phase (50% of total progress)
-        progress_callback(total_read / 2, file_size, speed, eta);
     }
-    
-    // Now upload the data
-    let body = ByteStream::from(data);
-    let response = client
-        .put_object()
-        .bucket(bucket)
-        .key(key)
-        .body(body)
-        .send()
-        .await?;
-    
-    // Emit final progress
-    progress_callback(file_size, file_size, 0.0, 0);
-    
-    Ok(response.e_tag().unwrap_or("").to_string())
 }
 
 pub async fn put_object_from_bytes(
@@ -236,18 +400,39 @@ pub async fn delete_object(
     Ok(())
 }
 
+/// Largest object a single CopyObject request can copy
+const MAX_SINGLE_COPY: i64 = 5 * 1024 * 1024 * 1024;
+
+/// Part size for copying larger objects with UploadPartCopy
+const COPY_PART_SIZE: i64 = 1024 * 1024 * 1024;
+
+/// Whether an object of `size` bytes is too large for a single CopyObject request
+fn needs_part_copy(size: i64) -> bool {
+    size > MAX_SINGLE_COPY
+}
+
+/// `x-amz-copy-source` value for a key: the key is URL-encoded, its slashes kept
+fn copy_source(bucket: &str, key: &str) -> String {
+    let mut source = format!("{}/", bucket);
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => source.push(byte as char),
+            _ => source.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    source
+}
+
 pub async fn copy_object(
     client: &Client,
     bucket: &str,
     source_key: &str,
     dest_key: &str,
 ) -> Result<()> {
-    let copy_source = format!("{}/{}", bucket, source_key);
-    
     client
         .copy_object()
         .bucket(bucket)
-        .copy_source(copy_source)
+        .copy_source(copy_source(bucket, source_key))
         .key(dest_key)
         .send()
         .await?;
@@ -255,6 +440,79 @@ pub async fn copy_object(
     Ok(())
 }
 
+/// Copy an object of any size within the bucket, without downloading it.
+/// Objects over 5 GiB are copied in parts, keeping their `sha256` metadata.
+pub async fn copy_large_object(
+    client: &Client,
+    bucket: &str,
+    source_key: &str,
+    dest_key: &str,
+    size: i64,
+) -> Result<()> {
+    if !needs_part_copy(size) {
+        return copy_object(client, bucket, source_key, dest_key).await;
+    }
+
+    let source = head_object(client, bucket, source_key)
+        .await?
+        .with_context(|| format!("{} no longer exists", source_key))?;
+    let mut request = client.create_multipart_upload().bucket(bucket).key(dest_key);
+    if let Some(sha256) = &source.sha256 {
+        request = request.metadata(SHA256_METADATA_KEY, sha256);
+    }
+    let upload = request.send().await.context("Failed to start multipart copy")?;
+    let upload_id = upload.upload_id().context("No upload ID for multipart copy")?;
+
+    let copied = async {
+        let mut parts = Vec::new();
+        let mut start = 0;
+        while start < source.size {
+            let end = (start + COPY_PART_SIZE).min(source.size) - 1;
+            let part_number = parts.len() as i32 + 1;
+            let part = client
+                .upload_part_copy()
+                .bucket(bucket)
+                .key(dest_key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .copy_source(copy_source(bucket, source_key))
+                .copy_source_range(format!("bytes={}-{}", start, end))
+                .send()
+                .await
+                .with_context(|| format!("Failed to copy part {} of {}", part_number, source_key))?;
+            let etag = part
+                .copy_part_result()
+                .and_then(|result| result.e_tag())
+                .with_context(|| format!("No ETag for copied part {}", part_number))?;
+            parts.push(CompletedPart::builder().part_number(part_number).e_tag(etag).build());
+            start = end + 1;
+        }
+
+        client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(dest_key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .send()
+            .await
+            .context("Failed to complete multipart copy")?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    if copied.is_err() {
+        let _ = client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(dest_key)
+            .upload_id(upload_id)
+            .send()
+            .await;
+    }
+    copied
+}
+
 /// Information about a stalled multipart upload
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct StalledUpload {
@@ -264,23 +522,27 @@ pub struct StalledUpload {
     pub age_hours: i64,
 }
 
-/// List all in-progress multipart uploads in the bucket
+/// List all in-progress multipart uploads in the bucket, following every page
 pub async fn list_multipart_uploads(
     client: &Client,
     bucket: &str,
 ) -> Result<Vec<StalledUpload>> {
-    let response = client
-        .list_multipart_uploads()
-        .bucket(bucket)
-        .send()
-        .await
-        .context("Failed to list multipart uploads")?;
-
     let mut uploads = Vec::new();
     let now = chrono::Utc::now();
+    let mut key_marker: Option<String> = None;
+    let mut upload_id_marker: Option<String> = None;
+
+    loop {
+        let response = client
+            .list_multipart_uploads()
+            .bucket(bucket)
+            .set_key_marker(key_marker.take())
+            .set_upload_id_marker(upload_id_marker.take())
+            .send()
+            .await
+            .context("Failed to list multipart uploads")?;
 
-    if let Some(upload_list) = response.uploads {
-        for upload in upload_list {
+        for upload in response.uploads() {
             let upload_id = upload.upload_id().unwrap_or("").to_string();
             let key = upload.key().unwrap_or("").to_string();
             
@@ -306,12 +568,79 @@ pub async fn list_multipart_uploads(
                 age_hours,
             });
         }
+
+        if !response.is_truncated().unwrap_or(false) {
+            break;
+        }
+        key_marker = response.next_key_marker().map(|s| s.to_string());
+        upload_id_marker = response.next_upload_id_marker().map(|s| s.to_string());
+        if key_marker.is_none() && upload_id_marker.is_none() {
+            // A truncated page without markers would loop forever
+            log::warn!("Multipart upload listing was truncated without a continuation marker");
+            break;
+        }
     }
 
     log::info!("Found {} in-progress multipart uploads", uploads.len());
     Ok(uploads)
 }
 
+/// A part already stored for an in-progress multipart upload
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UploadedPart {
+    pub part_number: i32,
+    pub size: i64,
+    pub etag: String,
+    pub last_modified: Option<String>,
+    /// Base64 SHA-256 the part was uploaded with, if the upload uses part checksums
+    pub checksum_sha256: Option<String>,
+}
+
+/// List every part stored so far for a multipart upload, following every page
+pub async fn list_parts(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+) -> Result<Vec<UploadedPart>> {
+    let mut parts = Vec::new();
+    let mut part_number_marker: Option<String> = None;
+
+    loop {
+        let response = client
+            .list_parts()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .set_part_number_marker(part_number_marker.take())
+            .send()
+            .await
+            .context(format!("Failed to list parts of upload {} for key {}", upload_id, key))?;
+
+        for part in response.parts() {
+            parts.push(UploadedPart {
+                part_number: part.part_number().unwrap_or(0),
+                size: part.size().unwrap_or(0),
+                etag: part.e_tag().unwrap_or("").to_string(),
+                last_modified: part.last_modified().map(|dt| dt.to_string()),
+                checksum_sha256: part.checksum_sha256().map(|s| s.to_string()),
+            });
+        }
+
+        if !response.is_truncated().unwrap_or(false) {
+            break;
+        }
+        part_number_marker = response.next_part_number_marker().map(|s| s.to_string());
+        if part_number_marker.is_none() {
+            log::warn!("Part listing was truncated without a continuation marker");
+            break;
+        }
+    }
+
+    parts.sort_by_key(|p| p.part_number);
+    Ok(parts)
+}
+
 /// Abort a specific multipart upload
 pub async fn abort_multipart_upload(
     client: &Client,
@@ -332,28 +661,36 @@ pub async fn abort_multipart_upload(
     Ok(())
 }
 
-/// Clean up stalled multipart uploads older than the specified hours
-/// Returns the number of uploads cleaned up
+/// What a stalled upload cleanup did
+#[derive(Debug, Clone, Default)]
+pub struct StaleCleanup {
+    pub cleaned: Vec<StalledUpload>,
+    /// Old enough to clean up, but left alone because a local upload can still resume them
+    pub protected: usize,
+    pub failed: usize,
+}
+
+/// Clean up stalled multipart uploads older than the specified hours,
+/// never aborting an upload id listed in `protected`
 pub async fn cleanup_stalled_uploads(
     client: &Client,
     bucket: &str,
     max_age_hours: i64,
-) -> Result<(i32, Vec<StalledUpload>)> {
+    protected: &HashSet<String>,
+) -> Result<StaleCleanup> {
     let uploads = list_multipart_uploads(client, bucket).await?;
-    
-    let stalled: Vec<StalledUpload> = uploads
-        .into_iter()
-        .filter(|u| u.age_hours >= max_age_hours)
-        .collect();
+    let mut result = StaleCleanup::default();
 
-    let mut cleaned_count = 0;
-    let mut cleaned_uploads = Vec::new();
+    for upload in uploads.iter().filter(|u| u.age_hours >= max_age_hours) {
+        if protected.contains(&upload.upload_id) {
+            result.protected += 1;
+            log::info!("Keeping resumable upload {} for key {}", upload.upload_id, upload.key);
+            continue;
+        }
 
-    for upload in &stalled {
         match abort_multipart_upload(client, bucket, &upload.key, &upload.upload_id).await {
             Ok(_) => {
-                cleaned_count += 1;
-                cleaned_uploads.push(upload.clone());
+                result.cleaned.push(upload.clone());
                 log::info!(
                     "Cleaned up stalled upload: {} (age: {} hours)",
                     upload.key,
@@ -361,6 +698,7 @@ pub async fn cleanup_stalled_uploads(
                 );
             }
             Err(e) => {
+                result.failed += 1;
                 log::error!(
                     "Failed to clean up upload {} for key {}: {}",
                     upload.upload_id,
@@ -372,11 +710,29 @@ pub async fn cleanup_stalled_uploads(
     }
 
     log::info!(
-        "Cleanup complete: {} stalled uploads removed (threshold: {} hours)",
-        cleaned_count,
+        "Cleanup complete: {} stalled uploads removed, {} kept as resumable (threshold: {} hours)",
+        result.cleaned.len(),
+        result.protected,
         max_age_hours
     );
 
-    Ok((cleaned_count, cleaned_uploads))
+    Ok(result)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_source_encoding() {
+        assert_eq!(copy_source("b", "docs/report.pdf"), "b/docs/report.pdf");
+        assert_eq!(copy_source("b", "my photos/été+1.jpg"), "b/my%20photos/%C3%A9t%C3%A9%2B1.jpg");
+    }
+
+    #[test]
+    fn test_part_copy_threshold() {
+        assert!(!needs_part_copy(0));
+        assert!(!needs_part_copy(5 * 1024 * 1024 * 1024));
+        assert!(needs_part_copy(5 * 1024 * 1024 * 1024 + 1));
+    }
+}
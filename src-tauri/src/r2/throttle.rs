@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Shared by every upload, so the limit applies to the app as a whole
+pub static UPLOADS: Throttle = Throttle::new();
+
+/// Caps throughput across concurrent transfers. Bodies book each block as they send it, so the
+/// limit holds over a few blocks rather than only on average over a transfer.
+pub struct Throttle {
+    /// Bytes per second; 0 means unlimited
+    limit: AtomicU64,
+    /// When the bytes already granted will have gone out at the limit
+    free_at: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    pub const fn new() -> Self {
+        Self { limit: AtomicU64::new(0), free_at: Mutex::new(None) }
+    }
+
+    /// Takes effect from the next block
+    pub fn set_limit(&self, bytes_per_sec: u64) {
+        self.limit.store(bytes_per_sec, Ordering::Relaxed);
+        *self.free_at.lock().unwrap() = None;
+    }
+
+    /// Book `bytes` after those already granted and return when they may start, or None if unlimited
+    pub(super) fn reserve(&self, bytes: u64, now: Instant) -> Option<Instant> {
+        let limit = self.limit.load(Ordering::Relaxed);
+        if limit == 0 {
+            return None;
+        }
+
+        let mut free_at = self.free_at.lock().unwrap();
+        let start = free_at.filter(|at| *at > now).unwrap_or(now);
+        *free_at = Some(start + Duration::from_secs_f64(bytes as f64 / limit as f64));
+        Some(start)
+    }
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_spaces_requests_at_the_limit() {
+        let throttle = Throttle::new();
+        let now = Instant::now();
+        assert_eq!(throttle.reserve(1024, now), None);
+
+        throttle.set_limit(1024);
+        assert_eq!(throttle.reserve(2048, now), Some(now));
+        assert_eq!(throttle.reserve(1024, now), Some(now + Duration::from_secs(2)));
+        assert_eq!(throttle.reserve(1024, now), Some(now + Duration::from_secs(3)));
+
+        // Idle time isn't saved up for a burst later
+        let later = now + Duration::from_secs(60);
+        assert_eq!(throttle.reserve(1024, later), Some(later));
+
+        throttle.set_limit(0);
+        assert_eq!(throttle.reserve(1024, later), None);
+    }
+}
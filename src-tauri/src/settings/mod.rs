@@ -0,0 +1,215 @@
+use anyhow::{bail, Result};
+use std::sync::RwLock;
+
+use crate::db::Database;
+use crate::r2::throttle;
+use crate::utils::AppSettings;
+
+/// Emitted with the new settings whenever they are saved
+pub const CHANGED_EVENT: &str = "settings-changed";
+
+/// Each part in flight is held in memory, so chunk size times parallel uploads stays under this
+pub const MAX_UPLOAD_MEMORY: usize = 1024 * 1024 * 1024;
+/// S3's smallest multipart part, and the largest part that fits in the memory bound on its own
+pub const MIN_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+pub const MAX_CHUNK_SIZE: usize = MAX_UPLOAD_MEMORY;
+/// More parallel part uploads than this only adds memory use
+pub const MAX_PARALLEL_UPLOADS: usize = 32;
+
+const THEMES: &[&str] = &["system", "light", "dark"];
+const CONFLICT_POLICIES: &[&str] = &["ask", "overwrite", "skip", "rename"];
+
+const THEME_KEY: &str = "theme";
+const CHUNK_SIZE_KEY: &str = "chunk_size";
+const PARALLEL_UPLOADS_KEY: &str = "parallel_uploads";
+const BANDWIDTH_LIMIT_KEY: &str = "bandwidth_limit";
+const CONFLICT_RESOLUTION_KEY: &str = "conflict_resolution";
+const NOTIFICATIONS_KEY: &str = "notifications";
+
+impl AppSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&self.chunk_size) {
+            bail!("Chunk size must be between 5 MB and 1 GB");
+        }
+        if !(1..=MAX_PARALLEL_UPLOADS).contains(&self.parallel_uploads) {
+            bail!("Parallel uploads must be between 1 and {}", MAX_PARALLEL_UPLOADS);
+        }
+        if self.chunk_size * self.parallel_uploads > MAX_UPLOAD_MEMORY {
+            bail!(
+                "Chunk size times parallel uploads must be at most 1 GB; with this chunk size, use at most {} parallel uploads",
+                MAX_UPLOAD_MEMORY / self.chunk_size
+            );
+        }
+        if !THEMES.contains(&self.theme.as_str()) {
+            bail!("Unknown theme: {}", self.theme);
+        }
+        if !CONFLICT_POLICIES.contains(&self.conflict_resolution.as_str()) {
+            bail!("Unknown conflict resolution: {}", self.conflict_resolution);
+        }
+        Ok(())
+    }
+
+    /// Saved settings; missing or invalid values fall back to their defaults
+    pub async fn load(db: &Database) -> Result<Self> {
+        let defaults = Self::default();
+        let number = |value: Option<String>, default: usize| value.and_then(|v| v.parse().ok()).unwrap_or(default);
+
+        let mut settings = Self {
+            theme: db.get_setting(THEME_KEY).await?.unwrap_or(defaults.theme.clone()),
+            chunk_size: number(db.get_setting(CHUNK_SIZE_KEY).await?, defaults.chunk_size),
+            parallel_uploads: number(db.get_setting(PARALLEL_UPLOADS_KEY).await?, defaults.parallel_uploads),
+            bandwidth_limit: number(db.get_setting(BANDWIDTH_LIMIT_KEY).await?, defaults.bandwidth_limit),
+            conflict_resolution: match db.get_setting(CONFLICT_RESOLUTION_KEY).await?.as_deref() {
+                // Values from before conflict policies had their current names
+                Some("local") => "overwrite".to_string(),
+                Some("remote") => "skip".to_string(),
+                Some(value) => value.to_string(),
+                None => defaults.conflict_resolution.clone(),
+            },
+            notifications: db
+                .get_setting(NOTIFICATIONS_KEY)
+                .await?
+                .map(|v| v != "false")
+                .unwrap_or(defaults.notifications),
+        };
+
+        if !THEMES.contains(&settings.theme.as_str()) {
+            settings.theme = defaults.theme;
+        }
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&settings.chunk_size) {
+            settings.chunk_size = defaults.chunk_size;
+        }
+        if !(1..=MAX_PARALLEL_UPLOADS).contains(&settings.parallel_uploads) {
+            settings.parallel_uploads = defaults.parallel_uploads;
+        }
+        // Large chunks from before memory use was bounded keep their size, with fewer in flight
+        settings.parallel_uploads = settings.parallel_uploads.min(MAX_UPLOAD_MEMORY / settings.chunk_size);
+        if !CONFLICT_POLICIES.contains(&settings.conflict_resolution.as_str()) {
+            settings.conflict_resolution = defaults.conflict_resolution;
+        }
+        Ok(settings)
+    }
+
+    pub async fn save(&self, db: &Database) -> Result<()> {
+        self.validate()?;
+
+        db.set_settings(&[
+            (THEME_KEY, self.theme.clone()),
+            (CHUNK_SIZE_KEY, self.chunk_size.to_string()),
+            (PARALLEL_UPLOADS_KEY, self.parallel_uploads.to_string()),
+            (BANDWIDTH_LIMIT_KEY, self.bandwidth_limit.to_string()),
+            (CONFLICT_RESOLUTION_KEY, self.conflict_resolution.clone()),
+            (NOTIFICATIONS_KEY, self.notifications.to_string()),
+        ])
+        .await
+    }
+}
+
+/// The settings in effect. Every upload reads them as it starts, sync folders' included, so a
+/// change applies from the next file on without a restart; the bandwidth limit applies from
+/// the next request, in-flight uploads included.
+pub struct LiveSettings {
+    current: RwLock<AppSettings>,
+}
+
+impl LiveSettings {
+    pub fn new(settings: AppSettings) -> Self {
+        throttle::UPLOADS.set_limit(settings.bandwidth_limit as u64);
+        Self { current: RwLock::new(settings) }
+    }
+
+    pub fn current(&self) -> AppSettings {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, settings: AppSettings) {
+        throttle::UPLOADS.set_limit(settings.bandwidth_limit as u64);
+        *self.current.write().unwrap() = settings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_validate() {
+        assert!(AppSettings::default().validate().is_ok());
+
+        let small_chunks = AppSettings { chunk_size: 4 * 1024 * 1024, ..Default::default() };
+        assert!(small_chunks.validate().unwrap_err().to_string().contains("Chunk size"));
+        let no_parallelism = AppSettings { parallel_uploads: 0, ..Default::default() };
+        assert!(no_parallelism.validate().unwrap_err().to_string().contains("Parallel uploads"));
+        let theme = AppSettings { theme: "neon".to_string(), ..Default::default() };
+        assert!(theme.validate().is_err());
+        let policy = AppSettings { conflict_resolution: "newest".to_string(), ..Default::default() };
+        assert!(policy.validate().is_err());
+        let minimum = AppSettings { chunk_size: MIN_CHUNK_SIZE, parallel_uploads: 1, ..Default::default() };
+        assert!(minimum.validate().is_ok());
+
+        // 1 GB parts are fine one at a time, but not two of them in memory at once
+        let largest = AppSettings { chunk_size: MAX_CHUNK_SIZE, parallel_uploads: 1, ..Default::default() };
+        assert!(largest.validate().is_ok());
+        let two = AppSettings { parallel_uploads: 2, ..largest.clone() };
+        assert!(two.validate().unwrap_err().to_string().contains("at most 1 parallel"));
+        let huge = AppSettings { chunk_size: MAX_CHUNK_SIZE + 1, ..largest };
+        assert!(huge.validate().unwrap_err().to_string().contains("between 5 MB and 1 GB"));
+        let bounded = AppSettings { chunk_size: 64 * 1024 * 1024, parallel_uploads: 16, ..Default::default() };
+        assert!(bounded.validate().is_ok());
+        let too_many = AppSettings { parallel_uploads: 17, ..bounded };
+        assert!(too_many.validate().unwrap_err().to_string().contains("at most 16 parallel"));
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(Some(temp_dir.path().join("test.db"))).await.unwrap();
+        assert_eq!(AppSettings::load(&db).await.unwrap(), AppSettings::default());
+
+        let settings = AppSettings {
+            theme: "dark".to_string(),
+            chunk_size: 8 * 1024 * 1024,
+            parallel_uploads: 3,
+            bandwidth_limit: 512 * 1024,
+            conflict_resolution: "rename".to_string(),
+            notifications: false,
+        };
+        settings.save(&db).await.unwrap();
+        assert_eq!(AppSettings::load(&db).await.unwrap(), settings);
+
+        // Invalid settings are rejected without touching the saved ones
+        let invalid = AppSettings { parallel_uploads: 0, ..settings.clone() };
+        assert!(invalid.save(&db).await.is_err());
+        assert_eq!(AppSettings::load(&db).await.unwrap(), settings);
+    }
+
+    #[tokio::test]
+    async fn test_load_falls_back_on_bad_values() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(Some(temp_dir.path().join("test.db"))).await.unwrap();
+        db.set_setting(CHUNK_SIZE_KEY, "1024").await.unwrap();
+        db.set_setting(PARALLEL_UPLOADS_KEY, "many").await.unwrap();
+        db.set_setting(CONFLICT_RESOLUTION_KEY, "local").await.unwrap();
+
+        let settings = AppSettings::load(&db).await.unwrap();
+        let defaults = AppSettings::default();
+        assert_eq!(settings.chunk_size, defaults.chunk_size);
+        assert_eq!(settings.parallel_uploads, defaults.parallel_uploads);
+        assert_eq!(settings.conflict_resolution, "overwrite");
+
+        // Saved before memory use was bounded
+        db.set_setting(CHUNK_SIZE_KEY, &(512 * 1024 * 1024).to_string()).await.unwrap();
+        db.set_setting(PARALLEL_UPLOADS_KEY, "8").await.unwrap();
+        let settings = AppSettings::load(&db).await.unwrap();
+        assert_eq!(settings.parallel_uploads, 2);
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_live_settings() {
+        let live = LiveSettings::new(AppSettings::default());
+        live.replace(AppSettings { parallel_uploads: 2, ..Default::default() });
+        assert_eq!(live.current().parallel_uploads, 2);
+    }
+}
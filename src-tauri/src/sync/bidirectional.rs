@@ -0,0 +1,839 @@
+use anyhow::{bail, Context, Result};
+use aws_sdk_s3::Client;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use tauri::Emitter;
+use tokio::sync::watch;
+
+use super::deletions::{held_notice, DeletionGate};
+use super::engine::{expand, idle_status, Worker};
+use super::moves::expand_renames;
+use super::reconcile::collect_local;
+use super::watcher::Batch;
+use crate::db::{DownloadRecord, SyncEntry};
+use crate::r2::checksum::etags_match;
+use crate::r2::operations::{delete_object, get_object, head_object, ObjectInfo};
+use crate::upload::conflict::labeled_key;
+use crate::upload::fingerprint::{check_unchanged, hash_file, ChangedDuringUpload, LocalStat};
+use crate::utils::paths::{join_key, local_path_for, relative_path};
+use crate::utils::{
+    DeletionPolicy, DeletionSide, FileSyncState, ReconcileReport, SyncConflict, SyncDiffEntry, SyncDiffKind, SyncState,
+    SyncStatus,
+};
+
+/// Suffix of in-progress downloads, renamed into place once complete
+const DOWNLOAD_SUFFIX: &str = ".r2sync.part";
+
+/// How one side changed since the last sync recorded in the journal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Unchanged,
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    fn is_edit(self) -> bool {
+        matches!(self, Change::Created | Change::Modified)
+    }
+}
+
+/// What to do with a path once both sides have been compared with the journal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Upload,
+    Download,
+    DeleteRemote,
+    DeleteLocal,
+    /// Both sides gone, only the journal entry is left
+    Forget,
+    /// Both sides changed; identical content is fine, anything else is a conflict
+    CompareContents,
+    /// A deletion and a new file found to be the same file moved; never returned by `decide`
+    Move,
+}
+
+/// Whether the local file has to be hashed to tell if it changed:
+/// same size but a different modification time, and a journal hash to compare with
+pub fn needs_hash(local: &LocalStat, entry: &SyncEntry) -> bool {
+    local.size == entry.local_size && local.mtime != entry.local_mtime && entry.local_hash.is_some()
+}
+
+/// Local change since the journal entry. `hash` is the SHA-256 of the file when `needs_hash` asked for it.
+pub fn local_change(local: Option<&LocalStat>, entry: Option<&SyncEntry>, hash: Option<&str>) -> Change {
+    match (local, entry) {
+        (None, None) => Change::Unchanged,
+        (None, Some(_)) => Change::Deleted,
+        (Some(_), None) => Change::Created,
+        (Some(local), Some(entry)) => {
+            if local.size != entry.local_size {
+                Change::Modified
+            } else if local.mtime == entry.local_mtime {
+                Change::Unchanged
+            } else {
+                // Touched but possibly not edited
+                match (hash, entry.local_hash.as_deref()) {
+                    (Some(hash), Some(known)) if hash.eq_ignore_ascii_case(known) => Change::Unchanged,
+                    _ => Change::Modified,
+                }
+            }
+        }
+    }
+}
+
+/// Remote change since the journal entry, from the object's current ETag
+pub fn remote_change(remote_etag: Option<&str>, entry: Option<&SyncEntry>) -> Change {
+    match (remote_etag, entry) {
+        (None, None) => Change::Unchanged,
+        (None, Some(_)) => Change::Deleted,
+        (Some(_), None) => Change::Created,
+        (Some(etag), Some(entry)) if etags_match(etag, &entry.remote_etag) => Change::Unchanged,
+        (Some(_), Some(_)) => Change::Modified,
+    }
+}
+
+/// Combine both sides. An edit always beats a deletion so no content is lost.
+pub fn decide(local: Change, remote: Change) -> Action {
+    match (local, remote) {
+        (Change::Unchanged, Change::Unchanged) => Action::None,
+        (Change::Deleted, Change::Deleted) => Action::Forget,
+        (Change::Deleted, Change::Unchanged) => Action::DeleteRemote,
+        (Change::Unchanged, Change::Deleted) => Action::DeleteLocal,
+        (l, r) if l.is_edit() && r.is_edit() => Action::CompareContents,
+        (l, _) if l.is_edit() => Action::Upload,
+        _ => Action::Download,
+    }
+}
+
+/// Returned when a local file changed after the pass decided to replace or delete it
+#[derive(Debug, thiserror::Error)]
+#[error("{0} changed locally while it was being synced")]
+pub struct ChangedLocally(pub String);
+
+/// Fail with `ChangedLocally` unless the file at `path` still has the size and mtime the pass planned with.
+/// `planned` is `None` when the pass saw no file there; a file deleted since can always be replaced.
+pub(super) async fn ensure_unchanged(path: &Path, planned: Option<&LocalStat>) -> Result<()> {
+    if !tokio::fs::try_exists(path).await? {
+        return Ok(());
+    }
+    let now = LocalStat::read(&path.to_string_lossy()).await?;
+    match planned {
+        Some(then) if then.size == now.size && then.mtime == now.mtime => Ok(()),
+        _ => Err(ChangedLocally(path.display().to_string()).into()),
+    }
+}
+
+/// Name for this device's copy of a conflicting file, e.g. "report (conflict 2026-10-16 host).docx"
+pub fn conflict_name(relative: &str, date: &str, host: &str) -> String {
+    labeled_key(relative, &format!("conflict {} {}", date, host))
+}
+
+/// Name of this machine, used to label conflict copies
+pub fn host_name() -> String {
+    whoami::fallible::hostname()
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "this device".to_string())
+}
+
+/// Result of syncing one path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Unchanged,
+    Uploaded,
+    Downloaded,
+    Deleted,
+    Moved,
+    /// A deletion waiting for confirmation
+    Held,
+    Conflict,
+}
+
+/// Running totals for one two-way pass
+#[derive(Default)]
+struct Tally {
+    transferred: usize,
+    uploaded: usize,
+    downloaded: usize,
+    deleted: usize,
+    moved: usize,
+    held: usize,
+    conflicts: usize,
+    failed: usize,
+}
+
+impl Tally {
+    fn add(&mut self, outcome: &Result<Outcome>) {
+        match outcome {
+            Ok(Outcome::Unchanged) => {}
+            Ok(Outcome::Uploaded) => {
+                self.uploaded += 1;
+                self.transferred += 1;
+            }
+            Ok(Outcome::Downloaded) => {
+                self.downloaded += 1;
+                self.transferred += 1;
+            }
+            Ok(Outcome::Deleted) => self.deleted += 1,
+            Ok(Outcome::Moved) => {
+                self.moved += 1;
+                self.transferred += 1;
+            }
+            Ok(Outcome::Held) => self.held += 1,
+            Ok(Outcome::Conflict) => {
+                self.conflicts += 1;
+                self.transferred += 1;
+            }
+            // Queued again once the file settles
+            Err(e) if e.is::<ChangedDuringUpload>() => {}
+            Err(_) => self.failed += 1,
+        }
+    }
+}
+
+/// The badge a file gets after a pass acted on it, if the pass records one
+fn outcome_state(action: Action, outcome: &Result<Outcome>) -> Option<(FileSyncState, Option<String>)> {
+    match outcome {
+        Ok(Outcome::Downloaded | Outcome::Moved) => Some((FileSyncState::Synced, None)),
+        Ok(Outcome::Conflict) => Some((FileSyncState::Conflicted, None)),
+        // Uploads record their own failures as they happen; recording them here would count them twice
+        Err(_) if action == Action::Upload => None,
+        // Queued again once the file settles
+        Err(e) if e.is::<ChangedDuringUpload>() => None,
+        Err(e) => Some((FileSyncState::Error, Some(format!("{:#}", e)))),
+        Ok(_) => None,
+    }
+}
+
+/// A path's planned action, with what was learned deciding it
+pub(super) struct Planned {
+    pub(super) relative: String,
+    pub(super) action: Action,
+    pub(super) local: Option<LocalStat>,
+    /// Set when the file had to be hashed to tell whether it changed
+    hash: Option<String>,
+    /// For a move, the entry of the path it moved from
+    pub(super) entry: Option<SyncEntry>,
+    pub(super) moved_from: Option<String>,
+}
+
+impl Planned {
+    fn deletion(&self) -> Option<(String, DeletionSide)> {
+        match self.action {
+            Action::DeleteRemote => Some((self.relative.clone(), DeletionSide::Remote)),
+            Action::DeleteLocal => Some((self.relative.clone(), DeletionSide::Local)),
+            _ => None,
+        }
+    }
+}
+
+impl Worker {
+    /// Two-way comparison of the whole folder against its remote prefix and the journal
+    pub(super) async fn reconcile_two_way(&self, shutdown: &watch::Receiver<bool>) {
+        self.board.publish(idle_status(self.folder_id, SyncState::Syncing, Some("Scanning".to_string())));
+        self.clear_file_states().await;
+        let started = chrono::Utc::now();
+
+        let report = match self.sync_all_two_way(shutdown).await {
+            Ok(report) => report,
+            Err(e) => {
+                log::error!("Two-way sync of {} failed: {}", self.root.display(), e);
+                self.board.publish(idle_status(self.folder_id, SyncState::Error, Some(e.to_string())));
+                return;
+            }
+        };
+        log::info!(
+            "Synced {} both ways: {} uploaded, {} downloaded, {} moved, {} deleted, {} held, {} conflicts, {} failed",
+            self.root.display(),
+            report.uploaded,
+            report.downloaded,
+            report.moved,
+            report.deleted,
+            report.held,
+            report.conflicts,
+            report.failed
+        );
+
+        self.resolve_errors(None, started, shutdown).await;
+
+        let transferred = report.uploaded + report.downloaded + report.moved;
+        let (failed, held) = (report.failed, report.held);
+        self.board.publish_report(report);
+        self.finish_pass(transferred, failed, held_notice(held)).await;
+    }
+
+    async fn sync_all_two_way(&self, shutdown: &watch::Receiver<bool>) -> Result<ReconcileReport> {
+        // An unmounted drive must not read as every file having been deleted
+        if !self.root.is_dir() {
+            bail!("{} is not available", self.root.display());
+        }
+
+        let (client, bucket) = self.connection().await?;
+
+        let local: BTreeMap<String, LocalStat> = collect_local(&self.root, &self.remote_prefix)
+            .await
+            .into_values()
+            .filter_map(|file| Some((relative_path(&self.root, &file.path)?, file.stat)))
+            .collect();
+        let remote = self.list_remote(&client, &bucket).await?;
+        let entries = self.db.get_sync_entries(self.folder_id).await?;
+
+        self.quarantine_collisions(local.keys().chain(remote.keys()).map(String::as_str)).await;
+        let quarantined = self.quarantined().await;
+        let paths: BTreeSet<&String> = local
+            .keys()
+            .chain(remote.keys())
+            .chain(entries.keys())
+            .filter(|relative| !quarantined.contains(*relative))
+            .collect();
+        let mut report = ReconcileReport {
+            folder_id: self.folder_id,
+            scanned_at: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        };
+        let mut tally = Tally::default();
+
+        let mut plans = Vec::with_capacity(paths.len());
+        for relative in paths {
+            let etag = remote.get(relative).map(|o| o.etag.as_str());
+            match self.plan_path(relative, local.get(relative), etag, entries.get(relative)).await {
+                Ok(planned) => plans.push(planned),
+                Err(e) => {
+                    log::error!("Two-way sync of {} failed: {:#}", relative, e);
+                    self.record_file_state(relative, FileSyncState::Error, Some(format!("{:#}", e))).await;
+                    tally.failed += 1;
+                }
+            }
+        }
+
+        // Moved files are copied on the remote rather than uploaded again and deleted
+        let plans = self.pair_moves(plans, &[]).await;
+        let deletions: Vec<(String, DeletionSide)> = plans.iter().filter_map(Planned::deletion).collect();
+        let gate = self.gate_deletions(&deletions, entries.len()).await?;
+
+        let total = plans.len();
+        for (i, planned) in plans.iter().enumerate() {
+            if *shutdown.borrow() {
+                break;
+            }
+            self.publish_progress(total - i, &tally);
+
+            let relative = &planned.relative;
+            let outcome = self.apply(&client, &bucket, planned, &gate).await;
+            if let Err(e) = &outcome {
+                log::error!("Two-way sync of {} failed: {:#}", relative, e);
+            }
+            self.record_outcome(planned, &outcome).await;
+            tally.add(&outcome);
+            if matches!(outcome, Ok(Outcome::Moved)) {
+                continue;
+            }
+
+            let stat = local.get(relative);
+            let object = remote.get(relative);
+            let kind = match (stat, object) {
+                (None, None) => continue,
+                (Some(_), None) => SyncDiffKind::LocalOnly,
+                (None, Some(_)) => SyncDiffKind::RemoteOnly,
+                (Some(_), Some(_)) if matches!(outcome, Ok(Outcome::Unchanged)) => SyncDiffKind::Identical,
+                (Some(_), Some(_)) => SyncDiffKind::Changed,
+            };
+            report.count(kind);
+            if kind == SyncDiffKind::Identical {
+                continue;
+            }
+            report.entries.push(SyncDiffEntry {
+                key: self.remote_key(relative),
+                local_path: stat.map(|_| local_path_for(&self.root, relative).to_string_lossy().to_string()),
+                kind,
+                local_size: stat.map(|s| s.size),
+                remote_size: object.map(|o| o.size),
+            });
+        }
+
+        report.uploaded = tally.uploaded;
+        report.downloaded = tally.downloaded;
+        report.deleted = tally.deleted;
+        report.moved = tally.moved;
+        report.held = tally.held;
+        report.conflicts = tally.conflicts;
+        report.failed = tally.failed;
+        Ok(report)
+    }
+
+    /// Sync the paths behind a batch of watcher events, checking each against the remote.
+    /// Files moved within the batch are moved on the remote too; other deletions are left to
+    /// a full pass, which can tell how much of the folder they cover.
+    pub(super) async fn sync_paths_two_way(&self, pending: &mut Batch, shutdown: &watch::Receiver<bool>) {
+        let events = std::mem::take(pending);
+        let result = async {
+            let (client, bucket) = self.connection().await?;
+            let entries = self.db.get_sync_entries(self.folder_id).await?;
+
+            let mut paths: BTreeSet<String> = expand(events.paths.clone())
+                .await
+                .iter()
+                .filter_map(|path| relative_path(&self.root, path))
+                .collect();
+            // A removed file or folder no longer shows up in the walk, only in the journal
+            for path in events.paths.iter().filter(|p| !p.exists()) {
+                if let Some(relative) = relative_path(&self.root, path) {
+                    let nested = format!("{}/", relative);
+                    paths.extend(
+                        entries
+                            .keys()
+                            .filter(|known| **known == relative || known.starts_with(&nested))
+                            .cloned(),
+                    );
+                }
+            }
+            let quarantined = self.quarantined().await;
+            paths.retain(|relative| !quarantined.contains(relative));
+            Ok::<_, anyhow::Error>((client, bucket, entries, paths))
+        }
+        .await;
+
+        let (client, bucket, entries, paths) = match result {
+            Ok(found) => found,
+            Err(e) => {
+                log::error!("Two-way sync of {} failed: {}", self.root.display(), e);
+                self.board.publish(idle_status(self.folder_id, SyncState::Error, Some(e.to_string())));
+                return;
+            }
+        };
+        let renames: Vec<(String, String)> = events
+            .renames
+            .iter()
+            .filter_map(|(from, to)| Some((relative_path(&self.root, from)?, relative_path(&self.root, to)?)))
+            .collect();
+
+        let mut tally = Tally::default();
+        let mut plans = Vec::with_capacity(paths.len());
+        for (i, relative) in paths.iter().enumerate() {
+            if *shutdown.borrow() {
+                return;
+            }
+            self.publish_progress(paths.len() - i, &tally);
+
+            let planned = async {
+                let path = local_path_for(&self.root, relative);
+                let stat = match path.is_file() {
+                    true => Some(LocalStat::read(&path.to_string_lossy()).await?),
+                    false => None,
+                };
+                let key = self.remote_key(relative);
+                let etag = head_object(&client, &bucket, &key).await?.map(|o| o.etag);
+                self.plan_path(relative, stat.as_ref(), etag.as_deref(), entries.get(relative)).await
+            }
+            .await;
+            match planned {
+                Ok(planned) => plans.push(planned),
+                Err(e) => {
+                    log::error!("Two-way sync of {} failed: {:#}", relative, e);
+                    self.record_file_state(relative, FileSyncState::Error, Some(format!("{:#}", e))).await;
+                    tally.failed += 1;
+                }
+            }
+        }
+        let plans = self.pair_moves(plans, &expand_renames(&renames, &paths)).await;
+
+        let total = plans.len();
+        let mut deletions_found = false;
+        let no_deletions = DeletionGate::default();
+        for (i, planned) in plans.iter().enumerate() {
+            if *shutdown.borrow() {
+                break;
+            }
+            self.publish_progress(total - i, &tally);
+
+            if planned.deletion().is_some() {
+                deletions_found = true;
+                continue;
+            }
+            let outcome = self.apply(&client, &bucket, planned, &no_deletions).await;
+            if let Err(e) = &outcome {
+                log::error!("Two-way sync of {} failed: {:#}", planned.relative, e);
+            }
+            self.record_outcome(planned, &outcome).await;
+            tally.add(&outcome);
+        }
+
+        if deletions_found && !*shutdown.borrow() {
+            self.reconcile_two_way(shutdown).await;
+        } else {
+            self.finish_pass(tally.transferred, tally.failed, None).await;
+        }
+    }
+
+    /// Update the badge of a file after syncing it
+    async fn record_outcome(&self, planned: &Planned, outcome: &Result<Outcome>) {
+        if let Some((state, message)) = outcome_state(planned.action, outcome) {
+            self.record_file_state(&planned.relative, state, message).await;
+        }
+    }
+
+    fn publish_progress(&self, pending: usize, tally: &Tally) {
+        self.board.publish(SyncStatus {
+            folder_id: self.folder_id,
+            state: SyncState::Syncing,
+            pending,
+            uploaded: tally.transferred,
+            failed: tally.failed,
+            message: None,
+        });
+    }
+
+    /// Work out what one path needs, hashing the local file only if size and mtime can't tell
+    pub(super) async fn plan_path(
+        &self,
+        relative: &str,
+        local: Option<&LocalStat>,
+        remote_etag: Option<&str>,
+        entry: Option<&SyncEntry>,
+    ) -> Result<Planned> {
+        let hash = match (local, entry) {
+            (Some(stat), Some(entry)) if needs_hash(stat, entry) => {
+                let path = local_path_for(&self.root, relative);
+                Some(hash_file(&path.to_string_lossy()).await?.sha256)
+            }
+            _ => None,
+        };
+        let local_change = local_change(local, entry, hash.as_deref());
+        let remote_change = remote_change(remote_etag, entry);
+
+        Ok(Planned {
+            relative: relative.to_string(),
+            action: decide(local_change, remote_change),
+            local: local.copied(),
+            hash,
+            entry: entry.cloned(),
+            moved_from: None,
+        })
+    }
+
+    /// Carry out a planned action and update the path's journal entry
+    async fn apply(&self, client: &Client, bucket: &str, planned: &Planned, gate: &DeletionGate) -> Result<Outcome> {
+        let relative = planned.relative.as_str();
+        let path = local_path_for(&self.root, relative);
+
+        match planned.action {
+            Action::None => {
+                // A touched file whose content didn't change: remember the new mtime to skip hashing next time
+                if let (Some(stat), Some(entry), Some(_)) = (&planned.local, &planned.entry, &planned.hash) {
+                    let updated = SyncEntry { local_mtime: stat.mtime, local_inode: stat.inode, ..entry.clone() };
+                    self.db.save_sync_entry(self.folder_id, relative, &updated).await?;
+                }
+                Ok(Outcome::Unchanged)
+            }
+            Action::Upload => {
+                self.upload_and_record(client, bucket, relative, &path).await?;
+                Ok(Outcome::Uploaded)
+            }
+            Action::Download => match self.download_and_record(client, bucket, relative, &path, planned.local.as_ref()).await {
+                // Edited while the remote copy was downloading: both versions are now edits
+                Err(e) if e.is::<ChangedLocally>() => {
+                    self.keep_both(client, bucket, relative, &path).await?;
+                    Ok(Outcome::Conflict)
+                }
+                downloaded => downloaded.map(|_| Outcome::Downloaded),
+            },
+            Action::DeleteRemote | Action::DeleteLocal if !gate.allows(relative) => {
+                // Held for confirmation, or the policy keeps both copies
+                Ok(if gate.held > 0 && self.deletions.policy != DeletionPolicy::Never {
+                    Outcome::Held
+                } else {
+                    Outcome::Unchanged
+                })
+            }
+            Action::DeleteRemote => {
+                self.delete_remote(client, bucket, relative, gate).await?;
+                Ok(Outcome::Deleted)
+            }
+            Action::DeleteLocal => match self.delete_local(relative, gate, planned.local.as_ref()).await {
+                // The edit wins over the remote deletion next pass
+                Err(e) if e.is::<ChangedLocally>() => {
+                    log::info!("{}, not deleting it", e);
+                    Ok(Outcome::Unchanged)
+                }
+                deleted => deleted.map(|_| Outcome::Deleted),
+            },
+            Action::Forget => {
+                self.db.delete_sync_entry(self.folder_id, relative).await?;
+                Ok(Outcome::Unchanged)
+            }
+            Action::Move => {
+                let (Some(from), Some(entry), Some(stat)) = (&planned.moved_from, &planned.entry, planned.local) else {
+                    bail!("Incomplete move to {}", relative);
+                };
+                self.move_remote(client, bucket, from, relative, entry, stat).await?;
+                Ok(Outcome::Moved)
+            }
+            Action::CompareContents => {
+                // Both sides may have made the same edit
+                if self.record_if_identical(client, bucket, relative, &path).await? {
+                    Ok(Outcome::Unchanged)
+                } else {
+                    self.keep_both(client, bucket, relative, &path).await?;
+                    Ok(Outcome::Conflict)
+                }
+            }
+        }
+    }
+
+    /// Resolve a concurrent edit by keeping both copies: this device's version moves aside
+    /// under a conflict name and is uploaded, and the remote version takes the original name.
+    pub(super) async fn keep_both(&self, client: &Client, bucket: &str, relative: &str, path: &Path) -> Result<()> {
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let host = host_name();
+        let mut conflict_relative = conflict_name(relative, &date, &host);
+        let mut n = 2;
+        while local_path_for(&self.root, &conflict_relative).exists() {
+            conflict_relative = conflict_name(relative, &date, &format!("{} {}", host, n));
+            n += 1;
+        }
+        let conflict_path = local_path_for(&self.root, &conflict_relative);
+
+        tokio::fs::rename(path, &conflict_path)
+            .await
+            .with_context(|| format!("Failed to move {} aside", path.display()))?;
+        self.upload_and_record(client, bucket, &conflict_relative, &conflict_path).await?;
+        self.download_and_record(client, bucket, relative, path, None).await?;
+
+        let id = self.db.add_sync_conflict(self.folder_id, relative, &conflict_relative).await?;
+        log::warn!("Conflicting edits to {}, kept this device's copy as {}", relative, conflict_relative);
+
+        let conflict = SyncConflict {
+            id,
+            folder_id: self.folder_id,
+            relative_path: relative.to_string(),
+            conflict_path: conflict_relative,
+            detected_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            resolved_at: None,
+        };
+        if let Err(e) = self.board.app.emit("sync-conflict", &conflict) {
+            log::warn!("Failed to emit sync conflict: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Start tracking a file that already has the same content on both sides. Returns false if it differs.
+    pub(super) async fn record_if_identical(&self, client: &Client, bucket: &str, relative: &str, path: &Path) -> Result<bool> {
+        let key = self.remote_key(relative);
+        let bucket_id = self.db.get_current_bucket_id().await?;
+        let check = check_unchanged(&self.db, client, bucket, bucket_id, &path.to_string_lossy(), &key).await?;
+        let remote = match (check.is_unchanged(), check.remote) {
+            (true, Some(remote)) => remote,
+            _ => return Ok(false),
+        };
+
+        let local_hash = match check.hashes {
+            Some(hashes) => hashes.sha256,
+            None => hash_file(&path.to_string_lossy()).await?.sha256,
+        };
+        self.record(relative, check.local, local_hash, Some(remote)).await?;
+        Ok(true)
+    }
+
+    pub(super) async fn upload_and_record(&self, client: &Client, bucket: &str, relative: &str, path: &Path) -> Result<()> {
+        let key = join_key(&self.remote_prefix, relative);
+        let stored = self.remote_key(relative);
+        // The upload records its own state, failed or not
+        let uploaded = self.upload(path, key.clone(), false).await?;
+        if stored != key {
+            // Replaced by the copy under its NFC key
+            delete_object(client, bucket, &stored).await?;
+            self.forget_stored_key(relative);
+        }
+
+        // Journal the content that was sent, not whatever the file holds by now
+        let recorded = async {
+            let (stat, sha256) = uploaded.sent.with_context(|| format!("Upload of {} was skipped", key))?;
+            let remote = head_object(client, bucket, &key)
+                .await?
+                .with_context(|| format!("{} is missing after upload", key))?;
+            self.record(relative, stat, sha256, Some(remote)).await
+        }
+        .await;
+        if let Err(e) = &recorded {
+            self.record_file_state(relative, FileSyncState::Error, Some(format!("{:#}", e))).await;
+        }
+        recorded
+    }
+
+    /// Download to a temporary name first, so a half-written file never replaces a good one, nor one
+    /// changed since `planned` was read. The download goes in the transfer history whether or not it succeeds.
+    pub(super) async fn download_and_record(
+        &self,
+        client: &Client,
+        bucket: &str,
+        relative: &str,
+        path: &Path,
+        planned: Option<&LocalStat>,
+    ) -> Result<()> {
+        let key = self.remote_key(relative);
+        let started_at = chrono::Utc::now();
+        let downloaded = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+
+            let mut part = path.as_os_str().to_owned();
+            part.push(DOWNLOAD_SUFFIX);
+            let part = PathBuf::from(part);
+            // Read the ETag first: if the object changes mid-download, the next pass still sees a change
+            let remote = head_object(client, bucket, &key).await?;
+            get_object(client, bucket, &key, &part.to_string_lossy()).await?;
+            if let Err(e) = ensure_unchanged(path, planned).await {
+                tokio::fs::remove_file(&part).await.ok();
+                return Err(e);
+            }
+            tokio::fs::rename(&part, path)
+                .await
+                .with_context(|| format!("Failed to move download into place at {}", path.display()))?;
+
+            let path_str = path.to_string_lossy();
+            let stat = LocalStat::read(&path_str).await?;
+            let hashes = hash_file(&path_str).await?;
+            anyhow::Ok((stat, hashes.sha256, remote))
+        }
+        .await;
+
+        let download = DownloadRecord {
+            local_path: path.to_string_lossy().to_string(),
+            remote_path: key.clone(),
+            size: downloaded.as_ref().map_or(0, |(stat, ..)| stat.size),
+            etag: downloaded.as_ref().ok().and_then(|(.., remote)| remote.as_ref()).map(|r| r.etag.clone()),
+            sha256: downloaded.as_ref().ok().map(|(_, sha256, _)| sha256.clone()),
+            started_at,
+            error: downloaded.as_ref().err().map(|e| format!("{:#}", e)),
+        };
+        if let Err(e) = self.db.record_download(&download).await {
+            log::warn!("Failed to record download of {}: {}", key, e);
+        }
+
+        let (stat, sha256, remote) = downloaded?;
+        self.record(relative, stat, sha256, remote).await
+    }
+
+    /// Journal a file as in sync. A missing object is recorded with an empty ETag, so it reads as deleted next pass.
+    pub(super) async fn record(&self, relative: &str, stat: LocalStat, local_hash: String, remote: Option<ObjectInfo>) -> Result<()> {
+        let entry = SyncEntry {
+            local_size: stat.size,
+            local_mtime: stat.mtime,
+            local_inode: stat.inode,
+            local_hash: Some(local_hash),
+            remote_etag: remote.as_ref().map(|r| r.etag.clone()).unwrap_or_default(),
+            remote_last_modified: remote.and_then(|r| r.last_modified),
+        };
+        self.db.save_sync_entry(self.folder_id, relative, &entry).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(size: i64, mtime: i64, hash: &str, etag: &str) -> SyncEntry {
+        SyncEntry {
+            local_size: size,
+            local_mtime: mtime,
+            local_hash: Some(hash.to_string()),
+            remote_etag: etag.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_local_change() {
+        let known = entry(10, 1000, "abc", "\"e1\"");
+        let same = LocalStat { size: 10, mtime: 1000, inode: None };
+        let touched = LocalStat { size: 10, mtime: 2000, inode: None };
+        let resized = LocalStat { size: 11, mtime: 1000, inode: None };
+
+        assert_eq!(local_change(None, None, None), Change::Unchanged);
+        assert_eq!(local_change(None, Some(&known), None), Change::Deleted);
+        assert_eq!(local_change(Some(&same), None, None), Change::Created);
+        assert_eq!(local_change(Some(&same), Some(&known), None), Change::Unchanged);
+        assert_eq!(local_change(Some(&resized), Some(&known), None), Change::Modified);
+
+        assert!(needs_hash(&touched, &known));
+        assert!(!needs_hash(&resized, &known));
+        assert_eq!(local_change(Some(&touched), Some(&known), Some("ABC")), Change::Unchanged);
+        assert_eq!(local_change(Some(&touched), Some(&known), Some("def")), Change::Modified);
+    }
+
+    #[test]
+    fn test_remote_change() {
+        let known = entry(10, 1000, "abc", "\"e1\"");
+        assert_eq!(remote_change(None, None), Change::Unchanged);
+        assert_eq!(remote_change(None, Some(&known)), Change::Deleted);
+        assert_eq!(remote_change(Some("\"e1\""), None), Change::Created);
+        assert_eq!(remote_change(Some("e1"), Some(&known)), Change::Unchanged);
+        assert_eq!(remote_change(Some("\"e2\""), Some(&known)), Change::Modified);
+    }
+
+    #[test]
+    fn test_decide() {
+        use Change::*;
+        assert_eq!(decide(Unchanged, Unchanged), Action::None);
+        assert_eq!(decide(Created, Unchanged), Action::Upload);
+        assert_eq!(decide(Modified, Unchanged), Action::Upload);
+        assert_eq!(decide(Unchanged, Modified), Action::Download);
+        assert_eq!(decide(Unchanged, Created), Action::Download);
+        assert_eq!(decide(Deleted, Unchanged), Action::DeleteRemote);
+        assert_eq!(decide(Unchanged, Deleted), Action::DeleteLocal);
+        assert_eq!(decide(Deleted, Deleted), Action::Forget);
+        // An edit on one side beats a deletion on the other
+        assert_eq!(decide(Deleted, Modified), Action::Download);
+        assert_eq!(decide(Modified, Deleted), Action::Upload);
+        assert_eq!(decide(Modified, Modified), Action::CompareContents);
+        assert_eq!(decide(Created, Created), Action::CompareContents);
+    }
+
+    #[test]
+    fn test_conflict_name() {
+        assert_eq!(
+            conflict_name("docs/report.docx", "2026-10-16", "laptop"),
+            "docs/report (conflict 2026-10-16 laptop).docx"
+        );
+        assert_eq!(conflict_name("Makefile", "2026-10-16", "laptop"), "Makefile (conflict 2026-10-16 laptop)");
+    }
+
+    #[test]
+    fn test_outcome_state() {
+        let failed: Result<Outcome> = Err(anyhow::anyhow!("dispatch failure: connection reset"));
+        // Worker::upload already filed the failure
+        assert_eq!(outcome_state(Action::Upload, &failed), None);
+        assert_eq!(
+            outcome_state(Action::Download, &failed),
+            Some((FileSyncState::Error, Some("dispatch failure: connection reset".to_string())))
+        );
+        let changed: Result<Outcome> = Err(ChangedDuringUpload("a.txt".to_string()).into());
+        assert_eq!(outcome_state(Action::CompareContents, &changed), None);
+
+        assert_eq!(outcome_state(Action::Download, &Ok(Outcome::Downloaded)), Some((FileSyncState::Synced, None)));
+        assert_eq!(outcome_state(Action::CompareContents, &Ok(Outcome::Conflict)), Some((FileSyncState::Conflicted, None)));
+        assert_eq!(outcome_state(Action::Upload, &Ok(Outcome::Uploaded)), None);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        // Nothing there yet, or deleted since: fine to replace
+        assert!(ensure_unchanged(&path, None).await.is_ok());
+
+        std::fs::write(&path, "local").unwrap();
+        let stat = LocalStat::read(&path.to_string_lossy()).await.unwrap();
+        assert!(ensure_unchanged(&path, Some(&stat)).await.is_ok());
+        assert!(ensure_unchanged(&path, None).await.unwrap_err().is::<ChangedLocally>());
+
+        std::fs::write(&path, "edited locally").unwrap();
+        assert!(ensure_unchanged(&path, Some(&stat)).await.unwrap_err().is::<ChangedLocally>());
+        std::fs::remove_file(&path).unwrap();
+        assert!(ensure_unchanged(&path, Some(&stat)).await.is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client;
+use std::collections::HashSet;
+use tauri::Emitter;
+
+use super::bidirectional::ensure_unchanged;
+use super::engine::{Worker, TRASH_DIR};
+use crate::r2::operations::{copy_large_object, delete_object, head_object};
+use crate::upload::fingerprint::LocalStat;
+use crate::utils::paths::{join_key, local_path_for};
+use crate::utils::{DeletionPolicy, DeletionSide, HeldDeletionsNotice};
+
+/// The percentage threshold only applies once a pass deletes at least this many files,
+/// so deleting one file from a small folder doesn't need confirming
+const PERCENT_MIN_FILES: usize = 5;
+
+/// A folder's deletion settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeletionRules {
+    pub policy: DeletionPolicy,
+    pub max_files: i64,
+    pub max_percent: i64,
+}
+
+impl DeletionRules {
+    /// Whether `count` deletions in one pass, in a folder of `tracked` synced files, need confirming
+    pub fn exceeds_threshold(&self, count: usize, tracked: usize) -> bool {
+        if count == 0 {
+            return false;
+        }
+        count as i64 > self.max_files
+            || (count >= PERCENT_MIN_FILES && count as i64 * 100 > self.max_percent * tracked as i64)
+    }
+}
+
+/// Which of a pass's deletions may go ahead
+#[derive(Debug, Default)]
+pub struct DeletionGate {
+    allowed: HashSet<String>,
+    /// Deletions held for confirmation
+    pub held: usize,
+    /// Names this pass's trash folder
+    stamp: String,
+}
+
+impl DeletionGate {
+    /// A gate for deletions the user has already reviewed, such as those of an executed plan
+    pub fn confirmed(paths: impl IntoIterator<Item = String>) -> Self {
+        Self { allowed: paths.into_iter().collect(), held: 0, stamp: trash_stamp() }
+    }
+
+    pub fn allows(&self, relative: &str) -> bool {
+        self.allowed.contains(relative)
+    }
+}
+
+/// Names the trash folder of a pass started now
+fn trash_stamp() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H%M%S").to_string()
+}
+
+/// Where a trashed file goes, relative to the folder root or remote prefix
+pub fn trash_path(relative: &str, stamp: &str) -> String {
+    format!("{}/{}/{}", TRASH_DIR, stamp, relative)
+}
+
+/// Status line for a pass that held deletions
+pub fn held_notice(held: usize) -> Option<String> {
+    (held > 0).then(|| format!("{} deletion{} waiting for confirmation", held, if held == 1 { "" } else { "s" }))
+}
+
+impl Worker {
+    /// Decide which of a full pass's deletions go ahead. Confirmed deletions always do; the rest
+    /// are all held when there are more of them than the folder's threshold allows.
+    pub(super) async fn gate_deletions(&self, planned: &[(String, DeletionSide)], tracked: usize) -> Result<DeletionGate> {
+        let stamp = trash_stamp();
+        if self.deletions.policy == DeletionPolicy::Never {
+            self.db.replace_held_deletions(self.folder_id, &[]).await?;
+            return Ok(DeletionGate { stamp, ..Default::default() });
+        }
+
+        let approved = self.db.get_approved_deletions(self.folder_id).await?;
+        let (confirmed, pending): (Vec<_>, Vec<_>) =
+            planned.iter().cloned().partition(|(relative, _)| approved.contains(relative));
+        let mut allowed: HashSet<String> = confirmed.into_iter().map(|(relative, _)| relative).collect();
+
+        let held = if self.deletions.exceeds_threshold(pending.len(), tracked) {
+            log::warn!(
+                "Holding {} deletions in {} for confirmation ({} files synced)",
+                pending.len(),
+                self.root.display(),
+                tracked
+            );
+            pending
+        } else {
+            allowed.extend(pending.into_iter().map(|(relative, _)| relative));
+            Vec::new()
+        };
+
+        self.db.replace_held_deletions(self.folder_id, &held).await?;
+        if !held.is_empty() {
+            let notice = HeldDeletionsNotice { folder_id: self.folder_id, count: held.len() };
+            if let Err(e) = self.board.app.emit("sync-deletions-held", &notice) {
+                log::warn!("Failed to emit held deletions: {}", e);
+            }
+        }
+
+        Ok(DeletionGate { allowed, held: held.len(), stamp })
+    }
+
+    /// Remove the remote copy of a file deleted locally, moving it to the remote trash if the policy says so
+    pub(super) async fn delete_remote(&self, client: &Client, bucket: &str, relative: &str, gate: &DeletionGate) -> Result<()> {
+        let key = self.remote_key(relative);
+        if self.deletions.policy == DeletionPolicy::Trash {
+            let trash_key = join_key(&self.remote_prefix, &trash_path(relative, &gate.stamp));
+            // The journal knows the size of what was synced; objects over 5 GiB need a part copy
+            let size = match self.db.get_sync_entry(self.folder_id, relative).await? {
+                Some(entry) => entry.local_size,
+                None => head_object(client, bucket, &key).await?.map(|o| o.size).unwrap_or(0),
+            };
+            copy_large_object(client, bucket, &key, &trash_key, size).await?;
+        }
+        delete_object(client, bucket, &key).await?;
+        self.db.delete_sync_entry(self.folder_id, relative).await?;
+        log::info!("Deleted {} from remote, removed locally", key);
+        Ok(())
+    }
+
+    /// Remove the local copy of a file deleted remotely, moving it to the local trash if the policy says so.
+    /// A file changed since `planned` was read is left alone.
+    pub(super) async fn delete_local(&self, relative: &str, gate: &DeletionGate, planned: Option<&LocalStat>) -> Result<()> {
+        let path = local_path_for(&self.root, relative);
+        ensure_unchanged(&path, planned).await?;
+        if self.deletions.policy == DeletionPolicy::Trash {
+            let trashed = local_path_for(&self.root, &trash_path(relative, &gate.stamp));
+            if let Some(parent) = trashed.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::rename(&path, &trashed)
+                .await
+                .with_context(|| format!("Failed to move {} to the trash", path.display()))?;
+        } else {
+            tokio::fs::remove_file(&path)
+                .await
+                .with_context(|| format!("Failed to delete {}", path.display()))?;
+        }
+        self.db.delete_sync_entry(self.folder_id, relative).await?;
+        log::info!("Deleted {}, removed on remote", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(max_files: i64, max_percent: i64) -> DeletionRules {
+        DeletionRules { policy: DeletionPolicy::Trash, max_files, max_percent }
+    }
+
+    #[test]
+    fn test_exceeds_threshold() {
+        let rules = rules(50, 10);
+        assert!(!rules.exceeds_threshold(0, 0));
+        assert!(!rules.exceeds_threshold(50, 10_000));
+        assert!(rules.exceeds_threshold(51, 10_000));
+        // 10% of 100 files
+        assert!(!rules.exceeds_threshold(10, 100));
+        assert!(rules.exceeds_threshold(11, 100));
+        // A wiped folder is held however small it is, once past the percentage floor
+        assert!(rules.exceeds_threshold(5, 5));
+        assert!(!rules.exceeds_threshold(1, 3));
+    }
+
+    #[test]
+    fn test_trash_path() {
+        assert_eq!(
+            trash_path("a/b.txt", "2026-10-16 120000"),
+            ".r2sync-trash/2026-10-16 120000/a/b.txt"
+        );
+        assert_eq!(held_notice(0), None);
+        assert_eq!(held_notice(1).as_deref(), Some("1 deletion waiting for confirmation"));
+    }
+}
@@ -0,0 +1,781 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{mpsc, watch, Mutex, Notify};
+
+use super::deletions::DeletionRules;
+use super::reconcile;
+use super::schedule::{parse_last_sync, Schedule};
+use super::settle::Settler;
+use super::status;
+use super::watcher::{Batch, FolderWatcher};
+use crate::db::Database;
+use crate::upload::fingerprint::{ChangedDuringUpload, UploadError, Uploaded};
+use crate::utils::paths::{join_key, relative_key, relative_path, remote_key_for};
+use crate::utils::{
+    FileSyncState, FileSyncStatus, PlanExecution, R2Object, ReconcileReport, SyncDiffKind, SyncMode, SyncPlan, SyncState,
+    SyncStatus,
+};
+use crate::{AppState, SyncFolder};
+
+/// OS metadata files that are never synced
+const IGNORED_NAMES: &[&str] = &[".DS_Store", "Thumbs.db", "desktop.ini"];
+/// Partial downloads, editor scratch files and SQLite's transient journals
+const IGNORED_SUFFIXES: &[&str] = &[
+    ".tmp", ".temp", ".part", ".partial", ".crdownload", ".download", ".swp", ".swx", "~", "-journal", "-wal", "-shm",
+];
+/// Office owner files, LibreOffice and Emacs lock files
+const IGNORED_PREFIXES: &[&str] = &["~$", ".~lock.", ".#"];
+/// Folder, locally and under the remote prefix, that trashed files are moved into
+pub const TRASH_DIR: &str = ".r2sync-trash";
+
+/// Whether a file should be left out of sync
+pub fn is_ignored(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return true,
+    };
+
+    IGNORED_NAMES.contains(&name)
+        || path.components().any(|c| c.as_os_str() == TRASH_DIR)
+        || IGNORED_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+        || IGNORED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// All files under `dir`, not following symlinks
+fn walk_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Failed to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(t) if t.is_dir() && entry.file_name() != TRASH_DIR => walk_files(&path, files),
+            Ok(t) if t.is_file() => files.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// Turn a batch of changed paths into the files to upload: directories are expanded,
+/// deleted paths and ignored files are dropped
+pub(super) async fn expand(paths: HashSet<PathBuf>) -> Vec<PathBuf> {
+    tokio::task::spawn_blocking(move || {
+        let mut files = Vec::new();
+        for path in paths {
+            match std::fs::symlink_metadata(&path) {
+                Ok(meta) if meta.is_dir() => walk_files(&path, &mut files),
+                Ok(meta) if meta.is_file() => files.push(path),
+                _ => {}
+            }
+        }
+
+        files.retain(|f| !is_ignored(f));
+        files.sort();
+        files.dedup();
+        files
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// How long a worker with outstanding work waits before checking the connection again
+const CONNECT_RETRY: Duration = Duration::from_secs(30);
+/// Longest a worker sleeps before checking the clock against its next run, so a run
+/// isn't missed when the computer sleeps or the clock changes
+const SCHEDULE_CHECK: Duration = Duration::from_secs(60);
+
+/// Latest status and reconciliation report per folder, mirrored to the frontend as events
+#[derive(Clone)]
+pub(super) struct StatusBoard {
+    pub(super) app: AppHandle,
+    statuses: Arc<std::sync::Mutex<HashMap<i64, SyncStatus>>>,
+    reports: Arc<std::sync::Mutex<HashMap<i64, ReconcileReport>>>,
+    /// Signalled when the tray tooltip may need updating
+    tray: Arc<Notify>,
+}
+
+impl StatusBoard {
+    pub(super) fn publish_report(&self, report: ReconcileReport) {
+        self.app.emit("sync-reconcile", &report).ok();
+        if let Ok(mut reports) = self.reports.lock() {
+            reports.insert(report.folder_id, report);
+        }
+    }
+
+    fn report(&self, folder_id: i64) -> Option<ReconcileReport> {
+        self.reports.lock().ok().and_then(|r| r.get(&folder_id).cloned())
+    }
+
+    pub(super) fn publish(&self, status: SyncStatus) {
+        self.app.emit("sync-status", &status).ok();
+        if let Ok(mut statuses) = self.statuses.lock() {
+            statuses.insert(status.folder_id, status);
+        }
+        self.tray.notify_one();
+    }
+
+    /// Tell the frontend a file changed state, for its badge
+    pub(super) fn publish_file(&self, status: FileSyncStatus) {
+        self.app.emit("file-sync-status", &status).ok();
+        self.tray.notify_one();
+    }
+
+    /// Wait until something shown on the tray tooltip changed
+    pub(super) async fn tray_changed(&self) {
+        self.tray.notified().await;
+    }
+
+    pub(super) fn snapshot(&self) -> Vec<SyncStatus> {
+        self.statuses
+            .lock()
+            .map(|s| s.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Handle to a running folder worker
+struct FolderWorker {
+    /// The folder settings the worker was started with
+    config: SyncFolder,
+    shutdown: watch::Sender<bool>,
+    full_scan: mpsc::UnboundedSender<()>,
+    /// Wakes the worker to retry failed files that are due
+    retry: mpsc::UnboundedSender<()>,
+}
+
+impl FolderWorker {
+    /// Ask the worker to stop; it finishes the file it is uploading first
+    fn stop(&self) {
+        let _ = self.shutdown.send(true);
+    }
+
+    /// Whether the worker still matches the folder's settings
+    fn runs(&self, folder: &SyncFolder) -> bool {
+        let config = &self.config;
+        config.local_path == folder.local_path
+            && config.remote_path == folder.remote_path
+            && config.sync_mode == folder.sync_mode
+            && config.poll_interval_secs == folder.poll_interval_secs
+            && config.delete_removed == folder.delete_removed
+            && config.deletion_policy == folder.deletion_policy
+            && config.deletion_max_files == folder.deletion_max_files
+            && config.deletion_max_percent == folder.deletion_max_percent
+            && config.schedule == folder.schedule
+            && config.schedule_cron == folder.schedule_cron
+            && config.settle_secs == folder.settle_secs
+    }
+}
+
+/// Watches every enabled sync folder and uploads new and changed files under its remote prefix
+pub struct SyncEngine {
+    db: Arc<Database>,
+    board: StatusBoard,
+    workers: Mutex<HashMap<i64, FolderWorker>>,
+    /// Per-folder pass locks, shared by every worker made for the folder
+    passes: std::sync::Mutex<HashMap<i64, Arc<Mutex<()>>>>,
+}
+
+impl SyncEngine {
+    pub fn new(app: AppHandle, db: Arc<Database>) -> Self {
+        let board = StatusBoard {
+            app,
+            statuses: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            reports: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            tray: Arc::new(Notify::new()),
+        };
+        tauri::async_runtime::spawn(status::update_tray(board.clone(), db.clone()));
+
+        Self {
+            db,
+            board,
+            workers: Mutex::new(HashMap::new()),
+            passes: Default::default(),
+        }
+    }
+
+    /// Start, stop or restart workers so they match the enabled sync folders in the database
+    pub async fn reload(&self) -> Result<()> {
+        let wanted: HashMap<i64, SyncFolder> = self
+            .db
+            .get_sync_folders()
+            .await?
+            .into_iter()
+            .filter(|f| f.enabled)
+            .map(|f| (f.id, f))
+            .collect();
+
+        let mut workers = self.workers.lock().await;
+
+        workers.retain(|id, worker| {
+            let unchanged = wanted.get(id).is_some_and(|f| worker.runs(f));
+            if !unchanged {
+                worker.stop();
+                self.board.publish(idle_status(*id, SyncState::Stopped, None));
+            }
+            unchanged
+        });
+        if let Err(e) = self.db.clear_paused_next_runs().await {
+            log::warn!("Failed to clear next runs of paused folders: {}", e);
+        }
+
+        for (id, folder) in wanted {
+            if workers.contains_key(&id) {
+                continue;
+            }
+
+            match self.start_worker(&folder) {
+                Ok(worker) => {
+                    log::info!("Watching {} -> {}", folder.local_path, folder.remote_path);
+                    workers.insert(id, worker);
+                }
+                Err(e) => {
+                    log::error!("Failed to start sync for {}: {}", folder.local_path, e);
+                    self.board.publish(idle_status(id, SyncState::Error, Some(e.to_string())));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queue a reconciliation scan of a folder, uploading anything new or changed
+    pub async fn sync_now(&self, folder_id: i64) -> Result<()> {
+        let workers = self.workers.lock().await;
+        let worker = workers
+            .get(&folder_id)
+            .ok_or_else(|| anyhow!("Sync folder {} is not enabled", folder_id))?;
+
+        worker
+            .full_scan
+            .send(())
+            .map_err(|_| anyhow!("Sync folder {} is shutting down", folder_id))
+    }
+
+    /// Retry a folder's failed files that are due now, if the folder is being synced
+    pub async fn retry_errors(&self, folder_id: i64) {
+        if let Some(worker) = self.workers.lock().await.get(&folder_id) {
+            let _ = worker.retry.send(());
+        }
+    }
+
+    /// Everything a full pass of a folder would do, whether or not the folder is enabled
+    pub async fn preview(&self, folder_id: i64) -> Result<SyncPlan> {
+        self.worker_for_id(folder_id).await?.plan().await
+    }
+
+    /// Carry out a plan from `preview`, skipping paths that changed since
+    pub async fn execute(&self, plan: &SyncPlan) -> Result<PlanExecution> {
+        self.worker_for_id(plan.folder_id).await?.execute_plan(plan).await
+    }
+
+    /// Latest known status of every folder
+    pub fn statuses(&self) -> Vec<SyncStatus> {
+        self.board.snapshot()
+    }
+
+    /// Diff from the folder's most recent reconciliation scan
+    pub fn last_report(&self, folder_id: i64) -> Option<ReconcileReport> {
+        self.board.report(folder_id)
+    }
+
+    /// Sync status of local files and folders, for badges. A folder shows the most pressing
+    /// state among its files, along with how many are in each.
+    pub async fn file_statuses(&self, paths: &[String]) -> Result<Vec<FileSyncStatus>> {
+        status::file_statuses(&self.db, paths).await
+    }
+
+    fn start_worker(&self, folder: &SyncFolder) -> Result<FolderWorker> {
+        let root = PathBuf::from(&folder.local_path);
+        if !root.is_dir() {
+            bail!("{} is not a folder", folder.local_path);
+        }
+
+        let worker = self.worker_for(folder)?;
+        let watcher = match worker.schedule.watches() {
+            true => Some(FolderWatcher::new(&root)?),
+            false => None,
+        };
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let (full_scan, full_scan_rx) = mpsc::unbounded_channel();
+        let (retry, retry_rx) = mpsc::unbounded_channel();
+
+        let last_sync = folder.last_sync.as_deref().and_then(parse_last_sync);
+        let first_run = worker.schedule.first_run(last_sync, chrono::Utc::now());
+        tauri::async_runtime::spawn(worker.run(watcher, first_run, shutdown_rx, full_scan_rx, retry_rx));
+
+        Ok(FolderWorker {
+            config: folder.clone(),
+            shutdown,
+            full_scan,
+            retry,
+        })
+    }
+
+    /// A worker for one-off work on a folder, outside its watch loop
+    async fn worker_for_id(&self, folder_id: i64) -> Result<Worker> {
+        let folder = self
+            .db
+            .get_sync_folders()
+            .await?
+            .into_iter()
+            .find(|f| f.id == folder_id)
+            .ok_or_else(|| anyhow!("Sync folder {} not found", folder_id))?;
+        self.worker_for(&folder)
+    }
+
+    fn worker_for(&self, folder: &SyncFolder) -> Result<Worker> {
+        let interval = Duration::from_secs(folder.poll_interval_secs.max(1) as u64);
+        let polls_remote = folder.sync_mode != SyncMode::UploadOnly;
+        let schedule = Schedule::new(folder.schedule, folder.schedule_cron.as_deref(), interval, polls_remote)?;
+
+        Ok(Worker {
+            folder_id: folder.id,
+            root: PathBuf::from(&folder.local_path),
+            remote_prefix: folder.remote_path.clone(),
+            mode: folder.sync_mode,
+            schedule,
+            settle: Duration::from_secs(folder.settle_secs.max(0) as u64),
+            delete_removed: folder.delete_removed,
+            deletions: DeletionRules {
+                policy: folder.deletion_policy,
+                max_files: folder.deletion_max_files,
+                max_percent: folder.deletion_max_percent,
+            },
+            db: self.db.clone(),
+            board: self.board.clone(),
+            pass: self.passes.lock().unwrap().entry(folder.id).or_default().clone(),
+            stored_keys: Default::default(),
+            requeued: Default::default(),
+        })
+    }
+}
+
+/// The watcher's next batch of changes; never resolves for folders that aren't watched
+async fn next_batch(watcher: &mut Option<FolderWatcher>) -> Option<Batch> {
+    match watcher {
+        Some(watcher) => watcher.next_batch().await,
+        None => std::future::pending().await,
+    }
+}
+
+pub(super) fn idle_status(folder_id: i64, state: SyncState, message: Option<String>) -> SyncStatus {
+    SyncStatus {
+        folder_id,
+        state,
+        pending: 0,
+        uploaded: 0,
+        failed: 0,
+        message,
+    }
+}
+
+/// The task behind one folder: waits for changes and syncs them one file at a time
+pub(super) struct Worker {
+    pub(super) folder_id: i64,
+    pub(super) root: PathBuf,
+    pub(super) remote_prefix: String,
+    pub(super) mode: SyncMode,
+    pub(super) schedule: Schedule,
+    /// How long a changed file has to stay the same before it is synced
+    pub(super) settle: Duration,
+    pub(super) delete_removed: bool,
+    pub(super) deletions: DeletionRules,
+    pub(super) db: Arc<Database>,
+    pub(super) board: StatusBoard,
+    /// Held while syncing, so an executed plan never runs alongside the folder's own passes
+    pub(super) pass: Arc<Mutex<()>>,
+    /// Keys of listed objects that aren't stored in NFC, by relative path
+    stored_keys: std::sync::Mutex<HashMap<String, String>>,
+    /// Files that changed while they were uploaded, to be synced again once they settle
+    requeued: std::sync::Mutex<Vec<PathBuf>>,
+}
+
+impl Worker {
+    async fn run(
+        self,
+        mut watcher: Option<FolderWatcher>,
+        first_run: Option<DateTime<Utc>>,
+        mut shutdown: watch::Receiver<bool>,
+        mut full_scan: mpsc::UnboundedReceiver<()>,
+        mut retry: mpsc::UnboundedReceiver<()>,
+    ) {
+        let mut pending = Batch::default();
+        let mut settler = Settler::new(self.settle);
+        let mut needs_scan = false;
+        // Real-time folders start with a pass that catches up on changes made while they weren't watched
+        let mut next_run = first_run;
+        self.save_next_run(next_run).await;
+
+        loop {
+            if next_run.is_some_and(|at| at <= Utc::now()) {
+                needs_scan = true;
+                next_run = None;
+            }
+            // Failed files are retried across restarts, as their retries are stored
+            let retry_due = self.next_retry().await.is_some_and(|at| at <= Utc::now());
+
+            if needs_scan || !pending.is_empty() || retry_due {
+                if self.is_connected().await {
+                    let _pass = self.pass.lock().await;
+                    if needs_scan {
+                        needs_scan = false;
+                        // The scan covers everything queued so far
+                        pending.clear();
+                        settler.clear();
+                        match self.mode {
+                            SyncMode::UploadOnly => self.reconcile(&shutdown).await,
+                            SyncMode::Bidirectional => self.reconcile_two_way(&shutdown).await,
+                            SyncMode::Download => self.reconcile_mirror(&shutdown).await,
+                        }
+                        if !*shutdown.borrow() {
+                            next_run = self.schedule.next_run(Utc::now());
+                            self.save_next_run(next_run).await;
+                        }
+                    }
+                    let mut ready = match self.mode {
+                        // Local edits in a mirror are only flagged, which the next poll does
+                        SyncMode::Download => {
+                            pending.clear();
+                            Batch::default()
+                        }
+                        _ => settler.take_ready(&mut pending, Instant::now()),
+                    };
+                    if !ready.is_empty() {
+                        match self.mode {
+                            SyncMode::Bidirectional => self.sync_paths_two_way(&mut ready, &shutdown).await,
+                            _ => self.sync_pending(&mut ready, &shutdown).await,
+                        }
+                    }
+                    pending.paths.extend(std::mem::take(&mut *self.requeued.lock().unwrap()));
+                    if retry_due {
+                        self.retry_errors(&shutdown).await;
+                    }
+                } else {
+                    self.board.publish(SyncStatus {
+                        pending: pending.paths.len(),
+                        ..idle_status(self.folder_id, SyncState::Error, Some("Not connected to R2".to_string()))
+                    });
+                }
+            }
+
+            let outstanding = needs_scan || !pending.is_empty() || retry_due;
+            let until_next_run = next_run.map(|at| (at - Utc::now()).to_std().unwrap_or_default().min(SCHEDULE_CHECK));
+            // Retries that are already due wait for the connection like other outstanding work
+            let until_retry = self
+                .next_retry()
+                .await
+                .and_then(|at| (at - Utc::now()).to_std().ok())
+                .map(|wait| wait.min(SCHEDULE_CHECK));
+            let settle_check = settler.next_check().map(tokio::time::Instant::from_std);
+            tokio::select! {
+                _ = shutdown.changed() => break,
+                scan = full_scan.recv() => match scan {
+                    Some(()) => needs_scan = true,
+                    None => break,
+                },
+                batch = next_batch(&mut watcher) => match batch {
+                    Some(batch) => pending.extend(batch),
+                    None => break,
+                },
+                wake = retry.recv() => if wake.is_none() {
+                    break;
+                },
+                _ = tokio::time::sleep(CONNECT_RETRY), if outstanding => {}
+                _ = tokio::time::sleep(until_next_run.unwrap_or_default()), if until_next_run.is_some() => {}
+                _ = tokio::time::sleep(until_retry.unwrap_or_default()), if until_retry.is_some() => {}
+                _ = tokio::time::sleep_until(settle_check.unwrap_or_else(tokio::time::Instant::now)), if settle_check.is_some() => {}
+            }
+
+            if *shutdown.borrow() {
+                break;
+            }
+        }
+
+        log::info!("Stopped syncing {}", self.root.display());
+    }
+
+    async fn save_next_run(&self, next_run: Option<DateTime<Utc>>) {
+        let next_run = next_run.map(|at| at.to_rfc3339());
+        if let Err(e) = self.db.set_sync_folder_next_run(self.folder_id, next_run.as_deref()).await {
+            log::warn!("Failed to save next run of {}: {}", self.root.display(), e);
+        }
+    }
+
+    /// Compare the whole folder with its remote prefix and upload what is missing or changed
+    async fn reconcile(&self, shutdown: &watch::Receiver<bool>) {
+        self.board.publish(idle_status(self.folder_id, SyncState::Syncing, Some("Scanning".to_string())));
+        self.clear_file_states().await;
+        let started = Utc::now();
+
+        let mut report = match self.scan().await {
+            Ok(report) => report,
+            Err(e) => {
+                log::error!("Reconciliation of {} failed: {}", self.root.display(), e);
+                self.board.publish(idle_status(self.folder_id, SyncState::Error, Some(e.to_string())));
+                return;
+            }
+        };
+        log::info!(
+            "Reconciled {}: {} local only, {} remote only, {} changed, {} identical",
+            self.root.display(),
+            report.local_only,
+            report.remote_only,
+            report.changed,
+            report.identical
+        );
+
+        // Upload-only: the local side wins, remote-only objects are left alone
+        let relatives: Vec<String> = report
+            .entries
+            .iter()
+            .filter_map(|e| relative_path(&self.root, Path::new(e.local_path.as_deref()?)))
+            .collect();
+        self.quarantine_collisions(relatives.iter().map(String::as_str)).await;
+        let quarantined = self.quarantined().await;
+        let files: Vec<PathBuf> = report
+            .entries
+            .iter()
+            .filter(|e| matches!(e.kind, SyncDiffKind::LocalOnly | SyncDiffKind::Changed))
+            .filter_map(|e| e.local_path.as_ref().map(PathBuf::from))
+            .filter(|file| relative_path(&self.root, file).is_none_or(|r| !quarantined.contains(&r)))
+            .collect();
+
+        let (uploaded, failed) = self.upload_files(&files, false, shutdown).await;
+        self.resolve_errors(None, started, shutdown).await;
+        report.uploaded = uploaded;
+        report.failed = failed;
+        self.board.publish_report(report);
+        self.finish_pass(uploaded, failed, None).await;
+    }
+
+    /// Client and bucket of the current connection
+    pub(super) async fn connection(&self) -> Result<(aws_sdk_s3::Client, String)> {
+        let state = self.board.app.state::<Arc<Mutex<AppState>>>();
+        let app_state = state.lock().await;
+        let client_guard = app_state.r2_client.lock().await;
+        let client = client_guard.as_ref().ok_or_else(|| anyhow!("Not connected to R2"))?;
+        Ok((client.client().clone(), client.bucket().to_string()))
+    }
+
+    /// The folder's remote objects by relative path, in NFC. Objects stored under another
+    /// form are remembered, so requests for them use the key they are stored under.
+    pub(super) async fn list_remote(&self, client: &aws_sdk_s3::Client, bucket: &str) -> Result<BTreeMap<String, R2Object>> {
+        let prefix = reconcile::listing_prefix(&self.remote_prefix);
+        let remote: BTreeMap<String, R2Object> = reconcile::collect_remote(client, bucket, &self.remote_prefix)
+            .await?
+            .into_iter()
+            .filter_map(|(key, object)| Some((relative_key(prefix.as_deref(), &key)?.to_string(), object)))
+            .collect();
+
+        *self.stored_keys.lock().unwrap() = remote
+            .iter()
+            .filter(|(relative, object)| object.key != join_key(&self.remote_prefix, relative))
+            .map(|(relative, object)| (relative.clone(), object.key.clone()))
+            .collect();
+        Ok(remote)
+    }
+
+    /// Key of the object behind a relative path: where it is stored, if the last listing found
+    /// it under a key that isn't NFC, and otherwise its NFC key
+    pub(super) fn remote_key(&self, relative: &str) -> String {
+        match self.stored_keys.lock().unwrap().get(relative) {
+            Some(key) => key.clone(),
+            None => join_key(&self.remote_prefix, relative),
+        }
+    }
+
+    /// Forget a stored key once its object has been replaced by one under the NFC key
+    pub(super) fn forget_stored_key(&self, relative: &str) {
+        self.stored_keys.lock().unwrap().remove(relative);
+    }
+
+    async fn scan(&self) -> Result<ReconcileReport> {
+        let (client, bucket) = self.connection().await?;
+        let bucket_id = self.db.get_current_bucket_id().await?;
+
+        reconcile::scan(
+            &self.db,
+            &client,
+            &bucket,
+            bucket_id,
+            self.folder_id,
+            &self.root,
+            &self.remote_prefix,
+        )
+        .await
+    }
+
+    /// Upload the files behind a batch of watcher events; renamed files are copied on the remote instead
+    pub(super) async fn sync_pending(&self, pending: &mut Batch, shutdown: &watch::Receiver<bool>) {
+        let batch = std::mem::take(pending);
+        let copied = self.copy_renamed(&batch.renames).await;
+        let mut files = expand(batch.paths).await;
+        let quarantined = self.quarantined().await;
+        files.retain(|file| {
+            !copied.contains(file) && relative_path(&self.root, file).is_none_or(|r| !quarantined.contains(&r))
+        });
+
+        let (uploaded, failed) = self.upload_files(&files, true, shutdown).await;
+        self.finish_pass(uploaded + copied.len(), failed, None).await;
+    }
+
+    /// Upload files one at a time, publishing progress. Returns (uploaded, failed).
+    async fn upload_files(
+        &self,
+        files: &[PathBuf],
+        skip_unchanged: bool,
+        shutdown: &watch::Receiver<bool>,
+    ) -> (usize, usize) {
+        let total = files.len();
+        let (mut uploaded, mut failed) = (0, 0);
+
+        for (i, file) in files.iter().enumerate() {
+            if *shutdown.borrow() {
+                break;
+            }
+
+            self.board.publish(SyncStatus {
+                folder_id: self.folder_id,
+                state: SyncState::Syncing,
+                pending: total - i,
+                uploaded,
+                failed,
+                message: None,
+            });
+
+            let key = match remote_key_for(&self.root, &self.remote_prefix, file) {
+                Some(key) => key,
+                None => continue,
+            };
+
+            match self.upload(file, key, skip_unchanged).await {
+                Ok(_) => uploaded += 1,
+                Err(e) if e.is::<ChangedDuringUpload>() => log::info!("{}, syncing it again once it settles", e),
+                Err(e) => {
+                    failed += 1;
+                    log::error!("Sync upload of {} failed: {}", file.display(), e);
+                }
+            }
+        }
+
+        (uploaded, failed)
+    }
+
+    /// Publish the end of a pass. `notice` is shown when nothing failed, for things the user should look at.
+    pub(super) async fn finish_pass(&self, uploaded: usize, failed: usize, notice: Option<String>) {
+        if let Err(e) = self.db.mark_sync_folder_synced(self.folder_id).await {
+            log::warn!("Failed to update last sync time: {}", e);
+        }
+
+        let (state, message) = if failed > 0 {
+            (SyncState::Error, Some(format!("{} file{} failed to sync", failed, if failed == 1 { "" } else { "s" })))
+        } else {
+            (SyncState::Idle, notice)
+        };
+        self.board.publish(SyncStatus {
+            folder_id: self.folder_id,
+            state,
+            pending: 0,
+            uploaded,
+            failed,
+            message,
+        });
+    }
+
+    async fn is_connected(&self) -> bool {
+        let state = self.board.app.state::<Arc<Mutex<AppState>>>();
+        let app_state = state.lock().await;
+        let connected = app_state.r2_client.lock().await.is_some();
+        connected
+    }
+
+    /// Upload through the regular upload path, so progress, skip-unchanged and verification all apply.
+    /// The local folder is the source of truth, so existing keys are overwritten.
+    /// A file that changes while it is uploaded is queued to be synced again once it settles.
+    pub(super) async fn upload(&self, file: &Path, key: String, skip_unchanged: bool) -> Result<Uploaded> {
+        let relative = relative_path(&self.root, file);
+        if let Some(relative) = &relative {
+            self.record_file_state(relative, FileSyncState::Uploading, None).await;
+        }
+
+        let app = self.board.app.clone();
+        let state = self.board.app.state::<Arc<Mutex<AppState>>>();
+        let result = crate::upload_tracked_file(
+            app,
+            state,
+            file.to_string_lossy().to_string(),
+            key,
+            Some("overwrite".to_string()),
+            None,
+            Some(skip_unchanged),
+        )
+        .await
+        .map_err(|e| match e {
+            UploadError::Changed(changed) => {
+                self.requeued.lock().unwrap().push(file.to_path_buf());
+                anyhow!(changed)
+            }
+            UploadError::Exists(exists) => anyhow!(exists),
+            UploadError::Failed(message) => anyhow!(message),
+        });
+
+        if let Some(relative) = &relative {
+            let (state, message) = match &result {
+                Ok(_) => (FileSyncState::Synced, None),
+                Err(e) if e.is::<ChangedDuringUpload>() => (FileSyncState::Pending, None),
+                Err(e) => (FileSyncState::Error, Some(e.to_string())),
+            };
+            self.record_file_state(relative, state, message).await;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        assert!(is_ignored(Path::new("/a/.DS_Store")));
+        assert!(is_ignored(Path::new("/a/~$report.docx")));
+        assert!(is_ignored(Path::new("/a/movie.mkv.part")));
+        assert!(is_ignored(Path::new("/a/.~lock.budget.ods#")));
+        assert!(is_ignored(Path::new("/a/notes.txt~")));
+        assert!(is_ignored(Path::new("/a/photos.db-journal")));
+        assert!(is_ignored(Path::new("/a/photos.db-wal")));
+        assert!(!is_ignored(Path::new("/a/report.docx")));
+        assert!(!is_ignored(Path::new("/a/photos.db")));
+        assert!(!is_ignored(Path::new("/a/Cargo.lock")));
+        assert!(is_ignored(Path::new("/a/.r2sync-trash/2026-10-16/report.docx")));
+        assert!(is_ignored(Path::new("docs/.r2sync-trash/report.docx")));
+    }
+
+    #[tokio::test]
+    async fn test_expand_walks_directories() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        std::fs::write(dir.path().join("sub").join("b.txt"), b"b").unwrap();
+        std::fs::write(dir.path().join("sub").join(".DS_Store"), b"").unwrap();
+
+        let files = expand(HashSet::from([
+            dir.path().to_path_buf(),
+            dir.path().join("a.txt"),
+            dir.path().join("deleted.txt"),
+        ]))
+        .await;
+
+        assert_eq!(files, vec![dir.path().join("a.txt"), dir.path().join("sub").join("b.txt")]);
+    }
+}
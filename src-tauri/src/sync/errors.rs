@@ -0,0 +1,242 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use super::engine::Worker;
+use super::schedule::parse_last_sync;
+use super::watcher::Batch;
+use crate::db::Database;
+use crate::utils::paths::{self, local_path_for};
+use crate::utils::{FileSyncState, SyncErrorKind, SyncMode};
+
+/// Wait before the first retry of a transient error; it doubles with every failure after
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+/// Longest wait between retries
+const MAX_RETRY: Duration = Duration::from_secs(6 * 60 * 60);
+/// Failures in a row after which a transient error is quarantined like a permanent one
+pub const MAX_ATTEMPTS: i64 = 10;
+
+/// Marks the errors of files quarantined for colliding with another file
+const COLLISION: &str = "differs only in case or Unicode form from";
+
+/// Errors retrying can't fix, as they appear in messages from the OS, the SDK and R2.
+/// Anything else is taken to be transient.
+const PERMANENT_ERRORS: &[&str] = &[
+    "permission denied",
+    "access is denied",
+    "access denied",
+    "accessdenied",
+    "file name too long",
+    "filename or extension is too long",
+    "keytoolong",
+    "key is too long",
+    "invalidobjectname",
+    "entitytoolarge",
+    "is a directory",
+    "read-only file system",
+    "differs only in case",
+];
+
+/// Whether a sync error is worth retrying
+pub fn classify(message: &str) -> SyncErrorKind {
+    let message = message.to_lowercase();
+    match PERMANENT_ERRORS.iter().any(|pattern| message.contains(pattern)) {
+        true => SyncErrorKind::Permanent,
+        false => SyncErrorKind::Transient,
+    }
+}
+
+/// Wait before retrying a file that has failed `attempts` times in a row
+pub fn backoff(attempts: i64) -> Duration {
+    let doublings = attempts.clamp(1, 32) as u32 - 1;
+    FIRST_RETRY.saturating_mul(2u32.saturating_pow(doublings)).min(MAX_RETRY)
+}
+
+/// How a failure is filed: transient errors are retried after their backoff until they have
+/// failed `MAX_ATTEMPTS` times, then quarantined
+fn plan_retry(kind: SyncErrorKind, attempts: i64, now: DateTime<Utc>) -> (SyncErrorKind, Option<DateTime<Utc>>) {
+    match kind {
+        SyncErrorKind::Transient if attempts < MAX_ATTEMPTS => {
+            (kind, Some(now + ChronoDuration::from_std(backoff(attempts)).unwrap_or_default()))
+        }
+        _ => (SyncErrorKind::Permanent, None),
+    }
+}
+
+/// A time as SQLite's CURRENT_TIMESTAMP writes it, so stored times compare as text
+fn db_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Count one more failure of a file and file it for a retry or quarantine
+pub(super) async fn file_error(db: &Database, folder_id: i64, relative: &str, message: &str) -> anyhow::Result<()> {
+    let attempts = db.get_sync_error_attempts(folder_id, relative).await? + 1;
+    let (kind, next_retry) = plan_retry(classify(message), attempts, Utc::now());
+    if kind == SyncErrorKind::Permanent {
+        log::warn!("Quarantined {} after {} failed attempt(s): {}", relative, attempts, message);
+    }
+    let next_retry = next_retry.map(db_time);
+    db.save_sync_error(folder_id, relative, kind, message, attempts, next_retry.as_deref()).await
+}
+
+impl Worker {
+    /// Queue a file that failed to sync for a retry, or quarantine it
+    pub(super) async fn track_error(&self, relative: &str, message: &str) {
+        if let Err(e) = file_error(&self.db, self.folder_id, relative, message).await {
+            log::warn!("Failed to queue {} for a retry: {}", relative, e);
+        }
+    }
+
+    /// Forget a file's failures once it synced
+    pub(super) async fn clear_error(&self, relative: &str) {
+        if let Err(e) = self.db.delete_sync_error(self.folder_id, relative).await {
+            log::warn!("Failed to clear the sync error of {}: {}", relative, e);
+        }
+    }
+
+    /// Files left out of sync until the user retries them
+    pub(super) async fn quarantined(&self) -> HashSet<String> {
+        match self.db.get_quarantined_sync_errors(self.folder_id).await {
+            Ok(errors) => errors.into_keys().collect(),
+            Err(e) => {
+                log::warn!("Failed to load quarantined files of {}: {}", self.root.display(), e);
+                HashSet::new()
+            }
+        }
+    }
+
+    /// Quarantine files whose paths differ only in case or Unicode form, which would overwrite
+    /// each other on a case-insensitive filesystem and sync back and forth. Files that no longer
+    /// collide are released.
+    pub(super) async fn quarantine_collisions<'a>(&self, relatives: impl IntoIterator<Item = &'a str>) {
+        let groups = paths::collisions(relatives);
+        let quarantined = match self.db.get_quarantined_sync_errors(self.folder_id).await {
+            Ok(errors) => errors,
+            Err(e) => {
+                log::warn!("Failed to load quarantined files of {}: {}", self.root.display(), e);
+                return;
+            }
+        };
+
+        let colliding: HashSet<&String> = groups.iter().flatten().collect();
+        for (relative, error) in &quarantined {
+            if error.message.contains(COLLISION) && !colliding.contains(relative) {
+                self.clear_error(relative).await;
+            }
+        }
+
+        for group in &groups {
+            for relative in group.iter().filter(|r| !quarantined.contains_key(*r)) {
+                let others: Vec<&str> = group.iter().filter(|r| *r != relative).map(String::as_str).collect();
+                let message = format!("{} {} {}", relative, COLLISION, others.join(", "));
+                self.record_file_state(relative, FileSyncState::Error, Some(message)).await;
+            }
+        }
+    }
+
+    /// When the next queued error is due for a retry
+    pub(super) async fn next_retry(&self) -> Option<DateTime<Utc>> {
+        match self.db.get_next_sync_error_retry(self.folder_id).await {
+            Ok(next) => next.as_deref().and_then(parse_last_sync),
+            Err(e) => {
+                log::warn!("Failed to load retries of {}: {}", self.root.display(), e);
+                None
+            }
+        }
+    }
+
+    /// After a pass that started at `retried_at`: queued errors it retried without them
+    /// failing again are resolved, among `relative_paths` or across the folder
+    pub(super) async fn resolve_errors(
+        &self,
+        relative_paths: Option<&[String]>,
+        retried_at: DateTime<Utc>,
+        shutdown: &watch::Receiver<bool>,
+    ) {
+        // A pass cut short didn't get to every file
+        if *shutdown.borrow() {
+            return;
+        }
+        if let Err(e) = self.db.resolve_sync_errors(self.folder_id, relative_paths, &db_time(retried_at)).await {
+            log::warn!("Failed to resolve retried errors of {}: {}", self.root.display(), e);
+        }
+    }
+
+    /// Sync the files whose retry is due. Their next retry is pushed back first, so a pass
+    /// that fails as a whole doesn't retry them again straight away.
+    pub(super) async fn retry_errors(&self, shutdown: &watch::Receiver<bool>) {
+        let now = Utc::now();
+        let due = match self.db.get_due_sync_errors(self.folder_id, &db_time(now)).await {
+            Ok(due) => due,
+            Err(e) => {
+                log::warn!("Failed to load retries of {}: {}", self.root.display(), e);
+                return;
+            }
+        };
+        if due.is_empty() {
+            return;
+        }
+        log::info!("Retrying {} failed file(s) in {}", due.len(), self.root.display());
+
+        for (relative, attempts) in &due {
+            let next = now + ChronoDuration::from_std(backoff(*attempts)).unwrap_or_default();
+            if let Err(e) = self.db.set_sync_error_retry(self.folder_id, relative, &db_time(next)).await {
+                log::warn!("Failed to reschedule {}: {}", relative, e);
+            }
+        }
+
+        let relatives: Vec<String> = due.into_iter().map(|(relative, _)| relative).collect();
+        let mut batch = Batch {
+            paths: relatives.iter().map(|r| local_path_for(&self.root, r)).collect(),
+            renames: Vec::new(),
+        };
+        match self.mode {
+            // Mirrors only sync in full passes, which resolve errors themselves
+            SyncMode::Download => return self.reconcile_mirror(shutdown).await,
+            SyncMode::Bidirectional => self.sync_paths_two_way(&mut batch, shutdown).await,
+            SyncMode::UploadOnly => self.sync_pending(&mut batch, shutdown).await,
+        }
+        self.resolve_errors(Some(&relatives), now, shutdown).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("Failed to open /a.txt: Permission denied (os error 13)"), SyncErrorKind::Permanent);
+        assert_eq!(classify("Access is denied. (os error 5)"), SyncErrorKind::Permanent);
+        assert_eq!(classify("service error: AccessDenied"), SyncErrorKind::Permanent);
+        assert_eq!(classify("File name too long (os error 36)"), SyncErrorKind::Permanent);
+        assert_eq!(classify("KeyTooLongError: Your key is too long"), SyncErrorKind::Permanent);
+        assert_eq!(classify("Object key is too long (1030 bytes, the limit is 1024): a/b"), SyncErrorKind::Permanent);
+        assert_eq!(classify(&format!("a/Report.pdf {} a/report.pdf", COLLISION)), SyncErrorKind::Permanent);
+        assert_eq!(classify("dispatch failure: connection reset"), SyncErrorKind::Transient);
+        assert_eq!(classify("SlowDown: reduce your request rate"), SyncErrorKind::Transient);
+        assert_eq!(classify("something unexpected"), SyncErrorKind::Transient);
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(30));
+        assert_eq!(backoff(2), Duration::from_secs(60));
+        assert_eq!(backoff(4), Duration::from_secs(240));
+        assert_eq!(backoff(20), MAX_RETRY);
+        assert_eq!(backoff(i64::MAX), MAX_RETRY);
+        assert_eq!(backoff(0), FIRST_RETRY);
+    }
+
+    #[test]
+    fn test_plan_retry() {
+        let now = parse_last_sync("2026-10-18 12:00:00").unwrap();
+        let (kind, next) = plan_retry(SyncErrorKind::Transient, 2, now);
+        assert_eq!(kind, SyncErrorKind::Transient);
+        assert_eq!(next.map(db_time).as_deref(), Some("2026-10-18 12:01:00"));
+
+        assert_eq!(plan_retry(SyncErrorKind::Transient, MAX_ATTEMPTS, now), (SyncErrorKind::Permanent, None));
+        assert_eq!(plan_retry(SyncErrorKind::Permanent, 1, now), (SyncErrorKind::Permanent, None));
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::{bail, Result};
+use aws_sdk_s3::Client;
+use std::collections::{BTreeMap, BTreeSet};
+use tokio::sync::watch;
+
+use super::bidirectional::{local_change, needs_hash, remote_change, Change, ChangedLocally};
+use super::deletions::{held_notice, DeletionGate};
+use super::engine::{idle_status, Worker};
+use super::reconcile::collect_local;
+use crate::db::SyncEntry;
+use crate::upload::fingerprint::{hash_file, LocalStat};
+use crate::utils::paths::{local_path_for, relative_path};
+use crate::utils::{
+    DeletionPolicy, DeletionSide, FileSyncState, ReconcileReport, SyncDiffEntry, SyncDiffKind, SyncState, SyncStatus,
+};
+
+/// What a download-only mirror does with one path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorAction {
+    None,
+    Download,
+    DeleteLocal,
+    /// Drop the journal entry and leave the local file alone
+    Forget,
+    /// A local edit; keep it and report it instead of overwriting
+    Flag,
+    /// An untracked local file where an object exists; track it if identical, flag it otherwise
+    CompareContents,
+    /// A deletion waiting for confirmation
+    Held,
+}
+
+/// A path's planned mirror action, with what was learned deciding it
+pub(super) struct MirrorPlan {
+    relative: String,
+    pub(super) action: MirrorAction,
+    local: Option<LocalStat>,
+    hash: Option<String>,
+    entry: Option<SyncEntry>,
+}
+
+/// Decide how to bring one path in line with the remote. Local edits are never overwritten or
+/// deleted; a file deleted locally is simply downloaded again.
+pub fn plan(local: Change, remote: Change, delete_removed: bool) -> MirrorAction {
+    match (local, remote) {
+        (Change::Modified, Change::Deleted) if !delete_removed => MirrorAction::Forget,
+        (Change::Modified, _) => MirrorAction::Flag,
+        (Change::Created, Change::Created) => MirrorAction::CompareContents,
+        // Not in the bucket and never synced: not ours to touch
+        (Change::Created, _) => MirrorAction::None,
+        (Change::Deleted, Change::Deleted) => MirrorAction::Forget,
+        (Change::Deleted, _) => MirrorAction::Download,
+        (Change::Unchanged, Change::Created | Change::Modified) => MirrorAction::Download,
+        (Change::Unchanged, Change::Deleted) if delete_removed => MirrorAction::DeleteLocal,
+        (Change::Unchanged, Change::Deleted) => MirrorAction::Forget,
+        (Change::Unchanged, Change::Unchanged) => MirrorAction::None,
+    }
+}
+
+/// Flag a file edited after it was planned to be replaced or deleted, so the user's version is kept
+fn kept_if_changed(done: Result<()>, action: MirrorAction) -> Result<MirrorAction> {
+    match done {
+        Err(e) if e.is::<ChangedLocally>() => {
+            log::warn!("{}; keeping the local version", e);
+            Ok(MirrorAction::Flag)
+        }
+        done => done.map(|_| action),
+    }
+}
+
+impl Worker {
+    /// Poll the remote prefix and update the local mirror
+    pub(super) async fn reconcile_mirror(&self, shutdown: &watch::Receiver<bool>) {
+        self.board.publish(idle_status(self.folder_id, SyncState::Syncing, Some("Checking for changes".to_string())));
+        self.clear_file_states().await;
+        let started = chrono::Utc::now();
+
+        let report = match self.mirror(shutdown).await {
+            Ok(report) => report,
+            Err(e) => {
+                log::error!("Mirroring into {} failed: {}", self.root.display(), e);
+                self.board.publish(idle_status(self.folder_id, SyncState::Error, Some(e.to_string())));
+                return;
+            }
+        };
+        log::info!(
+            "Mirrored into {}: {} downloaded, {} deleted, {} held, {} edited locally, {} failed",
+            self.root.display(),
+            report.downloaded,
+            report.deleted,
+            report.held,
+            report.locally_modified,
+            report.failed
+        );
+
+        self.resolve_errors(None, started, shutdown).await;
+
+        let (downloaded, failed, flagged) = (report.downloaded, report.failed, report.locally_modified);
+        let flagged = (flagged > 0).then(|| format!("{} file{} edited locally", flagged, if flagged == 1 { "" } else { "s" }));
+        let notice = match (flagged, held_notice(report.held)) {
+            (Some(flagged), Some(held)) => Some(format!("{}, {}", flagged, held)),
+            (flagged, held) => flagged.or(held),
+        };
+        self.board.publish_report(report);
+        self.finish_pass(downloaded, failed, notice).await;
+    }
+
+    async fn mirror(&self, shutdown: &watch::Receiver<bool>) -> Result<ReconcileReport> {
+        // An unmounted drive must not read as every file having been deleted
+        if !self.root.is_dir() {
+            bail!("{} is not available", self.root.display());
+        }
+
+        let (client, bucket) = self.connection().await?;
+
+        let local: BTreeMap<String, LocalStat> = collect_local(&self.root, &self.remote_prefix)
+            .await
+            .into_values()
+            .filter_map(|file| Some((relative_path(&self.root, &file.path)?, file.stat)))
+            .collect();
+        let remote = self.list_remote(&client, &bucket).await?;
+        let entries = self.db.get_sync_entries(self.folder_id).await?;
+
+        self.quarantine_collisions(local.keys().chain(remote.keys()).map(String::as_str)).await;
+        let quarantined = self.quarantined().await;
+        let paths: BTreeSet<&String> = local
+            .keys()
+            .chain(remote.keys())
+            .chain(entries.keys())
+            .filter(|relative| !quarantined.contains(*relative))
+            .collect();
+        let mut report = ReconcileReport {
+            folder_id: self.folder_id,
+            scanned_at: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        };
+
+        let mut plans = Vec::with_capacity(paths.len());
+        for relative in paths {
+            let etag = remote.get(relative).map(|o| o.etag.as_str());
+            match self.plan_mirror_path(relative, local.get(relative), etag, entries.get(relative)).await {
+                Ok(planned) => plans.push(planned),
+                Err(e) => {
+                    log::error!("Mirroring {} failed: {:#}", relative, e);
+                    self.record_file_state(relative, FileSyncState::Error, Some(format!("{:#}", e))).await;
+                    report.failed += 1;
+                }
+            }
+        }
+
+        let deletions: Vec<(String, DeletionSide)> = plans
+            .iter()
+            .filter(|planned| planned.action == MirrorAction::DeleteLocal)
+            .map(|planned| (planned.relative.clone(), DeletionSide::Local))
+            .collect();
+        let gate = self.gate_deletions(&deletions, entries.len()).await?;
+
+        let total = plans.len();
+        for (i, planned) in plans.iter().enumerate() {
+            if *shutdown.borrow() {
+                break;
+            }
+            self.board.publish(SyncStatus {
+                folder_id: self.folder_id,
+                state: SyncState::Syncing,
+                pending: total - i,
+                uploaded: report.downloaded,
+                failed: report.failed,
+                message: None,
+            });
+
+            let relative = &planned.relative;
+            let stat = local.get(relative);
+            let object = remote.get(relative);
+            let action = match self.mirror_path(&client, &bucket, planned, &gate).await {
+                Ok(action) => action,
+                Err(e) => {
+                    log::error!("Mirroring {} failed: {:#}", relative, e);
+                    self.record_file_state(relative, FileSyncState::Error, Some(format!("{:#}", e))).await;
+                    report.failed += 1;
+                    continue;
+                }
+            };
+
+            let kind = match action {
+                MirrorAction::Flag => SyncDiffKind::LocallyModified,
+                MirrorAction::Download => {
+                    report.downloaded += 1;
+                    self.record_file_state(relative, FileSyncState::Synced, None).await;
+                    if stat.is_some() { SyncDiffKind::Changed } else { SyncDiffKind::RemoteOnly }
+                }
+                MirrorAction::DeleteLocal => {
+                    report.deleted += 1;
+                    SyncDiffKind::LocalOnly
+                }
+                MirrorAction::Held => {
+                    report.held += 1;
+                    SyncDiffKind::LocalOnly
+                }
+                _ => match (stat, object) {
+                    (None, None) => continue,
+                    (Some(_), None) => SyncDiffKind::LocalOnly,
+                    (None, Some(_)) => SyncDiffKind::RemoteOnly,
+                    (Some(_), Some(_)) => SyncDiffKind::Identical,
+                },
+            };
+            report.count(kind);
+            if kind == SyncDiffKind::Identical {
+                continue;
+            }
+            report.entries.push(SyncDiffEntry {
+                key: self.remote_key(relative),
+                local_path: stat.map(|_| local_path_for(&self.root, relative).to_string_lossy().to_string()),
+                kind,
+                local_size: stat.map(|s| s.size),
+                remote_size: object.map(|o| o.size),
+            });
+        }
+
+        Ok(report)
+    }
+
+    /// Work out what one path needs, hashing the local file only if size and mtime can't tell
+    pub(super) async fn plan_mirror_path(
+        &self,
+        relative: &str,
+        local: Option<&LocalStat>,
+        remote_etag: Option<&str>,
+        entry: Option<&SyncEntry>,
+    ) -> Result<MirrorPlan> {
+        let hash = match (local, entry) {
+            (Some(stat), Some(entry)) if needs_hash(stat, entry) => {
+                let path = local_path_for(&self.root, relative);
+                Some(hash_file(&path.to_string_lossy()).await?.sha256)
+            }
+            _ => None,
+        };
+        let local_change = local_change(local, entry, hash.as_deref());
+        let remote_change = remote_change(remote_etag, entry);
+
+        Ok(MirrorPlan {
+            relative: relative.to_string(),
+            action: plan(local_change, remote_change, self.delete_removed),
+            local: local.copied(),
+            hash,
+            entry: entry.cloned(),
+        })
+    }
+
+    /// Apply the mirror plan to one path and return what was done
+    async fn mirror_path(
+        &self,
+        client: &Client,
+        bucket: &str,
+        planned: &MirrorPlan,
+        gate: &DeletionGate,
+    ) -> Result<MirrorAction> {
+        let relative = planned.relative.as_str();
+        let path = local_path_for(&self.root, relative);
+        match planned.action {
+            MirrorAction::None => {
+                // Touched but not edited: remember the new mtime to skip hashing next time
+                if let (Some(stat), Some(entry), Some(_)) = (&planned.local, &planned.entry, &planned.hash) {
+                    let updated = SyncEntry { local_mtime: stat.mtime, local_inode: stat.inode, ..entry.clone() };
+                    self.db.save_sync_entry(self.folder_id, relative, &updated).await?;
+                }
+            }
+            MirrorAction::Download => {
+                let downloaded = self.download_and_record(client, bucket, relative, &path, planned.local.as_ref()).await;
+                return kept_if_changed(downloaded, planned.action);
+            }
+            MirrorAction::DeleteLocal if !gate.allows(relative) => {
+                // Held for confirmation, or the policy keeps local copies
+                if gate.held > 0 && self.deletions.policy != DeletionPolicy::Never {
+                    return Ok(MirrorAction::Held);
+                }
+                return Ok(MirrorAction::None);
+            }
+            MirrorAction::DeleteLocal => {
+                let deleted = self.delete_local(relative, gate, planned.local.as_ref()).await;
+                return kept_if_changed(deleted, planned.action);
+            }
+            MirrorAction::Forget => {
+                self.db.delete_sync_entry(self.folder_id, relative).await?;
+            }
+            MirrorAction::Flag | MirrorAction::Held => {
+                log::warn!("{} was edited locally; not overwriting it with the remote copy", path.display());
+            }
+            MirrorAction::CompareContents => {
+                if !self.record_if_identical(client, bucket, relative, &path).await? {
+                    log::warn!("{} differs from the remote copy; not overwriting it", path.display());
+                    return Ok(MirrorAction::Flag);
+                }
+                return Ok(MirrorAction::None);
+            }
+        }
+        Ok(planned.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_follows_remote() {
+        use Change::*;
+        assert_eq!(plan(Unchanged, Unchanged, false), MirrorAction::None);
+        assert_eq!(plan(Unchanged, Created, false), MirrorAction::Download);
+        assert_eq!(plan(Unchanged, Modified, false), MirrorAction::Download);
+        assert_eq!(plan(Unchanged, Deleted, false), MirrorAction::Forget);
+        assert_eq!(plan(Unchanged, Deleted, true), MirrorAction::DeleteLocal);
+        // Deleting a mirrored file locally just restores it
+        assert_eq!(plan(Deleted, Unchanged, false), MirrorAction::Download);
+        assert_eq!(plan(Deleted, Deleted, true), MirrorAction::Forget);
+    }
+
+    #[test]
+    fn test_plan_never_overwrites_local_edits() {
+        use Change::*;
+        assert_eq!(plan(Modified, Unchanged, true), MirrorAction::Flag);
+        assert_eq!(plan(Modified, Modified, true), MirrorAction::Flag);
+        assert_eq!(plan(Modified, Deleted, true), MirrorAction::Flag);
+        assert_eq!(plan(Modified, Deleted, false), MirrorAction::Forget);
+        assert_eq!(plan(Created, Created, true), MirrorAction::CompareContents);
+        assert_eq!(plan(Created, Unchanged, true), MirrorAction::None);
+    }
+
+    #[test]
+    fn test_files_edited_while_mirroring_are_flagged() {
+        let changed = Err(ChangedLocally("a.txt".to_string()).into());
+        assert_eq!(kept_if_changed(changed, MirrorAction::Download).unwrap(), MirrorAction::Flag);
+        assert_eq!(kept_if_changed(Ok(()), MirrorAction::DeleteLocal).unwrap(), MirrorAction::DeleteLocal);
+        assert!(kept_if_changed(Err(anyhow::anyhow!("offline")), MirrorAction::Download).is_err());
+    }
+}
@@ -0,0 +1,14 @@
+pub mod bidirectional;
+pub mod deletions;
+pub mod engine;
+pub mod errors;
+pub mod mirror;
+pub mod moves;
+pub mod plan;
+pub mod reconcile;
+pub mod schedule;
+pub mod settle;
+pub mod status;
+pub mod watcher;
+
+pub use engine::SyncEngine;
@@ -0,0 +1,352 @@
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::bidirectional::{Action, Planned};
+use super::engine::{expand, Worker};
+use crate::db::{FileFingerprint, SyncEntry};
+use crate::r2::checksum::etags_match;
+use crate::r2::operations::{copy_large_object, delete_object, head_object};
+use crate::upload::fingerprint::{hash_file, LocalStat};
+use crate::utils::paths::{join_key, local_path_for, remote_key_for};
+use crate::utils::DeletionPolicy;
+
+/// A rename keeps the size and modification time, so a file that still has both wasn't edited since
+fn same_metadata(stat: &LocalStat, entry: &SyncEntry) -> bool {
+    stat.size == entry.local_size && stat.mtime == entry.local_mtime
+}
+
+/// Pair vanished files with new ones by what identifies a file without reading it: a rename the
+/// OS reported, or the same inode. Size and modification time must agree as well, so a reused
+/// inode or a file edited after moving isn't taken for a plain move. Returns (from, to) pairs.
+pub fn match_by_identity(
+    vanished: &[(&str, &SyncEntry)],
+    appeared: &[(&str, &LocalStat)],
+    renames: &[(String, String)],
+) -> Vec<(String, String)> {
+    let entries: HashMap<&str, &SyncEntry> = vanished.iter().copied().collect();
+    let stats: HashMap<&str, &LocalStat> = appeared.iter().copied().collect();
+    let by_inode: HashMap<i64, &str> = vanished
+        .iter()
+        .filter_map(|(relative, entry)| Some((entry.local_inode?, *relative)))
+        .collect();
+
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    let mut taken: HashSet<&str> = HashSet::new();
+
+    let hinted = renames.iter().map(|(from, to)| (from.as_str(), to.as_str()));
+    let by_inode = appeared
+        .iter()
+        .filter_map(|(to, stat)| Some((*by_inode.get(&stat.inode?)?, *to)));
+    for (from, to) in hinted.chain(by_inode) {
+        if taken.contains(from) || taken.contains(to) {
+            continue;
+        }
+        let (Some(entry), Some(stat)) = (entries.get(from), stats.get(to)) else { continue };
+        if same_metadata(stat, entry) {
+            taken.insert(from);
+            taken.insert(to);
+            pairs.push((from.to_string(), to.to_string()));
+        }
+    }
+    pairs
+}
+
+/// Rename hints for the files in a batch: a renamed folder stands for every file under it
+pub fn expand_renames(renames: &[(String, String)], paths: &BTreeSet<String>) -> Vec<(String, String)> {
+    let mut expanded = Vec::new();
+    for (from, to) in renames {
+        let nested = format!("{}/", to);
+        for path in paths {
+            if let Some(rest) = path.strip_prefix(&nested) {
+                expanded.push((format!("{}/{}", from, rest), path.clone()));
+            }
+        }
+        expanded.push((from.clone(), to.clone()));
+    }
+    expanded
+}
+
+impl Worker {
+    /// Moves among a pass's deletions and new files: by identity first, then by content for new
+    /// files the size of a vanished one whose hash is in the journal
+    async fn find_moves(
+        &self,
+        vanished: &[(&str, &SyncEntry)],
+        appeared: &[(&str, &LocalStat)],
+        renames: &[(String, String)],
+    ) -> Vec<(String, String)> {
+        let mut pairs = match_by_identity(vanished, appeared, renames);
+        let mut taken: HashSet<String> = pairs.iter().flat_map(|(from, to)| [from.clone(), to.clone()]).collect();
+
+        for (to, stat) in appeared {
+            if taken.contains(*to) {
+                continue;
+            }
+            let candidates: Vec<&(&str, &SyncEntry)> = vanished
+                .iter()
+                .filter(|(from, entry)| {
+                    !taken.contains(*from) && entry.local_size == stat.size && entry.local_hash.is_some()
+                })
+                .collect();
+            if candidates.is_empty() {
+                continue;
+            }
+
+            let path = local_path_for(&self.root, to);
+            let hash = match hash_file(&path.to_string_lossy()).await {
+                Ok(hashes) => hashes.sha256,
+                Err(e) => {
+                    log::warn!("Failed to hash {} while looking for moves: {:#}", path.display(), e);
+                    continue;
+                }
+            };
+            let found = candidates
+                .iter()
+                .find(|(_, entry)| entry.local_hash.as_deref().is_some_and(|known| known.eq_ignore_ascii_case(&hash)));
+            if let Some((from, _)) = found {
+                taken.insert(from.to_string());
+                taken.insert(to.to_string());
+                pairs.push((from.to_string(), to.to_string()));
+            }
+        }
+        pairs
+    }
+
+    /// Replace each remote deletion that matches a new local file with a move to it.
+    /// `renames` are (from, to) relative paths the watcher saw being renamed.
+    pub(super) async fn pair_moves(&self, plans: Vec<Planned>, renames: &[(String, String)]) -> Vec<Planned> {
+        let vanished: Vec<(&str, &SyncEntry)> = plans
+            .iter()
+            .filter(|p| p.action == Action::DeleteRemote)
+            .filter_map(|p| Some((p.relative.as_str(), p.entry.as_ref()?)))
+            .collect();
+        let appeared: Vec<(&str, &LocalStat)> = plans
+            .iter()
+            .filter(|p| p.action == Action::Upload && p.entry.is_none())
+            .filter_map(|p| Some((p.relative.as_str(), p.local.as_ref()?)))
+            .collect();
+        if vanished.is_empty() || appeared.is_empty() {
+            return plans;
+        }
+
+        let moves: HashMap<String, String> = self
+            .find_moves(&vanished, &appeared, renames)
+            .await
+            .into_iter()
+            .map(|(from, to)| (to, from))
+            .collect();
+        if moves.is_empty() {
+            return plans;
+        }
+
+        let sources: HashSet<&String> = moves.values().collect();
+        let mut entries: HashMap<String, SyncEntry> = plans
+            .iter()
+            .filter(|p| sources.contains(&p.relative))
+            .filter_map(|p| Some((p.relative.clone(), p.entry.clone()?)))
+            .collect();
+        plans
+            .into_iter()
+            .filter(|p| !sources.contains(&p.relative))
+            .map(|mut planned| {
+                if let Some(from) = moves.get(&planned.relative) {
+                    planned.action = Action::Move;
+                    planned.entry = entries.remove(from);
+                    planned.moved_from = Some(from.clone());
+                }
+                planned
+            })
+            .collect()
+    }
+
+    /// Copy a moved file's object to its new key and journal the file under its new path, then
+    /// remove the old object. Nothing is lost, so moves don't go through the deletion gate;
+    /// folders that never propagate deletions keep the old object, as they would otherwise.
+    pub(super) async fn move_remote(
+        &self,
+        client: &Client,
+        bucket: &str,
+        from: &str,
+        to: &str,
+        entry: &SyncEntry,
+        stat: LocalStat,
+    ) -> Result<()> {
+        let from_key = self.remote_key(from);
+        let to_key = join_key(&self.remote_prefix, to);
+        let local_hash = entry.local_hash.clone().with_context(|| format!("No recorded hash for {}", from))?;
+
+        copy_large_object(client, bucket, &from_key, &to_key, entry.local_size).await?;
+        let copied = head_object(client, bucket, &to_key)
+            .await?
+            .with_context(|| format!("{} is missing after copy", to_key))?;
+        // The stat from planning: if the file was edited since, the next pass still sees it
+        self.record(to, stat, local_hash, Some(copied)).await?;
+
+        if self.deletions.policy != DeletionPolicy::Never {
+            delete_object(client, bucket, &from_key).await?;
+            self.db.delete_sync_entry(self.folder_id, from).await?;
+        }
+        log::info!("Moved {} to {} on R2", from_key, to_key);
+        Ok(())
+    }
+
+    /// Upload-only folders keep the objects of files that are gone, so a rename only needs the
+    /// old object copied to the new key. Done when the fingerprint shows the old object is what
+    /// was uploaded from the file and the file is unchanged since. Returns the files copied.
+    pub(super) async fn copy_renamed(&self, renames: &[(PathBuf, PathBuf)]) -> HashSet<PathBuf> {
+        let mut copied = HashSet::new();
+        if renames.is_empty() {
+            return copied;
+        }
+        let connection = async { Ok::<_, anyhow::Error>((self.connection().await?, self.db.get_current_bucket_id().await?)) };
+        let ((client, bucket), bucket_id) = match connection.await {
+            Ok((connection, Some(bucket_id))) => (connection, bucket_id),
+            // Without a bucket there are no fingerprints to go by
+            Ok((_, None)) => return copied,
+            Err(e) => {
+                log::warn!("Can't copy renamed files in {}: {}", self.root.display(), e);
+                return copied;
+            }
+        };
+
+        for (from, to) in renames {
+            // A renamed folder moves every file in it
+            for file in expand(HashSet::from([to.clone()])).await {
+                let old = match file.strip_prefix(to) {
+                    Ok(rest) if rest.as_os_str().is_empty() => from.clone(),
+                    Ok(rest) => from.join(rest),
+                    Err(_) => continue,
+                };
+                if old.exists() {
+                    continue;
+                }
+                match self.copy_if_unchanged(&client, &bucket, bucket_id, &old, &file).await {
+                    Ok(true) => {
+                        copied.insert(file);
+                    }
+                    Ok(false) => {}
+                    Err(e) => log::warn!("Failed to copy {} on R2, uploading it instead: {:#}", file.display(), e),
+                }
+            }
+        }
+        copied
+    }
+
+    async fn copy_if_unchanged(&self, client: &Client, bucket: &str, bucket_id: i64, old: &Path, new: &Path) -> Result<bool> {
+        let (Some(old_key), Some(new_key)) = (
+            remote_key_for(&self.root, &self.remote_prefix, old),
+            remote_key_for(&self.root, &self.remote_prefix, new),
+        ) else {
+            return Ok(false);
+        };
+        let Some(fingerprint) = self.db.get_file_fingerprint(bucket_id, &old_key).await? else { return Ok(false) };
+        let stat = LocalStat::read(&new.to_string_lossy()).await?;
+        if stat.size != fingerprint.local_size || stat.mtime != fingerprint.local_mtime {
+            return Ok(false);
+        }
+        let Some(remote) = head_object(client, bucket, &old_key).await? else { return Ok(false) };
+        if !fingerprint.etag.as_deref().is_some_and(|etag| etags_match(etag, &remote.etag)) {
+            return Ok(false);
+        }
+
+        copy_large_object(client, bucket, &old_key, &new_key, remote.size).await?;
+        let copied = head_object(client, bucket, &new_key)
+            .await?
+            .with_context(|| format!("{} is missing after copy", new_key))?;
+        let fingerprint = FileFingerprint {
+            remote_size: copied.size,
+            etag: Some(copied.etag),
+            local_size: stat.size,
+            local_mtime: stat.mtime,
+            ..fingerprint
+        };
+        self.db.save_file_fingerprint(bucket_id, &new_key, &fingerprint).await?;
+        log::info!("Copied {} to {} on R2 instead of uploading it again", old_key, new_key);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(size: i64, mtime: i64, inode: Option<i64>) -> SyncEntry {
+        SyncEntry {
+            local_size: size,
+            local_mtime: mtime,
+            local_inode: inode,
+            local_hash: Some("abc".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn stat(size: i64, mtime: i64, inode: Option<i64>) -> LocalStat {
+        LocalStat { size, mtime, inode }
+    }
+
+    fn pair(from: &str, to: &str) -> (String, String) {
+        (from.to_string(), to.to_string())
+    }
+
+    #[test]
+    fn test_match_by_inode() {
+        let video = entry(40, 1000, Some(7));
+        let notes = entry(5, 1000, Some(8));
+        let moved = stat(40, 1000, Some(7));
+        // Same inode, but written to since: not a plain move
+        let edited = stat(6, 2000, Some(8));
+
+        let pairs = match_by_identity(
+            &[("video.mov", &video), ("notes.txt", &notes)],
+            &[("clips/video.mov", &moved), ("notes2.txt", &edited)],
+            &[],
+        );
+        assert_eq!(pairs, vec![pair("video.mov", "clips/video.mov")]);
+    }
+
+    #[test]
+    fn test_match_by_rename_hint() {
+        // No inodes on this platform
+        let a = entry(10, 1000, None);
+        let b = entry(10, 1000, None);
+        let new_a = stat(10, 1000, None);
+
+        let renames = [pair("b.txt", "c.txt")];
+        let pairs = match_by_identity(&[("a.txt", &a), ("b.txt", &b)], &[("c.txt", &new_a)], &renames);
+        assert_eq!(pairs, vec![pair("b.txt", "c.txt")]);
+
+        // Without a hint, identical metadata alone isn't enough
+        assert!(match_by_identity(&[("a.txt", &a)], &[("c.txt", &new_a)], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_expand_renames() {
+        let paths: BTreeSet<String> = ["clips/a.mov", "clips/raw/b.mov", "clipsx/c.mov", "notes.txt"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let renames = [pair("videos", "clips"), pair("todo.txt", "notes.txt")];
+
+        assert_eq!(
+            expand_renames(&renames, &paths),
+            vec![
+                pair("videos/a.mov", "clips/a.mov"),
+                pair("videos/raw/b.mov", "clips/raw/b.mov"),
+                pair("videos", "clips"),
+                pair("todo.txt", "notes.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_takes_each_file_once() {
+        let a = entry(10, 1000, Some(1));
+        let new_a = stat(10, 1000, Some(1));
+        let renames = [pair("a.txt", "b.txt"), pair("a.txt", "c.txt")];
+
+        let pairs = match_by_identity(&[("a.txt", &a)], &[("b.txt", &new_a), ("c.txt", &new_a)], &renames);
+        assert_eq!(pairs, vec![pair("a.txt", "b.txt")]);
+    }
+}
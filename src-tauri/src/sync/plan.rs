@@ -0,0 +1,324 @@
+use anyhow::{bail, Context, Result};
+use aws_sdk_s3::Client;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::bidirectional::Action;
+use super::deletions::DeletionGate;
+use super::engine::Worker;
+use super::mirror::MirrorAction;
+use super::reconcile::{classify, collect_local, collect_remote};
+use crate::r2::operations::head_object;
+use crate::upload::fingerprint::LocalStat;
+use crate::utils::paths::{join_key, local_path_for, relative_path};
+use crate::utils::{
+    DeletionPolicy, PlanAction, PlanActionKind, PlanExecution, R2Object, SyncDiffKind, SyncMode, SyncPlan, SyncState,
+    SyncStatus,
+};
+
+/// Plan action for a two-way decision; `None` for decisions that only touch the journal
+fn two_way_kind(action: Action) -> Option<PlanActionKind> {
+    match action {
+        Action::Upload => Some(PlanActionKind::Upload),
+        Action::Download => Some(PlanActionKind::Download),
+        Action::DeleteRemote => Some(PlanActionKind::DeleteRemote),
+        Action::DeleteLocal => Some(PlanActionKind::DeleteLocal),
+        Action::CompareContents => Some(PlanActionKind::Conflict),
+        Action::Move => Some(PlanActionKind::Move),
+        Action::None | Action::Forget => None,
+    }
+}
+
+/// Plan action for a mirror decision; local edits are only flagged, so they don't appear
+fn mirror_kind(action: MirrorAction) -> Option<PlanActionKind> {
+    match action {
+        MirrorAction::Download => Some(PlanActionKind::Download),
+        MirrorAction::DeleteLocal => Some(PlanActionKind::DeleteLocal),
+        _ => None,
+    }
+}
+
+fn planned(relative: String, kind: PlanActionKind, local: Option<&LocalStat>, remote: Option<&R2Object>) -> PlanAction {
+    PlanAction {
+        relative_path: relative,
+        kind,
+        local_size: local.map(|s| s.size),
+        local_mtime: local.map(|s| s.mtime),
+        remote_size: remote.map(|o| o.size),
+        remote_etag: remote.map(|o| o.etag.clone()),
+        moved_from: None,
+    }
+}
+
+/// Why an action of an executed plan was left out
+enum SkipReason {
+    Changed,
+    NeverDeletes,
+}
+
+/// Whether both copies are still as they were when the action was planned
+pub fn matches_plan(action: &PlanAction, local: Option<&LocalStat>, remote_etag: Option<&str>) -> bool {
+    let local_now = local.map(|s| (s.size, s.mtime));
+    let local_then = action.local_size.zip(action.local_mtime);
+    local_now == local_then && remote_etag == action.remote_etag.as_deref()
+}
+
+impl Worker {
+    /// Work out everything a full pass would do, without changing either side
+    pub(super) async fn plan(&self) -> Result<SyncPlan> {
+        if !self.root.is_dir() {
+            bail!("{} is not available", self.root.display());
+        }
+
+        let (client, bucket) = self.connection().await?;
+        let files = collect_local(&self.root, &self.remote_prefix).await;
+        let mut plan = SyncPlan::new(self.folder_id, self.mode);
+
+        if self.mode == SyncMode::UploadOnly {
+            // Upload-only: the local side wins, remote-only objects are left alone
+            let objects = collect_remote(&client, &bucket, &self.remote_prefix).await?;
+            let bucket_id = self.db.get_current_bucket_id().await?;
+            let classified =
+                classify(&self.db, &client, &bucket, bucket_id, &self.remote_prefix, &files, &objects).await?;
+            for (key, kind) in classified {
+                if !matches!(kind, SyncDiffKind::LocalOnly | SyncDiffKind::Changed) {
+                    continue;
+                }
+                let Some(file) = files.get(&key) else { continue };
+                let Some(relative) = relative_path(&self.root, &file.path) else { continue };
+                plan.push(planned(relative, PlanActionKind::Upload, Some(&file.stat), objects.get(&key)));
+            }
+            return Ok(plan);
+        }
+
+        let local: BTreeMap<String, LocalStat> = files
+            .into_values()
+            .filter_map(|file| Some((relative_path(&self.root, &file.path)?, file.stat)))
+            .collect();
+        let remote = self.list_remote(&client, &bucket).await?;
+        let entries = self.db.get_sync_entries(self.folder_id).await?;
+
+        let paths: BTreeSet<&String> = local.keys().chain(remote.keys()).chain(entries.keys()).collect();
+        // The folder keeps both copies of deleted files
+        let kept = |kind: PlanActionKind| {
+            matches!(kind, PlanActionKind::DeleteRemote | PlanActionKind::DeleteLocal)
+                && self.deletions.policy == DeletionPolicy::Never
+        };
+
+        if self.mode == SyncMode::Bidirectional {
+            let mut plans = Vec::with_capacity(paths.len());
+            for relative in paths {
+                let etag = remote.get(relative).map(|o| o.etag.as_str());
+                plans.push(self.plan_path(relative, local.get(relative), etag, entries.get(relative)).await?);
+            }
+            for path_plan in self.pair_moves(plans, &[]).await {
+                let Some(kind) = two_way_kind(path_plan.action).filter(|kind| !kept(*kind)) else { continue };
+                // A move is planned against the object it copies
+                let source = path_plan.moved_from.as_ref().unwrap_or(&path_plan.relative);
+                let object = remote.get(source);
+                let mut action = planned(path_plan.relative.clone(), kind, local.get(&path_plan.relative), object);
+                action.moved_from = path_plan.moved_from;
+                plan.push(action);
+            }
+            return Ok(plan);
+        }
+
+        for relative in paths {
+            let stat = local.get(relative);
+            let object = remote.get(relative);
+            let etag = object.map(|o| o.etag.as_str());
+            let action = self.plan_mirror_path(relative, stat, etag, entries.get(relative)).await?.action;
+            let Some(kind) = mirror_kind(action).filter(|kind| !kept(*kind)) else { continue };
+            plan.push(planned(relative.clone(), kind, stat, object));
+        }
+
+        Ok(plan)
+    }
+
+    /// Carry out a plan from `plan`. Each action is applied only if neither copy changed since;
+    /// the deletions in it count as confirmed, since the user has seen them.
+    pub(super) async fn execute_plan(&self, plan: &SyncPlan) -> Result<PlanExecution> {
+        if plan.folder_id != self.folder_id || plan.mode != self.mode {
+            bail!("The folder's sync settings changed since the plan was made; preview it again");
+        }
+        if !self.root.is_dir() {
+            bail!("{} is not available", self.root.display());
+        }
+
+        // Waits for a pass the folder's own worker has under way
+        let _pass = self.pass.lock().await;
+        let (client, bucket) = self.connection().await?;
+        if self.mode != SyncMode::UploadOnly {
+            // Learns which objects are stored under keys that aren't NFC
+            self.list_remote(&client, &bucket).await?;
+        }
+        let gate = DeletionGate::confirmed(
+            plan.actions.iter().filter(|a| a.is_deletion()).map(|a| a.relative_path.clone()),
+        );
+        let mut result = PlanExecution { folder_id: self.folder_id, ..Default::default() };
+        let mut changed = 0;
+        let mut kept = 0;
+
+        let total = plan.actions.len();
+        for (i, action) in plan.actions.iter().enumerate() {
+            self.board.publish(SyncStatus {
+                folder_id: self.folder_id,
+                state: SyncState::Syncing,
+                pending: total - i,
+                uploaded: result.applied,
+                failed: result.failed,
+                message: None,
+            });
+
+            let relative = &action.relative_path;
+            let applied = async {
+                if action.is_deletion() && self.deletions.policy == DeletionPolicy::Never {
+                    return Ok(Some(SkipReason::NeverDeletes));
+                }
+                if !self.still_current(&client, &bucket, action).await? {
+                    return Ok(Some(SkipReason::Changed));
+                }
+                self.apply_planned(&client, &bucket, action, &gate).await?;
+                Ok::<_, anyhow::Error>(None)
+            }
+            .await;
+
+            match applied {
+                Ok(None) => result.applied += 1,
+                Ok(Some(reason)) => {
+                    match reason {
+                        SkipReason::Changed => {
+                            log::info!("Skipping {}: it changed since the plan was made", relative);
+                            changed += 1;
+                        }
+                        SkipReason::NeverDeletes => {
+                            log::info!("Skipping deletion of {}: the folder never propagates deletions", relative);
+                            kept += 1;
+                        }
+                    }
+                    result.skipped.push(relative.clone());
+                }
+                Err(e) => {
+                    log::error!("Planned sync of {} failed: {:#}", relative, e);
+                    result.failed += 1;
+                }
+            }
+        }
+
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let notices: Vec<String> = [
+            (changed > 0).then(|| format!("{} file{} changed since the plan was made", changed, plural(changed))),
+            (kept > 0).then(|| format!("{} deletion{} skipped by the folder's policy", kept, plural(kept))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let notice = (!notices.is_empty()).then(|| notices.join("; "));
+        self.finish_pass(result.applied, result.failed, notice).await;
+        Ok(result)
+    }
+
+    async fn still_current(&self, client: &Client, bucket: &str, action: &PlanAction) -> Result<bool> {
+        let path = local_path_for(&self.root, &action.relative_path);
+        let local = match path.is_file() {
+            true => Some(LocalStat::read(&path.to_string_lossy()).await?),
+            false => None,
+        };
+        if let Some(from) = &action.moved_from {
+            // Moves also need the old file to still be gone and nothing at the new key yet
+            let key = join_key(&self.remote_prefix, &action.relative_path);
+            if local_path_for(&self.root, from).exists() || head_object(client, bucket, &key).await?.is_some() {
+                return Ok(false);
+            }
+        }
+
+        let source = action.moved_from.as_deref().unwrap_or(&action.relative_path);
+        let key = self.remote_key(source);
+        let remote = head_object(client, bucket, &key).await?;
+        Ok(matches_plan(action, local.as_ref(), remote.as_ref().map(|o| o.etag.as_str())))
+    }
+
+    async fn apply_planned(&self, client: &Client, bucket: &str, action: &PlanAction, gate: &DeletionGate) -> Result<()> {
+        let relative = action.relative_path.as_str();
+        let path = local_path_for(&self.root, relative);
+        let planned = action.local_size.zip(action.local_mtime).map(|(size, mtime)| LocalStat { size, mtime, inode: None });
+
+        match action.kind {
+            // Upload-only folders go through the regular upload path, like their own passes
+            PlanActionKind::Upload if self.mode == SyncMode::UploadOnly => {
+                let key = join_key(&self.remote_prefix, relative);
+                self.upload(&path, key, false).await?;
+            }
+            PlanActionKind::Upload => self.upload_and_record(client, bucket, relative, &path).await?,
+            PlanActionKind::Download => self.download_and_record(client, bucket, relative, &path, planned.as_ref()).await?,
+            PlanActionKind::DeleteRemote => self.delete_remote(client, bucket, relative, gate).await?,
+            PlanActionKind::DeleteLocal => self.delete_local(relative, gate, planned.as_ref()).await?,
+            PlanActionKind::Conflict => {
+                if !self.record_if_identical(client, bucket, relative, &path).await? {
+                    self.keep_both(client, bucket, relative, &path).await?;
+                }
+            }
+            PlanActionKind::Move => {
+                let from = action.moved_from.as_deref().context("A planned move has no source")?;
+                let entry = self
+                    .db
+                    .get_sync_entry(self.folder_id, from)
+                    .await?
+                    .with_context(|| format!("{} is no longer in the journal", from))?;
+                let stat = LocalStat::read(&path.to_string_lossy()).await?;
+                self.move_remote(client, bucket, from, relative, &entry, stat).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(kind: PlanActionKind, local: Option<(i64, i64)>, etag: Option<&str>) -> PlanAction {
+        PlanAction {
+            relative_path: "a.txt".to_string(),
+            kind,
+            local_size: local.map(|(size, _)| size),
+            local_mtime: local.map(|(_, mtime)| mtime),
+            remote_size: etag.map(|_| 10),
+            remote_etag: etag.map(str::to_string),
+            moved_from: None,
+        }
+    }
+
+    fn stat(size: i64, mtime: i64) -> LocalStat {
+        LocalStat { size, mtime, inode: None }
+    }
+
+    #[test]
+    fn test_matches_plan() {
+        let upload = action(PlanActionKind::Upload, Some((5, 1000)), None);
+        assert!(matches_plan(&upload, Some(&stat(5, 1000)), None));
+        // Edited again, or the object appeared in the meantime
+        assert!(!matches_plan(&upload, Some(&stat(5, 2000)), None));
+        assert!(!matches_plan(&upload, Some(&stat(5, 1000)), Some("\"abc\"")));
+
+        let download = action(PlanActionKind::Download, None, Some("\"abc\""));
+        assert!(matches_plan(&download, None, Some("\"abc\"")));
+        assert!(!matches_plan(&download, None, Some("\"def\"")));
+        assert!(!matches_plan(&download, Some(&stat(1, 1)), Some("\"abc\"")));
+    }
+
+    #[test]
+    fn test_plan_totals() {
+        let mut plan = SyncPlan::new(1, SyncMode::Bidirectional);
+        plan.push(action(PlanActionKind::Upload, Some((5, 1000)), None));
+        plan.push(action(PlanActionKind::Download, None, Some("\"abc\"")));
+        plan.push(action(PlanActionKind::Conflict, Some((3, 1000)), Some("\"def\"")));
+        plan.push(action(PlanActionKind::DeleteLocal, Some((4, 1000)), None));
+        // Copied on the remote, so no bytes move
+        plan.push(action(PlanActionKind::Move, Some((50, 1000)), Some("\"ghi\"")));
+
+        assert_eq!((plan.uploads, plan.downloads, plan.deletes, plan.conflicts, plan.moves), (1, 1, 1, 1, 1));
+        assert_eq!(plan.upload_bytes, 8);
+        assert_eq!(plan.download_bytes, 20);
+        assert_eq!(plan.actions.len(), 5);
+    }
+}
@@ -0,0 +1,281 @@
+use anyhow::Result;
+use aws_sdk_s3::Client;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::engine::{expand, is_ignored};
+use crate::db::{Database, FileFingerprint};
+use crate::r2::operations::{list_all_objects, ObjectInfo};
+use crate::upload::fingerprint::{check_unchanged, compare_cached, LocalStat, Verdict};
+use crate::utils::paths::{normalize_key, remote_key_for};
+use crate::utils::{R2Object, ReconcileReport, SyncDiffEntry, SyncDiffKind};
+
+/// A local file found by the scan
+#[derive(Debug, Clone)]
+pub(crate) struct LocalFile {
+    pub path: PathBuf,
+    pub stat: LocalStat,
+}
+
+/// Classification before any file has been hashed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pending {
+    Known(SyncDiffKind),
+    /// Present on both sides with the same size, but the cache can't vouch for it
+    NeedsHash,
+}
+
+/// Listing prefix for a sync folder's remote path; `None` means the whole bucket
+pub(crate) fn listing_prefix(remote_prefix: &str) -> Option<String> {
+    let prefix = remote_prefix.trim_matches('/');
+    (!prefix.is_empty()).then(|| format!("{}/", prefix))
+}
+
+/// Compare the local tree with the remote listing using only metadata and the fingerprint cache
+pub(crate) fn diff(
+    local: &BTreeMap<String, LocalFile>,
+    remote: &BTreeMap<String, R2Object>,
+    cached: &HashMap<String, FileFingerprint>,
+) -> Vec<(String, Pending)> {
+    let keys: HashSet<&String> = local.keys().chain(remote.keys()).collect();
+    let mut keys: Vec<&String> = keys.into_iter().collect();
+    keys.sort();
+
+    keys.into_iter()
+        .map(|key| {
+            let pending = match (local.get(key), remote.get(key)) {
+                (Some(_), None) => Pending::Known(SyncDiffKind::LocalOnly),
+                (None, _) => Pending::Known(SyncDiffKind::RemoteOnly),
+                (Some(file), Some(object)) => {
+                    let info = ObjectInfo {
+                        key: key.clone(),
+                        size: object.size,
+                        etag: object.etag.clone(),
+                        last_modified: None,
+                        sha256: None,
+                    };
+                    match compare_cached(&file.stat, cached.get(key), Some(&info)) {
+                        Verdict::Unchanged(_) => Pending::Known(SyncDiffKind::Identical),
+                        Verdict::Changed(_) => Pending::Known(SyncDiffKind::Changed),
+                        Verdict::NeedsHash => Pending::NeedsHash,
+                    }
+                }
+            };
+            (key.clone(), pending)
+        })
+        .collect()
+}
+
+/// Every syncable file under `root`, keyed by its object key
+pub(crate) async fn collect_local(root: &Path, remote_prefix: &str) -> BTreeMap<String, LocalFile> {
+    let mut local = BTreeMap::new();
+    for path in expand(HashSet::from([root.to_path_buf()])).await {
+        let key = match remote_key_for(root, remote_prefix, &path) {
+            Some(key) => key,
+            None => continue,
+        };
+        match LocalStat::read(&path.to_string_lossy()).await {
+            Ok(stat) => {
+                local.insert(key, LocalFile { path, stat });
+            }
+            // Deleted since the walk
+            Err(e) => log::debug!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    local
+}
+
+/// Every object under the folder's remote prefix, keyed by object key
+pub(crate) async fn collect_remote(
+    client: &Client,
+    bucket: &str,
+    remote_prefix: &str,
+) -> Result<BTreeMap<String, R2Object>> {
+    let prefix = listing_prefix(remote_prefix);
+    let objects = list_all_objects(client, bucket, prefix.as_deref())
+        .await?
+        .into_iter()
+        // Zero-byte "folder" placeholders, and files that are never synced
+        .filter(|o| !o.key.ends_with('/') && !is_ignored(Path::new(&o.key)));
+    Ok(by_normalized_key(objects))
+}
+
+/// Objects keyed by the NFC form of their key, the form local paths map to; each keeps the key
+/// it is stored under, which requests for it have to use. When keys differ only in form, the
+/// object stored in NFC is the one that counts: the others are copies from before keys were
+/// normalized.
+pub(crate) fn by_normalized_key(objects: impl IntoIterator<Item = R2Object>) -> BTreeMap<String, R2Object> {
+    let mut by_key: BTreeMap<String, R2Object> = BTreeMap::new();
+    for object in objects {
+        let key = normalize_key(&object.key);
+        match by_key.get(&key) {
+            Some(existing) if existing.key == key => {
+                log::debug!("Ignoring {:?}, a copy of {:?} in another Unicode form", object.key, key);
+            }
+            _ => {
+                by_key.insert(key, object);
+            }
+        }
+    }
+    by_key
+}
+
+/// Classify every path found on either side, hashing files only when size, modification time
+/// and the cached ETag can't settle it. Keys are object keys.
+pub(crate) async fn classify(
+    db: &Database,
+    client: &Client,
+    bucket: &str,
+    bucket_id: Option<i64>,
+    remote_prefix: &str,
+    local: &BTreeMap<String, LocalFile>,
+    remote: &BTreeMap<String, R2Object>,
+) -> Result<Vec<(String, SyncDiffKind)>> {
+    let prefix = listing_prefix(remote_prefix);
+    let cached = match bucket_id {
+        Some(id) => db.get_file_fingerprints(id, prefix.as_deref().unwrap_or("")).await?,
+        None => HashMap::new(),
+    };
+
+    let mut classified = Vec::new();
+    for (key, pending) in diff(local, remote, &cached) {
+        let kind = match pending {
+            Pending::Known(kind) => kind,
+            Pending::NeedsHash => {
+                // NeedsHash is only produced for keys present on both sides
+                let path = local.get(&key).map(|f| f.path.to_string_lossy().to_string()).unwrap_or_default();
+                let stored = remote.get(&key).map_or(key.as_str(), |o| o.key.as_str());
+                match check_unchanged(db, client, bucket, bucket_id, &path, stored).await {
+                    Ok(check) if check.is_unchanged() => SyncDiffKind::Identical,
+                    Ok(_) => SyncDiffKind::Changed,
+                    Err(e) => {
+                        log::warn!("Could not compare {}: {}", path, e);
+                        SyncDiffKind::Changed
+                    }
+                }
+            }
+        };
+        classified.push((key, kind));
+    }
+    Ok(classified)
+}
+
+/// Full comparison of a sync folder against its remote prefix.
+/// Files are only hashed when size, modification time and the cached ETag can't settle it.
+pub async fn scan(
+    db: &Database,
+    client: &Client,
+    bucket: &str,
+    bucket_id: Option<i64>,
+    folder_id: i64,
+    root: &Path,
+    remote_prefix: &str,
+) -> Result<ReconcileReport> {
+    let local = collect_local(root, remote_prefix).await;
+    let remote = collect_remote(client, bucket, remote_prefix).await?;
+
+    let mut report = ReconcileReport {
+        folder_id,
+        scanned_at: chrono::Utc::now().to_rfc3339(),
+        ..Default::default()
+    };
+
+    for (key, kind) in classify(db, client, bucket, bucket_id, remote_prefix, &local, &remote).await? {
+        report.count(kind);
+        if kind == SyncDiffKind::Identical {
+            continue;
+        }
+
+        let file = local.get(&key);
+        report.entries.push(SyncDiffEntry {
+            local_path: file.map(|f| f.path.to_string_lossy().to_string()),
+            local_size: file.map(|f| f.stat.size),
+            remote_size: remote.get(&key).map(|o| o.size),
+            key,
+            kind,
+        });
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn local_file(size: i64, mtime: i64) -> LocalFile {
+        LocalFile {
+            path: PathBuf::from("/unused"),
+            stat: LocalStat { size, mtime, inode: None },
+        }
+    }
+
+    fn object(key: &str, size: i64, etag: &str) -> R2Object {
+        R2Object {
+            key: key.to_string(),
+            size,
+            last_modified: Utc::now(),
+            etag: etag.to_string(),
+            is_directory: false,
+        }
+    }
+
+    #[test]
+    fn test_listing_prefix() {
+        assert_eq!(listing_prefix("docs"), Some("docs/".to_string()));
+        assert_eq!(listing_prefix("/docs/"), Some("docs/".to_string()));
+        assert_eq!(listing_prefix(""), None);
+    }
+
+    #[test]
+    fn test_diff_classifies_every_path() {
+        let local = BTreeMap::from([
+            ("docs/new.txt".to_string(), local_file(1, 1000)),
+            ("docs/same.txt".to_string(), local_file(5, 1000)),
+            ("docs/resized.txt".to_string(), local_file(7, 1000)),
+            ("docs/touched.txt".to_string(), local_file(5, 2000)),
+        ]);
+        let remote = BTreeMap::from([
+            ("docs/same.txt".to_string(), object("docs/same.txt", 5, "\"aaa\"")),
+            ("docs/resized.txt".to_string(), object("docs/resized.txt", 6, "\"bbb\"")),
+            ("docs/touched.txt".to_string(), object("docs/touched.txt", 5, "\"ccc\"")),
+            ("docs/gone.txt".to_string(), object("docs/gone.txt", 3, "\"ddd\"")),
+        ]);
+        let cached = HashMap::from([
+            (
+                "docs/same.txt".to_string(),
+                FileFingerprint { remote_size: 5, etag: Some("\"aaa\"".to_string()), sha256: None, local_size: 5, local_mtime: 1000 },
+            ),
+            (
+                "docs/touched.txt".to_string(),
+                FileFingerprint { remote_size: 5, etag: Some("\"ccc\"".to_string()), sha256: None, local_size: 5, local_mtime: 1000 },
+            ),
+        ]);
+
+        let result: HashMap<String, Pending> = diff(&local, &remote, &cached).into_iter().collect();
+        assert_eq!(result["docs/new.txt"], Pending::Known(SyncDiffKind::LocalOnly));
+        assert_eq!(result["docs/gone.txt"], Pending::Known(SyncDiffKind::RemoteOnly));
+        assert_eq!(result["docs/same.txt"], Pending::Known(SyncDiffKind::Identical));
+        assert_eq!(result["docs/resized.txt"], Pending::Known(SyncDiffKind::Changed));
+        assert_eq!(result["docs/touched.txt"], Pending::NeedsHash);
+    }
+
+    #[test]
+    fn test_keys_are_matched_in_nfc() {
+        let nfd = object("docs/Cafe\u{301}.txt", 5, "\"old\"");
+        let nfc = object("docs/Caf\u{e9}.txt", 5, "\"new\"");
+
+        let listed = by_normalized_key([nfd.clone(), object("docs/a.txt", 1, "\"a\"")]);
+        assert_eq!(listed.keys().collect::<Vec<_>>(), ["docs/Caf\u{e9}.txt", "docs/a.txt"]);
+        // Requests still go to the key the object is stored under
+        assert_eq!(listed["docs/Caf\u{e9}.txt"].key, nfd.key);
+
+        // A copy stored in NFC wins over the older decomposed one, in either order
+        for objects in [[nfd.clone(), nfc.clone()], [nfc.clone(), nfd.clone()]] {
+            let listed = by_normalized_key(objects);
+            assert_eq!(listed.len(), 1);
+            assert_eq!(listed["docs/Caf\u{e9}.txt"].etag, "\"new\"");
+        }
+    }
+}
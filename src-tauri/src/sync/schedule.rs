@@ -0,0 +1,268 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, LocalResult, NaiveDateTime, TimeZone, Timelike, Utc};
+use std::time::Duration;
+
+use crate::utils::ScheduleKind;
+
+/// How far ahead a cron expression is searched for its next match; covers leap days
+const CRON_HORIZON_DAYS: i64 = 4 * 366;
+
+/// A five-field cron expression: minute, hour, day of month, month and day of week.
+/// Fields take `*`, numbers, ranges, lists and `/` steps; times are local.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// As in cron, when both day fields are restricted a day matching either one runs
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_number(value: &str, field: &str) -> Result<u32> {
+    value.parse().map_err(|_| anyhow!("Invalid value '{}' in '{}'", value, field))
+}
+
+/// Bitmask of the values a field allows
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_number(step, field)?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("Invalid step in '{}'", field);
+        }
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (parse_number(start, field)?, parse_number(end, field)?),
+                // "5/15" runs from 5 to the end of the range
+                None if part.contains('/') => (parse_number(range, field)?, max),
+                None => {
+                    let value = parse_number(range, field)?;
+                    (value, value)
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            bail!("'{}' is outside {}-{}", part, min, max);
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("A schedule needs five fields: minute, hour, day of month, month and day of week");
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // Both 0 and 7 are Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+
+        let schedule = Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        };
+        if schedule.next_after(Local::now()).is_none() {
+            bail!("'{}' never runs", expression);
+        }
+        Ok(schedule)
+    }
+
+    fn day_matches(&self, date: &NaiveDateTime) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    /// The first matching minute after `after`
+    pub fn next_after<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let tz = after.timezone();
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let limit = start + ChronoDuration::days(CRON_HORIZON_DAYS);
+
+        let mut t = start;
+        while t < limit {
+            let midnight = t.date().and_hms_opt(0, 0, 0)?;
+            if self.months & (1 << t.month()) == 0 {
+                let first = t.date().with_day(1)?;
+                let next_month = if first.month() == 12 {
+                    first.with_year(first.year() + 1)?.with_month(1)?
+                } else {
+                    first.with_month(first.month() + 1)?
+                };
+                t = next_month.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(&t) {
+                t = midnight + ChronoDuration::days(1);
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = t.with_minute(0)? + ChronoDuration::hours(1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t += ChronoDuration::minutes(1);
+            } else {
+                match tz.from_local_datetime(&t) {
+                    LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => return Some(time),
+                    // Skipped by a daylight saving change
+                    LocalResult::None => t += ChronoDuration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// When a folder's full passes run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Changes are picked up by the watcher; two-way and mirror folders also poll the remote
+    Realtime { poll: Option<Duration> },
+    /// A full pass at a fixed interval, without watching the folder
+    Interval(Duration),
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    pub fn new(kind: ScheduleKind, cron: Option<&str>, interval: Duration, polls_remote: bool) -> Result<Self> {
+        Ok(match kind {
+            ScheduleKind::Realtime => Schedule::Realtime { poll: polls_remote.then_some(interval) },
+            ScheduleKind::Interval => Schedule::Interval(interval),
+            ScheduleKind::Cron => {
+                let expression = cron.ok_or_else(|| anyhow!("A cron schedule needs an expression"))?;
+                Schedule::Cron(CronSchedule::parse(expression)?)
+            }
+        })
+    }
+
+    /// Whether the folder is watched for changes between passes
+    pub fn watches(&self) -> bool {
+        matches!(self, Schedule::Realtime { .. })
+    }
+
+    /// When the first pass after startup is due. Real-time folders catch up straight away;
+    /// interval folders only if the last pass is older than the interval.
+    pub fn first_run(&self, last_sync: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Realtime { .. } => Some(now),
+            Schedule::Interval(interval) => {
+                let due = last_sync.map(|last| last + ChronoDuration::from_std(*interval).unwrap_or_default());
+                Some(due.map_or(now, |due| due.max(now)))
+            }
+            Schedule::Cron(cron) => cron.next_after(now.with_timezone(&Local)).map(|t| t.with_timezone(&Utc)),
+        }
+    }
+
+    /// When the pass after one finishing at `now` is due; `None` if only changes trigger one
+    pub fn next_run(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Realtime { poll } => poll.map(|poll| now + ChronoDuration::from_std(poll).unwrap_or_default()),
+            Schedule::Interval(interval) => Some(now + ChronoDuration::from_std(*interval).unwrap_or_default()),
+            Schedule::Cron(cron) => cron.next_after(now.with_timezone(&Local)).map(|t| t.with_timezone(&Utc)),
+        }
+    }
+}
+
+/// `last_sync` as stored by SQLite's CURRENT_TIMESTAMP, which is UTC
+pub fn parse_last_sync(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").ok().map(|t| t.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap().and_utc()
+    }
+
+    fn next(expression: &str, after: &str) -> String {
+        CronSchedule::parse(expression)
+            .unwrap()
+            .next_after(at(after))
+            .unwrap()
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        // 2026-10-16 is a Friday
+        assert_eq!(next("30 2 * * *", "2026-10-16 01:00"), "2026-10-16 02:30");
+        assert_eq!(next("30 2 * * *", "2026-10-16 02:30"), "2026-10-17 02:30");
+        assert_eq!(next("*/15 * * * *", "2026-10-16 10:07"), "2026-10-16 10:15");
+        assert_eq!(next("0 22-23,0-5 * * *", "2026-10-16 06:00"), "2026-10-16 22:00");
+        assert_eq!(next("0 3 * * 1-5", "2026-10-16 04:00"), "2026-10-19 03:00");
+        assert_eq!(next("0 0 1 * *", "2026-12-15 00:00"), "2027-01-01 00:00");
+        assert_eq!(next("0 0 29 2 *", "2026-03-01 00:00"), "2028-02-29 00:00");
+        assert_eq!(next("@weekly", "2026-10-16 00:00"), "2026-10-18 00:00");
+        // Sunday as 7
+        assert_eq!(next("0 12 * * 7", "2026-10-16 00:00"), "2026-10-18 12:00");
+        // Both day fields restricted: either one matches
+        assert_eq!(next("0 0 20 * 6", "2026-10-16 00:00"), "2026-10-17 00:00");
+    }
+
+    #[test]
+    fn test_cron_rejects_invalid() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("5-1 * * * *").is_err());
+        assert!(CronSchedule::parse("0 0 30 2 *").is_err());
+        assert!(CronSchedule::parse("a * * * *").is_err());
+    }
+
+    #[test]
+    fn test_schedule_runs() {
+        let now = at("2026-10-16 12:00");
+        let hour = Duration::from_secs(3600);
+
+        let realtime = Schedule::new(ScheduleKind::Realtime, None, hour, false).unwrap();
+        assert!(realtime.watches());
+        assert_eq!(realtime.first_run(None, now), Some(now));
+        assert_eq!(realtime.next_run(now), None);
+        let polling = Schedule::new(ScheduleKind::Realtime, None, hour, true).unwrap();
+        assert_eq!(polling.next_run(now), Some(at("2026-10-16 13:00")));
+
+        let interval = Schedule::new(ScheduleKind::Interval, None, hour, false).unwrap();
+        assert!(!interval.watches());
+        assert_eq!(interval.first_run(None, now), Some(now));
+        assert_eq!(interval.first_run(Some(at("2026-10-16 11:30")), now), Some(at("2026-10-16 12:30")));
+        assert_eq!(interval.first_run(Some(at("2026-10-15 11:30")), now), Some(now));
+
+        assert!(Schedule::new(ScheduleKind::Cron, None, hour, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_last_sync() {
+        assert_eq!(parse_last_sync("2026-10-16 12:00:00"), Some(at("2026-10-16 12:00")));
+        assert_eq!(parse_last_sync("yesterday"), None);
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::watcher::Batch;
+
+/// Size and modification time: what keeps changing while a file is being written
+type Snapshot = (u64, Option<SystemTime>);
+
+fn snapshot(path: &Path) -> Option<Snapshot> {
+    let metadata = std::fs::metadata(path).ok()?;
+    metadata.is_file().then(|| (metadata.len(), metadata.modified().ok()))
+}
+
+/// Holds changed files back until applications are done writing them: a file is ready
+/// once its size and modification time have stayed the same for the settle window
+pub struct Settler {
+    window: Duration,
+    /// Files still waiting, with how they looked when last seen changing
+    waiting: HashMap<PathBuf, (Snapshot, Instant)>,
+}
+
+impl Settler {
+    pub fn new(window: Duration) -> Self {
+        Self { window, waiting: HashMap::new() }
+    }
+
+    /// Take the paths in `pending` that are ready to sync, leaving files that are still changing.
+    /// Removed paths and folders are ready straight away.
+    pub fn take_ready(&mut self, pending: &mut Batch, now: Instant) -> Batch {
+        self.take_ready_with(pending, now, snapshot)
+    }
+
+    fn take_ready_with(&mut self, pending: &mut Batch, now: Instant, stat: impl Fn(&Path) -> Option<Snapshot>) -> Batch {
+        let mut ready = Batch::default();
+        for path in std::mem::take(&mut pending.paths) {
+            let settled = match stat(&path) {
+                None => true,
+                Some(current) => match self.waiting.get(&path) {
+                    Some((seen, since)) if *seen == current => now.duration_since(*since) >= self.window,
+                    _ => {
+                        self.waiting.insert(path.clone(), (current, now));
+                        self.window.is_zero()
+                    }
+                },
+            };
+            if settled {
+                self.waiting.remove(&path);
+                ready.paths.insert(path);
+            } else {
+                pending.paths.insert(path);
+            }
+        }
+
+        // Both ends of a rename go together, once the file it ended at is ready
+        for (from, to) in std::mem::take(&mut pending.renames) {
+            if ready.paths.contains(&to) {
+                ready.renames.push((from, to));
+            } else {
+                if ready.paths.remove(&from) {
+                    pending.paths.insert(from.clone());
+                }
+                pending.renames.push((from, to));
+            }
+        }
+        ready
+    }
+
+    /// When the file that has waited longest could be ready
+    pub fn next_check(&self) -> Option<Instant> {
+        self.waiting.values().map(|(_, since)| *since + self.window).min()
+    }
+
+    pub fn clear(&mut self) {
+        self.waiting.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn batch(paths: &[&str]) -> Batch {
+        Batch { paths: paths.iter().map(PathBuf::from).collect(), renames: Vec::new() }
+    }
+
+    #[test]
+    fn test_waits_for_writes_to_stop() {
+        let window = Duration::from_secs(5);
+        let mut settler = Settler::new(window);
+        let start = Instant::now();
+        let size = RefCell::new(100);
+        let stat = |path: &Path| (path == Path::new("export.mov")).then(|| (*size.borrow(), None));
+
+        let mut pending = batch(&["export.mov", "removed.txt"]);
+        let ready = settler.take_ready_with(&mut pending, start, stat);
+        assert_eq!(ready.paths, batch(&["removed.txt"]).paths);
+        assert_eq!(settler.next_check(), Some(start + window));
+
+        // Still growing: the window starts over
+        *size.borrow_mut() = 200;
+        let later = start + Duration::from_secs(6);
+        assert!(settler.take_ready_with(&mut pending, later, stat).is_empty());
+        assert_eq!(settler.next_check(), Some(later + window));
+
+        let ready = settler.take_ready_with(&mut pending, later + window, stat);
+        assert_eq!(ready.paths, batch(&["export.mov"]).paths);
+        assert!(pending.is_empty());
+        assert_eq!(settler.next_check(), None);
+    }
+
+    #[test]
+    fn test_zero_window_is_immediate() {
+        let mut settler = Settler::new(Duration::ZERO);
+        let mut pending = batch(&["a.txt"]);
+        let ready = settler.take_ready_with(&mut pending, Instant::now(), |_| Some((1, None)));
+        assert_eq!(ready.paths.len(), 1);
+        assert_eq!(settler.next_check(), None);
+    }
+
+    #[test]
+    fn test_rename_waits_for_destination() {
+        let mut settler = Settler::new(Duration::from_secs(5));
+        let (from, to) = (PathBuf::from("old.mov"), PathBuf::from("new.mov"));
+        let mut pending = Batch { paths: [from.clone(), to.clone()].into(), renames: vec![(from.clone(), to.clone())] };
+        let stat = |path: &Path| (path == Path::new("new.mov")).then_some((10, None));
+
+        let start = Instant::now();
+        assert!(settler.take_ready_with(&mut pending, start, stat).is_empty());
+        assert_eq!(pending.paths.len(), 2);
+
+        let ready = settler.take_ready_with(&mut pending, start + Duration::from_secs(5), stat);
+        assert_eq!(ready.renames, vec![(from, to)]);
+        assert_eq!(ready.paths.len(), 2);
+    }
+}
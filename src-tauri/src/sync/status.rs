@@ -0,0 +1,419 @@
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Manager;
+
+use super::engine::{expand, is_ignored, StatusBoard, Worker};
+use crate::db::Database;
+use crate::upload::fingerprint::LocalStat;
+use crate::upload::manager::UploadManager;
+use crate::utils::paths::{join_key, local_path_for, relative_path};
+use crate::utils::{
+    FileStatusCounts, FileSyncState, FileSyncStatus, SyncError, SyncMode, SyncStatus, UploadProgress, UploadStatus,
+};
+use crate::SyncFolder;
+
+/// Id of the tray icon, whose tooltip sums up what sync is doing
+pub const TRAY_ID: &str = "main";
+const TRAY_TITLE: &str = "Cloudflare Backup";
+/// Shortest time between tooltip updates, which can be asked for once per file
+const TRAY_REFRESH: Duration = Duration::from_secs(1);
+
+/// State, upload progress and message of one file
+type Resolved = (FileSyncState, Option<f64>, Option<String>);
+
+/// What is known about a file, from which its state follows
+#[derive(Default)]
+struct Evidence<'a> {
+    /// Size and modification time now; `None` if the file is gone
+    stat: Option<(i64, i64)>,
+    /// Size and modification time when it last synced, from the journal or the upload fingerprint
+    synced: Option<(i64, i64)>,
+    /// State recorded by the worker while uploading or after a failure, with its message
+    recorded: Option<&'a (String, Option<String>)>,
+    /// Most recent upload of the file
+    upload: Option<&'a UploadProgress>,
+    conflicted: bool,
+    /// Why the file is left out of sync, if it is
+    quarantined: Option<&'a SyncError>,
+}
+
+fn resolve(evidence: &Evidence) -> Resolved {
+    match evidence.quarantined {
+        Some(error) if error.ignored => return (FileSyncState::Ignored, None, None),
+        Some(error) => return (FileSyncState::Error, None, Some(error.message.clone())),
+        None => {}
+    }
+    let upload = evidence.upload;
+    if let Some(upload) = upload.filter(|u| matches!(u.status, UploadStatus::Pending | UploadStatus::Uploading)) {
+        return (FileSyncState::Uploading, Some(upload.progress), None);
+    }
+    match evidence.recorded.map(|(state, message)| (state.as_str(), message)) {
+        Some(("uploading", _)) => return (FileSyncState::Uploading, None, None),
+        _ if evidence.conflicted => return (FileSyncState::Conflicted, None, None),
+        Some(("error", message)) => return (FileSyncState::Error, None, message.clone()),
+        _ => {}
+    }
+
+    match (evidence.stat, evidence.synced) {
+        (Some(stat), Some(synced)) if stat == synced => (FileSyncState::Synced, None, None),
+        // Never synced and not there: nothing to badge
+        (None, None) => (FileSyncState::Untracked, None, None),
+        // Changed, new, or deleted locally and not yet synced
+        _ => (FileSyncState::Pending, None, None),
+    }
+}
+
+/// Resolve the state of files in one sync folder, keyed by relative path
+async fn resolve_folder(
+    db: &Database,
+    folder: &SyncFolder,
+    bucket_id: Option<i64>,
+    relatives: &[String],
+) -> Result<HashMap<String, Resolved>> {
+    let root = Path::new(&folder.local_path);
+    let recorded = db.get_sync_file_states(folder.id, relatives).await?;
+    let quarantined = db.get_quarantined_sync_errors(folder.id).await?;
+    let conflicted: HashSet<String> = db
+        .get_sync_conflicts(Some(folder.id))
+        .await?
+        .into_iter()
+        .flat_map(|c| [c.relative_path, c.conflict_path])
+        .collect();
+
+    // Upload-only folders keep no journal; their uploads leave a fingerprint instead
+    let synced: HashMap<String, (i64, i64)> = match (folder.sync_mode, bucket_id) {
+        (SyncMode::UploadOnly, Some(bucket_id)) => {
+            let keys: Vec<String> = relatives.iter().map(|r| join_key(&folder.remote_path, r)).collect();
+            let fingerprints = db.get_file_fingerprints_for(bucket_id, &keys).await?;
+            relatives
+                .iter()
+                .zip(&keys)
+                .filter_map(|(relative, key)| fingerprints.get(key).map(|f| (relative.clone(), (f.local_size, f.local_mtime))))
+                .collect()
+        }
+        (SyncMode::UploadOnly, None) => HashMap::new(),
+        _ => db
+            .get_sync_entries_for(folder.id, relatives)
+            .await?
+            .into_iter()
+            .map(|(relative, entry)| (relative, (entry.local_size, entry.local_mtime)))
+            .collect(),
+    };
+
+    let local_paths: Vec<String> = relatives
+        .iter()
+        .map(|r| local_path_for(root, r).to_string_lossy().to_string())
+        .collect();
+    let uploads = UploadManager::new(db.pool().clone()).get_latest_uploads(&local_paths).await?;
+
+    let mut resolved = HashMap::with_capacity(relatives.len());
+    for (relative, local_path) in relatives.iter().zip(&local_paths) {
+        let stat = LocalStat::read(local_path).await.ok();
+        let evidence = Evidence {
+            stat: stat.map(|s| (s.size, s.mtime)),
+            synced: synced.get(relative).copied(),
+            recorded: recorded.get(relative),
+            upload: uploads.get(local_path),
+            conflicted: conflicted.contains(relative),
+            quarantined: quarantined.get(relative),
+        };
+        resolved.insert(relative.clone(), resolve(&evidence));
+    }
+    Ok(resolved)
+}
+
+/// What a requested path stands for
+enum Target {
+    Untracked,
+    Ignored { folder_id: i64 },
+    File { folder: usize, relative: String },
+    /// A sync folder or a folder inside one, badged by the files it holds
+    Folder { folder: usize, relatives: Vec<String> },
+}
+
+async fn target_of(folders: &[SyncFolder], path: &Path) -> Target {
+    // Sync folders can be nested; the innermost one syncs the path
+    let found = folders
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| path.starts_with(&f.local_path))
+        .max_by_key(|(_, f)| f.local_path.len());
+    let (index, folder) = match found {
+        Some(found) => found,
+        None => return Target::Untracked,
+    };
+    let root = Path::new(&folder.local_path);
+
+    if path != root && is_ignored(path) {
+        return Target::Ignored { folder_id: folder.id };
+    }
+    if path.is_dir() {
+        let relatives = expand(HashSet::from([path.to_path_buf()]))
+            .await
+            .iter()
+            .filter_map(|file| relative_path(root, file))
+            .collect();
+        return Target::Folder { folder: index, relatives };
+    }
+    match relative_path(root, path) {
+        Some(relative) => Target::File { folder: index, relative },
+        None => Target::Untracked,
+    }
+}
+
+/// Sync status of local files and folders, in the order asked for
+pub(super) async fn file_statuses(db: &Database, paths: &[String]) -> Result<Vec<FileSyncStatus>> {
+    let folders = db.get_sync_folders().await?;
+    let bucket_id = db.get_current_bucket_id().await?;
+
+    // Files behind every path, grouped by sync folder so each folder is looked up once
+    let mut targets = Vec::with_capacity(paths.len());
+    let mut wanted: HashMap<usize, BTreeSet<String>> = HashMap::new();
+    for path in paths {
+        let target = target_of(&folders, Path::new(path)).await;
+        match &target {
+            Target::File { folder, relative } => {
+                wanted.entry(*folder).or_default().insert(relative.clone());
+            }
+            Target::Folder { folder, relatives } => wanted.entry(*folder).or_default().extend(relatives.iter().cloned()),
+            Target::Untracked | Target::Ignored { .. } => {}
+        }
+        targets.push(target);
+    }
+
+    let mut resolved = HashMap::new();
+    for (index, relatives) in wanted {
+        let relatives: Vec<String> = relatives.into_iter().collect();
+        resolved.insert(index, resolve_folder(db, &folders[index], bucket_id, &relatives).await?);
+    }
+
+    let statuses = paths
+        .iter()
+        .zip(targets)
+        .map(|(path, target)| {
+            let status = |folder_id, (state, progress, message): Resolved, counts| FileSyncStatus {
+                path: path.clone(),
+                folder_id,
+                state,
+                progress,
+                message,
+                counts,
+            };
+            match target {
+                Target::Untracked => status(None, (FileSyncState::Untracked, None, None), None),
+                Target::Ignored { folder_id } => status(Some(folder_id), (FileSyncState::Ignored, None, None), None),
+                Target::File { folder, relative } => {
+                    let found = resolved[&folder].get(&relative).cloned();
+                    status(Some(folders[folder].id), found.unwrap_or((FileSyncState::Untracked, None, None)), None)
+                }
+                Target::Folder { folder, relatives } => {
+                    let mut counts = FileStatusCounts::default();
+                    for relative in &relatives {
+                        if let Some((state, _, _)) = resolved[&folder].get(relative) {
+                            counts.add(*state);
+                        }
+                    }
+                    status(Some(folders[folder].id), (counts.summary(), None, None), Some(counts))
+                }
+            }
+        })
+        .collect();
+    Ok(statuses)
+}
+
+impl Worker {
+    /// Record a file's new state and tell the frontend. Only uploads in progress and failures
+    /// are stored; other states follow from the journal. Failures also go to the retry queue.
+    pub(super) async fn record_file_state(&self, relative: &str, state: FileSyncState, message: Option<String>) {
+        match state {
+            FileSyncState::Error => self.track_error(relative, message.as_deref().unwrap_or("Sync failed")).await,
+            FileSyncState::Synced | FileSyncState::Conflicted => self.clear_error(relative).await,
+            _ => {}
+        }
+
+        let saved = match state {
+            FileSyncState::Uploading => self.db.set_sync_file_state(self.folder_id, relative, "uploading", None).await,
+            FileSyncState::Error => self.db.set_sync_file_state(self.folder_id, relative, "error", message.as_deref()).await,
+            _ => self.db.clear_sync_file_state(self.folder_id, relative).await,
+        };
+        if let Err(e) = saved {
+            log::warn!("Failed to record the sync state of {}: {}", relative, e);
+        }
+
+        self.board.publish_file(FileSyncStatus {
+            path: local_path_for(&self.root, relative).to_string_lossy().to_string(),
+            folder_id: Some(self.folder_id),
+            state,
+            progress: None,
+            message,
+            counts: None,
+        });
+    }
+
+    /// Forget recorded failures before a full pass, which records whatever still fails
+    pub(super) async fn clear_file_states(&self) {
+        if let Err(e) = self.db.clear_sync_file_states(self.folder_id).await {
+            log::warn!("Failed to clear the sync states of {}: {}", self.root.display(), e);
+        }
+    }
+}
+
+fn count(n: usize, one: &str, many: &str) -> String {
+    format!("{} {}", n, if n == 1 { one } else { many })
+}
+
+/// Tray tooltip summing up every folder
+fn tray_tooltip(statuses: &[SyncStatus], file_states: &HashMap<String, i64>, conflicts: usize) -> String {
+    if statuses.is_empty() {
+        return TRAY_TITLE.to_string();
+    }
+
+    let uploading = file_states.get("uploading").copied().unwrap_or(0) as usize;
+    let errors = file_states.get("error").copied().unwrap_or(0) as usize;
+    let pending: usize = statuses.iter().map(|s| s.pending).sum::<usize>().saturating_sub(uploading);
+
+    let parts: Vec<String> = [
+        (uploading, format!("{} uploading", uploading)),
+        (pending, format!("{} pending", pending)),
+        (errors, count(errors, "error", "errors")),
+        (conflicts, count(conflicts, "conflict", "conflicts")),
+    ]
+    .into_iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(_, part)| part)
+    .collect();
+
+    let summary = match parts.is_empty() {
+        true => "Up to date".to_string(),
+        false => parts.join(", "),
+    };
+    format!("{}\n{}", TRAY_TITLE, summary)
+}
+
+/// Keep the tray tooltip in step with the status board, refreshing at most once per `TRAY_REFRESH`
+pub(super) async fn update_tray(board: StatusBoard, db: Arc<Database>) {
+    // Uploads recorded by a previous run can't still be going
+    if let Err(e) = db.clear_interrupted_sync_file_states().await {
+        log::warn!("Failed to clear interrupted sync states: {}", e);
+    }
+
+    loop {
+        board.tray_changed().await;
+
+        let file_states = db.count_sync_file_states().await.unwrap_or_default();
+        let conflicts = db.get_sync_conflicts(None).await.map(|c| c.len()).unwrap_or(0);
+        let tooltip = tray_tooltip(&board.snapshot(), &file_states, conflicts);
+        if let Some(tray) = board.app.tray_by_id(TRAY_ID) {
+            if let Err(e) = tray.set_tooltip(Some(tooltip)) {
+                log::warn!("Failed to update the tray tooltip: {}", e);
+            }
+        }
+
+        tokio::time::sleep(TRAY_REFRESH).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SyncState;
+
+    fn upload(status: UploadStatus, progress: f64) -> UploadProgress {
+        UploadProgress {
+            id: "1".to_string(),
+            file_name: "a.txt".to_string(),
+            file_path: "/docs/a.txt".to_string(),
+            remote_path: "docs/a.txt".to_string(),
+            total_size: 100,
+            uploaded_size: progress as i64,
+            progress,
+            speed: 0.0,
+            eta: 0,
+            status,
+            error_message: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let state = |evidence: Evidence| resolve(&evidence).0;
+        let same = Some((10, 1000));
+
+        assert_eq!(state(Evidence { stat: same, synced: same, ..Default::default() }), FileSyncState::Synced);
+        // Edited since it synced, new, or deleted
+        assert_eq!(state(Evidence { stat: Some((10, 2000)), synced: same, ..Default::default() }), FileSyncState::Pending);
+        assert_eq!(state(Evidence { stat: same, ..Default::default() }), FileSyncState::Pending);
+        assert_eq!(state(Evidence { synced: same, ..Default::default() }), FileSyncState::Pending);
+        assert_eq!(state(Evidence::default()), FileSyncState::Untracked);
+
+        let uploading = upload(UploadStatus::Uploading, 40.0);
+        let resolved = resolve(&Evidence { stat: same, synced: same, upload: Some(&uploading), ..Default::default() });
+        assert_eq!(resolved, (FileSyncState::Uploading, Some(40.0), None));
+        // A finished upload says nothing the fingerprint doesn't
+        let failed = upload(UploadStatus::Failed, 40.0);
+        assert_eq!(state(Evidence { stat: same, synced: same, upload: Some(&failed), ..Default::default() }), FileSyncState::Synced);
+
+        let error = ("error".to_string(), Some("Access denied".to_string()));
+        let resolved = resolve(&Evidence { stat: same, recorded: Some(&error), ..Default::default() });
+        assert_eq!(resolved, (FileSyncState::Error, None, Some("Access denied".to_string())));
+        assert_eq!(
+            state(Evidence { stat: same, recorded: Some(&error), conflicted: true, ..Default::default() }),
+            FileSyncState::Conflicted
+        );
+        let recorded = ("uploading".to_string(), None);
+        assert_eq!(state(Evidence { stat: same, recorded: Some(&recorded), ..Default::default() }), FileSyncState::Uploading);
+
+        let mut quarantined = SyncError {
+            id: 1,
+            folder_id: 1,
+            relative_path: "a.txt".to_string(),
+            kind: crate::utils::SyncErrorKind::Permanent,
+            message: "Permission denied".to_string(),
+            attempts: 1,
+            next_retry_at: None,
+            ignored: false,
+            first_failed_at: "2026-10-18 12:00:00".to_string(),
+            last_failed_at: "2026-10-18 12:00:00".to_string(),
+        };
+        let resolved = resolve(&Evidence { stat: same, synced: same, quarantined: Some(&quarantined), ..Default::default() });
+        assert_eq!(resolved, (FileSyncState::Error, None, Some("Permission denied".to_string())));
+        quarantined.ignored = true;
+        assert_eq!(state(Evidence { stat: same, quarantined: Some(&quarantined), ..Default::default() }), FileSyncState::Ignored);
+    }
+
+    #[test]
+    fn test_folder_summary() {
+        let mut counts = FileStatusCounts::default();
+        assert_eq!(counts.summary(), FileSyncState::Synced);
+        for state in [FileSyncState::Synced, FileSyncState::Pending, FileSyncState::Ignored] {
+            counts.add(state);
+        }
+        assert_eq!(counts.summary(), FileSyncState::Pending);
+        counts.add(FileSyncState::Error);
+        assert_eq!(counts.summary(), FileSyncState::Error);
+        assert_eq!((counts.synced, counts.pending, counts.error), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_tray_tooltip() {
+        let status = |pending| SyncStatus {
+            folder_id: 1,
+            state: SyncState::Syncing,
+            pending,
+            uploaded: 0,
+            failed: 0,
+            message: None,
+        };
+        assert_eq!(tray_tooltip(&[], &HashMap::new(), 0), "Cloudflare Backup");
+        assert_eq!(tray_tooltip(&[status(0)], &HashMap::new(), 0), "Cloudflare Backup\nUp to date");
+
+        let states = HashMap::from([("uploading".to_string(), 1), ("error".to_string(), 2)]);
+        assert_eq!(
+            tray_tooltip(&[status(3), status(2)], &states, 1),
+            "Cloudflare Backup\n1 uploading, 4 pending, 2 errors, 1 conflict"
+        );
+    }
+}
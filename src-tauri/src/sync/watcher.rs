@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Quiet period after the last filesystem event before a batch is handed over
+pub const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// What the OS reported for one path, or for both ends of a rename
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    Changed(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+/// Changed paths collected from a burst of events. Both ends of a rename are in `paths` too;
+/// `renames` only hints which files moved, so they can be matched without hashing.
+#[derive(Debug, Default)]
+pub struct Batch {
+    pub paths: HashSet<PathBuf>,
+    pub renames: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Batch {
+    fn add(&mut self, event: WatchEvent) {
+        match event {
+            WatchEvent::Changed(path) => {
+                self.paths.insert(path);
+            }
+            WatchEvent::Renamed { from, to } => {
+                self.paths.insert(from.clone());
+                self.paths.insert(to.clone());
+                self.renames.push((from, to));
+            }
+        }
+    }
+
+    pub fn extend(&mut self, other: Batch) {
+        self.paths.extend(other.paths);
+        self.renames.extend(other.renames);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.renames.clear();
+    }
+}
+
+/// Recursive `notify` watcher for one sync folder that hands out debounced batches of changed paths
+pub struct FolderWatcher {
+    // Dropping the watcher stops the OS watch
+    _watcher: RecommendedWatcher,
+    events: mpsc::UnboundedReceiver<WatchEvent>,
+    pending: Batch,
+}
+
+impl FolderWatcher {
+    pub fn new(root: &Path) -> Result<Self> {
+        let (tx, events) = mpsc::unbounded_channel();
+
+        let mut watcher = RecommendedWatcher::new(
+            move |res: notify::Result<Event>| match res {
+                Ok(event) if is_relevant(&event.kind) => {
+                    let rename = matches!(event.kind, EventKind::Modify(ModifyKind::Name(RenameMode::Both)));
+                    match &event.paths[..] {
+                        [from, to] if rename => {
+                            let _ = tx.send(WatchEvent::Renamed { from: from.clone(), to: to.clone() });
+                        }
+                        _ => {
+                            for path in event.paths {
+                                let _ = tx.send(WatchEvent::Changed(path));
+                            }
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("File watcher error: {}", e),
+            },
+            Config::default(),
+        )
+        .context("Failed to create file watcher")?;
+
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", root.display()))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            pending: Batch::default(),
+        })
+    }
+
+    /// Wait for changes, then keep collecting until `DEBOUNCE` passes without another event.
+    /// Paths collected so far survive if this future is dropped, so it is safe to use in `select!`.
+    /// Returns `None` once the watcher has shut down.
+    pub async fn next_batch(&mut self) -> Option<Batch> {
+        debounce(&mut self.events, &mut self.pending, DEBOUNCE).await
+    }
+}
+
+fn is_relevant(kind: &EventKind) -> bool {
+    matches!(kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+}
+
+async fn debounce(
+    events: &mut mpsc::UnboundedReceiver<WatchEvent>,
+    pending: &mut Batch,
+    quiet: Duration,
+) -> Option<Batch> {
+    if pending.is_empty() {
+        pending.add(events.recv().await?);
+    }
+
+    // Stops when the quiet period elapses or the channel closes
+    while let Ok(Some(event)) = tokio::time::timeout(quiet, events.recv()).await {
+        pending.add(event);
+    }
+
+    Some(std::mem::take(pending))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_debounce_collects_burst() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut pending = Batch::default();
+
+        tx.send(WatchEvent::Changed(PathBuf::from("a"))).unwrap();
+        tx.send(WatchEvent::Changed(PathBuf::from("b"))).unwrap();
+        tx.send(WatchEvent::Changed(PathBuf::from("a"))).unwrap();
+
+        let batch = debounce(&mut rx, &mut pending, Duration::from_millis(50)).await.unwrap();
+        assert_eq!(batch.paths.len(), 2);
+        assert!(batch.renames.is_empty());
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_debounce_keeps_renames() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut pending = Batch::default();
+
+        let (from, to) = (PathBuf::from("old.mov"), PathBuf::from("new.mov"));
+        tx.send(WatchEvent::Renamed { from: from.clone(), to: to.clone() }).unwrap();
+
+        let batch = debounce(&mut rx, &mut pending, Duration::from_millis(50)).await.unwrap();
+        assert!(batch.paths.contains(&from) && batch.paths.contains(&to));
+        assert_eq!(batch.renames, vec![(from, to)]);
+    }
+
+    #[tokio::test]
+    async fn test_debounce_returns_none_when_closed() {
+        let (tx, mut rx) = mpsc::unbounded_channel::<WatchEvent>();
+        drop(tx);
+
+        let mut pending = Batch::default();
+        assert!(debounce(&mut rx, &mut pending, Duration::from_millis(10)).await.is_none());
+    }
+}
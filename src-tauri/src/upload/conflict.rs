@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::{oneshot, Mutex};
+
+/// How long an "ask" prompt waits for the frontend before falling back to skipping
+pub const PROMPT_TIMEOUT_SECS: u64 = 600;
+
+/// What to do when an upload targets a key that already exists remotely
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    Overwrite,
+    Skip,
+    Rename,
+    Ask,
+}
+
+impl ConflictPolicy {
+    /// Parse a stored `conflict_resolution` setting.
+    /// "local"/"remote" are older names for keeping the local or the remote copy.
+    pub fn from_setting(value: &str) -> Self {
+        match value {
+            "overwrite" | "local" => ConflictPolicy::Overwrite,
+            "skip" | "remote" => ConflictPolicy::Skip,
+            "rename" => ConflictPolicy::Rename,
+            _ => ConflictPolicy::Ask,
+        }
+    }
+}
+
+/// A concrete decision for one conflicting upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictAction {
+    Overwrite,
+    Skip,
+    Rename,
+}
+
+impl ConflictAction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "overwrite" => Some(ConflictAction::Overwrite),
+            "skip" => Some(ConflictAction::Skip),
+            "rename" => Some(ConflictAction::Rename),
+            _ => None,
+        }
+    }
+}
+
+/// Payload of the `upload-conflict` event sent to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictPrompt {
+    pub id: String,
+    pub batch_id: Option<String>,
+    pub local_path: String,
+    pub remote_key: String,
+    pub local_size: i64,
+    pub remote_size: i64,
+    pub remote_last_modified: Option<String>,
+}
+
+/// Build "name (n).ext" next to `key`, keeping the directory part intact
+pub fn suffixed_key(key: &str, n: u32) -> String {
+    labeled_key(key, &n.to_string())
+}
+
+/// Insert " (label)" before the extension of the last path segment, e.g. "docs/a (label).txt"
+pub fn labeled_key(key: &str, label: &str) -> String {
+    let (dir, name) = match key.rfind('/') {
+        Some(idx) => key.split_at(idx + 1),
+        None => ("", key),
+    };
+
+    // A leading dot marks a hidden file, not an extension
+    match name.rfind('.') {
+        Some(idx) if idx > 0 => {
+            let (stem, ext) = name.split_at(idx);
+            format!("{}{} ({}){}", dir, stem, label, ext)
+        }
+        _ => format!("{}{} ({})", dir, name, label),
+    }
+}
+
+/// A prompt waiting for an answer, with the batch it belongs to
+struct PendingPrompt {
+    batch_id: Option<String>,
+    tx: oneshot::Sender<ConflictAction>,
+}
+
+/// Tracks outstanding "ask" prompts and per-batch answers the user chose to remember
+#[derive(Default)]
+pub struct ConflictResolver {
+    pending: Mutex<HashMap<String, PendingPrompt>>,
+    remembered: Mutex<HashMap<String, ConflictAction>>,
+}
+
+impl ConflictResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer previously remembered for this batch, if any
+    pub async fn remembered(&self, batch_id: &str) -> Option<ConflictAction> {
+        self.remembered.lock().await.get(batch_id).copied()
+    }
+
+    /// Register a prompt and return the receiver its answer will arrive on
+    pub async fn register(
+        &self,
+        prompt_id: &str,
+        batch_id: Option<&str>,
+    ) -> oneshot::Receiver<ConflictAction> {
+        let (tx, rx) = oneshot::channel();
+        let prompt = PendingPrompt {
+            batch_id: batch_id.map(|b| b.to_string()),
+            tx,
+        };
+        self.pending.lock().await.insert(prompt_id.to_string(), prompt);
+        rx
+    }
+
+    /// Drop a prompt that timed out or was abandoned
+    pub async fn forget(&self, prompt_id: &str) {
+        self.pending.lock().await.remove(prompt_id);
+    }
+
+    /// Deliver the user's answer, optionally applying it to the rest of the prompt's batch.
+    /// Returns false if the prompt is no longer waiting.
+    pub async fn answer(&self, prompt_id: &str, action: ConflictAction, remember: bool) -> bool {
+        let prompt = match self.pending.lock().await.remove(prompt_id) {
+            Some(prompt) => prompt,
+            None => return false,
+        };
+
+        if remember {
+            if let Some(batch_id) = prompt.batch_id {
+                self.remembered.lock().await.insert(batch_id, action);
+            }
+        }
+
+        prompt.tx.send(action).is_ok()
+    }
+
+    /// Forget remembered answers once a batch has finished
+    pub async fn end_batch(&self, batch_id: &str) {
+        self.remembered.lock().await.remove(batch_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_from_setting() {
+        assert_eq!(ConflictPolicy::from_setting("overwrite"), ConflictPolicy::Overwrite);
+        assert_eq!(ConflictPolicy::from_setting("skip"), ConflictPolicy::Skip);
+        assert_eq!(ConflictPolicy::from_setting("rename"), ConflictPolicy::Rename);
+        assert_eq!(ConflictPolicy::from_setting("ask"), ConflictPolicy::Ask);
+
+        // Legacy values and unknown input
+        assert_eq!(ConflictPolicy::from_setting("local"), ConflictPolicy::Overwrite);
+        assert_eq!(ConflictPolicy::from_setting("remote"), ConflictPolicy::Skip);
+        assert_eq!(ConflictPolicy::from_setting("newest"), ConflictPolicy::Ask);
+    }
+
+    #[test]
+    fn test_suffixed_key_with_extension() {
+        assert_eq!(suffixed_key("file.txt", 1), "file (1).txt");
+        assert_eq!(suffixed_key("docs/report.pdf", 2), "docs/report (2).pdf");
+        assert_eq!(suffixed_key("backup.tar.gz", 1), "backup.tar (1).gz");
+    }
+
+    #[test]
+    fn test_suffixed_key_without_extension() {
+        assert_eq!(suffixed_key("README", 1), "README (1)");
+        assert_eq!(suffixed_key("a/b/Makefile", 3), "a/b/Makefile (3)");
+    }
+
+    #[test]
+    fn test_suffixed_key_hidden_file() {
+        assert_eq!(suffixed_key(".env", 1), ".env (1)");
+        assert_eq!(suffixed_key("config/.gitignore", 1), "config/.gitignore (1)");
+    }
+
+    #[test]
+    fn test_suffixed_key_dot_in_directory() {
+        assert_eq!(suffixed_key("v1.2/notes", 1), "v1.2/notes (1)");
+    }
+
+    #[tokio::test]
+    async fn test_resolver_delivers_answer() {
+        let resolver = ConflictResolver::new();
+        let rx = resolver.register("prompt-1", None).await;
+
+        assert!(resolver.answer("prompt-1", ConflictAction::Rename, false).await);
+        assert_eq!(rx.await.unwrap(), ConflictAction::Rename);
+
+        // Answering twice is a no-op
+        assert!(!resolver.answer("prompt-1", ConflictAction::Skip, false).await);
+    }
+
+    #[tokio::test]
+    async fn test_resolver_remembers_batch_answer() {
+        let resolver = ConflictResolver::new();
+        let _rx = resolver.register("prompt-1", Some("batch-1")).await;
+
+        assert!(resolver.remembered("batch-1").await.is_none());
+        resolver.answer("prompt-1", ConflictAction::Skip, true).await;
+        assert_eq!(resolver.remembered("batch-1").await, Some(ConflictAction::Skip));
+
+        resolver.end_batch("batch-1").await;
+        assert!(resolver.remembered("batch-1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resolver_forget() {
+        let resolver = ConflictResolver::new();
+        let _rx = resolver.register("prompt-1", None).await;
+        resolver.forget("prompt-1").await;
+
+        assert!(!resolver.answer("prompt-1", ConflictAction::Overwrite, false).await);
+    }
+
+    #[tokio::test]
+    async fn test_resolver_answer_without_remember() {
+        let resolver = ConflictResolver::new();
+        let _rx = resolver.register("prompt-1", Some("batch-1")).await;
+
+        resolver.answer("prompt-1", ConflictAction::Overwrite, false).await;
+        assert!(resolver.remembered("batch-1").await.is_none());
+    }
+}
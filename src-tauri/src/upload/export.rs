@@ -0,0 +1,278 @@
+//! Transfer history as an audit log: one row per finished upload or download, oldest first,
+//! written as CSV or JSON Lines. Rows are streamed from the database to the file, so the size of the
+//! history doesn't matter.
+
+use anyhow::Result;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqliteRow, QueryBuilder, Row, Sqlite};
+use std::path::Path;
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use super::manager::{push_history_filter, HISTORY_TIME};
+use super::UploadManager;
+use crate::utils::{csv, HistoryFilter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+/// One exported transfer. Times are UTC, RFC 3339.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TransferRecord {
+    /// `upload` or `download`
+    pub direction: &'static str,
+    pub id: String,
+    pub status: String,
+    pub bucket: Option<String>,
+    pub local_path: String,
+    pub remote_key: String,
+    pub size: i64,
+    pub etag: Option<String>,
+    pub sha256: Option<String>,
+    pub verification: Option<String>,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+    pub duration_secs: Option<i64>,
+    pub error: Option<String>,
+}
+
+const CSV_HEADER: &str = "direction,id,status,bucket,local_path,remote_key,size,etag,sha256,verification,\
+started_at,completed_at,duration_secs,error";
+
+impl TransferRecord {
+    fn from_row(row: &SqliteRow) -> Result<Self> {
+        Ok(Self {
+            direction: if row.try_get::<&str, _>("direction")? == "download" { "download" } else { "upload" },
+            id: row.try_get("id")?,
+            status: row.try_get("status")?,
+            bucket: row.try_get("bucket")?,
+            local_path: row.try_get("file_path")?,
+            remote_key: row.try_get("remote_path")?,
+            size: row.try_get("total_size")?,
+            etag: row.try_get("etag")?,
+            sha256: row.try_get("sha256")?,
+            verification: row.try_get("verification")?,
+            started_at: row.try_get("started_at")?,
+            completed_at: row.try_get("completed_at")?,
+            duration_secs: row.try_get("duration_secs")?,
+            error: row.try_get("error_message")?,
+        })
+    }
+
+    fn write_csv(&self, line: &mut String) {
+        let size = self.size.to_string();
+        let duration = self.duration_secs.map(|d| d.to_string());
+        let fields = [
+            Some(self.direction),
+            Some(self.id.as_str()),
+            Some(self.status.as_str()),
+            self.bucket.as_deref(),
+            Some(self.local_path.as_str()),
+            Some(self.remote_key.as_str()),
+            Some(size.as_str()),
+            self.etag.as_deref(),
+            self.sha256.as_deref(),
+            self.verification.as_deref(),
+            self.started_at.as_deref(),
+            self.completed_at.as_deref(),
+            duration.as_deref(),
+            self.error.as_deref(),
+        ];
+        csv::push_record(line, fields.into_iter().map(|field| field.unwrap_or("")));
+    }
+}
+
+/// Select the transfers in `table` that match `filter`, as `TransferRecord` columns plus the
+/// ones to sort by. Downloads have the same columns as uploads except `verification`.
+fn push_transfers(query: &mut QueryBuilder<'_, Sqlite>, table: &str, filter: &HistoryFilter) -> Result<()> {
+    let (direction, verification) = match table {
+        "uploads" => ("upload", "verification"),
+        _ => ("download", "NULL"),
+    };
+    query.push(format!(
+        "SELECT '{direction}' AS direction, id, status, file_path, remote_path, total_size, etag, sha256,
+                {verification} AS verification, error_message,
+                (SELECT name FROM buckets WHERE buckets.id = {table}.bucket_id) AS bucket,
+                strftime('%Y-%m-%dT%H:%M:%SZ', started_at) AS started_at,
+                strftime('%Y-%m-%dT%H:%M:%SZ', completed_at) AS completed_at,
+                CAST(ROUND((julianday(completed_at) - julianday(started_at)) * 86400) AS INTEGER) AS duration_secs,
+                {HISTORY_TIME} AS finished_at, rowid AS seq
+         FROM {table}"
+    ));
+    push_history_filter(query, filter)
+}
+
+impl UploadManager {
+    /// Write the finished uploads and downloads matching `filter` to `path`, returning how many
+    /// were written. The file only appears once it is complete.
+    pub async fn export_history(&self, filter: &HistoryFilter, format: ExportFormat, path: &Path) -> Result<u64> {
+        let mut query = QueryBuilder::<Sqlite>::new("");
+        push_transfers(&mut query, "uploads", filter)?;
+        query.push(" UNION ALL ");
+        push_transfers(&mut query, "downloads", filter)?;
+        query.push(" ORDER BY finished_at ASC, direction DESC, seq ASC");
+
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let result = async {
+            let mut out = BufWriter::new(tokio::fs::File::create(&partial).await?);
+            let mut line = String::new();
+            if format == ExportFormat::Csv {
+                out.write_all(CSV_HEADER.as_bytes()).await?;
+                out.write_all(b"\r\n").await?;
+            }
+
+            let mut count = 0;
+            let mut rows = query.build().fetch(&self.pool);
+            while let Some(row) = rows.try_next().await? {
+                let record = TransferRecord::from_row(&row)?;
+                line.clear();
+                match format {
+                    ExportFormat::Csv => record.write_csv(&mut line),
+                    ExportFormat::Jsonl => {
+                        line.push_str(&serde_json::to_string(&record)?);
+                        line.push('\n');
+                    }
+                }
+                out.write_all(line.as_bytes()).await?;
+                count += 1;
+            }
+
+            out.flush().await?;
+            out.into_inner().sync_all().await?;
+            anyhow::Ok(count)
+        }
+        .await;
+
+        match result {
+            Ok(count) => {
+                tokio::fs::rename(&partial, path).await?;
+                Ok(count)
+            }
+            Err(e) => {
+                tokio::fs::remove_file(&partial).await.ok();
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::SqlitePool;
+    use tempfile::TempDir;
+
+    async fn setup() -> (UploadManager, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let db_url = format!("sqlite://{}?mode=rwc", temp_dir.path().join("test.db").display());
+        let pool = SqlitePool::connect(&db_url).await.unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO buckets (name, account_id, access_key_id, secret_access_key, endpoint)
+             VALUES ('backups', 'account', 'key', 'secret', 'https://endpoint.com')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let manager = UploadManager::new(pool);
+        for (path, status, started, completed, error) in [
+            ("/docs/a.pdf", "completed", "2024-03-01 10:00:00", "2024-03-01 10:00:42", None),
+            ("/docs/q1, \"final\".xlsx", "failed", "2024-03-02 09:00:00", "2024-03-02 09:00:05", Some("Timed out\nretrying")),
+            ("/docs/c.txt", "completed", "2024-03-05 08:00:00", "2024-03-05 08:00:01", None),
+        ] {
+            let id = manager.create_upload(1, path, &path[1..], 2048, 256).await.unwrap();
+            manager.set_upload_sha256(&id, "ab12").await.unwrap();
+            sqlx::query("UPDATE uploads SET status = ?, started_at = ?, completed_at = ?, error_message = ?, etag = '\"e1\"' WHERE id = ?")
+                .bind(status)
+                .bind(started)
+                .bind(completed)
+                .bind(error)
+                .bind(&id)
+                .execute(&manager.pool)
+                .await
+                .unwrap();
+        }
+        // Still running, so not part of the history
+        manager.create_upload(1, "/docs/d.bin", "docs/d.bin", 10, 256).await.unwrap();
+        sqlx::query(
+            "INSERT INTO downloads (id, bucket_id, file_path, remote_path, total_size, status, started_at, completed_at, etag)
+             VALUES ('d1', 1, '/restore/a.pdf', 'docs/a.pdf', 2048, 'completed', '2024-03-04 12:00:00', '2024-03-04 12:00:03', '\"e1\"')",
+        )
+        .execute(&manager.pool)
+        .await
+        .unwrap();
+        (manager, temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_export_csv() {
+        let (manager, temp_dir) = setup().await;
+        let path = temp_dir.path().join("history.csv");
+
+        let count = manager.export_history(&HistoryFilter::default(), ExportFormat::Csv, &path).await.unwrap();
+        assert_eq!(count, 4);
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].starts_with("upload,"));
+        assert!(lines[1].ends_with(
+            ",completed,backups,/docs/a.pdf,docs/a.pdf,2048,\"\"\"e1\"\"\",ab12,,2024-03-01T10:00:00Z,2024-03-01T10:00:42Z,42,"
+        ));
+        assert!(lines[2].contains(",\"/docs/q1, \"\"final\"\".xlsx\","));
+        assert!(lines[2].ends_with(",5,\"Timed out\nretrying\""));
+        assert_eq!(
+            lines[3],
+            "download,d1,completed,backups,/restore/a.pdf,docs/a.pdf,2048,\"\"\"e1\"\"\",,,2024-03-04T12:00:00Z,2024-03-04T12:00:03Z,3,"
+        );
+        assert!(lines[4].starts_with("upload,"));
+        assert!(lines[4].contains("/docs/c.txt"));
+        assert_eq!(lines[5], "");
+        assert!(!temp_dir.path().join("history.csv.partial").exists());
+    }
+
+    #[tokio::test]
+    async fn test_export_jsonl_with_date_range() {
+        let (manager, temp_dir) = setup().await;
+        let path = temp_dir.path().join("history.jsonl");
+        let filter = HistoryFilter {
+            since: Some("2024-03-02".to_string()),
+            until: Some("2024-03-05".to_string()),
+            ..Default::default()
+        };
+
+        let count = manager.export_history(&filter, ExportFormat::Jsonl, &path).await.unwrap();
+        assert_eq!(count, 2);
+
+        let jsonl = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<serde_json::Value> = jsonl.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records[1]["direction"], "download");
+        assert_eq!(records[1]["local_path"], "/restore/a.pdf");
+        let record = &records[0];
+        assert_eq!(record["direction"], "upload");
+        assert_eq!(record["status"], "failed");
+        assert_eq!(record["local_path"], "/docs/q1, \"final\".xlsx");
+        assert_eq!(record["sha256"], "ab12");
+        assert_eq!(record["duration_secs"], 5);
+        assert_eq!(record["error"], "Timed out\nretrying");
+        assert_eq!(record["bucket"], "backups");
+    }
+
+    #[tokio::test]
+    async fn test_failed_export_leaves_no_file() {
+        let (manager, temp_dir) = setup().await;
+        let path = temp_dir.path().join("history.csv");
+        let filter = HistoryFilter { since: Some("not a date".to_string()), ..Default::default() };
+
+        assert!(manager.export_history(&filter, ExportFormat::Csv, &path).await.is_err());
+        assert!(!path.exists());
+        let missing_dir = temp_dir.path().join("missing").join("history.csv");
+        assert!(manager.export_history(&HistoryFilter::default(), ExportFormat::Csv, &missing_dir).await.is_err());
+    }
+}
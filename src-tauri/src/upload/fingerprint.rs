@@ -0,0 +1,375 @@
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+use crate::db::{Database, FileFingerprint};
+use crate::r2::operations::{head_object, ObjectExists, ObjectInfo};
+
+const HASH_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Content hashes of a local file, hex encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    pub md5: String,
+    pub sha256: String,
+}
+
+/// Returned when a file was written to while it was uploaded, so the object may mix old and new content
+#[derive(Debug, thiserror::Error)]
+#[error("{0} changed while it was being uploaded")]
+pub struct ChangedDuringUpload(pub String);
+
+/// Why an upload failed, for callers that handle some failures differently
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error(transparent)]
+    Changed(#[from] ChangedDuringUpload),
+    /// A conditional write found the key taken
+    #[error(transparent)]
+    Exists(#[from] ObjectExists),
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl UploadError {
+    /// Keep the cause of a failed transfer where a caller can act on it
+    pub fn from_transfer(error: anyhow::Error) -> Self {
+        match error.downcast::<ObjectExists>() {
+            Ok(exists) => exists.into(),
+            Err(error) => error.to_string().into(),
+        }
+    }
+}
+
+impl From<String> for UploadError {
+    fn from(message: String) -> Self {
+        UploadError::Failed(message)
+    }
+}
+
+impl From<&str> for UploadError {
+    fn from(message: &str) -> Self {
+        UploadError::Failed(message.to_string())
+    }
+}
+
+/// A tracked upload's id, and the local content it sent unless it was skipped
+#[derive(Debug, Clone)]
+pub struct Uploaded {
+    pub id: String,
+    /// The file's stat and SHA-256 as read for the upload, still matching the file once it was sent
+    pub sent: Option<(LocalStat, String)>,
+}
+
+impl Uploaded {
+    pub fn skipped(id: String) -> Self {
+        Self { id, sent: None }
+    }
+}
+
+/// Size, modification time and identity of a local file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LocalStat {
+    pub size: i64,
+    /// Milliseconds since the Unix epoch
+    pub mtime: i64,
+    /// Inode number on Unix; `None` where the platform has no stable equivalent
+    pub inode: Option<i64>,
+}
+
+impl LocalStat {
+    pub async fn read(path: &str) -> Result<Self> {
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .with_context(|| format!("Failed to stat {}", path))?;
+
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+
+        Ok(Self { size: metadata.len() as i64, mtime, inode: inode_of(&metadata) })
+    }
+}
+
+#[cfg(unix)]
+fn inode_of(metadata: &std::fs::Metadata) -> Option<i64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino() as i64)
+}
+
+#[cfg(not(unix))]
+fn inode_of(_metadata: &std::fs::Metadata) -> Option<i64> {
+    None
+}
+
+/// Outcome of comparing a local file with the remote object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Unchanged(&'static str),
+    Changed(&'static str),
+    /// Size matches but nothing cheaper than hashing the file can tell
+    NeedsHash,
+}
+
+/// Hash a file in one pass, producing both MD5 (for single-part ETags) and SHA-256
+pub async fn hash_file(path: &str) -> Result<FileHashes> {
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)
+            .with_context(|| format!("Failed to open {}", path))?;
+        let mut md5 = Md5::new();
+        let mut sha256 = Sha256::new();
+        let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+
+        loop {
+            let n = file.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            md5.update(&buffer[..n]);
+            sha256.update(&buffer[..n]);
+        }
+
+        Ok(FileHashes {
+            md5: format!("{:x}", md5.finalize()),
+            sha256: format!("{:x}", sha256.finalize()),
+        })
+    })
+    .await?
+}
+
+fn normalize_etag(etag: &str) -> &str {
+    etag.trim_matches('"')
+}
+
+/// Decide from metadata alone whether the remote copy matches the local file.
+/// The cache row only counts if it still describes the object currently on the remote.
+pub fn compare_cached(
+    local: &LocalStat,
+    cached: Option<&FileFingerprint>,
+    remote: Option<&ObjectInfo>,
+) -> Verdict {
+    let remote = match remote {
+        Some(remote) => remote,
+        None => return Verdict::Changed("not on remote"),
+    };
+
+    if remote.size != local.size {
+        return Verdict::Changed("size differs");
+    }
+
+    if let Some(cached) = cached {
+        let same_local = cached.local_size == local.size && cached.local_mtime == local.mtime;
+        let same_etag = cached
+            .etag
+            .as_deref()
+            .is_some_and(|etag| !etag.is_empty() && normalize_etag(etag) == normalize_etag(&remote.etag));
+        let same_sha256 = cached.sha256.is_some() && cached.sha256 == remote.sha256;
+
+        if same_local && (same_etag || same_sha256) {
+            return Verdict::Unchanged("size and modification time match");
+        }
+    }
+
+    Verdict::NeedsHash
+}
+
+/// Compare local content hashes against what the remote object tells us about its content
+pub fn compare_hashes(hashes: &FileHashes, remote: &ObjectInfo) -> Verdict {
+    if let Some(sha256) = &remote.sha256 {
+        return if sha256.eq_ignore_ascii_case(&hashes.sha256) {
+            Verdict::Unchanged("SHA-256 matches")
+        } else {
+            Verdict::Changed("content differs")
+        };
+    }
+
+    // Multipart ETags ("<hash>-<parts>") are not an MD5 of the content
+    let etag = normalize_etag(&remote.etag);
+    if !etag.is_empty() && !etag.contains('-') {
+        return if etag.eq_ignore_ascii_case(&hashes.md5) {
+            Verdict::Unchanged("MD5 matches ETag")
+        } else {
+            Verdict::Changed("content differs")
+        };
+    }
+
+    Verdict::Changed("remote content hash unknown")
+}
+
+/// Result of `check_unchanged`, keeping what was learned so the upload can reuse it
+#[derive(Debug, Clone)]
+pub struct ChangeCheck {
+    pub verdict: Verdict,
+    pub local: LocalStat,
+    pub remote: Option<ObjectInfo>,
+    pub hashes: Option<FileHashes>,
+}
+
+impl ChangeCheck {
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self.verdict, Verdict::Unchanged(_))
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self.verdict {
+            Verdict::Unchanged(reason) | Verdict::Changed(reason) => reason,
+            Verdict::NeedsHash => "unknown",
+        }
+    }
+}
+
+/// Work out whether `local_path` differs from the object at `remote_key`.
+/// Tries the cached size/mtime first and only hashes the file when that is inconclusive.
+pub async fn check_unchanged(
+    db: &Database,
+    client: &Client,
+    bucket: &str,
+    bucket_id: Option<i64>,
+    local_path: &str,
+    remote_key: &str,
+) -> Result<ChangeCheck> {
+    let local = LocalStat::read(local_path).await?;
+    let remote = head_object(client, bucket, remote_key).await?;
+    let cached = match bucket_id {
+        Some(id) => db.get_file_fingerprint(id, remote_key).await?,
+        None => None,
+    };
+
+    let mut check = ChangeCheck {
+        verdict: compare_cached(&local, cached.as_ref(), remote.as_ref()),
+        local,
+        remote,
+        hashes: None,
+    };
+
+    if check.verdict == Verdict::NeedsHash {
+        let hashes = hash_file(local_path).await?;
+        // NeedsHash is only returned when the remote object exists
+        if let Some(remote) = &check.remote {
+            check.verdict = compare_hashes(&hashes, remote);
+        }
+
+        // Remember the match so the next run can skip hashing
+        if let (Verdict::Unchanged(_), Some(id), Some(remote)) = (&check.verdict, bucket_id, &check.remote) {
+            let fingerprint = FileFingerprint {
+                remote_size: remote.size,
+                etag: Some(remote.etag.clone()),
+                sha256: Some(hashes.sha256.clone()),
+                local_size: local.size,
+                local_mtime: local.mtime,
+            };
+            db.save_file_fingerprint(id, remote_key, &fingerprint).await?;
+        }
+
+        check.hashes = Some(hashes);
+    }
+
+    Ok(check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn remote(size: i64, etag: &str, sha256: Option<&str>) -> ObjectInfo {
+        ObjectInfo {
+            key: "file.txt".to_string(),
+            size,
+            etag: etag.to_string(),
+            last_modified: None,
+            sha256: sha256.map(|s| s.to_string()),
+        }
+    }
+
+    fn cached(local_size: i64, local_mtime: i64, etag: &str) -> FileFingerprint {
+        FileFingerprint {
+            remote_size: local_size,
+            etag: Some(etag.to_string()),
+            sha256: None,
+            local_size,
+            local_mtime,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hash_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello world").unwrap();
+
+        let hashes = hash_file(file.path().to_str().unwrap()).await.unwrap();
+        assert_eq!(hashes.md5, "5eb63bbbe01eeed093cb22bb8f5acdc3");
+        assert_eq!(
+            hashes.sha256,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+    }
+
+    #[test]
+    fn test_compare_cached_missing_remote() {
+        let local = LocalStat { size: 10, mtime: 1000, inode: None };
+        assert_eq!(compare_cached(&local, None, None), Verdict::Changed("not on remote"));
+    }
+
+    #[test]
+    fn test_compare_cached_size_differs() {
+        let local = LocalStat { size: 10, mtime: 1000, inode: None };
+        let remote = remote(11, "\"abc\"", None);
+        assert_eq!(compare_cached(&local, None, Some(&remote)), Verdict::Changed("size differs"));
+    }
+
+    #[test]
+    fn test_compare_cached_hit() {
+        let local = LocalStat { size: 10, mtime: 1000, inode: None };
+        let remote = remote(10, "\"abc\"", None);
+        let row = cached(10, 1000, "abc");
+        assert!(matches!(compare_cached(&local, Some(&row), Some(&remote)), Verdict::Unchanged(_)));
+    }
+
+    #[test]
+    fn test_compare_cached_stale_row_needs_hash() {
+        let local = LocalStat { size: 10, mtime: 2000, inode: None };
+        let remote = remote(10, "\"abc\"", None);
+
+        // Local file touched since the row was written
+        let row = cached(10, 1000, "abc");
+        assert_eq!(compare_cached(&local, Some(&row), Some(&remote)), Verdict::NeedsHash);
+
+        // Remote replaced since the row was written
+        let row = cached(10, 2000, "other");
+        assert_eq!(compare_cached(&local, Some(&row), Some(&remote)), Verdict::NeedsHash);
+    }
+
+    #[test]
+    fn test_compare_hashes_single_part_etag() {
+        let hashes = FileHashes { md5: "abc".to_string(), sha256: "def".to_string() };
+        assert!(matches!(compare_hashes(&hashes, &remote(1, "\"ABC\"", None)), Verdict::Unchanged(_)));
+        assert_eq!(compare_hashes(&hashes, &remote(1, "\"abd\"", None)), Verdict::Changed("content differs"));
+    }
+
+    #[test]
+    fn test_compare_hashes_multipart_uses_sha256() {
+        let hashes = FileHashes { md5: "abc".to_string(), sha256: "def".to_string() };
+        assert!(matches!(
+            compare_hashes(&hashes, &remote(1, "\"xyz-3\"", Some("def"))),
+            Verdict::Unchanged(_)
+        ));
+        assert_eq!(
+            compare_hashes(&hashes, &remote(1, "\"xyz-3\"", None)),
+            Verdict::Changed("remote content hash unknown")
+        );
+    }
+
+    #[test]
+    fn test_taken_key_survives_as_upload_error() {
+        let taken = anyhow::Error::new(ObjectExists("docs/a (1).txt".to_string())).context("Upload failed");
+        assert!(matches!(UploadError::from_transfer(taken), UploadError::Exists(ObjectExists(key)) if key == "docs/a (1).txt"));
+        assert!(matches!(UploadError::from_transfer(anyhow::anyhow!("timed out")), UploadError::Failed(_)));
+    }
+}
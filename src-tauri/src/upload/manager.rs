@@ -1,10 +1,28 @@
-use sqlx::{SqlitePool, Row};
+use sqlx::{sqlite::SqliteRow, QueryBuilder, Row, Sqlite, SqlitePool};
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
-use anyhow::Result;
-use crate::utils::{UploadProgress, UploadStatus};
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::utils::paths;
+use crate::utils::{
+    DailyTransfers, HistoryFilter, HistoryPage, HistoryStats, UploadHistoryEntry, UploadProgress, UploadStatus,
+    UploadVerification,
+};
+
+/// Paths per query when looking up the latest uploads, well under SQLite's bind limit
+const LATEST_UPLOADS_BATCH: usize = 500;
+
+/// Largest history page a caller can ask for
+pub const MAX_HISTORY_PAGE: i64 = 500;
+
+/// When a history entry finished, or started if it never did
+pub(super) const HISTORY_TIME: &str = "COALESCE(completed_at, started_at)";
+
+const HISTORY_COLUMNS: &str =
+    "id, file_path, remote_path, total_size, status, error_message, started_at, completed_at, etag, verification";
 
 pub struct UploadManager {
-    pool: SqlitePool,
+    pub(super) pool: SqlitePool,
 }
 
 impl UploadManager {
@@ -21,12 +39,6 @@ impl UploadManager {
         chunk_size: i64,
     ) -> Result<String> {
         let upload_id = Uuid::new_v4().to_string();
-        // Normalize path separators for cross-platform compatibility (Windows uses \)
-        let normalized_path = file_path.replace('\\', "/");
-        let _file_name = std::path::Path::new(&normalized_path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
 
         sqlx::query(
             r#"
@@ -89,6 +101,56 @@ impl UploadManager {
         Ok(())
     }
 
+    /// Record the final ETag and whether the object was verified against the local file
+    pub async fn set_upload_verification(
+        &self,
+        upload_id: &str,
+        etag: Option<&str>,
+        verification: UploadVerification,
+    ) -> Result<()> {
+        sqlx::query("UPDATE uploads SET etag = ?, verification = ? WHERE id = ?")
+            .bind(etag)
+            .bind(verification.as_str())
+            .bind(upload_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the whole-file SHA-256 of what is being uploaded
+    pub async fn set_upload_sha256(&self, upload_id: &str, sha256: &str) -> Result<()> {
+        sqlx::query("UPDATE uploads SET sha256 = ? WHERE id = ?")
+            .bind(sha256)
+            .bind(upload_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Multipart upload ids that a local upload record can still resume, and so must not be aborted
+    pub async fn resumable_multipart_ids(&self) -> Result<HashSet<String>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "SELECT upload_id FROM uploads
+             WHERE upload_id IS NOT NULL
+               AND status IN ('pending', 'uploading', 'paused', 'failed')"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// Find the upload record that owns a remote multipart upload id, returning its id and local path
+    pub async fn find_by_multipart_id(&self, multipart_id: &str) -> Result<Option<(String, String)>> {
+        let row = sqlx::query_as::<_, (String, String)>(
+            "SELECT id, file_path FROM uploads WHERE upload_id = ? ORDER BY started_at DESC LIMIT 1"
+        )
+        .bind(multipart_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
     pub async fn save_chunk(
         &self,
         upload_id: &str,
@@ -128,51 +190,7 @@ impl UploadManager {
         .fetch_optional(&self.pool)
         .await?;
 
-        if let Some(row) = row {
-            let file_path: String = row.try_get("file_path")?;
-            // Normalize path separators for cross-platform compatibility (Windows uses \)
-            let normalized_path = file_path.replace('\\', "/");
-            let file_name = std::path::Path::new(&normalized_path)
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("")
-                .to_string();
-
-            let total_size: i64 = row.try_get("total_size")?;
-            let uploaded_size: i64 = row.try_get("uploaded_size")?;
-            let progress = if total_size > 0 {
-                (uploaded_size as f64 / total_size as f64) * 100.0
-            } else {
-                0.0
-            };
-
-            let status_str: String = row.try_get("status")?;
-            let status = match status_str.as_str() {
-                "pending" => UploadStatus::Pending,
-                "uploading" => UploadStatus::Uploading,
-                "paused" => UploadStatus::Paused,
-                "completed" => UploadStatus::Completed,
-                "failed" => UploadStatus::Failed,
-                "cancelled" => UploadStatus::Cancelled,
-                _ => UploadStatus::Pending,
-            };
-
-            Ok(Some(UploadProgress {
-                id: row.try_get("id")?,
-                file_name,
-                file_path,
-                remote_path: row.try_get("remote_path")?,
-                total_size,
-                uploaded_size,
-                progress,
-                speed: 0.0,
-                eta: 0,
-                status,
-                error_message: row.try_get("error_message").ok(),
-            }))
-        } else {
-            Ok(None)
-        }
+        row.as_ref().map(progress_from_row).transpose()
     }
 
     pub async fn get_active_uploads(&self) -> Result<Vec<UploadProgress>> {
@@ -187,54 +205,107 @@ impl UploadManager {
         .fetch_all(&self.pool)
         .await?;
 
-        let mut uploads = Vec::new();
-        for row in rows {
-            let file_path: String = row.try_get("file_path")?;
-            // Normalize path separators for cross-platform compatibility (Windows uses \)
-            let normalized_path = file_path.replace('\\', "/");
-            let file_name = std::path::Path::new(&normalized_path)
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("")
-                .to_string();
-
-            let total_size: i64 = row.try_get("total_size")?;
-            let uploaded_size: i64 = row.try_get("uploaded_size")?;
-            let progress = if total_size > 0 {
-                (uploaded_size as f64 / total_size as f64) * 100.0
-            } else {
-                0.0
-            };
-
-            let status_str: String = row.try_get("status")?;
-            let status = match status_str.as_str() {
-                "pending" => UploadStatus::Pending,
-                "uploading" => UploadStatus::Uploading,
-                "paused" => UploadStatus::Paused,
-                "completed" => UploadStatus::Completed,
-                "failed" => UploadStatus::Failed,
-                "cancelled" => UploadStatus::Cancelled,
-                _ => UploadStatus::Pending,
-            };
-
-            uploads.push(UploadProgress {
-                id: row.try_get("id")?,
-                file_name,
-                file_path,
-                remote_path: row.try_get("remote_path")?,
-                total_size,
-                uploaded_size,
-                progress,
-                speed: 0.0,
-                eta: 0,
-                status,
-                error_message: row.try_get("error_message").ok(),
-            });
-        }
+        rows.iter().map(progress_from_row).collect()
+    }
 
+    /// The most recent upload of each of the given local files that has one, keyed by path
+    pub async fn get_latest_uploads(&self, file_paths: &[String]) -> Result<HashMap<String, UploadProgress>> {
+        let mut uploads = HashMap::new();
+        for chunk in file_paths.chunks(LATEST_UPLOADS_BATCH) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "SELECT id, file_path, remote_path, total_size, uploaded_size, status, error_message
+                 FROM uploads WHERE rowid IN (SELECT MAX(rowid) FROM uploads WHERE file_path IN (",
+            );
+            let mut paths = query.separated(", ");
+            for path in chunk {
+                paths.push_bind(path);
+            }
+            query.push(") GROUP BY file_path)");
+
+            for row in query.build().fetch_all(&self.pool).await? {
+                let upload = progress_from_row(&row)?;
+                uploads.insert(upload.file_path.clone(), upload);
+            }
+        }
         Ok(uploads)
     }
 
+    /// Most recent finished uploads, newest first. Unlike `query_history`, `limit` isn't capped
+    pub async fn get_upload_history(&self, limit: i64) -> Result<Vec<UploadHistoryEntry>> {
+        self.history_entries(&HistoryFilter::default(), 0, limit).await
+    }
+
+    /// One page of the finished uploads matching `filter`, newest first
+    pub async fn query_history(&self, filter: &HistoryFilter, offset: i64, limit: i64) -> Result<HistoryPage> {
+        let offset = offset.max(0);
+        let limit = limit.clamp(1, MAX_HISTORY_PAGE);
+
+        let mut count = QueryBuilder::<Sqlite>::new("SELECT COUNT(*) FROM uploads");
+        push_history_filter(&mut count, filter)?;
+        let (total,): (i64,) = count.build_query_as().fetch_one(&self.pool).await?;
+
+        let entries = self.history_entries(filter, offset, limit).await?;
+        Ok(HistoryPage { entries, total, offset, limit })
+    }
+
+    async fn history_entries(&self, filter: &HistoryFilter, offset: i64, limit: i64) -> Result<Vec<UploadHistoryEntry>> {
+        let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {} FROM uploads", HISTORY_COLUMNS));
+        push_history_filter(&mut query, filter)?;
+        query.push(format!(" ORDER BY {} DESC, rowid DESC LIMIT ", HISTORY_TIME));
+        query.push_bind(limit);
+        query.push(" OFFSET ");
+        query.push_bind(offset);
+
+        let rows = query.build().fetch_all(&self.pool).await?;
+        rows.iter().map(history_from_row).collect()
+    }
+
+    /// Totals and bytes per day over the finished uploads matching `filter`
+    pub async fn history_stats(&self, filter: &HistoryFilter) -> Result<HistoryStats> {
+        let mut totals = QueryBuilder::<Sqlite>::new(
+            "SELECT
+                COUNT(CASE WHEN status = 'completed' THEN 1 END),
+                COUNT(CASE WHEN status = 'failed' THEN 1 END),
+                COUNT(CASE WHEN status = 'cancelled' THEN 1 END),
+                COALESCE(SUM(CASE WHEN status = 'completed' THEN total_size END), 0),
+                COALESCE(SUM(CASE WHEN status = 'completed' AND completed_at > started_at THEN total_size END), 0),
+                SUM(CASE WHEN status = 'completed' AND completed_at > started_at
+                    THEN (julianday(completed_at) - julianday(started_at)) * 86400 END)
+             FROM uploads",
+        );
+        push_history_filter(&mut totals, filter)?;
+        let (completed, failed, cancelled, bytes_transferred, timed_bytes, timed_secs): (i64, i64, i64, i64, i64, Option<f64>) =
+            totals.build_query_as().fetch_one(&self.pool).await?;
+
+        let mut daily = QueryBuilder::<Sqlite>::new(format!(
+            "SELECT date({0}) AS day, SUM(total_size), COUNT(*) FROM uploads",
+            HISTORY_TIME
+        ));
+        push_history_filter(&mut daily, filter)?;
+        daily.push(" AND status = 'completed' GROUP BY day ORDER BY day");
+        let daily = daily
+            .build_query_as::<(String, i64, i64)>()
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(day, bytes, count)| DailyTransfers { day, bytes, count })
+            .collect();
+
+        let decided = completed + failed;
+        Ok(HistoryStats {
+            completed,
+            failed,
+            cancelled,
+            success_rate: if decided > 0 { completed as f64 / decided as f64 } else { 0.0 },
+            bytes_transferred,
+            average_throughput: match timed_secs {
+                Some(secs) if secs > 0.0 => timed_bytes as f64 / secs,
+                _ => 0.0,
+            },
+            daily,
+        })
+    }
+
     pub async fn get_completed_chunks(&self, upload_id: &str) -> Result<Vec<(i32, String)>> {
         let rows = sqlx::query(
             "SELECT part_number, etag FROM upload_chunks WHERE upload_id = ? AND status = 'completed' ORDER BY part_number"
@@ -254,6 +325,121 @@ impl UploadManager {
     }
 }
 
+/// Append the WHERE clause selecting finished uploads that match `filter`
+pub(super) fn push_history_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &HistoryFilter) -> Result<()> {
+    query.push(" WHERE status IN (");
+    let mut statuses = query.separated(", ");
+    let finished = [UploadStatus::Completed, UploadStatus::Failed, UploadStatus::Cancelled];
+    for status in if filter.statuses.is_empty() { &finished[..] } else { &filter.statuses[..] } {
+        match status {
+            UploadStatus::Completed => statuses.push_bind("completed"),
+            UploadStatus::Failed => statuses.push_bind("failed"),
+            UploadStatus::Cancelled => statuses.push_bind("cancelled"),
+            other => bail!("{:?} uploads haven't finished and have no history", other),
+        };
+    }
+    query.push(")");
+
+    if let Some(since) = &filter.since {
+        query.push(format!(" AND {} >= ", HISTORY_TIME)).push_bind(history_bound(since)?);
+    }
+    if let Some(until) = &filter.until {
+        query.push(format!(" AND {} < ", HISTORY_TIME)).push_bind(history_bound(until)?);
+    }
+    if let Some(bucket_id) = filter.bucket_id {
+        query.push(" AND bucket_id = ").push_bind(bucket_id);
+    }
+    if let Some(text) = filter.path_contains.as_deref().filter(|t| !t.is_empty()) {
+        let pattern = format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        query.push(" AND (file_path LIKE ").push_bind(pattern.clone()).push(" ESCAPE '\\'");
+        query.push(" OR remote_path LIKE ").push_bind(pattern).push(" ESCAPE '\\')");
+    }
+    if let Some(min_size) = filter.min_size {
+        query.push(" AND total_size >= ").push_bind(min_size);
+    }
+    if let Some(max_size) = filter.max_size {
+        query.push(" AND total_size <= ").push_bind(max_size);
+    }
+    Ok(())
+}
+
+/// A date or RFC 3339 time in the form the uploads table stores times in, so they compare as text
+fn history_bound(value: &str) -> Result<String> {
+    let time = if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0).unwrap_or_default()
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        time.with_timezone(&Utc).naive_utc()
+    } else {
+        bail!("Invalid date {:?}; expected YYYY-MM-DD or an RFC 3339 time", value);
+    };
+    Ok(time.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn history_from_row(row: &SqliteRow) -> Result<UploadHistoryEntry> {
+    let file_path: String = row.try_get("file_path")?;
+    let file_name = paths::file_name(&file_path).to_string();
+
+    let status_str: String = row.try_get("status")?;
+    let status = match status_str.as_str() {
+        "completed" => UploadStatus::Completed,
+        "failed" => UploadStatus::Failed,
+        _ => UploadStatus::Cancelled,
+    };
+    let verification: Option<String> = row.try_get("verification")?;
+
+    Ok(UploadHistoryEntry {
+        id: row.try_get("id")?,
+        file_name,
+        file_path,
+        remote_path: row.try_get("remote_path")?,
+        total_size: row.try_get("total_size")?,
+        status,
+        error_message: row.try_get("error_message")?,
+        started_at: row.try_get("started_at")?,
+        completed_at: row.try_get("completed_at")?,
+        etag: row.try_get("etag")?,
+        verification: verification.as_deref().and_then(UploadVerification::parse),
+    })
+}
+
+fn progress_from_row(row: &SqliteRow) -> Result<UploadProgress> {
+    let file_path: String = row.try_get("file_path")?;
+    let file_name = paths::file_name(&file_path).to_string();
+
+    let total_size: i64 = row.try_get("total_size")?;
+    let uploaded_size: i64 = row.try_get("uploaded_size")?;
+    let progress = if total_size > 0 {
+        (uploaded_size as f64 / total_size as f64) * 100.0
+    } else {
+        0.0
+    };
+
+    let status_str: String = row.try_get("status")?;
+    let status = match status_str.as_str() {
+        "pending" => UploadStatus::Pending,
+        "uploading" => UploadStatus::Uploading,
+        "paused" => UploadStatus::Paused,
+        "completed" => UploadStatus::Completed,
+        "failed" => UploadStatus::Failed,
+        "cancelled" => UploadStatus::Cancelled,
+        _ => UploadStatus::Pending,
+    };
+
+    Ok(UploadProgress {
+        id: row.try_get("id")?,
+        file_name,
+        file_path,
+        remote_path: row.try_get("remote_path")?,
+        total_size,
+        uploaded_size,
+        progress,
+        speed: 0.0,
+        eta: 0,
+        status,
+        error_message: row.try_get("error_message").ok(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -267,8 +453,7 @@ mod tests {
         let pool = SqlitePool::connect(&db_url).await.unwrap();
         
         // Run migrations
-        let migrations = include_str!("../../migrations/001_init.sql");
-        sqlx::query(migrations).execute(&pool).await.unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
         
         // Add a test bucket first (required for foreign key)
         sqlx::query(
@@ -415,6 +600,26 @@ mod tests {
         assert!(active.iter().any(|u| u.id == id2));
     }
 
+    #[tokio::test]
+    async fn test_get_latest_uploads() {
+        let (pool, _temp_dir) = setup_test_db().await;
+        let manager = UploadManager::new(pool);
+
+        let first = manager.create_upload(1, "/docs/a.txt", "a.txt", 100, 100).await.unwrap();
+        manager.update_upload_status(&first, "failed", None, Some("timed out")).await.unwrap();
+        let second = manager.create_upload(1, "/docs/a.txt", "a.txt", 100, 100).await.unwrap();
+        manager.update_upload_status(&second, "uploading", Some(25), None).await.unwrap();
+        manager.create_upload(1, "/docs/b.txt", "b.txt", 100, 100).await.unwrap();
+
+        let paths = ["/docs/a.txt".to_string(), "/docs/none.txt".to_string()];
+        let latest = manager.get_latest_uploads(&paths).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        let upload = &latest["/docs/a.txt"];
+        assert_eq!(upload.id, second);
+        assert!(matches!(upload.status, UploadStatus::Uploading));
+        assert_eq!(upload.progress, 25.0);
+    }
+
     #[tokio::test]
     async fn test_save_and_get_chunks() {
         let (pool, _temp_dir) = setup_test_db().await;
@@ -558,6 +763,165 @@ mod tests {
         assert_eq!(upload.progress, 0.0);
     }
 
+    #[tokio::test]
+    async fn test_upload_history_includes_verification() {
+        let (pool, _temp_dir) = setup_test_db().await;
+        let manager = UploadManager::new(pool);
+
+        let verified = manager.create_upload(1, "/a.bin", "a.bin", 100, 256).await.unwrap();
+        manager.update_upload_status(&verified, "completed", Some(100), None).await.unwrap();
+        manager
+            .set_upload_verification(&verified, Some("\"abc-2\""), UploadVerification::Verified)
+            .await
+            .unwrap();
+
+        // Still running, so not part of history
+        manager.create_upload(1, "/b.bin", "b.bin", 100, 256).await.unwrap();
+
+        let history = manager.get_upload_history(10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, verified);
+        assert_eq!(history[0].file_name, "a.bin");
+        assert_eq!(history[0].etag.as_deref(), Some("\"abc-2\""));
+        assert_eq!(history[0].verification, Some(UploadVerification::Verified));
+    }
+
+    #[tokio::test]
+    async fn test_resumable_multipart_ids() {
+        let (pool, _temp_dir) = setup_test_db().await;
+        let manager = UploadManager::new(pool);
+
+        let paused = manager.create_upload(1, "/a.iso", "a.iso", 1024, 256).await.unwrap();
+        manager.set_multipart_upload_id(&paused, "mpu-paused").await.unwrap();
+        manager.update_upload_status(&paused, "paused", None, None).await.unwrap();
+
+        let done = manager.create_upload(1, "/b.iso", "b.iso", 1024, 256).await.unwrap();
+        manager.set_multipart_upload_id(&done, "mpu-done").await.unwrap();
+        manager.update_upload_status(&done, "completed", Some(1024), None).await.unwrap();
+
+        let ids = manager.resumable_multipart_ids().await.unwrap();
+        assert!(ids.contains("mpu-paused"));
+        assert!(!ids.contains("mpu-done"));
+    }
+
+    #[tokio::test]
+    async fn test_find_by_multipart_id() {
+        let (pool, _temp_dir) = setup_test_db().await;
+        let manager = UploadManager::new(pool);
+
+        let upload_id = manager.create_upload(1, "/big.iso", "big.iso", 1024, 256).await.unwrap();
+        manager.set_multipart_upload_id(&upload_id, "mpu-123").await.unwrap();
+
+        let found = manager.find_by_multipart_id("mpu-123").await.unwrap();
+        assert_eq!(found, Some((upload_id, "/big.iso".to_string())));
+        assert!(manager.find_by_multipart_id("mpu-unknown").await.unwrap().is_none());
+    }
+
+    /// A finished upload with the given times, as SQLite stores them
+    async fn add_finished(manager: &UploadManager, path: &str, size: i64, status: &str, started: &str, completed: &str) {
+        let id = manager.create_upload(1, path, &path[1..], size, 256).await.unwrap();
+        sqlx::query("UPDATE uploads SET status = ?, started_at = ?, completed_at = ? WHERE id = ?")
+            .bind(status)
+            .bind(started)
+            .bind(completed)
+            .bind(&id)
+            .execute(manager.pool())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_history() {
+        let (pool, _temp_dir) = setup_test_db().await;
+        let manager = UploadManager::new(pool);
+
+        add_finished(&manager, "/docs/a.pdf", 1000, "completed", "2024-03-01 10:00:00", "2024-03-01 10:00:10").await;
+        add_finished(&manager, "/docs/b_1.pdf", 3000, "failed", "2024-03-02 10:00:00", "2024-03-02 10:00:01").await;
+        add_finished(&manager, "/photos/c.jpg", 5000, "completed", "2024-03-03 10:00:00", "2024-03-03 10:00:05").await;
+        manager.create_upload(1, "/docs/active.pdf", "docs/active.pdf", 10, 256).await.unwrap();
+
+        let page = manager.query_history(&HistoryFilter::default(), 0, 2).await.unwrap();
+        assert_eq!(page.total, 3);
+        let names: Vec<&str> = page.entries.iter().map(|e| e.file_name.as_str()).collect();
+        assert_eq!(names, ["c.jpg", "b_1.pdf"]);
+        let page = manager.query_history(&HistoryFilter::default(), 2, 2).await.unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].file_name, "a.pdf");
+
+        let count = |filter: HistoryFilter| {
+            let manager = &manager;
+            async move { manager.query_history(&filter, 0, 50).await.unwrap().total }
+        };
+        assert_eq!(count(HistoryFilter { statuses: vec![UploadStatus::Failed], ..Default::default() }).await, 1);
+        assert_eq!(count(HistoryFilter { path_contains: Some("DOCS".into()), ..Default::default() }).await, 2);
+        // `_` is matched literally, not as a wildcard
+        assert_eq!(count(HistoryFilter { path_contains: Some("b_".into()), ..Default::default() }).await, 1);
+        assert_eq!(count(HistoryFilter { path_contains: Some("a_".into()), ..Default::default() }).await, 0);
+        assert_eq!(count(HistoryFilter { since: Some("2024-03-02".into()), ..Default::default() }).await, 2);
+        assert_eq!(
+            count(HistoryFilter { until: Some("2024-03-02T10:00:01Z".into()), ..Default::default() }).await,
+            1
+        );
+        assert_eq!(count(HistoryFilter { min_size: Some(2000), max_size: Some(4000), ..Default::default() }).await, 1);
+        assert_eq!(count(HistoryFilter { bucket_id: Some(2), ..Default::default() }).await, 0);
+
+        let invalid = HistoryFilter { since: Some("yesterday".into()), ..Default::default() };
+        assert!(manager.query_history(&invalid, 0, 50).await.is_err());
+        let unfinished = HistoryFilter { statuses: vec![UploadStatus::Paused], ..Default::default() };
+        assert!(manager.query_history(&unfinished, 0, 50).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upload_history_limit_is_not_capped() {
+        let (pool, _temp_dir) = setup_test_db().await;
+        let manager = UploadManager::new(pool);
+
+        for i in 0..=MAX_HISTORY_PAGE {
+            let path = format!("/docs/{}.txt", i);
+            add_finished(&manager, &path, 1, "completed", "2024-03-01 10:00:00", "2024-03-01 10:00:01").await;
+        }
+
+        assert_eq!(manager.get_upload_history(1000).await.unwrap().len() as i64, MAX_HISTORY_PAGE + 1);
+        let page = manager.query_history(&HistoryFilter::default(), 0, 1000).await.unwrap();
+        assert_eq!(page.limit, MAX_HISTORY_PAGE);
+        assert_eq!(page.entries.len() as i64, MAX_HISTORY_PAGE);
+    }
+
+    #[tokio::test]
+    async fn test_history_stats() {
+        let (pool, _temp_dir) = setup_test_db().await;
+        let manager = UploadManager::new(pool);
+
+        let stats = manager.history_stats(&HistoryFilter::default()).await.unwrap();
+        assert_eq!(stats.success_rate, 0.0);
+        assert!(stats.daily.is_empty());
+
+        add_finished(&manager, "/a", 1000, "completed", "2024-03-01 10:00:00", "2024-03-01 10:00:10").await;
+        add_finished(&manager, "/b", 3000, "completed", "2024-03-01 11:00:00", "2024-03-01 11:00:10").await;
+        add_finished(&manager, "/c", 500, "completed", "2024-03-02 10:00:00", "2024-03-02 10:00:00").await;
+        add_finished(&manager, "/d", 9000, "failed", "2024-03-02 11:00:00", "2024-03-02 11:00:03").await;
+        add_finished(&manager, "/e", 9000, "cancelled", "2024-03-02 12:00:00", "2024-03-02 12:00:03").await;
+
+        let stats = manager.history_stats(&HistoryFilter::default()).await.unwrap();
+        assert_eq!((stats.completed, stats.failed, stats.cancelled), (3, 1, 1));
+        assert_eq!(stats.success_rate, 0.75);
+        assert_eq!(stats.bytes_transferred, 4500);
+        // The instant upload has no measurable duration and is left out of throughput
+        assert!((stats.average_throughput - 200.0).abs() < 0.01);
+        assert_eq!(
+            stats.daily,
+            vec![
+                DailyTransfers { day: "2024-03-01".into(), bytes: 4000, count: 2 },
+                DailyTransfers { day: "2024-03-02".into(), bytes: 500, count: 1 },
+            ]
+        );
+
+        let one_day = HistoryFilter { since: Some("2024-03-02".into()), ..Default::default() };
+        let stats = manager.history_stats(&one_day).await.unwrap();
+        assert_eq!(stats.bytes_transferred, 500);
+        assert_eq!(stats.success_rate, 0.5);
+    }
+
     // Add helper method for tests
     impl UploadManager {
         #[cfg(test)]
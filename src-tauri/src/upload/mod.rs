@@ -1,3 +1,7 @@
 pub mod manager;
+pub mod conflict;
+pub mod fingerprint;
+pub mod export;
 
 pub use manager::UploadManager;
+pub use conflict::{ConflictAction, ConflictPolicy, ConflictResolver};
@@ -0,0 +1,35 @@
+//! Minimal RFC 4180 writing for the exports, which only ever write flat records
+
+/// Append one field, quoted when it holds a separator, quote or line break
+pub fn push_field(line: &mut String, value: &str) {
+    if value.contains([',', '"', '\n', '\r']) {
+        line.push('"');
+        line.push_str(&value.replace('"', "\"\""));
+        line.push('"');
+    } else {
+        line.push_str(value);
+    }
+}
+
+/// Append a whole record, terminated by CRLF
+pub fn push_record<'a>(line: &mut String, fields: impl IntoIterator<Item = &'a str>) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        push_field(line, field);
+    }
+    line.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_record() {
+        let mut line = String::new();
+        push_record(&mut line, ["plain", "a,b", "say \"hi\"", "two\nlines", ""]);
+        assert_eq!(line, "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\r\n");
+    }
+}
@@ -1,3 +1,5 @@
+pub mod csv;
+pub mod paths;
 pub mod types;
 
 pub use types::*;
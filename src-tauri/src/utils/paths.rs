@@ -0,0 +1,308 @@
+//! Mapping between local paths and object keys.
+//!
+//! Keys are always NFC and `/`-separated, whatever the local filesystem stores. Characters a
+//! platform can't have in file names are escaped into the Private Use Area on the way to disk
+//! (U+F000 plus the ASCII code, the mapping Windows' Services for Mac uses) and unescaped on the
+//! way back, so a file keeps its key when it round-trips through a Windows machine.
+
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Longest object key R2 accepts, in UTF-8 bytes
+pub const MAX_KEY_BYTES: usize = 1024;
+
+/// Escaped characters are moved up to U+F000 + their ASCII code
+const ESCAPE_BASE: u32 = 0xF000;
+
+/// Characters Windows doesn't allow in file names, besides control characters
+const WINDOWS_ILLEGAL: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+/// Device names Windows reserves, with or without an extension
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Last component of a local path or key, whichever separator it uses
+pub fn file_name(path: &str) -> &str {
+    path.trim_end_matches(['/', '\\']).rsplit(['/', '\\']).next().unwrap_or("")
+}
+
+/// `path` as a folder prefix ending in `/`; empty stays empty, meaning the bucket root
+pub fn dir_prefix(path: &str) -> String {
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", path)
+    }
+}
+
+/// A key as it is stored: NFC, so names typed on macOS and Linux give the same key
+pub fn normalize_key(key: &str) -> String {
+    if is_nfc(key) {
+        key.to_string()
+    } else {
+        key.nfc().collect()
+    }
+}
+
+/// Reject keys R2 won't store
+pub fn check_key(key: &str) -> Result<()> {
+    if key.is_empty() || key.starts_with('/') {
+        bail!("Invalid object key {:?}", key);
+    }
+    if key.len() > MAX_KEY_BYTES {
+        bail!("Object key is too long ({} bytes, the limit is {}): {}", key.len(), MAX_KEY_BYTES, key);
+    }
+    Ok(())
+}
+
+/// Key form of one local file name: escapes undone and normalized to NFC
+pub fn key_component(name: &str) -> String {
+    unescape_component(name, cfg!(windows))
+}
+
+/// Undoes `escape_component`. Elsewhere than on Windows only escaped `.` and `..` are
+/// undone, so names that really contain Private Use characters keep them.
+fn unescape_component(name: &str, windows: bool) -> String {
+    let escaped_dots = matches!(name, "\u{f02e}" | "\u{f02e}\u{f02e}");
+    if windows || escaped_dots {
+        let unescaped: String = name.chars().map(unescape_char).collect();
+        normalize_key(&unescaped)
+    } else {
+        normalize_key(name)
+    }
+}
+
+/// File name for one key component on this platform
+pub fn local_component(part: &str) -> String {
+    escape_component(part, cfg!(windows))
+}
+
+/// `.` and `..` are escaped everywhere, so no key can point outside the sync folder. With
+/// `windows`, so are illegal characters, trailing dots and spaces, and reserved device names.
+fn escape_component(part: &str, windows: bool) -> String {
+    if part == "." || part == ".." {
+        return part.chars().map(escape_char).collect();
+    }
+    if !windows {
+        return part.to_string();
+    }
+
+    let mut chars: Vec<char> = part
+        .chars()
+        .map(|c| if c.is_ascii_control() || WINDOWS_ILLEGAL.contains(&c) { escape_char(c) } else { c })
+        .collect();
+    if let Some(last) = chars.last_mut().filter(|c| matches!(c, '.' | ' ')) {
+        *last = escape_char(*last);
+    }
+    let stem = part.split('.').next().unwrap_or(part);
+    if WINDOWS_RESERVED.iter().any(|name| name.eq_ignore_ascii_case(stem)) {
+        let end = stem.chars().count() - 1;
+        chars[end] = escape_char(chars[end]);
+    }
+    chars.into_iter().collect()
+}
+
+fn escape_char(c: char) -> char {
+    char::from_u32(ESCAPE_BASE + c as u32).unwrap_or(c)
+}
+
+fn unescape_char(c: char) -> char {
+    match c as u32 {
+        code @ 0xF001..=0xF07F => char::from_u32(code - ESCAPE_BASE).unwrap_or(c),
+        _ => c,
+    }
+}
+
+/// Path of `path` relative to `root` in key form, always with `/` separators
+pub fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str().map(key_component))
+        .collect::<Option<_>>()?;
+    if parts.is_empty() {
+        return None;
+    }
+    Some(parts.join("/"))
+}
+
+/// Object key for a relative path under the folder's remote prefix
+pub fn join_key(remote_prefix: &str, relative: &str) -> String {
+    let prefix = remote_prefix.trim_matches('/');
+    if prefix.is_empty() {
+        relative.to_string()
+    } else {
+        format!("{}/{}", prefix, relative)
+    }
+}
+
+/// Object key for `path` under the folder's remote prefix
+pub fn remote_key_for(root: &Path, remote_prefix: &str, path: &Path) -> Option<String> {
+    relative_path(root, path).map(|relative| join_key(remote_prefix, &relative))
+}
+
+/// Relative path of `key` under the listing prefix
+pub fn relative_key<'a>(prefix: Option<&str>, key: &'a str) -> Option<&'a str> {
+    let relative = match prefix {
+        Some(prefix) => key.strip_prefix(prefix)?,
+        None => key,
+    };
+    (!relative.is_empty()).then_some(relative)
+}
+
+/// Local path for a `/`-separated relative path. A name that already exists decomposed
+/// (as macOS writes them) is used as it is rather than created a second time composed.
+pub fn local_path_for(root: &Path, relative: &str) -> PathBuf {
+    relative.split('/').fold(root.to_path_buf(), |path, part| {
+        let name = local_component(part);
+        if name.is_ascii() {
+            return path.join(name);
+        }
+        let composed = path.join(&name);
+        let decomposed = path.join(name.nfd().collect::<String>());
+        if !composed.exists() && decomposed.exists() {
+            decomposed
+        } else {
+            composed
+        }
+    })
+}
+
+/// Form under which paths are the same file on a case-insensitive filesystem
+fn fold(relative: &str) -> String {
+    key_component(relative).to_lowercase()
+}
+
+/// Paths that differ only in case or Unicode form, which a case-insensitive filesystem would
+/// merge into one file. Each group is sorted; groups are in path order.
+pub fn collisions<'a>(relatives: impl IntoIterator<Item = &'a str>) -> Vec<Vec<String>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for relative in relatives {
+        let group = groups.entry(fold(relative)).or_default();
+        if !group.iter().any(|r| r == relative) {
+            group.push(relative.to_string());
+        }
+    }
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort();
+            group
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name_and_prefix() {
+        assert_eq!(file_name("C:\\Users\\me\\report.pdf"), "report.pdf");
+        assert_eq!(file_name("/home/me/report.pdf"), "report.pdf");
+        assert_eq!(file_name("docs/archive/"), "archive");
+        assert_eq!(dir_prefix("docs/archive"), "docs/archive/");
+        assert_eq!(dir_prefix("docs/"), "docs/");
+        assert_eq!(dir_prefix(""), "");
+    }
+
+    #[test]
+    fn test_normalization() {
+        let decomposed = "Cafe\u{301}.txt";
+        assert_eq!(normalize_key(decomposed), "Caf\u{e9}.txt");
+        assert_eq!(key_component(decomposed), "Caf\u{e9}.txt");
+        assert_eq!(normalize_key("plain.txt"), "plain.txt");
+    }
+
+    #[test]
+    fn test_check_key() {
+        assert!(check_key("docs/a.txt").is_ok());
+        assert!(check_key(&"é".repeat(512)).is_ok());
+        let err = check_key(&"é".repeat(513)).unwrap_err();
+        assert!(err.to_string().contains("too long"));
+        assert!(check_key("").is_err());
+        assert!(check_key("/abs").is_err());
+    }
+
+    #[test]
+    fn test_windows_escaping_round_trips() {
+        for name in ["a:b?.txt", "what*<>|\"\\.md", "trailing. ", "CON", "nul.txt", "tab\there", "plain.txt"] {
+            let escaped = escape_component(name, true);
+            assert!(!escaped.chars().any(|c| WINDOWS_ILLEGAL.contains(&c) || c.is_ascii_control()), "{}", escaped);
+            assert!(!escaped.ends_with(['.', ' ']));
+            assert_eq!(unescape_component(&escaped, true), name);
+        }
+        assert_eq!(escape_component("plain.txt", true), "plain.txt");
+        assert_eq!(escape_component("CON", true), "CO\u{f04e}");
+        assert_eq!(escape_component("console.txt", true), "console.txt");
+        assert_eq!(escape_component("a:b", false), "a:b");
+    }
+
+    #[test]
+    fn test_private_use_names_round_trip_off_windows() {
+        assert_eq!(unescape_component("a\u{f03a}b", false), "a\u{f03a}b");
+        assert_eq!(unescape_component("\u{f02e}\u{f02e}", false), "..");
+        assert_eq!(unescape_component("\u{f02e}\u{f02e}\u{f02e}", false), "\u{f02e}\u{f02e}\u{f02e}");
+        assert_eq!(unescape_component("a\u{f03a}b", true), "a:b");
+    }
+
+    #[test]
+    fn test_dot_components_stay_inside_root() {
+        let root = Path::new("/sync");
+        let path = local_path_for(root, "../../etc/passwd");
+        assert!(path.starts_with(root));
+        assert_eq!(path.components().count(), Path::new("/sync/x/y/etc/passwd").components().count());
+        assert_eq!(relative_path(root, &path).as_deref(), Some("../../etc/passwd"));
+    }
+
+    #[test]
+    fn test_remote_key_for() {
+        let root = Path::new("/home/user/docs");
+        let file = root.join("reports").join("q1.pdf");
+
+        assert_eq!(remote_key_for(root, "backup/docs/", &file), Some("backup/docs/reports/q1.pdf".to_string()));
+        assert_eq!(remote_key_for(root, "", &file), Some("reports/q1.pdf".to_string()));
+        assert_eq!(remote_key_for(root, "/", &file), Some("reports/q1.pdf".to_string()));
+        assert_eq!(remote_key_for(root, "docs", &root.join("Cafe\u{301}")), Some("docs/Caf\u{e9}".to_string()));
+        assert_eq!(remote_key_for(root, "docs", root), None);
+        assert_eq!(remote_key_for(root, "docs", Path::new("/elsewhere/a.txt")), None);
+    }
+
+    #[test]
+    fn test_relative_key() {
+        assert_eq!(relative_key(Some("docs/"), "docs/a/b.txt"), Some("a/b.txt"));
+        assert_eq!(relative_key(Some("docs/"), "other/b.txt"), None);
+        assert_eq!(relative_key(None, "b.txt"), Some("b.txt"));
+        assert_eq!(relative_key(Some("docs/"), "docs/"), None);
+    }
+
+    #[test]
+    fn test_local_path_finds_decomposed_name() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("Cafe\u{301}.txt"), b"x").unwrap();
+
+        let path = local_path_for(dir.path(), "Caf\u{e9}.txt");
+        assert!(path.exists());
+        assert_eq!(relative_path(dir.path(), &path).as_deref(), Some("Caf\u{e9}.txt"));
+        assert_eq!(local_path_for(dir.path(), "new\u{e9}.txt"), dir.path().join("new\u{e9}.txt"));
+    }
+
+    #[test]
+    fn test_collisions() {
+        let paths = ["docs/Report.pdf", "docs/report.pdf", "Caf\u{e9}", "Cafe\u{301}", "other.txt", "docs/report.pdf"];
+        assert_eq!(
+            collisions(paths),
+            vec![
+                vec!["Cafe\u{301}".to_string(), "Caf\u{e9}".to_string()],
+                vec!["docs/Report.pdf".to_string(), "docs/report.pdf".to_string()],
+            ]
+        );
+        assert!(collisions(["a.txt", "b.txt"]).is_empty());
+    }
+}
@@ -49,6 +49,684 @@ pub enum UploadStatus {
     Cancelled,
 }
 
+/// Whether an uploaded object was confirmed to match the local file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadVerification {
+    Verified,
+    Mismatch,
+    /// Nothing to compare against, e.g. parts uploaded by an earlier session
+    Unverified,
+}
+
+impl UploadVerification {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UploadVerification::Verified => "verified",
+            UploadVerification::Mismatch => "mismatch",
+            UploadVerification::Unverified => "unverified",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "verified" => Some(UploadVerification::Verified),
+            "mismatch" => Some(UploadVerification::Mismatch),
+            "unverified" => Some(UploadVerification::Unverified),
+            _ => None,
+        }
+    }
+}
+
+/// A finished or failed upload as shown in upload history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadHistoryEntry {
+    pub id: String,
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+    #[serde(rename = "remotePath")]
+    pub remote_path: String,
+    #[serde(rename = "totalSize")]
+    pub total_size: i64,
+    pub status: UploadStatus,
+    #[serde(rename = "errorMessage")]
+    pub error_message: Option<String>,
+    #[serde(rename = "startedAt")]
+    pub started_at: Option<String>,
+    #[serde(rename = "completedAt")]
+    pub completed_at: Option<String>,
+    pub etag: Option<String>,
+    pub verification: Option<UploadVerification>,
+}
+
+/// Which finished transfers a history query covers; empty fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryFilter {
+    /// Finished statuses to include; all of them when empty
+    pub statuses: Vec<UploadStatus>,
+    /// Finished at or after, as `YYYY-MM-DD` or RFC 3339
+    pub since: Option<String>,
+    /// Finished before, as `YYYY-MM-DD` or RFC 3339
+    pub until: Option<String>,
+    #[serde(rename = "bucketId")]
+    pub bucket_id: Option<i64>,
+    /// Matched case-insensitively against the local path and the key
+    #[serde(rename = "pathContains")]
+    pub path_contains: Option<String>,
+    #[serde(rename = "minSize")]
+    pub min_size: Option<i64>,
+    #[serde(rename = "maxSize")]
+    pub max_size: Option<i64>,
+}
+
+/// One page of history, newest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryPage {
+    pub entries: Vec<UploadHistoryEntry>,
+    /// Entries matching the filter across all pages
+    pub total: i64,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+/// Totals over the transfers matching a history filter
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub completed: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    /// Share of completed and failed transfers that completed, from 0 to 1; cancelled ones
+    /// don't count either way
+    #[serde(rename = "successRate")]
+    pub success_rate: f64,
+    /// Size of the completed transfers
+    #[serde(rename = "bytesTransferred")]
+    pub bytes_transferred: i64,
+    /// Bytes per second over the time completed transfers took
+    #[serde(rename = "averageThroughput")]
+    pub average_throughput: f64,
+    /// Completed transfers per UTC day, oldest first
+    pub daily: Vec<DailyTransfers>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyTransfers {
+    /// `YYYY-MM-DD`
+    pub day: String,
+    pub bytes: i64,
+    pub count: i64,
+}
+
+/// How a sync folder moves changes between the local folder and its remote prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    UploadOnly,
+    Bidirectional,
+    /// Read-only local mirror of the remote prefix
+    Download,
+}
+
+impl SyncMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncMode::UploadOnly => "upload_only",
+            SyncMode::Bidirectional => "bidirectional",
+            SyncMode::Download => "download",
+        }
+    }
+
+    /// Parse a `sync_folders.sync_mode` value; the legacy "upload" means upload-only
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "upload_only" | "upload" => Some(SyncMode::UploadOnly),
+            "bidirectional" => Some(SyncMode::Bidirectional),
+            "download" => Some(SyncMode::Download),
+            _ => None,
+        }
+    }
+}
+
+/// When a sync folder's full passes run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleKind {
+    /// Watch the folder and sync changes as they happen
+    Realtime,
+    /// Scan at a fixed interval
+    Interval,
+    /// Scan at the times matched by a cron expression
+    Cron,
+}
+
+impl ScheduleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleKind::Realtime => "realtime",
+            ScheduleKind::Interval => "interval",
+            ScheduleKind::Cron => "cron",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "realtime" => Some(ScheduleKind::Realtime),
+            "interval" => Some(ScheduleKind::Interval),
+            "cron" => Some(ScheduleKind::Cron),
+            _ => None,
+        }
+    }
+}
+
+/// How a sync folder carries deletions over to the other side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionPolicy {
+    /// Deletions are not propagated; the other copy stays
+    Never,
+    Immediate,
+    /// Deleted files are moved into the folder's trash on the other side
+    Trash,
+}
+
+impl DeletionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeletionPolicy::Never => "never",
+            DeletionPolicy::Immediate => "immediate",
+            DeletionPolicy::Trash => "trash",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "never" => Some(DeletionPolicy::Never),
+            "immediate" => Some(DeletionPolicy::Immediate),
+            "trash" => Some(DeletionPolicy::Trash),
+            _ => None,
+        }
+    }
+}
+
+/// Which copy a deletion removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionSide {
+    Local,
+    Remote,
+}
+
+impl DeletionSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeletionSide::Local => "local",
+            DeletionSide::Remote => "remote",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "local" => Some(DeletionSide::Local),
+            "remote" => Some(DeletionSide::Remote),
+            _ => None,
+        }
+    }
+}
+
+/// A deletion held back because a pass wanted to delete more than the folder's threshold allows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldDeletion {
+    pub id: i64,
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+    #[serde(rename = "relativePath")]
+    pub relative_path: String,
+    pub side: DeletionSide,
+    #[serde(rename = "detectedAt")]
+    pub detected_at: String,
+}
+
+/// Payload of the `sync-deletions-held` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldDeletionsNotice {
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+    pub count: usize,
+}
+
+/// Whether retrying a failed sync can help
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncErrorKind {
+    /// Network trouble, throttling and the like: retried with backoff
+    Transient,
+    /// Permission denied, a name R2 rejects and the like: quarantined until the user steps in
+    Permanent,
+}
+
+impl SyncErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncErrorKind::Transient => "transient",
+            SyncErrorKind::Permanent => "permanent",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "transient" => Some(SyncErrorKind::Transient),
+            "permanent" => Some(SyncErrorKind::Permanent),
+            _ => None,
+        }
+    }
+}
+
+/// A file that failed to sync, waiting for a retry or in quarantine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncError {
+    pub id: i64,
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+    #[serde(rename = "relativePath")]
+    pub relative_path: String,
+    pub kind: SyncErrorKind,
+    pub message: String,
+    /// Failures in a row
+    pub attempts: i64,
+    /// When a transient error is retried next, UTC
+    #[serde(rename = "nextRetryAt")]
+    pub next_retry_at: Option<String>,
+    /// Left out of sync by the user
+    pub ignored: bool,
+    #[serde(rename = "firstFailedAt")]
+    pub first_failed_at: String,
+    #[serde(rename = "lastFailedAt")]
+    pub last_failed_at: String,
+}
+
+impl SyncError {
+    /// Whether the file is left out of sync until the user retries it
+    pub fn is_quarantined(&self) -> bool {
+        self.ignored || self.kind == SyncErrorKind::Permanent
+    }
+}
+
+/// A concurrent edit kept as two copies, waiting for the user to pick one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub id: i64,
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+    /// Path of the file, relative to the sync folder
+    #[serde(rename = "relativePath")]
+    pub relative_path: String,
+    /// Where this device's copy was moved to
+    #[serde(rename = "conflictPath")]
+    pub conflict_path: String,
+    #[serde(rename = "detectedAt")]
+    pub detected_at: String,
+    #[serde(rename = "resolvedAt")]
+    pub resolved_at: Option<String>,
+}
+
+/// What a sync folder's worker is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncState {
+    Idle,
+    Syncing,
+    Error,
+    Stopped,
+}
+
+/// Payload of the `sync-status` event, one per sync folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncStatus {
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+    pub state: SyncState,
+    /// Changed paths waiting to be synced
+    pub pending: usize,
+    /// Files uploaded in the last sync pass
+    pub uploaded: usize,
+    /// Files that failed in the last sync pass
+    pub failed: usize,
+    pub message: Option<String>,
+}
+
+/// Sync state of one local file, for badges
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSyncState {
+    Synced,
+    /// Changed since it last synced, or never synced
+    Pending,
+    Uploading,
+    /// Has an unresolved conflict copy
+    Conflicted,
+    Error,
+    /// Left out of sync, like editor scratch files
+    Ignored,
+    /// Not inside a sync folder
+    Untracked,
+}
+
+/// How many files under a folder are in each state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStatusCounts {
+    pub synced: usize,
+    pub pending: usize,
+    pub uploading: usize,
+    pub conflicted: usize,
+    pub error: usize,
+}
+
+impl FileStatusCounts {
+    pub fn add(&mut self, state: FileSyncState) {
+        match state {
+            FileSyncState::Synced => self.synced += 1,
+            FileSyncState::Pending => self.pending += 1,
+            FileSyncState::Uploading => self.uploading += 1,
+            FileSyncState::Conflicted => self.conflicted += 1,
+            FileSyncState::Error => self.error += 1,
+            FileSyncState::Ignored | FileSyncState::Untracked => {}
+        }
+    }
+
+    /// The state a folder badge shows: the most pressing one among its files
+    pub fn summary(&self) -> FileSyncState {
+        if self.error > 0 {
+            FileSyncState::Error
+        } else if self.conflicted > 0 {
+            FileSyncState::Conflicted
+        } else if self.uploading > 0 {
+            FileSyncState::Uploading
+        } else if self.pending > 0 {
+            FileSyncState::Pending
+        } else {
+            FileSyncState::Synced
+        }
+    }
+}
+
+/// Sync status of a local file or folder; also the payload of the `file-sync-status` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSyncStatus {
+    pub path: String,
+    /// Sync folder the path is in
+    #[serde(rename = "folderId")]
+    pub folder_id: Option<i64>,
+    pub state: FileSyncState,
+    /// Percent uploaded, while uploading
+    pub progress: Option<f64>,
+    pub message: Option<String>,
+    /// For folders, how many files inside are in each state
+    pub counts: Option<FileStatusCounts>,
+}
+
+/// How a path in a sync folder compares with its remote object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncDiffKind {
+    LocalOnly,
+    RemoteOnly,
+    Changed,
+    Identical,
+    /// Edited locally in a download-only mirror, so left alone
+    LocallyModified,
+}
+
+/// One path from a reconciliation scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncDiffEntry {
+    pub key: String,
+    #[serde(rename = "localPath")]
+    pub local_path: Option<String>,
+    pub kind: SyncDiffKind,
+    #[serde(rename = "localSize")]
+    pub local_size: Option<i64>,
+    #[serde(rename = "remoteSize")]
+    pub remote_size: Option<i64>,
+}
+
+/// Result of comparing a whole sync folder against its remote prefix
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReconcileReport {
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+    /// Every path that is not identical
+    pub entries: Vec<SyncDiffEntry>,
+    #[serde(rename = "localOnly")]
+    pub local_only: usize,
+    #[serde(rename = "remoteOnly")]
+    pub remote_only: usize,
+    pub changed: usize,
+    pub identical: usize,
+    pub uploaded: usize,
+    pub downloaded: usize,
+    pub deleted: usize,
+    /// Local moves and renames carried out with a server-side copy
+    pub moved: usize,
+    pub conflicts: usize,
+    #[serde(rename = "locallyModified")]
+    pub locally_modified: usize,
+    /// Deletions waiting for confirmation
+    pub held: usize,
+    pub failed: usize,
+    #[serde(rename = "scannedAt")]
+    pub scanned_at: String,
+}
+
+impl ReconcileReport {
+    /// Add a path to the count for its kind
+    pub fn count(&mut self, kind: SyncDiffKind) {
+        match kind {
+            SyncDiffKind::LocalOnly => self.local_only += 1,
+            SyncDiffKind::RemoteOnly => self.remote_only += 1,
+            SyncDiffKind::Changed => self.changed += 1,
+            SyncDiffKind::Identical => self.identical += 1,
+            SyncDiffKind::LocallyModified => self.locally_modified += 1,
+        }
+    }
+}
+
+/// What a sync plan does with one path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanActionKind {
+    Upload,
+    Download,
+    DeleteRemote,
+    DeleteLocal,
+    /// Edited on both sides; both copies are kept unless their contents turn out identical
+    Conflict,
+    /// Moved or renamed locally; the object is copied to its new key on R2 instead of uploaded
+    Move,
+}
+
+/// One action of a sync plan, with the state of both copies it was planned against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanAction {
+    #[serde(rename = "relativePath")]
+    pub relative_path: String,
+    pub kind: PlanActionKind,
+    #[serde(rename = "localSize")]
+    pub local_size: Option<i64>,
+    #[serde(rename = "localMtime")]
+    pub local_mtime: Option<i64>,
+    #[serde(rename = "remoteSize")]
+    pub remote_size: Option<i64>,
+    #[serde(rename = "remoteEtag")]
+    pub remote_etag: Option<String>,
+    /// Where a moved file used to be; `remote_etag` is then that object's ETag
+    #[serde(rename = "movedFrom")]
+    pub moved_from: Option<String>,
+}
+
+impl PlanAction {
+    pub fn is_deletion(&self) -> bool {
+        matches!(self.kind, PlanActionKind::DeleteRemote | PlanActionKind::DeleteLocal)
+    }
+}
+
+/// Everything a full sync pass of a folder would do, computed without changing anything
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPlan {
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+    pub mode: SyncMode,
+    pub actions: Vec<PlanAction>,
+    pub uploads: usize,
+    pub downloads: usize,
+    pub deletes: usize,
+    pub conflicts: usize,
+    pub moves: usize,
+    #[serde(rename = "uploadBytes")]
+    pub upload_bytes: i64,
+    #[serde(rename = "downloadBytes")]
+    pub download_bytes: i64,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+impl SyncPlan {
+    pub fn new(folder_id: i64, mode: SyncMode) -> Self {
+        Self {
+            folder_id,
+            mode,
+            actions: Vec::new(),
+            uploads: 0,
+            downloads: 0,
+            deletes: 0,
+            conflicts: 0,
+            moves: 0,
+            upload_bytes: 0,
+            download_bytes: 0,
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Add an action and its share of the totals
+    pub fn push(&mut self, action: PlanAction) {
+        let local = action.local_size.unwrap_or(0);
+        let remote = action.remote_size.unwrap_or(0);
+        match action.kind {
+            PlanActionKind::Upload => {
+                self.uploads += 1;
+                self.upload_bytes += local;
+            }
+            PlanActionKind::Download => {
+                self.downloads += 1;
+                self.download_bytes += remote;
+            }
+            PlanActionKind::DeleteRemote | PlanActionKind::DeleteLocal => self.deletes += 1,
+            // Keeping both copies uploads the local one and downloads the remote one
+            PlanActionKind::Conflict => {
+                self.conflicts += 1;
+                self.upload_bytes += local;
+                self.download_bytes += remote;
+            }
+            PlanActionKind::Move => self.moves += 1,
+        }
+        self.actions.push(action);
+    }
+}
+
+/// Result of executing a sync plan
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlanExecution {
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+    pub applied: usize,
+    pub failed: usize,
+    /// Paths left alone because they changed after the plan was made
+    pub skipped: Vec<String>,
+}
+
+/// One run of a maintenance task against one bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceLogEntry {
+    pub id: i64,
+    pub task: String,
+    /// "scheduled" or "manual"
+    pub trigger: String,
+    #[serde(rename = "bucketName")]
+    pub bucket_name: Option<String>,
+    /// "success" or "error"
+    pub status: String,
+    #[serde(rename = "cleanedCount")]
+    pub cleaned_count: i64,
+    #[serde(rename = "protectedCount")]
+    pub protected_count: i64,
+    pub message: Option<String>,
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<String>,
+}
+
+/// Size and contents of the app database, for troubleshooting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseDiagnostics {
+    pub path: String,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: i64,
+    /// Write-ahead log not yet checkpointed into the main file
+    #[serde(rename = "walBytes")]
+    pub wal_bytes: i64,
+    /// Unused pages that `VACUUM` would give back
+    #[serde(rename = "freeBytes")]
+    pub free_bytes: i64,
+    #[serde(rename = "journalMode")]
+    pub journal_mode: String,
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: i64,
+    pub tables: Vec<TableRowCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableRowCount {
+    pub table: String,
+    pub rows: i64,
+}
+
+/// A local file and the key it would be uploaded to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadPlanItem {
+    #[serde(rename = "localPath")]
+    pub local_path: String,
+    #[serde(rename = "remoteKey")]
+    pub remote_key: String,
+}
+
+/// Dry-run decision for one file of an "only upload changed files" run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadPreviewEntry {
+    #[serde(rename = "localPath")]
+    pub local_path: String,
+    #[serde(rename = "remoteKey")]
+    pub remote_key: String,
+    pub size: i64,
+    pub upload: bool,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UploadPreview {
+    pub entries: Vec<UploadPreviewEntry>,
+    #[serde(rename = "uploadCount")]
+    pub upload_count: usize,
+    #[serde(rename = "uploadBytes")]
+    pub upload_bytes: i64,
+    #[serde(rename = "skipCount")]
+    pub skip_count: usize,
+    #[serde(rename = "skipBytes")]
+    pub skip_bytes: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct R2Credentials {
     #[serde(rename = "accountId")]
@@ -60,12 +738,19 @@ pub struct R2Credentials {
     pub endpoint: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AppSettings {
     pub theme: String,
+    /// Part size for multipart uploads, in bytes
+    #[serde(rename = "chunkSize")]
     pub chunk_size: usize,
+    /// Parts of one multipart upload sent at once
+    #[serde(rename = "parallelUploads")]
     pub parallel_uploads: usize,
+    /// Upload bytes per second across the app; 0 means unlimited
+    #[serde(rename = "bandwidthLimit")]
     pub bandwidth_limit: usize,
+    #[serde(rename = "conflictResolution")]
     pub conflict_resolution: String,
     pub notifications: bool,
 }
@@ -59,12 +59,76 @@ export interface SyncFolder {
   lastSync?: string;
 }
 
+export interface UploadHistoryEntry {
+  id: string;
+  fileName: string;
+  filePath: string;
+  remotePath: string;
+  totalSize: number;
+  status: 'completed' | 'failed' | 'cancelled';
+  errorMessage: string | null;
+  startedAt: string | null;
+  completedAt: string | null;
+  etag: string | null;
+  verification: 'verified' | 'mismatch' | 'unverified' | null;
+}
+
+export interface HistoryFilter {
+  statuses?: UploadHistoryEntry['status'][];
+  /** Finished at or after, as YYYY-MM-DD or RFC 3339 */
+  since?: string;
+  /** Finished before, as YYYY-MM-DD or RFC 3339 */
+  until?: string;
+  bucketId?: number;
+  pathContains?: string;
+  minSize?: number;
+  maxSize?: number;
+}
+
+export interface HistoryPage {
+  entries: UploadHistoryEntry[];
+  total: number;
+  offset: number;
+  limit: number;
+}
+
+export interface HistoryStats {
+  completed: number;
+  failed: number;
+  cancelled: number;
+  /** 0 to 1, over completed and failed transfers */
+  successRate: number;
+  bytesTransferred: number;
+  /** Bytes per second */
+  averageThroughput: number;
+  daily: { day: string; bytes: number; count: number }[];
+}
+
+export interface UploadPreviewEntry {
+  localPath: string;
+  remoteKey: string;
+  size: number;
+  upload: boolean;
+  reason: string;
+}
+
+export interface UploadPreview {
+  entries: UploadPreviewEntry[];
+  uploadCount: number;
+  uploadBytes: number;
+  skipCount: number;
+  skipBytes: number;
+}
+
 export interface AppSettings {
   theme: 'light' | 'dark' | 'system';
+  /** Multipart part size in bytes, at least 5 MB */
   chunkSize: number;
+  /** Parts of one upload sent at once, 1 to 32 */
   parallelUploads: number;
+  /** Upload bytes per second, 0 for unlimited */
   bandwidthLimit: number;
-  conflictResolution: 'ask' | 'local' | 'remote' | 'newest';
+  conflictResolution: 'ask' | 'overwrite' | 'skip' | 'rename';
   notifications: boolean;
 }
 
@@ -96,8 +160,63 @@ export interface StalledUpload {
   age_hours: number;
 }
 
+export interface UploadedPart {
+  part_number: number;
+  size: number;
+  etag: string;
+  last_modified: string | null;
+  checksum_sha256: string | null;
+}
+
+export interface StalledUploadDetails {
+  upload_id: string;
+  key: string;
+  parts: UploadedPart[];
+  stored_bytes: number;
+  local_path: string | null;
+}
+
 export interface CleanupResult {
   cleaned_count: number;
   cleaned_uploads: StalledUpload[];
+  protected_count: number;
   threshold_hours: number;
 }
+
+export interface MaintenanceSettings {
+  enabled: boolean;
+  staleUploadMaxAgeHours: number;
+  intervalHours: number;
+  /** Days finished uploads stay in the history, 0 to keep them forever */
+  historyRetentionDays: number;
+}
+
+export interface DatabaseMaintenance {
+  prunedUploads: number;
+  orphanedChunks: number;
+  reclaimedBytes: number;
+  integrityErrors: string[];
+}
+
+export interface DatabaseDiagnostics {
+  path: string;
+  sizeBytes: number;
+  walBytes: number;
+  freeBytes: number;
+  journalMode: string;
+  schemaVersion: number;
+  tables: { table: string; rows: number }[];
+}
+
+export interface MaintenanceLogEntry {
+  id: number;
+  task: string;
+  trigger: 'scheduled' | 'manual';
+  bucketName: string | null;
+  status: 'success' | 'error';
+  cleanedCount: number;
+  protectedCount: number;
+  message: string | null;
+  startedAt: string;
+  finishedAt: string | null;
+}